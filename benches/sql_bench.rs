@@ -1,6 +1,10 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
 use my_sql_db::sql::engine::kv::KVEngine;
-use my_sql_db::sql::engine::Engine;
+use my_sql_db::sql::engine::{Engine, Transaction};
+use my_sql_db::sql::parser::ast::{bind_expression, Sentence};
+use my_sql_db::sql::parser::lexer::Lexer;
+use my_sql_db::sql::parser::Parser;
+use my_sql_db::sql::types::{Row, Value};
 use my_sql_db::storage::disk::DiskEngine;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
@@ -27,7 +31,8 @@ pub fn benchmark_operations(c: &mut Criterion) {
         println!("Database file will be at: {:?}", db_file);
 
         let kv_engine =
-            KVEngine::new(DiskEngine::new(db_file).expect("Failed to create DiskEngine"));
+            KVEngine::new(DiskEngine::new(db_file).expect("Failed to create DiskEngine"))
+                .expect("Failed to open KVEngine");
 
         let mut session = kv_engine.session().expect("Failed to create session");
 
@@ -124,12 +129,485 @@ pub fn benchmark_operations(c: &mut Criterion) {
     println!("=== SQL Benchmarks Completed ===");
 }
 
+// 一条语句插入 10k 行：逐行写入 vs 批量写入
+pub fn benchmark_batch_insert(c: &mut Criterion) {
+    const ROWS: usize = 10_000;
+
+    let mut group = c.benchmark_group("Batch Insert");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+
+    // 每次迭代使用一个新的空表，建表不计入耗时
+    let setup = || {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let kv_engine = KVEngine::new(
+            DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+        )
+        .expect("Failed to open KVEngine");
+        kv_engine
+            .session()
+            .expect("Failed to create session")
+            .execute("CREATE TABLE test (id INT PRIMARY KEY, value TEXT INDEX);")
+            .expect("Failed to create table");
+        (temp_dir, kv_engine)
+    };
+    let rows = || -> Vec<Row> {
+        (0..ROWS)
+            .map(|i| {
+                vec![
+                    Value::Integer(i as i64),
+                    Value::String(format!("value_{}", i % 100)),
+                ]
+            })
+            .collect()
+    };
+
+    // 优化之前的写法：每行单独检查主键、读写索引
+    group.bench_function("row_by_row_10k", |b| {
+        b.iter_batched(
+            || (setup(), rows()),
+            |((_temp_dir, kv_engine), rows)| {
+                let mut transaction = kv_engine.begin().expect("Begin failed");
+                for row in rows {
+                    transaction
+                        .create_row("test".into(), row)
+                        .expect("Insert failed");
+                }
+                transaction.commit().expect("Commit failed");
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("batch_10k", |b| {
+        b.iter_batched(
+            || (setup(), rows()),
+            |((_temp_dir, kv_engine), rows)| {
+                let mut transaction = kv_engine.begin().expect("Begin failed");
+                transaction
+                    .create_rows("test".into(), rows)
+                    .expect("Insert failed");
+                transaction.commit().expect("Commit failed");
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    // 端到端：一条 insert 语句，包含解析开销
+    let sql = format!(
+        "INSERT INTO test VALUES {};",
+        (0..ROWS)
+            .map(|i| format!("({}, 'value_{}')", i, i % 100))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    group.bench_function("sql_batch_10k", |b| {
+        b.iter_batched(
+            setup,
+            |(_temp_dir, kv_engine)| {
+                kv_engine
+                    .session()
+                    .expect("Failed to create session")
+                    .execute(&sql)
+                    .expect("Insert failed")
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+// 一条语句向 20 列的表插入 10k 行：不指定列（不需要补全默认值） vs 指定列（乱序并且省略部分列）
+pub fn benchmark_wide_insert(c: &mut Criterion) {
+    const ROWS: usize = 10_000;
+    const COLUMNS: usize = 20;
+
+    let mut group = c.benchmark_group("Wide Insert");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+
+    let setup = || {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let kv_engine = KVEngine::new(
+            DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+        )
+        .expect("Failed to open KVEngine");
+        let columns = (1..COLUMNS)
+            .map(|i| format!("c{} INT DEFAULT 0", i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        kv_engine
+            .session()
+            .expect("Failed to create session")
+            .execute(&format!(
+                "CREATE TABLE test (c0 INT PRIMARY KEY, {});",
+                columns
+            ))
+            .expect("Failed to create table");
+        (temp_dir, kv_engine)
+    };
+
+    let positional = format!(
+        "INSERT INTO test VALUES {};",
+        (0..ROWS)
+            .map(|i| {
+                let values = (0..COLUMNS)
+                    .map(|j| (i + j).to_string())
+                    .collect::<Vec<_>>();
+                format!("({})", values.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    // 倒序给出前 15 列，剩下的列使用默认值
+    let named_columns = (0..15).rev().map(|j| format!("c{}", j)).collect::<Vec<_>>();
+    let named = format!(
+        "INSERT INTO test ({}) VALUES {};",
+        named_columns.join(", "),
+        (0..ROWS)
+            .map(|i| {
+                let values = (0..15)
+                    .rev()
+                    .map(|j| (i + j).to_string())
+                    .collect::<Vec<_>>();
+                format!("({})", values.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    for (name, sql) in [("positional_10k", &positional), ("named_10k", &named)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                setup,
+                |(_temp_dir, kv_engine)| {
+                    kv_engine
+                        .session()
+                        .expect("Failed to create session")
+                        .execute(sql)
+                        .expect("Insert failed")
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+// 在 10 列的表上过滤 100k 行：列位置绑定一次 vs 每行都按列名查找
+pub fn benchmark_filter(c: &mut Criterion) {
+    const ROWS: usize = 100_000;
+
+    let mut group = c.benchmark_group("Filter");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+
+    let columns: Vec<String> = (0..10).map(|i| format!("c{}", i)).collect();
+    let rows: Vec<Row> = (0..ROWS)
+        .map(|i| {
+            (0..10)
+                .map(|j| Value::Integer((i * 10 + j) as i64 % 1000))
+                .collect()
+        })
+        .collect();
+    // 条件引用靠后的列，按列名查找时需要比较更多列名
+    let filter = Parser::new("select * from t where c9 = 99;")
+        .parse()
+        .expect("Parse failed");
+    let filter = match filter {
+        Sentence::Select {
+            where_condition: Some(filter),
+            ..
+        } => filter,
+        _ => unreachable!(),
+    };
+
+    // 优化之前的求值方式：每一行都重新解析列的位置
+    group.bench_function("bind_per_row_100k", |b| {
+        b.iter(|| {
+            rows.iter()
+                .filter(|row| {
+                    bind_expression(&filter, &columns, &columns)
+                        .and_then(|e| e.evaluate(row, row))
+                        .expect("Evaluate failed")
+                        == Value::Boolean(true)
+                })
+                .count()
+        })
+    });
+
+    group.bench_function("bind_once_100k", |b| {
+        b.iter(|| {
+            let bound = bind_expression(&filter, &columns, &columns).expect("Bind failed");
+            rows.iter()
+                .filter(|row| {
+                    bound.evaluate(row, row).expect("Evaluate failed") == Value::Boolean(true)
+                })
+                .count()
+        })
+    });
+
+    // 端到端：包含从存储中读取、反序列化行的开销
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let kv_engine = KVEngine::new(
+        DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+    )
+    .expect("Failed to open KVEngine");
+    let mut session = kv_engine.session().expect("Failed to create session");
+    session
+        .execute(&format!(
+            "CREATE TABLE t ({});",
+            columns
+                .iter()
+                .map(|c| format!("{} INT", c))
+                .collect::<Vec<_>>()
+                .join(", ")
+                .replacen("INT", "INT PRIMARY KEY", 1)
+        ))
+        .expect("Failed to create table");
+    let mut transaction = kv_engine.begin().expect("Begin failed");
+    let pk_rows = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut row = row.clone();
+            row[0] = Value::Integer(i as i64);
+            row
+        })
+        .collect();
+    transaction
+        .create_rows("t".into(), pk_rows)
+        .expect("Insert failed");
+    transaction.commit().expect("Commit failed");
+    group.bench_function("sql_filter_100k", |b| {
+        b.iter(|| {
+            session
+                .execute("SELECT * FROM t WHERE c9 = 99;")
+                .expect("Select failed")
+        })
+    });
+
+    group.finish();
+}
+
+// 词法分析 + 语法分析约 1MB 的 sql 脚本（数千条 insert）
+pub fn benchmark_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Parse");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+
+    let mut statements = vec![
+        "CREATE TABLE orders (id INT PRIMARY KEY, customer_name VARCHAR NOT NULL, Amount FLOAT DEFAULT 0.0, paid BOOLEAN INDEX);".to_string(),
+    ];
+    let mut size = statements[0].len();
+    let mut i = 0;
+    while size < 1024 * 1024 {
+        let sql = match i % 4 {
+            0 => format!(
+                "INSERT INTO orders (id, customer_name, Amount, paid) VALUES ({}, 'customer_{}', {}.5, true), ({}, 'customer_{}', {}.25, false);",
+                i * 2, i, i, i * 2 + 1, i, i
+            ),
+            1 => format!(
+                "select id, customer_name, amount from orders where Amount >= {} order by customer_name desc limit 10;",
+                i
+            ),
+            2 => format!("update orders set paid = TRUE, amount = {} where id = {};", i, i),
+            _ => format!("delete FROM orders WHERE id = {};", i),
+        };
+        size += sql.len() + 1;
+        statements.push(sql);
+        i += 1;
+    }
+    let script = statements.join("\n");
+
+    group.bench_function("lex_1mb", |b| {
+        b.iter(|| {
+            Lexer::new(&script)
+                .collect::<my_sql_db::error::Result<Vec<_>>>()
+                .expect("Lex failed")
+                .len()
+        })
+    });
+
+    group.bench_function("parse_1mb", |b| {
+        b.iter(|| {
+            for sql in &statements {
+                Parser::new(sql).parse().expect("Parse failed");
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// 1000 条小语句反复读取同一张表的结构：每次都从存储中读取并反序列化 vs 事务内缓存
+pub fn benchmark_schema_cache(c: &mut Criterion) {
+    const STATEMENTS: usize = 1000;
+
+    let mut group = c.benchmark_group("Schema Cache");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(2));
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let kv_engine = KVEngine::new(
+        DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+    )
+    .expect("Failed to open KVEngine");
+    let columns = (1..20)
+        .map(|i| format!("c{} INT DEFAULT 0", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut session = kv_engine.session().expect("Failed to create session");
+    session
+        .execute(&format!(
+            "CREATE TABLE test (c0 INT PRIMARY KEY, {});",
+            columns
+        ))
+        .expect("Failed to create table");
+
+    // 每次查找都在新事务中进行，缓存不生效，每次都要反序列化表结构
+    group.bench_function("get_table_uncached_1k", |b| {
+        b.iter(|| {
+            for _ in 0..STATEMENTS {
+                let transaction = kv_engine.begin_read_only().expect("Begin failed");
+                transaction
+                    .must_get_table("test".into())
+                    .expect("Get table failed");
+            }
+        })
+    });
+
+    // 同一个事务中查找，只有第一次需要反序列化
+    group.bench_function("get_table_cached_1k", |b| {
+        b.iter(|| {
+            let transaction = kv_engine.begin_read_only().expect("Begin failed");
+            for _ in 0..STATEMENTS {
+                transaction
+                    .must_get_table("test".into())
+                    .expect("Get table failed");
+            }
+        })
+    });
+
+    // 端到端：显式事务中执行 1000 条单行查询，事务内的语句共享表结构缓存
+    group.bench_function("sql_select_in_transaction_1k", |b| {
+        b.iter(|| {
+            session.execute("BEGIN;").expect("Begin failed");
+            for i in 0..STATEMENTS {
+                session
+                    .execute(&format!("SELECT * FROM test WHERE c0 = {};", i))
+                    .expect("Select failed");
+            }
+            session.execute("COMMIT;").expect("Commit failed");
+        })
+    });
+
+    group.finish();
+}
+
+// 全表扫描 100k 行，按每秒扫描的行数统计：存储层扫描 + 反序列化，以及包含结果集构造的端到端查询
+pub fn benchmark_full_scan(c: &mut Criterion) {
+    const ROWS: usize = 100_000;
+
+    let mut group = c.benchmark_group("Full Scan");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+    group.throughput(Throughput::Elements(ROWS as u64));
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let kv_engine = KVEngine::new(
+        DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+    )
+    .expect("Failed to open KVEngine");
+    let mut session = kv_engine.session().expect("Failed to create session");
+    session
+        .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT, score FLOAT, active BOOL);")
+        .expect("Failed to create table");
+    let rows = (0..ROWS)
+        .map(|i| {
+            vec![
+                Value::Integer(i as i64),
+                Value::String(format!("name_{}", i)),
+                Value::Float(i as f64 / 10.0),
+                Value::Boolean(i % 2 == 0),
+            ]
+        })
+        .collect();
+    let mut transaction = kv_engine.begin().expect("Begin failed");
+    transaction
+        .create_rows("t".into(), rows)
+        .expect("Insert failed");
+    transaction.commit().expect("Commit failed");
+
+    group.bench_function("scan_100k", |b| {
+        b.iter(|| {
+            let transaction = kv_engine.begin_read_only().expect("Begin failed");
+            transaction
+                .scan("t".into(), None)
+                .expect("Scan failed")
+                .len()
+        })
+    });
+
+    group.bench_function("sql_full_scan_100k", |b| {
+        b.iter(|| session.execute("SELECT * FROM t;").expect("Select failed"))
+    });
+
+    group.finish();
+}
+
+// 回滚一个插入了 10k 行（包括索引）的事务，插入不计入耗时
+pub fn benchmark_rollback(c: &mut Criterion) {
+    const ROWS: usize = 10_000;
+
+    let mut group = c.benchmark_group("Rollback");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(5));
+
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let kv_engine = KVEngine::new(
+        DiskEngine::new(temp_dir.path().join("test.db")).expect("Failed to create DiskEngine"),
+    )
+    .expect("Failed to open KVEngine");
+    kv_engine
+        .session()
+        .expect("Failed to create session")
+        .execute("CREATE TABLE test (id INT PRIMARY KEY, value TEXT INDEX);")
+        .expect("Failed to create table");
+
+    group.bench_function("rollback_10k", |b| {
+        b.iter_batched(
+            || {
+                let rows = (0..ROWS)
+                    .map(|i| {
+                        vec![
+                            Value::Integer(i as i64),
+                            Value::String(format!("value_{}", i % 100)),
+                        ]
+                    })
+                    .collect();
+                let mut transaction = kv_engine.begin().expect("Begin failed");
+                transaction
+                    .create_rows("test".into(), rows)
+                    .expect("Insert failed");
+                transaction
+            },
+            |transaction| transaction.rollback().expect("Rollback failed"),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default()
         .sample_size(10)
         .measurement_time(Duration::from_secs(1))
         .warm_up_time(Duration::from_millis(500));
-    targets = benchmark_operations
+    targets = benchmark_operations, benchmark_batch_insert, benchmark_wide_insert, benchmark_filter, benchmark_parse, benchmark_schema_cache, benchmark_full_scan, benchmark_rollback
 }
 criterion_main!(benches);
@@ -0,0 +1,229 @@
+// 分析查询的示例：生成一批订单数据批量导入，再执行分组聚合、连接、排序等查询并打印耗时
+// 每个查询的结果都会和直接在内存中计算的结果对比
+//
+// cargo run --release --example analytics [行数]
+use my_sql_db::{DiskEngine, Engine, KVEngine, Result, ResultSet, Row, Session, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+
+const DEFAULT_ROWS: usize = 50_000;
+const CUSTOMERS: i64 = 500;
+const BATCH_SIZE: usize = 1_000;
+const CATEGORIES: [&str; 5] = ["books", "games", "garden", "music", "toys"];
+const REGIONS: [&str; 4] = ["east", "north", "south", "west"];
+
+struct Order {
+    id: i64,
+    customer_id: i64,
+    category: &'static str,
+    quantity: i64,
+    amount: f64,
+}
+
+// 线性同余生成器，固定种子，每次运行生成相同的数据
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+fn generate_orders(count: usize) -> Vec<Order> {
+    let mut rng = Lcg(42);
+    (0..count as i64)
+        .map(|id| Order {
+            id,
+            customer_id: rng.next(CUSTOMERS as u64) as i64,
+            category: CATEGORIES[rng.next(CATEGORIES.len() as u64) as usize],
+            quantity: 1 + rng.next(10) as i64,
+            // 金额保留两位小数以内，精确到分
+            amount: rng.next(100_000) as f64 / 100.0,
+        })
+        .collect()
+}
+
+fn region_of(customer_id: i64) -> &'static str {
+    REGIONS[customer_id as usize % REGIONS.len()]
+}
+
+fn timed<T>(label: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let start = Instant::now();
+    let result = f()?;
+    println!("{:<32} {:>10.2?}", label, start.elapsed());
+    Ok(result)
+}
+
+fn query(s: &mut Session<KVEngine<DiskEngine>>, sql: &str) -> Result<Vec<Row>> {
+    match s.execute(sql)? {
+        ResultSet::Scan { rows, .. } => Ok(rows),
+        _ => unreachable!(),
+    }
+}
+
+fn import(s: &mut Session<KVEngine<DiskEngine>>, orders: &[Order]) -> Result<()> {
+    s.execute("create table customers (cid int primary key, region text index);")?;
+    s.execute(
+        "create table orders (
+             id int primary key,
+             customer_id int index,
+             category text,
+             quantity int,
+             amount float
+         );",
+    )?;
+    let customers = (0..CUSTOMERS)
+        .map(|id| format!("({}, '{}')", id, region_of(id)))
+        .collect::<Vec<_>>();
+    s.execute(&format!(
+        "insert into customers values {};",
+        customers.join(", ")
+    ))?;
+
+    // 整个导入放在一个事务中，分批插入
+    s.execute("begin;")?;
+    for batch in orders.chunks(BATCH_SIZE) {
+        let values = batch
+            .iter()
+            .map(|o| {
+                format!(
+                    "({}, {}, '{}', {}, {:?})",
+                    o.id, o.customer_id, o.category, o.quantity, o.amount
+                )
+            })
+            .collect::<Vec<_>>();
+        s.execute(&format!("insert into orders values {};", values.join(", ")))?;
+    }
+    s.execute("commit;")?;
+    Ok(())
+}
+
+fn run(dir: &Path, rows: usize) -> Result<()> {
+    let engine = KVEngine::new(DiskEngine::new(dir.join("analytics-log"))?)?;
+    let mut s = engine.session()?;
+    let orders = generate_orders(rows);
+    timed(&format!("import {} orders", rows), || {
+        import(&mut s, &orders)
+    })?;
+
+    let count = timed("count(*)", || {
+        s.query_scalar("select count(*) from orders;")
+    })?;
+    assert_eq!(count, Value::Integer(rows as i64));
+
+    // 按类别分组：订单数、总数量、平均金额
+    let by_category = timed("group by category", || {
+        query(
+            &mut s,
+            "select category, count(id), sum(quantity), avg(amount) from orders
+             group by category order by category;",
+        )
+    })?;
+    let mut expected = BTreeMap::new();
+    for o in orders.iter() {
+        let entry = expected.entry(o.category).or_insert((0, 0, 0.0));
+        entry.0 += 1;
+        entry.1 += o.quantity;
+        entry.2 += o.amount;
+    }
+    assert_eq!(by_category.len(), expected.len());
+    for (row, (category, (n, quantity, amount))) in by_category.iter().zip(expected) {
+        println!("  {:?}", row);
+        assert_eq!(row[0], Value::String(category.into()));
+        assert_eq!(row[1], Value::Integer(n));
+        assert_eq!(row[2], Value::Integer(quantity));
+        match row[3] {
+            Value::Float(avg) => assert!((avg - amount / n as f64).abs() < 1e-6),
+            ref v => panic!("unexpected avg: {}", v),
+        }
+    }
+
+    // 连接客户表，按地区统计订单数
+    let by_region = timed("join + group by region", || {
+        query(
+            &mut s,
+            "select region, count(id) from orders join customers on customer_id = cid
+             group by region order by region;",
+        )
+    })?;
+    let mut expected = BTreeMap::new();
+    for o in orders.iter() {
+        *expected.entry(region_of(o.customer_id)).or_insert(0) += 1;
+    }
+    let expected = expected
+        .into_iter()
+        .map(|(region, n)| vec![Value::String(region.into()), Value::Integer(n)])
+        .collect::<Vec<_>>();
+    println!("  {:?}", by_region);
+    assert_eq!(by_region, expected);
+
+    // 过滤、排序取前几名
+    let top = timed("filter + order by + limit", || {
+        query(
+            &mut s,
+            "select id, amount from orders where category = 'books'
+             order by amount desc, id asc limit 5;",
+        )
+    })?;
+    let mut expected = orders
+        .iter()
+        .filter(|o| o.category == "books")
+        .collect::<Vec<_>>();
+    expected.sort_by(|a, b| b.amount.total_cmp(&a.amount).then(a.id.cmp(&b.id)));
+    let expected = expected
+        .iter()
+        .take(5)
+        .map(|o| vec![Value::Integer(o.id), Value::Float(o.amount)])
+        .collect::<Vec<_>>();
+    assert_eq!(top, expected);
+
+    // 索引查询：某个客户的全部订单
+    let customer_orders = timed("index lookup", || {
+        query(&mut s, "select id from orders where customer_id = 7;")
+    })?;
+    let expected = orders.iter().filter(|o| o.customer_id == 7).count();
+    assert_eq!(customer_orders.len(), expected);
+
+    // 表的校验和，相同的数据得到相同的结果
+    let checksum = |s: &mut Session<KVEngine<DiskEngine>>| -> Result<u64> {
+        match s.execute("checksum table orders;")? {
+            ResultSet::ChecksumTable { checksum, .. } => Ok(checksum),
+            _ => unreachable!(),
+        }
+    };
+    let before = timed("checksum table", || checksum(&mut s))?;
+    s.execute("update orders set quantity = 0 where id = 0;")?;
+    assert_ne!(checksum(&mut s)?, before);
+    s.execute(&format!(
+        "update orders set quantity = {} where id = 0;",
+        orders[0].quantity
+    ))?;
+    assert_eq!(checksum(&mut s)?, before);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let rows = match std::env::args().nth(1) {
+        Some(arg) => arg.parse().expect("number of rows"),
+        None => DEFAULT_ROWS,
+    };
+    let dir = tempfile::tempdir()?;
+    run(dir.path(), rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use my_sql_db::Result;
+
+    // 未优化的测试构建导入 5 万行需要半分钟左右，测试中只导入 5000 行，完整的数据量用 cargo run 运行
+    #[test]
+    fn test_analytics_example() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        super::run(dir.path(), 5_000)
+    }
+}
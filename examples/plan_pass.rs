@@ -0,0 +1,53 @@
+// 在 planner 之外编写优化规则的示例：
+// 遍历执行计划统计各类节点，并将 HashJoin 改写为 NestedLoopJoin 后执行
+use my_sql_db::error::Result;
+use my_sql_db::sql::engine::kv::KVEngine;
+use my_sql_db::sql::engine::{Engine, Transaction};
+use my_sql_db::sql::parser::ast::join_conjuncts;
+use my_sql_db::sql::parser::Parser;
+use my_sql_db::sql::planner::{Node, Plan};
+use my_sql_db::storage::memory::MemoryEngine;
+use std::collections::BTreeMap;
+
+// 用户自定义的优化规则
+fn hash_join_to_nested_loop(plan: Plan) -> Plan {
+    plan.map(|node| match node {
+        Node::HashJoin {
+            left,
+            right,
+            condition,
+            residual,
+            outer,
+        } => Node::NestedLoopJoin {
+            left,
+            right,
+            // 嵌套循环对整个连接条件逐对求值
+            condition: join_conjuncts(condition.into_iter().chain(residual).collect()),
+            outer,
+        },
+        node => node,
+    })
+}
+
+fn main() -> Result<()> {
+    let kvengine = KVEngine::new(MemoryEngine::new())?;
+    let mut s = kvengine.session()?;
+    s.execute("create table t1 (a int primary key, b text);")?;
+    s.execute("create table t2 (c int primary key, d text);")?;
+    s.execute("insert into t1 values (1, 'a'), (2, 'b'), (3, 'c');")?;
+    s.execute("insert into t2 values (2, 'x'), (3, 'y'), (4, 'z');")?;
+
+    let mut transaction = kvengine.begin()?;
+    let sql = "select * from t1 left join t2 on a = c;";
+    let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+
+    let mut kinds = BTreeMap::new();
+    plan.walk(|node| *kinds.entry(node.kind()).or_insert(0) += 1);
+    println!("nodes: {:?}", kinds);
+
+    let plan = hash_join_to_nested_loop(plan);
+    println!("{}", plan.0);
+    println!("{}", plan.execute(&mut transaction)?.to_string());
+    transaction.commit()?;
+    Ok(())
+}
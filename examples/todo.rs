@@ -0,0 +1,188 @@
+// 嵌入式使用的示例：一个保存在磁盘上的待办事项应用
+// 打开存储引擎、建表，添加、完成、删除任务，按条件和优先级查询，关闭之后重新打开数据仍然存在
+//
+// cargo run --example todo
+use my_sql_db::{DiskEngine, Engine, KVEngine, Result, ResultSet, Row, Session, Value};
+use std::path::Path;
+
+const SCHEMA_VERSION: &str = "1";
+
+#[derive(Debug, PartialEq)]
+struct Task {
+    id: i64,
+    title: String,
+    priority: i64,
+}
+
+struct TodoApp {
+    session: Session<KVEngine<DiskEngine>>,
+    next_id: i64,
+}
+
+impl TodoApp {
+    // 打开数据目录下的存储文件，第一次打开时建表并记录表结构的版本
+    fn open(dir: &Path) -> Result<Self> {
+        let engine = KVEngine::new(DiskEngine::new(dir.join("todo-log"))?)?;
+        let mut session = engine.session()?;
+        let tables = match session.execute("show tables;")? {
+            ResultSet::TableNames { names } => names,
+            _ => unreachable!(),
+        };
+        if !tables.iter().any(|name| name == "tasks") {
+            session.execute_batch(&format!(
+                "begin;
+                 create table tasks (
+                     id int primary key,
+                     title text not null,
+                     priority int default 0,
+                     done bool default false
+                 );
+                 kv put 'todo.schema_version' '{}';
+                 commit;",
+                SCHEMA_VERSION
+            ))?;
+        }
+        let next_id = match session.query_scalar("select max(id) from tasks;")? {
+            Value::Integer(id) => id + 1,
+            _ => 1,
+        };
+        Ok(Self { session, next_id })
+    }
+
+    fn schema_version(&mut self) -> Result<Value> {
+        Ok(self
+            .query("kv get 'todo.schema_version';")?
+            .remove(0)
+            .remove(1))
+    }
+
+    fn add(&mut self, title: &str, priority: i64) -> Result<i64> {
+        let id = self.next_id;
+        self.session.execute(&format!(
+            "insert into tasks (id, title, priority) values ({}, '{}', {});",
+            id, title, priority
+        ))?;
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    // 标记任务完成，任务不存在或者已经完成时返回 false
+    fn complete(&mut self, id: i64) -> Result<bool> {
+        match self.session.execute(&format!(
+            "update tasks set done = true where id = {} and done = false;",
+            id
+        ))? {
+            ResultSet::Update { count } => Ok(count > 0),
+            _ => unreachable!(),
+        }
+    }
+
+    // 删除已经完成的任务，返回删除的数量
+    fn clear_done(&mut self) -> Result<usize> {
+        match self
+            .session
+            .execute("delete from tasks where done = true;")?
+        {
+            ResultSet::Delete { count } => Ok(count),
+            _ => unreachable!(),
+        }
+    }
+
+    // 未完成的任务，优先级高的在前，同优先级按添加顺序
+    fn pending(&mut self, min_priority: i64) -> Result<Vec<Task>> {
+        let rows = self.query(&format!(
+            "select id, title, priority from tasks
+             where done = false and priority >= {}
+             order by priority desc, id asc;",
+            min_priority
+        ))?;
+        Ok(rows.into_iter().map(Task::from_row).collect())
+    }
+
+    // 已完成和未完成的任务数量
+    fn summary(&mut self) -> Result<(i64, i64)> {
+        let mut summary = (0, 0);
+        for row in self.query("select done, count(id) from tasks group by done;")? {
+            match (&row[0], &row[1]) {
+                (Value::Boolean(true), Value::Integer(n)) => summary.0 = *n,
+                (Value::Boolean(false), Value::Integer(n)) => summary.1 = *n,
+                _ => unreachable!(),
+            }
+        }
+        Ok(summary)
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Row>> {
+        match self.session.execute(sql)? {
+            ResultSet::Scan { rows, .. } => Ok(rows),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Task {
+    fn from_row(row: Row) -> Self {
+        match row.as_slice() {
+            [Value::Integer(id), Value::String(title), Value::Integer(priority)] => Task {
+                id: *id,
+                title: title.clone(),
+                priority: *priority,
+            },
+            _ => panic!("unexpected task row: {:?}", row),
+        }
+    }
+}
+
+fn run(dir: &Path) -> Result<()> {
+    let mut app = TodoApp::open(dir)?;
+    let write = app.add("write report", 2)?;
+    let review = app.add("review pull request", 3)?;
+    let groceries = app.add("buy groceries", 1)?;
+    let call = app.add("call plumber", 3)?;
+
+    let pending = app.pending(0)?;
+    for task in pending.iter() {
+        println!("[{}] {} (priority {})", task.id, task.title, task.priority);
+    }
+    let ids = pending.iter().map(|t| t.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![review, call, write, groceries]);
+
+    assert!(app.complete(review)?);
+    assert!(!app.complete(review)?);
+    assert!(!app.complete(100)?);
+    assert!(app.complete(groceries)?);
+    assert_eq!(app.summary()?, (2, 2));
+    let important = app.pending(2)?;
+    println!("important: {:?}", important);
+    assert_eq!(
+        important.iter().map(|t| t.id).collect::<Vec<_>>(),
+        vec![call, write]
+    );
+    assert_eq!(app.clear_done()?, 2);
+
+    // 关闭之后重新打开，数据和编号都接着之前的继续
+    drop(app);
+    let mut app = TodoApp::open(dir)?;
+    assert_eq!(app.schema_version()?, Value::String(SCHEMA_VERSION.into()));
+    assert_eq!(app.summary()?, (0, 2));
+    let next = app.add("water plants", 0)?;
+    assert_eq!(next, call + 1);
+    println!("after reopen: {:?}", app.pending(0)?);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    run(dir.path())
+}
+
+#[cfg(test)]
+mod tests {
+    use my_sql_db::Result;
+
+    #[test]
+    fn test_todo_example() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        super::run(dir.path())
+    }
+}
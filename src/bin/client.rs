@@ -11,15 +11,19 @@ use std::env;
 use std::error::Error;
 use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
+use tokio::runtime::{Handle, RuntimeFlavor};
 use tokio_util::codec::{FramedRead, FramedWrite, LinesCodec};
 
+use my_sql_db::protocol::{
+    Banner, DEFAULT_MAX_LINE_LENGTH, ERROR_PREFIX, RESPONSE_END, RESULT_SEPARATOR, STATUS_PREFIX,
+};
 use my_sql_db::sql::parser::lexer::Keyword;
 use strum::IntoEnumIterator;
 
-const RESPONSE_END: &str = "!!!THIS IS THE END!!!";
 const HISTORY_FILE: &str = ".history";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5); // 连接后等待服务端握手信息的时间
 
 // 命令行历史文件存储路径为，本项目根目录下
 fn get_history_path() -> PathBuf {
@@ -102,7 +106,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .unwrap_or_else(|| "127.0.0.1:8080".to_string());
 
     let addr = addr.parse::<SocketAddr>()?;
-    let mut client = Client::new(addr).await?;
+    let mut repl = Repl {
+        client: Client::new(addr).await?,
+        timing: true,
+    };
 
     // 配置 Rustyline
     let config = Config::builder()
@@ -128,7 +135,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut multiline = String::new();
     loop {
         let prompt = if multiline.is_empty() {
-            match client.transaction_version {
+            match repl.client.transaction_version {
                 Some(version) => format!("transaction#{}>> ", version),
                 None => "sql-db>> ".to_string(),
             }
@@ -140,6 +147,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         match readline {
             Ok(line) => {
                 let trimmed = line.trim();
+                // 反斜杠开头的是客户端命令，单独成行，不需要分号结尾
+                if trimmed.starts_with('\\') {
+                    editor.add_history_entry(trimmed)?;
+                    let res = match parse_meta_command(trimmed) {
+                        Ok(command) => repl.run_meta_command(command, &mut multiline).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    if let Err(e) = res {
+                        println!("Error: {}", e);
+                    }
+                    continue;
+                }
                 if !trimmed.is_empty() {
                     multiline.push_str(trimmed);
                     multiline.push(' '); // 保留空格
@@ -151,14 +170,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             break;
                         }
                         editor.add_history_entry(&cmd)?;
-                        // 记录命令开始执行时间
-                        let start_time = Instant::now();
-                        if let Err(e) = client.exec_cmd(&cmd).await {
-                            println!("Error executing command: {}", e);
-                        }
-                        // 记录结束时间并计算耗时
-                        let duration = start_time.elapsed();
-                        println!("[Execution time: {:?}]", duration);
+                        repl.run_sql(&cmd).await;
                     }
                 }
             }
@@ -171,61 +183,724 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // 退出前回滚未结束的事务
+    repl.client.rollback_if_needed().await;
+
     // 保存历史记录
     editor.save_history(&get_history_path())?;
 
     Ok(())
 }
 
+// 客户端命令：以反斜杠开头，在客户端处理，可以转换为 sql 的才发送给服务端
+#[derive(Debug, PartialEq)]
+enum MetaCommand {
+    Sql(String),      // 转换为 sql 发送给服务端，如 \dt 即 show tables;
+    Timing,           // 切换是否打印执行时间
+    Include(PathBuf), // 逐条执行本地 sql 文件中的语句
+    Edit,             // 用 $EDITOR 编辑当前输入的内容
+    Help,             // 列出所有客户端命令
+}
+
+// 客户端命令的用法和说明，\? 时打印
+const META_COMMANDS: [(&str, &str); 6] = [
+    ("\\dt", "list tables"),
+    ("\\d TABLE", "describe table"),
+    ("\\timing", "toggle printing of execution time"),
+    ("\\i FILE", "execute statements from a local file"),
+    ("\\e", "edit the current input with $EDITOR"),
+    ("\\?", "show this help"),
+];
+
+// 解析一行客户端命令，命令不存在或者参数不对时返回错误信息
+fn parse_meta_command(line: &str) -> Result<MetaCommand, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let args = words.collect::<Vec<_>>();
+    match (name, args.as_slice()) {
+        ("\\dt", []) => Ok(MetaCommand::Sql("show tables;".into())),
+        ("\\d", [table]) => Ok(MetaCommand::Sql(format!("show table {};", table))),
+        ("\\timing", []) => Ok(MetaCommand::Timing),
+        ("\\i", [path]) => Ok(MetaCommand::Include(PathBuf::from(path))),
+        ("\\e", []) => Ok(MetaCommand::Edit),
+        ("\\?", []) => Ok(MetaCommand::Help),
+        ("\\dt" | "\\d" | "\\timing" | "\\i" | "\\e" | "\\?", _) => {
+            Err(format!("wrong arguments for {}, try \\? for help", name))
+        }
+        _ => Err(format!("unknown command {}, try \\? for help", name)),
+    }
+}
+
+// 把 sql 文本拆分为单条语句，去掉结尾的分号和 -- 注释，换行替换为空格
+// 引号中的分号和 -- 不拆分，和词法分析一样，字符串以单引号或者双引号开头，遇到和开头相同的引号结束
+// 字符串中的两个引号相当于结束之后立即开始一个新的字符串，同样不会拆分
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            // 只有和开头相同的引号才结束字符串，单引号字符串中的双引号是普通字符
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '-') if chars.peek() == Some(&'-') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            (None, ';') => {
+                statements.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            (None, '\n' | '\r') => {
+                current.push(' ');
+                continue;
+            }
+            (None, _) => {}
+        }
+        current.push(c);
+    }
+    statements.push(current.trim().to_string());
+    statements.retain(|s| !s.is_empty());
+    statements
+}
+
+// 把内容写入临时文件，用 $EDITOR（默认 vi）打开，编辑器退出后读回修改后的内容
+fn edit_in_editor(content: &str) -> Result<String, Box<dyn Error>> {
+    let file = tempfile::Builder::new().suffix(".sql").tempfile()?;
+    std::fs::write(file.path(), content)?;
+    let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new(&editor)
+        .arg(file.path())
+        .status()
+        .map_err(|e| format!("failed to start editor {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("editor {} exited with {}", editor, status).into());
+    }
+    Ok(std::fs::read_to_string(file.path())?)
+}
+
+// 交互式命令行的状态：服务端连接和客户端命令的设置
+struct Repl {
+    client: Client,
+    timing: bool, // 是否打印每条命令的执行时间
+}
+
+impl Repl {
+    // 发送一条命令并打印结果，出错时只打印错误
+    async fn run_sql(&mut self, sql: &str) {
+        // 记录命令开始执行时间
+        let start_time = Instant::now();
+        if let Err(e) = self.client.exec_cmd(sql).await {
+            println!("Error executing command: {}", e);
+        }
+        if self.timing {
+            println!("[Execution time: {:?}]", start_time.elapsed());
+        }
+    }
+
+    // 执行客户端命令，buffer 是还没有以分号结束的多行输入，\e 编辑的就是它
+    async fn run_meta_command(
+        &mut self,
+        command: MetaCommand,
+        buffer: &mut String,
+    ) -> Result<(), Box<dyn Error>> {
+        match command {
+            MetaCommand::Sql(sql) => self.run_sql(&sql).await,
+            MetaCommand::Timing => {
+                self.timing = !self.timing;
+                println!("Timing is {}.", if self.timing { "on" } else { "off" });
+            }
+            // 和交互输入一样，某条语句出错时打印错误，继续执行之后的语句
+            MetaCommand::Include(path) => {
+                let sql = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                for statement in split_statements(&sql) {
+                    self.run_sql(&statement).await;
+                }
+            }
+            // 编辑后的内容以分号结尾则直接执行，否则放回输入中继续编辑
+            MetaCommand::Edit => {
+                let edited = edit_in_editor(buffer.trim_end())?;
+                buffer.clear();
+                match edited.trim_end().ends_with(';') {
+                    true => {
+                        for statement in split_statements(&edited) {
+                            self.run_sql(&statement).await;
+                        }
+                    }
+                    false => {
+                        for line in edited.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                            buffer.push_str(line);
+                            buffer.push(' ');
+                        }
+                    }
+                }
+            }
+            MetaCommand::Help => {
+                for (usage, description) in META_COMMANDS {
+                    println!("{:<12} {}", usage, description);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct Client {
+    addr: SocketAddr, // 服务端地址，连接断开后用于重连
     stream: TcpStream,
     transaction_version: Option<u64>,
+    max_line_length: usize,
 }
 
 impl Client {
     pub async fn new(address: SocketAddr) -> Result<Self, Box<dyn Error>> {
-        let stream = TcpStream::connect(address).await?;
+        let max_line_length = env::var("MAX_LINE_LENGTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_LINE_LENGTH);
         Ok(Self {
-            stream,
+            addr: address,
+            stream: connect(address, max_line_length).await?,
             transaction_version: None,
+            max_line_length,
         })
     }
 
     pub async fn exec_cmd(&mut self, cmd: &str) -> Result<(), Box<dyn Error>> {
+        // 过长的命令直接在本地拒绝，不发送给服务端
+        if cmd.len() > self.max_line_length {
+            return Err(format!(
+                "command exceeds the max length of {} bytes, please split it into smaller statements",
+                self.max_line_length
+            )
+            .into());
+        }
+
+        match self.request(cmd).await {
+            Ok((lines, status)) => {
+                // 打印执行结果，多条语句的结果之间空一行
+                let results = split_results(lines);
+                for (i, result) in results.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    for line in result {
+                        println!("{}", line);
+                    }
+                }
+                // 优先使用服务端附带的事务状态，没有状态行时（旧版本服务端）依次从每条语句的结果推断
+                self.transaction_version = match status.as_deref().and_then(parse_status) {
+                    Some(version) => version,
+                    None => results
+                        .iter()
+                        .fold(self.transaction_version, |state, result| {
+                            next_transaction_state(state, result)
+                        }),
+                };
+                Ok(())
+            }
+            Err(e) => {
+                // 连接断开，重连后不会重试当前命令
+                println!("Connection lost: {}", e);
+                self.reconnect().await
+            }
+        }
+    }
+
+    // 发送命令，读取直到结束符的全部响应和事务状态行，结束符之前连接断开视为出错
+    async fn request(
+        &mut self,
+        cmd: &str,
+    ) -> Result<(Vec<String>, Option<String>), Box<dyn Error>> {
         let (r, w) = self.stream.split();
-        let mut sink = FramedWrite::new(w, LinesCodec::new());
-        let mut stream = FramedRead::new(r, LinesCodec::new());
+        let mut sink = FramedWrite::new(w, LinesCodec::new_with_max_length(self.max_line_length));
+        let mut stream = FramedRead::new(r, LinesCodec::new_with_max_length(self.max_line_length));
 
         // 发送命令
         sink.send(cmd).await?;
 
         // 接收执行结果
+        let mut lines = Vec::new();
+        let mut status = None;
         while let Some(val) = stream.try_next().await? {
             if val == RESPONSE_END {
-                break;
+                return Ok((lines, status));
             }
-            // 解析事务命令
-            if val.starts_with("TRANSACTION") {
-                let args = val.split(" ").collect::<Vec<_>>();
-                if args[2] == "COMMIT" || args[2] == "ROLLBACK" {
-                    self.transaction_version = None;
-                }
-                if args[2] == "BEGIN" {
-                    let version = args[1].parse::<u64>().unwrap();
-                    self.transaction_version = Some(version);
-                }
+            match val.strip_prefix(STATUS_PREFIX) {
+                Some(s) => status = Some(s.to_string()),
+                None => lines.push(val),
             }
-            // 打印执行结果
-            println!("{}", val);
         }
+        Err("connection closed by server".into())
+    }
+
+    // 重新连接服务端，原连接上的事务已经丢失
+    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(version) = self.transaction_version.take() {
+            println!(
+                "Transaction #{} was lost, its changes were not committed",
+                version
+            );
+        }
+        self.stream = connect(self.addr, self.max_line_length).await?;
+        println!("Reconnected to {}", self.addr);
         Ok(())
     }
+
+    // 退出前回滚未结束的事务
+    pub async fn rollback_if_needed(&mut self) {
+        if self.transaction_version.is_some() {
+            if let Err(e) = self.exec_cmd("ROLLBACK;").await {
+                println!("Error rolling back transaction: {}", e);
+            }
+        }
+    }
+}
+
+// 连接服务端并完成握手：服务端先发送一行握手信息，确认是本数据库的服务端、协议主版本一致之后才开始发送命令
+// 协议次版本不同时只给出提示
+async fn connect(address: SocketAddr, max_line_length: usize) -> Result<TcpStream, Box<dyn Error>> {
+    let mut stream = TcpStream::connect(address).await?;
+    let mut reader = FramedRead::new(
+        &mut stream,
+        LinesCodec::new_with_max_length(max_line_length),
+    );
+    let line = match tokio::time::timeout(HANDSHAKE_TIMEOUT, reader.try_next()).await {
+        Ok(line) => line?.ok_or("connection closed by server before handshake")?,
+        Err(_) => {
+            return Err(format!(
+                "no handshake from {} within {:?}, it may not be a sql-db server",
+                address, HANDSHAKE_TIMEOUT
+            )
+            .into())
+        }
+    };
+    if let Some(warning) = Banner::parse(&line)?.check()? {
+        println!("Warning: {}", warning);
+    }
+    Ok(stream)
+}
+
+// 按分隔行把响应拆分为每条语句的结果
+fn split_results(lines: Vec<String>) -> Vec<Vec<String>> {
+    let mut results = vec![Vec::new()];
+    for line in lines {
+        if line == RESULT_SEPARATOR {
+            results.push(Vec::new());
+        } else {
+            results.last_mut().unwrap().push(line);
+        }
+    }
+    results
+}
+
+// 解析服务端附带的事务状态：TRANSACTION <version> ACTIVE 或 IDLE，格式不对时返回None
+fn parse_status(status: &str) -> Option<Option<u64>> {
+    match status.split(' ').collect::<Vec<_>>().as_slice() {
+        ["TRANSACTION", version, "ACTIVE"] => version.parse().ok().map(Some),
+        ["IDLE"] => Some(None),
+        _ => None,
+    }
+}
+
+// 根据服务端的响应计算新的事务状态
+// 只看响应的第一行，错误响应不改变状态；服务端在事务被动结束时会先返回一行 ROLLBACK
+fn next_transaction_state(current: Option<u64>, lines: &[String]) -> Option<u64> {
+    let Some(first) = lines.first() else {
+        return current;
+    };
+    if first.starts_with(ERROR_PREFIX) {
+        return current;
+    }
+    let args = first.split(' ').collect::<Vec<_>>();
+    match args.as_slice() {
+        ["TRANSACTION", version, "BEGIN"] => version.parse::<u64>().ok().or(current),
+        ["TRANSACTION", _, "COMMIT" | "ROLLBACK"] => None,
+        _ => current,
+    }
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
-        if self.transaction_version.is_some() {
-            futures::executor::block_on(self.exec_cmd("ROLLBACK;")).expect("rollback failed");
+        // 尽力回滚：只有在多线程 runtime 中才能安全地阻塞等待，其他情况直接跳过
+        if self.transaction_version.is_none() {
+            return;
+        }
+        if let Ok(handle) = Handle::try_current() {
+            if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+                let _ = tokio::task::block_in_place(|| handle.block_on(self.exec_cmd("ROLLBACK;")));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use my_sql_db::protocol::{PROTOCOL_MAJOR, PROTOCOL_MINOR};
+    use my_sql_db::{Engine, KVEngine, MemoryEngine};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_next_transaction_state() {
+        // 按顺序执行的一组命令响应，以及每条命令执行后期望的事务状态
+        let script = vec![
+            (lines(&["TRANSACTION 3 BEGIN"]), Some(3)),
+            (lines(&["INSERT 1 rows"]), Some(3)),
+            (
+                lines(&["Error: Internal Error: [Get Table] Table \" t \" does not exist"]),
+                Some(3),
+            ),
+            (
+                lines(&["Error: Internal Error: [Exec Transaction] Already in transaction"]),
+                Some(3),
+            ),
+            (lines(&["SAVEPOINT s1"]), Some(3)),
+            (lines(&["TRANSACTION 3 COMMIT"]), None),
+            (
+                lines(&["Error: Internal Error: [Exec Transaction] Not in transaction"]),
+                None,
+            ),
+            (lines(&["TRANSACTION 5 BEGIN"]), Some(5)),
+            // 提交失败，服务端先告知事务已回滚
+            (
+                lines(&[
+                    "TRANSACTION 5 ROLLBACK",
+                    "Error: Write conflicted in transaction, please try again",
+                ]),
+                None,
+            ),
+            (lines(&["TRANSACTION 6 BEGIN"]), Some(6)),
+            (lines(&["TRANSACTION 6 ROLLBACK"]), None),
+            // 格式不对的响应不改变状态
+            (lines(&["TRANSACTION x BEGIN"]), None),
+            (lines(&["TRANSACTION"]), None),
+            (lines(&[]), None),
+        ];
+        let mut state = None;
+        for (response, expected) in script {
+            state = next_transaction_state(state, &response);
+            assert_eq!(state, expected, "response: {:?}", response);
+        }
+
+        // 查询结果中的内容不影响事务状态
+        let state = next_transaction_state(
+            Some(7),
+            &lines(&["a", "--", "TRANSACTION 7 COMMIT", "(1 rows)"]),
+        );
+        assert_eq!(state, Some(7));
+    }
+
+    #[test]
+    fn test_split_results() {
+        // 单条语句的结果不拆分
+        assert_eq!(
+            split_results(lines(&["a", "--", "1", "(1 rows)"])),
+            vec![lines(&["a", "--", "1", "(1 rows)"])]
+        );
+        assert_eq!(split_results(vec![]), vec![lines(&[])]);
+
+        let response = lines(&[
+            "TRANSACTION 3 BEGIN",
+            RESULT_SEPARATOR,
+            "a",
+            "--",
+            "1",
+            "(1 rows)",
+            RESULT_SEPARATOR,
+            "TRANSACTION 3 ROLLBACK",
+            "Error: Write conflicted in transaction, please try again",
+        ]);
+        let results = split_results(response);
+        assert_eq!(
+            results,
+            vec![
+                lines(&["TRANSACTION 3 BEGIN"]),
+                lines(&["a", "--", "1", "(1 rows)"]),
+                lines(&[
+                    "TRANSACTION 3 ROLLBACK",
+                    "Error: Write conflicted in transaction, please try again"
+                ]),
+            ]
+        );
+        // 没有状态行时依次根据每条语句的结果推断事务状态
+        assert_eq!(next_transaction_state(None, &results[0]), Some(3));
+        assert_eq!(
+            results
+                .iter()
+                .fold(None, |state, result| next_transaction_state(state, result)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status("TRANSACTION 3 ACTIVE"), Some(Some(3)));
+        assert_eq!(parse_status("IDLE"), Some(None));
+        // 无法识别的状态交给 next_transaction_state 从响应内容推断
+        assert_eq!(parse_status("TRANSACTION x ACTIVE"), None);
+        assert_eq!(parse_status(""), None);
+    }
+
+    async fn send_banner(socket: &mut BufReader<TcpStream>) {
+        socket
+            .write_all(format!("{}\n", Banner::current()).as_bytes())
+            .await
+            .unwrap();
+    }
+
+    // 模拟的服务端：发送给定的握手信息，之后对每条命令都返回同样的响应
+    async fn mock_server(banner: String) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            socket
+                .write_all(format!("{}\n", banner).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            while socket.read_line(&mut line).await.unwrap() > 0 {
+                socket
+                    .write_all(format!("INSERT 1 rows\n{}\n", RESPONSE_END).as_bytes())
+                    .await
+                    .unwrap();
+                line.clear();
+            }
+        });
+        Ok(addr)
+    }
+
+    #[tokio::test]
+    async fn test_handshake() -> Result<(), Box<dyn Error>> {
+        let addr = mock_server(Banner::current().to_string()).await?;
+        let mut client = Client::new(addr).await?;
+        assert_eq!(
+            client.request("insert into t values (1)").await?,
+            (lines(&["INSERT 1 rows"]), None)
+        );
+
+        // 协议次版本不同时仍然可以连接
+        let banner = Banner {
+            protocol_minor: PROTOCOL_MINOR + 1,
+            ..Banner::current()
+        };
+        let addr = mock_server(banner.to_string()).await?;
+        Client::new(addr).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_refused() -> Result<(), Box<dyn Error>> {
+        // 连接的不是本数据库的服务端
+        let addr = mock_server("SSH-2.0-OpenSSH_9.6".into()).await?;
+        let err = Client::new(addr).await.err().unwrap();
+        assert!(
+            err.to_string().contains("is not a MY-SQL-DB server"),
+            "{}",
+            err
+        );
+
+        // 协议主版本不同
+        let banner = Banner {
+            protocol_major: PROTOCOL_MAJOR + 1,
+            ..Banner::current()
+        };
+        let addr = mock_server(banner.to_string()).await?;
+        let err = Client::new(addr).await.err().unwrap();
+        assert!(err.to_string().contains("incompatible"), "{}", err);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reconnect() -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            // 第一个连接开启事务后直接断开
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            send_banner(&mut socket).await;
+            let mut line = String::new();
+            socket.read_line(&mut line).await.unwrap();
+            socket
+                .write_all(format!("TRANSACTION 1 BEGIN\n{}\n", RESPONSE_END).as_bytes())
+                .await
+                .unwrap();
+            line.clear();
+            socket.read_line(&mut line).await.unwrap();
+            drop(socket);
+
+            // 第二个连接正常响应
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            send_banner(&mut socket).await;
+            loop {
+                line.clear();
+                if socket.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                socket
+                    .write_all(format!("INSERT 1 rows\n{}\n", RESPONSE_END).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut client = Client::new(addr).await?;
+        client.exec_cmd("begin").await?;
+        assert_eq!(client.transaction_version, Some(1));
+
+        // 连接断开后自动重连，事务状态被清除
+        client.exec_cmd("insert into t values (1)").await?;
+        assert_eq!(client.transaction_version, None);
+
+        // 重连后的连接可以继续使用
+        client.exec_cmd("insert into t values (1)").await?;
+        assert_eq!(client.transaction_version, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_meta_command() {
+        for (line, command) in [
+            ("\\dt", MetaCommand::Sql("show tables;".into())),
+            ("\\d t1", MetaCommand::Sql("show table t1;".into())),
+            ("\\d   t1  ", MetaCommand::Sql("show table t1;".into())),
+            ("\\timing", MetaCommand::Timing),
+            ("\\i init.sql", MetaCommand::Include("init.sql".into())),
+            ("\\e", MetaCommand::Edit),
+            ("\\?", MetaCommand::Help),
+        ] {
+            assert_eq!(parse_meta_command(line), Ok(command), "{}", line);
+        }
+        // 帮助中列出的每个命令都可以解析
+        for (usage, _) in META_COMMANDS {
+            let line = usage.replace("TABLE", "t1").replace("FILE", "a.sql");
+            assert!(parse_meta_command(&line).is_ok(), "{}", usage);
+        }
+
+        for (line, err) in [
+            ("\\d", "wrong arguments for \\d, try \\? for help"),
+            ("\\dt t1", "wrong arguments for \\dt, try \\? for help"),
+            ("\\i", "wrong arguments for \\i, try \\? for help"),
+            ("\\x", "unknown command \\x, try \\? for help"),
+            ("\\", "unknown command \\, try \\? for help"),
+        ] {
+            assert_eq!(parse_meta_command(line), Err(err.to_string()), "{}", line);
         }
     }
+
+    #[test]
+    fn test_split_statements() {
+        assert_eq!(
+            split_statements(
+                "create table t (\n  a int primary key, -- 主键; 注释\n  b text\n);\n\ninsert into t values (1, 'a;b'), (2, \"--\");\nselect * from t"
+            ),
+            lines(&[
+                "create table t (   a int primary key,    b text )",
+                "insert into t values (1, 'a;b'), (2, \"--\")",
+                "select * from t",
+            ])
+        );
+        assert_eq!(
+            split_statements("select 1 from `a;b`;;  ;"),
+            lines(&["select 1 from `a;b`"])
+        );
+        assert_eq!(split_statements("  -- only a comment\n"), lines(&[]));
+        // 不同的引号互相嵌套时不会提前结束字符串
+        assert_eq!(
+            split_statements("insert into t values (1, 'say \"hi\"; x');\nselect \"it's; ok\";"),
+            lines(&[
+                "insert into t values (1, 'say \"hi\"; x')",
+                "select \"it's; ok\"",
+            ])
+        );
+        // 字符串中两个引号表示一个引号，不会结束字符串
+        assert_eq!(
+            split_statements("select 'it''s; ok';"),
+            lines(&["select 'it''s; ok'"])
+        );
+    }
+
+    // 进程内的服务端：每条命令都在同一个会话中执行，按协议返回结果
+    async fn sql_server() -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            send_banner(&mut socket).await;
+            let mut session = KVEngine::new(MemoryEngine::new())
+                .unwrap()
+                .session()
+                .unwrap();
+            let mut line = String::new();
+            while socket.read_line(&mut line).await.unwrap() > 0 {
+                let response = match session.execute(line.trim()) {
+                    Ok(result) => result.to_string(),
+                    Err(e) => format!("{}{}", ERROR_PREFIX, e),
+                };
+                socket
+                    .write_all(format!("{}\n{}\n", response, RESPONSE_END).as_bytes())
+                    .await
+                    .unwrap();
+                line.clear();
+            }
+        });
+        Ok(addr)
+    }
+
+    #[tokio::test]
+    async fn test_include_file() -> Result<(), Box<dyn Error>> {
+        let addr = sql_server().await?;
+        let mut repl = Repl {
+            client: Client::new(addr).await?,
+            timing: false,
+        };
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("init.sql");
+        std::fs::write(
+            &path,
+            "-- 建表并写入数据\ncreate table t (\n  a int primary key,\n  b text\n);\ninsert into t values (1, 'x;y');\ninsert into t values (1, 'dup');\ninsert into t values (2, 'z');\n",
+        )?;
+
+        // 逐条执行文件中的语句，某条出错之后继续执行
+        let mut buffer = String::new();
+        repl.run_meta_command(MetaCommand::Include(path), &mut buffer)
+            .await?;
+        let (rows, _) = repl.client.request("select b from t order by a;").await?;
+        let result = rows.join("\n");
+        assert!(
+            result.contains("x;y") && result.contains("(2 rows)"),
+            "{}",
+            result
+        );
+        assert!(!result.contains("dup"), "{}", result);
+
+        // 文件不存在
+        let missing = dir.path().join("missing.sql");
+        let err = repl
+            .run_meta_command(MetaCommand::Include(missing), &mut buffer)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("failed to read"), "{}", err);
+
+        // \timing 切换是否打印执行时间
+        repl.run_meta_command(MetaCommand::Timing, &mut buffer)
+            .await?;
+        assert!(repl.timing);
+        Ok(())
+    }
 }
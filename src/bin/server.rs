@@ -2,17 +2,27 @@
 
 use tokio::net::{TcpListener, TcpStream};
 use tokio_stream::StreamExt;
-use tokio_util::codec::{Framed, LinesCodec};
+use tokio_util::codec::{Framed, LinesCodec, LinesCodecError};
 
 use futures::SinkExt;
-use my_sql_db::error::Result;
+use my_sql_db::error::{Error, Result};
+use my_sql_db::protocol::{
+    Banner, DEFAULT_MAX_LINE_LENGTH, ERROR_PREFIX, RESPONSE_END, RESULT_SEPARATOR, STATUS_PREFIX,
+};
 use my_sql_db::sql::engine;
+use my_sql_db::sql::engine::database::{Databases, DEFAULT_DATABASE};
 use my_sql_db::sql::engine::kv::KVEngine;
+use my_sql_db::sql::engine::CancelToken;
+use my_sql_db::sql::executor::ResultSet;
+use my_sql_db::sql::parser::Parser;
+use my_sql_db::sql::types::Value;
 use my_sql_db::storage::disk::DiskEngine;
 
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
@@ -41,40 +51,198 @@ struct Choice {
     message: Message,
 }
 
-const DB_STORAGE_PATH: &str = "./tmp/sqldb-test/log"; // 指定存储文件
-const RESPONSE_END: &str = "!!!THIS IS THE END!!!"; // 结束符，内容可以自定义一个不常见的字符串
+const DEFAULT_STORAGE_DIR: &str = "./tmp/sqldb-test"; // 存储目录，每个数据库一个子目录，可以通过 .env 中的 DB_STORAGE_DIR 配置
+const DEFAULT_SYNC_INTERVAL_MS: u64 = 1000; // 定时刷盘的间隔，可以通过 .env 中的 SYNC_INTERVAL_MS 配置，0 表示不定时刷盘
 
 // 定义请求类型
 enum Request {
-    SQL(String), // SQL命令
-    AI,          // AI命令
+    SQL(String),                      // SQL命令
+    AI,                               // AI命令
+    ShowProcessList,                  // show processlist; 查看所有连接正在执行的语句
+    Kill(u64),                        // kill <id>; 取消正在执行的语句
+    Status,                           // status; 查看当前连接的事务状态
+    SetMaxColumnWidth(Option<usize>), // set max_column_width = <n>; 限制结果每列的展示宽度，0表示不限制
+}
+
+impl Request {
+    fn parse(line: String) -> Self {
+        let trimmed = line.trim().trim_end_matches(';').to_lowercase();
+        let words = trimmed.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["ai"] => Request::AI,
+            ["show", "processlist"] => Request::ShowProcessList,
+            ["status"] => Request::Status,
+            ["kill", id] => match id.parse() {
+                Ok(id) => Request::Kill(id),
+                Err(_) => Request::SQL(line),
+            },
+            ["set", "max_column_width", "=", width] => match width.parse() {
+                Ok(0) => Request::SetMaxColumnWidth(None),
+                Ok(width) => Request::SetMaxColumnWidth(Some(width)),
+                Err(_) => Request::SQL(line),
+            },
+            _ => Request::SQL(line),
+        }
+    }
+}
+
+// 正在执行的语句
+struct Process {
+    sql: String,
+    started: Instant,
+    token: CancelToken,
+}
+
+// 所有连接共享的正在执行的语句列表，每条语句执行前分配一个id
+#[derive(Default)]
+pub struct ProcessList {
+    next_id: u64,
+    running: BTreeMap<u64, Process>,
+}
+
+pub type SharedProcessList = Arc<Mutex<ProcessList>>;
+
+impl ProcessList {
+    // 登记一条将要执行的语句，返回其id和取消标记
+    fn register(&mut self, sql: &str) -> (u64, CancelToken) {
+        self.next_id += 1;
+        let token = CancelToken::new();
+        self.running.insert(
+            self.next_id,
+            Process {
+                sql: sql.trim().to_string(),
+                started: Instant::now(),
+                token: token.clone(),
+            },
+        );
+        (self.next_id, token)
+    }
+
+    // 语句执行结束
+    fn finish(&mut self, id: u64) {
+        self.running.remove(&id);
+    }
+
+    // 取消语句，语句会在执行器下一次检查时返回错误
+    fn kill(&self, id: u64) -> bool {
+        match self.running.get(&id) {
+            Some(process) => {
+                process.token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn to_result_set(&self) -> ResultSet {
+        ResultSet::Scan {
+            columns: vec!["id".into(), "time".into(), "state".into(), "sql".into()],
+            rows: self
+                .running
+                .iter()
+                .map(|(id, process)| {
+                    vec![
+                        Value::Integer(*id as i64),
+                        Value::String(format!("{:.3}s", process.started.elapsed().as_secs_f64())),
+                        Value::String(if process.token.is_cancelled() {
+                            "killed".into()
+                        } else {
+                            "running".into()
+                        }),
+                        Value::String(process.sql.clone()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+// 事务状态：显式事务中为 TRANSACTION <version> ACTIVE，否则为 IDLE
+fn transaction_status(version: Option<u64>) -> String {
+    match version {
+        Some(version) => format!("TRANSACTION {} ACTIVE", version),
+        None => "IDLE".to_string(),
+    }
+}
+
+// 在多线程运行时中执行同步的耗时操作时，让出当前工作线程，其他连接（如 kill）仍然可以被处理
+fn run_blocking<R>(f: impl FnOnce() -> R) -> R {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
 }
 
 pub struct ServerSession<E: engine::Engine> {
     session: engine::Session<E>,
-    history: Vec<String>, // 维护历史 SQL 命令，供 AI 推荐使用
+    history: Vec<String>,            // 维护历史 SQL 命令，供 AI 推荐使用
+    max_line_length: usize,          // 单条请求的最大长度
+    max_column_width: Option<usize>, // 查询结果每列的最大展示宽度，None表示不限制
+    processes: SharedProcessList,
 }
 
 impl<E: engine::Engine + 'static> ServerSession<E> {
-    pub fn new(engine: MutexGuard<'_, E>) -> Result<Self> {
+    pub fn new(engine: MutexGuard<'_, E>, processes: SharedProcessList) -> Result<Self> {
+        Self::from_session(engine.session()?, processes)
+    }
+
+    // 使用已经创建好的会话，比如可以切换数据库的会话
+    pub fn from_session(
+        mut session: engine::Session<E>,
+        processes: SharedProcessList,
+    ) -> Result<Self> {
+        // 可选：限制单条 insert 语句的 values 行数
+        session.set_max_insert_rows(
+            env::var("MAX_INSERT_ROWS")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+        );
+        // 可选：显式事务中的建表、删表另开事务立即提交
+        session.set_ddl_autocommit(
+            env::var("DDL_AUTOCOMMIT").is_ok_and(|v| matches!(v.as_str(), "1" | "true" | "on")),
+        );
         Ok(Self {
-            session: engine.session()?,
+            session,
             history: Vec::new(),
+            max_line_length: env::var("MAX_LINE_LENGTH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_LINE_LENGTH),
+            max_column_width: env::var("MAX_COLUMN_WIDTH")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|&width| width > 0),
+            processes,
         })
     }
 
     pub async fn handle_request(&mut self, socket: TcpStream) -> Result<()> {
-        let mut lines = Framed::new(socket, LinesCodec::new());
+        let mut lines = Framed::new(
+            socket,
+            LinesCodec::new_with_max_length(self.max_line_length),
+        );
+        // 先发送握手信息，客户端据此确认连接的是本数据库的服务端以及协议版本是否兼容
+        if let Err(e) = lines.send(Banner::current().to_string()).await {
+            eprintln!("error on sending handshake; error = {e:?}");
+            return Ok(());
+        }
 
-        while let Some(result) = lines.next().await {
+        // 解码出错（请求过长）后，Framed 会先返回一次 None，之后仍然可以继续读取
+        let mut skip_none = false;
+        loop {
+            let result = match lines.next().await {
+                Some(result) => result,
+                None if skip_none => {
+                    skip_none = false;
+                    continue;
+                }
+                None => break,
+            };
             match result {
                 Ok(line) => {
-                    let trimmed = line.trim();
-                    let request = if trimmed.eq_ignore_ascii_case("AI;") {
-                        Request::AI
-                    } else {
-                        Request::SQL(line)
-                    };
+                    let request = Request::parse(line);
 
                     let response = match request {
                         // 用户输入AI; 命令
@@ -83,7 +251,28 @@ impl<E: engine::Engine + 'static> ServerSession<E> {
                             if self.history.is_empty() {
                                 Ok("SQL history is empty, AI recommend failed.".to_string())
                             } else {
-                                self.get_ai_recommendation(&self.history).await
+                                self.get_ai_recommendation(&self.history)
+                                    .await
+                                    .map_err(|e| format!("{}{}", ERROR_PREFIX, e))
+                            }
+                        }
+                        Request::ShowProcessList => Ok(self
+                            .processes
+                            .lock()?
+                            .to_result_set()
+                            .to_string_with_width(self.max_column_width)),
+                        Request::Status => {
+                            Ok(transaction_status(self.session.transaction_version()))
+                        }
+                        Request::SetMaxColumnWidth(width) => {
+                            self.max_column_width = width;
+                            Ok(format!("SET max_column_width = {}", width.unwrap_or(0)))
+                        }
+                        Request::Kill(id) => {
+                            if self.processes.lock()?.kill(id) {
+                                Ok(format!("KILL {}", id))
+                            } else {
+                                Err(format!("{}Unknown statement id {}", ERROR_PREFIX, id))
                             }
                         }
                         // 用户输入SQL
@@ -92,32 +281,86 @@ impl<E: engine::Engine + 'static> ServerSession<E> {
                                 self.history.push(sql.clone());
                             }
                             // 执行SQL
-                            self.session
-                                .execute(&sql)
-                                .map(|rs| rs.to_string())
-                                .map_err(|e| e.into())
+                            let (id, token) = self.processes.lock()?.register(&sql);
+                            self.session.set_cancel_token(Some(token));
+                            let result = self.execute_sql(&sql);
+                            self.processes.lock()?.finish(id);
+                            Ok(result)
                         }
                     };
 
-                    // 发送执行结果
-                    let res = response.unwrap_or_else(|e| e.to_string());
-                    if let Err(e) = lines.send(res.as_str()).await {
-                        eprintln!("error on sending response; error = {e:?}");
-                    }
-                    // 发送结束符
-                    if let Err(e) = lines.send(RESPONSE_END).await {
-                        eprintln!("error on sending response end; error = {e:?}");
-                    }
+                    // 发送执行结果，出错时发送带 Error: 前缀的错误信息
+                    let res = response.unwrap_or_else(|e| e);
+                    self.send_response(&mut lines, &res).await;
+                }
+                Err(LinesCodecError::MaxLineLengthExceeded) => {
+                    skip_none = true;
+                    // 请求过长，返回错误信息，超出部分会被丢弃，连接保持可用
+                    let res = format!(
+                        "{}Request exceeds the max length of {} bytes, please split it into smaller statements",
+                        ERROR_PREFIX, self.max_line_length
+                    );
+                    self.send_response(&mut lines, &res).await;
                 }
                 Err(e) => {
                     eprintln!("error on decoding from socket; error = {e:?}");
+                    break;
                 }
             }
         }
 
+        // 连接断开时显式事务还没有结束，回滚该事务，否则它写入的数据会一直让其他事务写冲突
+        run_blocking(|| self.session.rollback_transaction())?;
         Ok(())
     }
 
+    // 执行一条或多条以分号分隔的语句，每条语句的结果之间用分隔符隔开，遇到错误则不再执行后面的语句
+    fn execute_sql(&mut self, sql: &str) -> String {
+        let sentences = match Parser::new(sql).parse_batch() {
+            Ok(sentences) if !sentences.is_empty() => sentences,
+            // 空的请求按单条语句解析，返回期望一条语句的错误
+            Ok(_) => match Parser::new(sql).parse() {
+                Ok(sentence) => vec![sentence],
+                Err(e) => return format!("{}{}", ERROR_PREFIX, e),
+            },
+            Err(e) => return format!("{}{}", ERROR_PREFIX, e),
+        };
+
+        let mut results = Vec::new();
+        for sentence in sentences {
+            let version = self.session.transaction_version();
+            match run_blocking(|| self.session.execute_sentence(sentence)) {
+                Ok(rs) => results.push(rs.to_string_with_width(self.max_column_width)),
+                Err(e) => {
+                    // 执行失败但显式事务已经结束（如提交失败），先告知客户端事务已回滚
+                    results.push(match version {
+                        Some(v) if self.session.transaction_version().is_none() => {
+                            format!("TRANSACTION {} ROLLBACK\n{}{}", v, ERROR_PREFIX, e)
+                        }
+                        _ => format!("{}{}", ERROR_PREFIX, e),
+                    });
+                    break;
+                }
+            }
+        }
+        results.join(&format!("\n{}\n", RESULT_SEPARATOR))
+    }
+
+    // 发送响应，之后是一行事务状态和结束符
+    async fn send_response(&self, lines: &mut Framed<TcpStream, LinesCodec>, res: &str) {
+        if let Err(e) = lines.send(res).await {
+            eprintln!("error on sending response; error = {e:?}");
+        }
+        let status = transaction_status(self.session.transaction_version());
+        if let Err(e) = lines.send(format!("{}{}", STATUS_PREFIX, status)).await {
+            eprintln!("error on sending response status; error = {e:?}");
+        }
+        // 发送结束符
+        if let Err(e) = lines.send(RESPONSE_END).await {
+            eprintln!("error on sending response end; error = {e:?}");
+        }
+    }
+
     // 调用外部 AI API，获取推荐
     async fn get_ai_recommendation(&self, history: &[String]) -> Result<String> {
         // 从.env读取配置
@@ -205,6 +448,7 @@ impl<E: engine::Engine + 'static> ServerSession<E> {
 async fn main() -> Result<()> {
     // 启动前先加载.env
     dotenv().ok();
+    init_logger();
 
     let addr = env::args()
         .nth(1)
@@ -213,20 +457,123 @@ async fn main() -> Result<()> {
     let listener = TcpListener::bind(&addr).await?;
     println!("SQL DB starts, server is listening on: {addr}");
 
-    // 初始化DB
-    let p = PathBuf::from(DB_STORAGE_PATH);
-    let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+    // 初始化DB，每个数据库的数据存放在存储目录下各自的子目录中
+    let storage_dir = env::var("DB_STORAGE_DIR").unwrap_or_else(|_| DEFAULT_STORAGE_DIR.into());
+    check_legacy_storage(Path::new(&storage_dir))?;
+    let databases = Databases::new(PathBuf::from(storage_dir), |path| {
+        KVEngine::new(DiskEngine::new(path)?)
+    });
+    databases.get_or_create(DEFAULT_DATABASE)?;
+
+    // 提交时只写到操作系统的缓存中，定时刷盘，机器宕机时最多丢失一个间隔内提交的事务
+    let sync_interval = env::var("SYNC_INTERVAL_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_SYNC_INTERVAL_MS);
+    if sync_interval > 0 {
+        tokio::spawn(periodic_sync(
+            databases.clone(),
+            Duration::from_millis(sync_interval),
+        ));
+    }
 
-    // 多线程下的读写
-    let shared_engine = Arc::new(Mutex::new(kvengine));
+    tokio::select! {
+        res = serve(listener, databases.clone()) => res,
+        // ctrl-c 正常退出前刷一次盘
+        _ = tokio::signal::ctrl_c() => {
+            println!("SQL DB is shutting down");
+            sync_all(&databases)
+        }
+    }
+}
 
+// 旧版本把数据存放在存储目录下的 log 文件中，现在每个数据库各自一个子目录
+// 发现旧的存储文件时拒绝启动，否则会在旁边新建一个空的 default 数据库，看起来像数据丢失了
+fn check_legacy_storage(storage_dir: &Path) -> Result<()> {
+    let legacy = storage_dir.join("log");
+    if legacy.is_file() {
+        return Err(Error::Storage(format!(
+            "[Server] Found data file {} written by an older version, databases are now stored in {}, export the data with the version that wrote it and import it into database \" {} \"",
+            legacy.display(),
+            storage_dir.join("<name>").join("sqldb-log").display(),
+            DEFAULT_DATABASE
+        )));
+    }
+    Ok(())
+}
+
+// 所有已经打开的数据库都刷一次盘
+fn sync_all<E: engine::Engine + 'static>(databases: &Databases<E>) -> Result<()> {
+    for engine in databases.engines()? {
+        engine.sync()?;
+    }
+    Ok(())
+}
+
+// 定时刷盘的后台任务，刷盘是阻塞的磁盘操作，放到阻塞线程池中执行
+async fn periodic_sync<E>(databases: Databases<E>, interval: Duration)
+where
+    E: engine::Engine + Send + 'static,
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await; // 第一次 tick 立即完成，跳过
+    loop {
+        ticker.tick().await;
+        let databases = databases.clone();
+        match tokio::task::spawn_blocking(move || sync_all(&databases)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("error on periodic sync; error = {e:?}"),
+            Err(e) => eprintln!("error on periodic sync; error = {e:?}"),
+        }
+    }
+}
+
+// 引擎的日志输出到标准错误，日志级别由 .env 中的 SQLDB_LOG 配置（error、warn、info、debug、trace）
+// 不配置时不输出日志
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn init_logger() {
+    static LOGGER: StderrLogger = StderrLogger;
+    let level = match env::var("SQLDB_LOG").map(|v| v.parse::<log::LevelFilter>()) {
+        Ok(Ok(level)) => level,
+        Ok(Err(_)) => {
+            eprintln!("Invalid SQLDB_LOG, expected one of error, warn, info, debug, trace");
+            return;
+        }
+        Err(_) => return,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// 接受连接，每个连接一个 session，初始使用默认数据库，所有连接共享同一个语句列表
+async fn serve<E>(listener: TcpListener, databases: Databases<E>) -> Result<()>
+where
+    E: engine::Engine + Send + Sync + 'static,
+    E::Transaction: Send + Sync,
+{
+    let processes = SharedProcessList::default();
     loop {
         match listener.accept().await {
             Ok((socket, _)) => {
-                // 拿到sql引擎的克隆实例
-                let db = shared_engine.clone();
                 // 通过session执行sql语句
-                let mut server_session = ServerSession::new(db.lock()?)?;
+                let session = databases.session(DEFAULT_DATABASE)?;
+                let mut server_session = ServerSession::from_session(session, processes.clone())?;
 
                 // 开启一个tokio任务去处理当前socket的请求
                 tokio::spawn(async move {
@@ -242,3 +589,422 @@ async fn main() -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use my_sql_db::sql::engine::Engine;
+    use my_sql_db::storage::memory::MemoryEngine;
+
+    // 连接服务端，读取并校验握手信息
+    async fn connect(addr: std::net::SocketAddr) -> Result<Framed<TcpStream, LinesCodec>> {
+        let mut lines = Framed::new(TcpStream::connect(addr).await?, LinesCodec::new());
+        let banner = lines.next().await.unwrap().unwrap();
+        assert_eq!(Banner::parse(&banner)?, Banner::current());
+        Ok(lines)
+    }
+
+    // 发送一条请求，读取直到结束符的全部响应，不包括事务状态行
+    async fn request(lines: &mut Framed<TcpStream, LinesCodec>, cmd: &str) -> Vec<String> {
+        request_with_status(lines, cmd).await.0
+    }
+
+    // 发送一条请求，返回响应和响应附带的事务状态
+    async fn request_with_status(
+        lines: &mut Framed<TcpStream, LinesCodec>,
+        cmd: &str,
+    ) -> (Vec<String>, String) {
+        lines.send(cmd).await.unwrap();
+        let mut res = Vec::new();
+        let mut status = String::new();
+        while let Some(line) = lines.next().await {
+            let line = line.unwrap();
+            if line == RESPONSE_END {
+                break;
+            }
+            match line.strip_prefix(STATUS_PREFIX) {
+                Some(s) => status = s.to_string(),
+                None => res.push(line),
+            }
+        }
+        (res, status)
+    }
+
+    #[tokio::test]
+    async fn test_max_line_length() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let engine = Mutex::new(KVEngine::new(MemoryEngine::new())?);
+        let mut server_session = ServerSession::new(engine.lock()?, SharedProcessList::default())?;
+        server_session.max_line_length = 64;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            server_session.handle_request(socket).await.unwrap();
+        });
+
+        let mut lines = connect(addr).await?;
+        assert_eq!(
+            request(&mut lines, "create table t1 (a int primary key);").await,
+            vec!["CREATE TABLE t1"]
+        );
+
+        // 过长的请求返回错误信息，而不是断开连接
+        let values = (1..100).map(|i| format!("({})", i)).collect::<Vec<_>>();
+        let sql = format!("insert into t1 values {};", values.join(", "));
+        let res = request(&mut lines, &sql).await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].contains("exceeds the max length of 64 bytes"));
+
+        // 连接依然可用
+        assert_eq!(
+            request(&mut lines, "insert into t1 values (1), (2);").await,
+            vec!["INSERT 2 rows"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_response() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let engine = Mutex::new(KVEngine::new(MemoryEngine::new())?);
+        let mut server_session = ServerSession::new(engine.lock()?, SharedProcessList::default())?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            server_session.handle_request(socket).await.unwrap();
+        });
+
+        let mut lines = connect(addr).await?;
+        request(&mut lines, "create table t1 (a int primary key);").await;
+        assert_eq!(request(&mut lines, "begin;").await.len(), 1);
+
+        // 执行失败的语句带 Error: 前缀，事务依然活跃
+        let res = request(&mut lines, "insert into t2 values (1);").await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].starts_with(ERROR_PREFIX));
+        assert_eq!(
+            request(&mut lines, "insert into t1 values (1);").await,
+            vec!["INSERT 1 rows"]
+        );
+        let res = request(&mut lines, "commit;").await;
+        assert!(res[0].starts_with("TRANSACTION") && res[0].ends_with("COMMIT"));
+
+        // 不在事务中提交同样返回错误
+        let res = request(&mut lines, "commit;").await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].starts_with(ERROR_PREFIX));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_kill() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let dir = tempfile::tempdir()?;
+        let databases = Databases::new(dir.path().to_path_buf(), |_| {
+            KVEngine::new(MemoryEngine::new())
+        });
+        databases.get_or_create(DEFAULT_DATABASE)?;
+        tokio::spawn(serve(listener, databases));
+
+        let mut lines1 = connect(addr).await?;
+        let mut lines2 = connect(addr).await?;
+        request(&mut lines1, "create table t1 (a int primary key);").await;
+        request(&mut lines1, "create table t2 (b int primary key);").await;
+        let values = |range: std::ops::Range<i32>| {
+            range
+                .map(|i| format!("({})", i))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        request(
+            &mut lines1,
+            &format!("insert into t1 values {};", values(0..5000)),
+        )
+        .await;
+        request(
+            &mut lines1,
+            &format!("insert into t2 values {};", values(5000..10000)),
+        )
+        .await;
+
+        // 连接条件永远不满足，嵌套循环连接需要比较 5000 * 5000 次
+        let sql = "select * from t1 join t2 on t1.a > t2.b;";
+        let query = tokio::spawn(async move {
+            let res = request(&mut lines1, sql).await;
+            (res, lines1)
+        });
+
+        // 在 processlist 中找到这条语句的id
+        let mut id = None;
+        for _ in 0..500 {
+            let res = request(&mut lines2, "show processlist;").await;
+            assert_eq!(
+                res[0].split('|').map(|c| c.trim()).collect::<Vec<_>>(),
+                vec!["id", "time", "state", "sql"]
+            );
+            if let Some(line) = res.iter().find(|l| l.ends_with(sql)) {
+                id = Some(line.split('|').next().unwrap().trim().parse::<u64>()?);
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let id = id.expect("the join should show up in processlist");
+        assert_eq!(
+            request(&mut lines2, &format!("kill {};", id)).await,
+            vec![format!("KILL {}", id)]
+        );
+
+        // 语句返回取消错误，连接依然可用
+        let (res, mut lines1) = query.await.unwrap();
+        assert_eq!(res, vec![format!("{}{}", ERROR_PREFIX, Error::Cancelled)]);
+        assert_eq!(
+            request(&mut lines1, "select * from t1 where a = 1;").await,
+            vec!["a", "--", "1", "(1 rows)"]
+        );
+
+        // 执行结束的语句从 processlist 中移除，不能再被 kill
+        let res = request(&mut lines2, "show processlist;").await;
+        assert_eq!(res.last().unwrap(), "(0 rows)");
+        let res = request(&mut lines2, &format!("kill {};", id)).await;
+        assert!(res[0].starts_with(ERROR_PREFIX));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_column_width() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let engine = Mutex::new(KVEngine::new(MemoryEngine::new())?);
+        let mut server_session = ServerSession::new(engine.lock()?, SharedProcessList::default())?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            server_session.handle_request(socket).await.unwrap();
+        });
+
+        let mut lines = connect(addr).await?;
+        request(&mut lines, "create table t (a int primary key, b varchar);").await;
+        request(
+            &mut lines,
+            "insert into t values (1, 'abcdefghijklmnopqrstuvwxyz'), (2, 'short');",
+        )
+        .await;
+
+        // 默认不截断
+        assert_eq!(
+            request(&mut lines, "select * from t;").await,
+            vec![
+                "a |b                         ",
+                "--+---------------------------",
+                "1 |abcdefghijklmnopqrstuvwxyz",
+                "2 |short                     ",
+                "(2 rows)",
+            ]
+        );
+
+        assert_eq!(
+            request(&mut lines, "set max_column_width = 8;").await,
+            vec!["SET max_column_width = 8"]
+        );
+        assert_eq!(
+            request(&mut lines, "select * from t;").await,
+            vec![
+                "a |b       ",
+                "--+---------",
+                "1 |abcde...",
+                "2 |short   ",
+                "(2 rows)",
+            ]
+        );
+
+        // 设置为0后恢复不截断
+        request(&mut lines, "set max_column_width = 0;").await;
+        assert_eq!(
+            request(&mut lines, "select b from t where a = 1;").await,
+            vec![
+                "b                         ",
+                "---------------------------",
+                "abcdefghijklmnopqrstuvwxyz",
+                "(1 rows)"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_status() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let engine = Mutex::new(KVEngine::new(MemoryEngine::new())?);
+        let mut server_session = ServerSession::new(engine.lock()?, SharedProcessList::default())?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            server_session.handle_request(socket).await.unwrap();
+        });
+
+        let mut lines = connect(addr).await?;
+        assert_eq!(request(&mut lines, "status;").await, vec!["IDLE"]);
+        assert_eq!(
+            request_with_status(&mut lines, "create table t (a int primary key);").await,
+            (vec!["CREATE TABLE t".to_string()], "IDLE".to_string())
+        );
+
+        // 事务中的每个响应都带有事务状态，包括出错的响应
+        let (res, status) = request_with_status(&mut lines, "begin;").await;
+        let version = res[0].split(' ').nth(1).unwrap().to_string();
+        let active = format!("TRANSACTION {} ACTIVE", version);
+        assert_eq!(status, active);
+        assert_eq!(
+            request_with_status(&mut lines, "STATUS").await,
+            (vec![active.clone()], active.clone())
+        );
+        let (res, status) = request_with_status(&mut lines, "select * from t2;").await;
+        assert!(res[0].starts_with(ERROR_PREFIX));
+        assert_eq!(status, active);
+
+        request(&mut lines, "commit;").await;
+        assert_eq!(
+            request_with_status(&mut lines, "status;").await,
+            (vec!["IDLE".to_string()], "IDLE".to_string())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multiple_statements() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let engine = Mutex::new(KVEngine::new(MemoryEngine::new())?);
+        let mut server_session = ServerSession::new(engine.lock()?, SharedProcessList::default())?;
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            server_session.handle_request(socket).await.unwrap();
+        });
+
+        let mut lines = connect(addr).await?;
+        assert_eq!(
+            request(
+                &mut lines,
+                "create table t (a int primary key); insert into t values (1); select * from t;"
+            )
+            .await,
+            vec![
+                "CREATE TABLE t",
+                RESULT_SEPARATOR,
+                "INSERT 1 rows",
+                RESULT_SEPARATOR,
+                "a",
+                "--",
+                "1",
+                "(1 rows)"
+            ]
+        );
+
+        // 出错之后的语句不再执行
+        let res = request(
+            &mut lines,
+            "insert into t values (2); insert into t values (1); insert into t values (3);",
+        )
+        .await;
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[..2], ["INSERT 1 rows", RESULT_SEPARATOR]);
+        assert!(res[2].starts_with(ERROR_PREFIX));
+        assert_eq!(
+            request(&mut lines, "select count(a) from t").await,
+            vec!["count", "------", "2    ", "(1 rows)"]
+        );
+
+        // 解析失败时一条语句都不执行
+        let res = request(&mut lines, "insert into t values (4); insert into").await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].starts_with(ERROR_PREFIX));
+        assert_eq!(request(&mut lines, "").await.len(), 1);
+        assert_eq!(
+            request(&mut lines, "select count(a) from t;").await[2],
+            "2    "
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rollback_on_disconnect() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let dir = tempfile::tempdir()?;
+        let databases = Databases::new(dir.path().to_path_buf(), |_| {
+            KVEngine::new(MemoryEngine::new())
+        });
+        databases.get_or_create(DEFAULT_DATABASE)?;
+        tokio::spawn(serve(listener, databases));
+
+        let mut lines1 = connect(addr).await?;
+        let mut lines2 = connect(addr).await?;
+        request(&mut lines1, "create table t (a int primary key, b int);").await;
+        request(&mut lines1, "insert into t values (1, 1);").await;
+        request(&mut lines1, "begin;").await;
+        request(&mut lines1, "update t set b = 2 where a = 1;").await;
+
+        // 事务还在进行中，其他连接修改同一行会写冲突
+        let update = "update t set b = 3 where a = 1;";
+        let res = request(&mut lines2, update).await;
+        assert!(res[0].starts_with(ERROR_PREFIX));
+
+        // 事务中途断开连接，服务端回滚事务之后，其他连接可以修改这一行
+        drop(lines1);
+        let start = Instant::now();
+        loop {
+            let res = request(&mut lines2, update).await;
+            if !res[0].starts_with(ERROR_PREFIX) {
+                assert_eq!(res, vec!["UPDATE 1 rows"]);
+                break;
+            }
+            assert!(
+                start.elapsed().as_secs() < 10,
+                "transaction was not rolled back"
+            );
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(
+            request(&mut lines2, "select b from t;").await,
+            vec!["b", "--", "3", "(1 rows)"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_periodic_sync() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let databases = Databases::new(dir.path().to_path_buf(), |_| {
+            KVEngine::new(MemoryEngine::new())
+        });
+        let engine = databases.get_or_create(DEFAULT_DATABASE)?;
+        let task = tokio::spawn(periodic_sync(databases, Duration::from_millis(5)));
+        let start = Instant::now();
+        while engine.sync_stats()?.count < 2 {
+            assert!(start.elapsed().as_secs() < 10, "periodic sync did not run");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        task.abort();
+        assert!(engine.sync_stats()?.last_sync.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_legacy_storage() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        check_legacy_storage(dir.path())?;
+        // 按数据库划分的子目录不受影响
+        std::fs::create_dir_all(dir.path().join(DEFAULT_DATABASE))?;
+        check_legacy_storage(dir.path())?;
+
+        // 旧版本的存储文件
+        std::fs::write(dir.path().join("log"), b"")?;
+        match check_legacy_storage(dir.path()) {
+            Err(Error::Storage(msg)) => {
+                assert!(msg.contains("written by an older version"), "{}", msg)
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+        Ok(())
+    }
+}
@@ -15,9 +15,43 @@ pub type Result<T> = std::result::Result<T, Error>;
 // 自定义错误类型
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
-    Parse(String),    // 在解析器阶段报错，内容为String的错误
-    Internal(String), // 在数据库内部运行时的报错
-    WriteConflict,    // 事务写冲突
+    Parse(String),        // 在解析器阶段报错，内容为String的错误
+    Internal(String),     // 在数据库内部运行时的报错
+    Schema(String),       // 表结构相关：建表的定义不合法、表已存在、违反非空或主键约束
+    Type(String),         // 数据类型不匹配：比较、拼接的值类型不同，写入的值和列的类型不同
+    Storage(String),      // 存储层的错误：读写文件失败、序列化失败、存储的数据格式不正确
+    NotFound(String),     // 表、列、数据库、保存点等不存在
+    WriteConflict,        // 事务写冲突
+    SerializationFailure, // 可串行化事务提交时发现读过的数据已被修改
+    Cancelled,            // 语句在执行过程中被取消（kill）
+}
+
+impl Error {
+    // 错误信息，没有附带信息的错误（如写冲突）返回None
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Error::Parse(msg)
+            | Error::Internal(msg)
+            | Error::Schema(msg)
+            | Error::Type(msg)
+            | Error::Storage(msg)
+            | Error::NotFound(msg) => Some(msg),
+            Error::WriteConflict | Error::SerializationFailure | Error::Cancelled => None,
+        }
+    }
+
+    // 修改错误信息，错误类别不变，没有附带信息的错误原样返回
+    pub fn map_message(self, f: impl FnOnce(String) -> String) -> Self {
+        match self {
+            Error::Parse(msg) => Error::Parse(f(msg)),
+            Error::Internal(msg) => Error::Internal(f(msg)),
+            Error::Schema(msg) => Error::Schema(f(msg)),
+            Error::Type(msg) => Error::Type(f(msg)),
+            Error::Storage(msg) => Error::Storage(f(msg)),
+            Error::NotFound(msg) => Error::NotFound(f(msg)),
+            err => err,
+        }
+    }
 }
 
 // 兼容系统本身的解析数字报错
@@ -43,41 +77,41 @@ impl<T> From<PoisonError<T>> for Error {
 // 序列化相关报错处理
 impl From<Box<ErrorKind>> for Error {
     fn from(value: Box<ErrorKind>) -> Self {
-        Error::Internal(value.to_string())
+        Error::Storage(value.to_string())
     }
 }
 
 // 文件相关错误
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        Error::Internal(value.to_string())
+        Error::Storage(value.to_string())
     }
 }
 
 // &[u8] -> Vec<u8> 相关错误
 impl From<TryFromSliceError> for Error {
     fn from(value: TryFromSliceError) -> Self {
-        Error::Internal(value.to_string())
+        Error::Storage(value.to_string())
     }
 }
 
 // String from utf 8 错误
 impl From<FromUtf8Error> for Error {
     fn from(value: FromUtf8Error) -> Self {
-        Error::Internal(value.to_string())
+        Error::Storage(value.to_string())
     }
 }
 
 // 事务key编码相关错误
 impl ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Internal(msg.to_string())
+        Error::Storage(msg.to_string())
     }
 }
 
 impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Internal(msg.to_string())
+        Error::Storage(msg.to_string())
     }
 }
 
@@ -86,7 +120,16 @@ impl Display for Error {
         match self {
             Error::Parse(err) => write!(f, "Parse Error: {}", err),
             Error::Internal(err) => write!(f, "Internal Error: {}", err),
+            Error::Schema(err) => write!(f, "Schema Error: {}", err),
+            Error::Type(err) => write!(f, "Type Error: {}", err),
+            Error::Storage(err) => write!(f, "Storage Error: {}", err),
+            Error::NotFound(err) => write!(f, "Not Found: {}", err),
             Error::WriteConflict => write!(f, "Write conflicted in transaction, please try again"),
+            Error::SerializationFailure => write!(
+                f,
+                "Could not serialize access due to concurrent update, please try again"
+            ),
+            Error::Cancelled => write!(f, "Query execution was cancelled"),
         }
     }
 }
@@ -1,3 +1,15 @@
 pub mod error;
+pub mod protocol;
 pub mod sql;
 pub mod storage;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
+// 嵌入使用时最常用的类型，在 crate 根部重新导出，示例见 examples/todo.rs
+pub use error::{Error, Result};
+pub use sql::engine::kv::KVEngine;
+pub use sql::engine::{Engine, Session};
+pub use sql::executor::ResultSet;
+pub use sql::types::{Row, Value};
+pub use storage::disk::DiskEngine;
+pub use storage::memory::MemoryEngine;
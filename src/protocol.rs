@@ -0,0 +1,136 @@
+// 客户端和服务端之间的文本协议，两个程序共用这里的常量
+//
+// 连接建立后服务端先发送一行握手信息：MY-SQL-DB <crate 版本> PROTOCOL <主版本>.<次版本>
+// 客户端校验之后，每次发送一行请求，服务端返回若干行结果，之后是一行事务状态和结束符
+
+use crate::error::{Error, Result};
+
+pub const BANNER_MAGIC: &str = "MY-SQL-DB"; // 握手信息的开头，用来确认连接的是本数据库的服务端
+pub const PROTOCOL_MAJOR: u32 = 1; // 主版本不同的客户端和服务端不能通信
+pub const PROTOCOL_MINOR: u32 = 0; // 次版本不同时可以通信，客户端给出提示
+pub const RESPONSE_END: &str = "!!!THIS IS THE END!!!"; // 结束符，内容可以自定义一个不常见的字符串
+pub const ERROR_PREFIX: &str = "Error: "; // 错误响应的前缀，客户端据此区分执行成功与失败
+pub const RESULT_SEPARATOR: &str = "!!!NEXT RESULT!!!"; // 一次请求包含多条语句时，分隔每条语句的结果
+pub const STATUS_PREFIX: &str = "!!!STATUS!!! "; // 每个响应在结束符之前附带一行事务状态，客户端据此维护事务状态
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 8 * 1024 * 1024; // 单条请求的最大长度，客户端和服务端需要保持一致
+
+// 服务端的握手信息
+#[derive(Debug, PartialEq)]
+pub struct Banner {
+    pub server_version: String,
+    pub protocol_major: u32,
+    pub protocol_minor: u32,
+}
+
+impl Banner {
+    // 本程序的握手信息
+    pub fn current() -> Self {
+        Self {
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_major: PROTOCOL_MAJOR,
+            protocol_minor: PROTOCOL_MINOR,
+        }
+    }
+
+    // 解析服务端发来的第一行，不是本数据库的握手信息时报错
+    pub fn parse(line: &str) -> Result<Self> {
+        let invalid = || {
+            Error::Parse(format!(
+                "[Protocol] Unexpected handshake {:?}, the server is not a {} server",
+                line, BANNER_MAGIC
+            ))
+        };
+        let words = line.split(' ').collect::<Vec<_>>();
+        let [BANNER_MAGIC, server_version, "PROTOCOL", protocol] = words.as_slice() else {
+            return Err(invalid());
+        };
+        let (major, minor) = protocol.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            server_version: server_version.to_string(),
+            protocol_major: major.parse().map_err(|_| invalid())?,
+            protocol_minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+
+    // 检查服务端的协议版本：主版本不同时报错，次版本不同时返回提示信息
+    pub fn check(&self) -> Result<Option<String>> {
+        if self.protocol_major != PROTOCOL_MAJOR {
+            return Err(Error::Internal(format!(
+                "[Protocol] Server {} speaks protocol {}.{}, which is incompatible with protocol {}.{} of this client",
+                self.server_version,
+                self.protocol_major,
+                self.protocol_minor,
+                PROTOCOL_MAJOR,
+                PROTOCOL_MINOR
+            )));
+        }
+        if self.protocol_minor != PROTOCOL_MINOR {
+            return Ok(Some(format!(
+                "Server {} speaks protocol {}.{}, this client speaks {}.{}, some features may not work",
+                self.server_version,
+                self.protocol_major,
+                self.protocol_minor,
+                PROTOCOL_MAJOR,
+                PROTOCOL_MINOR
+            )));
+        }
+        Ok(None)
+    }
+}
+
+impl std::fmt::Display for Banner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} PROTOCOL {}.{}",
+            BANNER_MAGIC, self.server_version, self.protocol_major, self.protocol_minor
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner() -> Result<()> {
+        let banner = Banner::current().to_string();
+        assert_eq!(
+            banner,
+            format!(
+                "MY-SQL-DB {} PROTOCOL {}.{}",
+                env!("CARGO_PKG_VERSION"),
+                PROTOCOL_MAJOR,
+                PROTOCOL_MINOR
+            )
+        );
+        assert_eq!(Banner::parse(&banner)?, Banner::current());
+        assert_eq!(Banner::current().check()?, None);
+
+        // 次版本不同只给出提示，主版本不同报错
+        let newer = Banner::parse(&format!("MY-SQL-DB 9.9.9 PROTOCOL {}.7", PROTOCOL_MAJOR))?;
+        assert!(newer.check()?.unwrap().contains("9.9.9"));
+        let incompatible = Banner::parse(&format!(
+            "MY-SQL-DB 9.9.9 PROTOCOL {}.0",
+            PROTOCOL_MAJOR + 1
+        ))?;
+        assert!(incompatible.check().is_err());
+
+        // 不是本数据库的握手信息
+        for line in [
+            "SSH-2.0-OpenSSH_9.6",
+            "",
+            "MY-SQL-DB 0.1.0",
+            "MY-SQL-DB 0.1.0 PROTOCOL 1",
+            "MY-SQL-DB 0.1.0 PROTOCOL x.0",
+            "OTHER-DB 0.1.0 PROTOCOL 1.0",
+        ] {
+            assert!(
+                matches!(Banner::parse(line), Err(Error::Parse(_))),
+                "{}",
+                line
+            );
+        }
+        Ok(())
+    }
+}
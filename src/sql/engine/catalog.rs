@@ -0,0 +1,109 @@
+use crate::error::{Error, Result};
+use crate::sql::engine::Transaction;
+use crate::sql::parser::lexer::quote_identifier;
+use crate::sql::schema::{Column, Table};
+use crate::sql::types::{Collation, DataType, Row, Value};
+
+// 系统目录：以只读虚拟表的形式暴露元数据，可以直接用 select 查询
+// 虚拟表不落盘，每次扫描时根据当前事务可见的表结构现场生成
+pub const TABLES: &str = "__tables__";
+pub const COLUMNS: &str = "__columns__";
+
+// 是否是系统目录表
+pub fn is_catalog_table(table_name: &str) -> bool {
+    table_name == TABLES || table_name == COLUMNS
+}
+
+// 系统目录表只读
+pub fn check_writable(table_name: &str) -> Result<()> {
+    if is_catalog_table(table_name) {
+        return Err(Error::Schema(format!(
+            "[Catalog] Table \" {} \" is a read-only system catalog",
+            table_name
+        )));
+    }
+    Ok(())
+}
+
+// 获取系统目录表的表结构，虚拟表没有主键，也没有索引
+pub fn get_table(table_name: &str) -> Option<Table> {
+    let columns = match table_name {
+        TABLES => vec![
+            column("name", DataType::String),
+            column("column_count", DataType::Integer),
+            column("primary_key", DataType::String),
+        ],
+        COLUMNS => vec![
+            column("table_name", DataType::String),
+            column("column_name", DataType::String),
+            column("datatype", DataType::String),
+            column("nullable", DataType::Boolean),
+            column("default", DataType::String),
+            column("primary_key", DataType::Boolean),
+            column("indexed", DataType::Boolean),
+        ],
+        _ => return None,
+    };
+    Some(Table {
+        name: table_name.into(),
+        columns,
+        comment: None,
+    })
+}
+
+// 扫描系统目录表，生成所有行
+pub fn scan<T: Transaction>(transaction: &T, table_name: &str) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    for name in transaction.get_all_table_names()? {
+        let table = transaction.must_get_table(name)?;
+        match table_name {
+            TABLES => rows.push(vec![
+                Value::String(table.name.clone()),
+                Value::Integer(table.columns.len() as i64),
+                table
+                    .columns
+                    .iter()
+                    .find(|c| c.is_primary_key)
+                    .map(|c| Value::String(c.name.clone()))
+                    .unwrap_or(Value::Null),
+            ]),
+            COLUMNS => {
+                for col in &table.columns {
+                    rows.push(vec![
+                        Value::String(table.name.clone()),
+                        Value::String(col.name.clone()),
+                        Value::String(format!("{:?}", col.datatype).to_uppercase()),
+                        Value::Boolean(col.nullable),
+                        // 没有默认值和默认值为NULL都展示为NULL
+                        match &col.default {
+                            Some(Value::Null) | None => Value::Null,
+                            Some(v) => Value::String(v.to_string()),
+                        },
+                        Value::Boolean(col.is_primary_key),
+                        Value::Boolean(col.is_index),
+                    ]);
+                }
+            }
+            _ => {
+                return Err(Error::NotFound(format!(
+                    "[Catalog] Table \" {} \" does not exist",
+                    quote_identifier(table_name)
+                )))
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn column(name: &str, datatype: DataType) -> Column {
+    Column {
+        name: name.into(),
+        datatype,
+        nullable: true,
+        default: None,
+        is_primary_key: false,
+        is_index: false,
+        collation: Collation::Binary,
+        precision: None,
+    }
+}
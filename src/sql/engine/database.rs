@@ -0,0 +1,131 @@
+use crate::error::{Error, Result};
+use crate::sql::engine::{Engine, Session};
+use crate::sql::parser::lexer::quote_identifier;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// 默认数据库，服务启动时如果不存在则自动创建
+pub const DEFAULT_DATABASE: &str = "default";
+
+// 每个数据库的存储文件名，存放在基础目录下以数据库名命名的子目录中：<base_dir>/<name>/sqldb-log
+const LOG_FILE_NAME: &str = "sqldb-log";
+
+// 管理基础目录下的多个数据库，每个数据库对应一个独立的引擎实例
+// 已经打开的引擎会被缓存，所有会话共享同一个实例，保证同一个存储文件只被打开一次
+pub struct Databases<E: Engine> {
+    base_dir: PathBuf,
+    open: Arc<dyn Fn(PathBuf) -> Result<E> + Send + Sync>, // 根据存储文件路径打开引擎
+    engines: Arc<Mutex<BTreeMap<String, E>>>,
+}
+
+impl<E: Engine> Clone for Databases<E> {
+    fn clone(&self) -> Self {
+        Self {
+            base_dir: self.base_dir.clone(),
+            open: self.open.clone(),
+            engines: self.engines.clone(),
+        }
+    }
+}
+
+impl<E: Engine + 'static> Databases<E> {
+    pub fn new(
+        base_dir: PathBuf,
+        open: impl Fn(PathBuf) -> Result<E> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            base_dir,
+            open: Arc::new(open),
+            engines: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    // 创建数据库，数据库已经存在时报错
+    pub fn create(&self, name: &str) -> Result<E> {
+        Self::check_name(name)?;
+        let mut engines = self.engines.lock()?;
+        if engines.contains_key(name) || self.base_dir.join(name).exists() {
+            return Err(Error::Schema(format!(
+                "[Create Database] Database \" {} \" already exists",
+                quote_identifier(name)
+            )));
+        }
+        std::fs::create_dir_all(self.base_dir.join(name))?;
+        self.open_engine(&mut engines, name)
+    }
+
+    // 获取已经存在的数据库
+    pub fn get(&self, name: &str) -> Result<E> {
+        let mut engines = self.engines.lock()?;
+        if let Some(engine) = engines.get(name) {
+            return Ok(engine.clone());
+        }
+        if Self::check_name(name).is_err() || !self.base_dir.join(name).is_dir() {
+            return Err(Error::NotFound(format!(
+                "[Use Database] Database \" {} \" does not exist",
+                quote_identifier(name)
+            )));
+        }
+        self.open_engine(&mut engines, name)
+    }
+
+    // 获取数据库，不存在时创建
+    pub fn get_or_create(&self, name: &str) -> Result<E> {
+        match self.get(name) {
+            Ok(engine) => Ok(engine),
+            Err(_) => self.create(name),
+        }
+    }
+
+    // 基础目录下的全部数据库，按名称排序
+    pub fn names(&self) -> Result<Vec<String>> {
+        if !self.base_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.base_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if Self::check_name(name).is_ok() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // 已经打开的引擎，用于定时刷盘等需要处理全部数据库的场景
+    pub fn engines(&self) -> Result<Vec<E>> {
+        Ok(self.engines.lock()?.values().cloned().collect())
+    }
+
+    // 创建一个使用指定数据库的会话，会话中可以通过 use 切换数据库
+    pub fn session(&self, name: &str) -> Result<Session<E>> {
+        let mut session = self.get(name)?.session()?;
+        session.databases = Some(self.clone());
+        session.database = Some(name.to_string());
+        Ok(session)
+    }
+
+    fn open_engine(&self, engines: &mut BTreeMap<String, E>, name: &str) -> Result<E> {
+        let engine = (self.open)(self.base_dir.join(name).join(LOG_FILE_NAME))?;
+        engines.insert(name.to_string(), engine.clone());
+        Ok(engine)
+    }
+
+    // 数据库名同时也是目录名，只允许字母、数字和下划线，避免出现 .. 或路径分隔符
+    fn check_name(name: &str) -> Result<()> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(Error::Schema(format!(
+                "[Create Database] Invalid database name \" {} \", only letters, digits and _ are allowed",
+                quote_identifier(name)
+            )));
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,20 @@
 use crate::error::{Error, Result};
-use crate::sql::engine::{Engine, Transaction};
-use crate::sql::parser::ast::{parse_expression, Expression};
-use crate::sql::schema::Table;
+use crate::sql::engine::{catalog, CancelToken, Engine, Transaction};
+use crate::sql::parser::ast::{bind_expression, collate_condition, Expression};
+use crate::sql::schema::{error_at_row, Column, Table};
 use crate::sql::types::{Row, Value};
-use crate::storage::keyencode::serialize_key;
+use crate::storage::keyencode::{deserialize_key, serialize_key, serialize_key_prefix};
+use crate::storage::mvcc::{Metrics, SyncStats};
 use crate::storage::{self, engine::Engine as storageEngine};
+use log::error;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 // self 即指 crate::storage
 
 // KV engine 定义
@@ -28,16 +36,159 @@ impl<E: storageEngine> Engine for KVEngine<E> {
     fn begin(&self) -> Result<Self::Transaction> {
         Ok(Self::Transaction::new(self.kv.begin()?))
     }
+
+    fn begin_serializable(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_serializable()?))
+    }
+
+    fn begin_read_only(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_read_only()?))
+    }
+
+    fn purge_table(&self, table_name: &str) -> Result<usize> {
+        catalog::check_writable(table_name)?;
+        // 只能清理已经删除的表
+        let transaction = self.begin_read_only()?;
+        let table = transaction.get_table(table_name.into())?;
+        transaction.commit()?;
+        if table.is_some() {
+            return Err(Error::Schema(format!(
+                "[Purge Table] Table \" {} \" still exists, drop it before purging",
+                table_name
+            )));
+        }
+        // 表结构定义、表数据和索引都属于这张表
+        // 列名为空且不写结尾，匹配全部列的索引
+        let index_prefix =
+            serialize_key_prefix(&PrefixKey::Index(table_name.into(), String::new()))?;
+        self.kv.purge(vec![
+            Key::Table(table_name.into()).encode()?,
+            PrefixKey::Row(table_name.into()).encode()?,
+            index_prefix,
+        ])
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.kv.sync()
+    }
+
+    fn sync_stats(&self) -> Result<SyncStats> {
+        self.kv.sync_stats()
+    }
+
+    fn metrics(&self) -> &Metrics {
+        self.kv.metrics()
+    }
 }
 
 // 封装存储引擎中的MvccTransaction
 pub struct KVTransaction<E: storageEngine> {
     transaction: storage::mvcc::MvccTransaction<E>,
+    cancel_token: Option<CancelToken>, // 当前语句的取消标记
+    // 表结构缓存，避免每条语句都从存储中读取并反序列化表结构
+    // 只在事务内有效，建表、删表时更新
+    table_cache: Mutex<HashMap<String, Table>>,
 }
 
 impl<E: storageEngine> KVTransaction<E> {
     pub fn new(transaction: storage::mvcc::MvccTransaction<E>) -> Self {
-        Self { transaction }
+        Self {
+            transaction,
+            cancel_token: None,
+            table_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 扫描表并按条件过滤，reverse 为 true 时从主键最大的行开始反向扫描
+    fn scan_rows(
+        &self,
+        table_name: String,
+        filter: Option<Expression>,
+        reverse: bool,
+    ) -> Result<Vec<Row>> {
+        let table = self.must_get_table(table_name.clone())?;
+        let all_rows = if catalog::is_catalog_table(&table_name) {
+            // 系统目录表的数据现场生成
+            let mut rows = catalog::scan(self, &table_name)?;
+            if reverse {
+                rows.reverse();
+            }
+            rows
+        } else {
+            // 根据前缀扫描表，行的key中只有主键，行数据中已经包含，只需要value
+            let prefix = PrefixKey::Row(table_name.clone()).encode()?;
+            let values = match reverse {
+                true => self.transaction.prefix_scan_values_reverse(prefix)?,
+                false => self.transaction.prefix_scan_values(prefix)?,
+            };
+            Metrics::add(
+                &self.transaction.metrics().rows_scanned,
+                values.len() as u64,
+            );
+            let mut all_rows = Vec::with_capacity(values.len());
+            for value in values {
+                let row =
+                    decode_with_context(&value, || format!("row of table \" {} \"", table_name))?;
+                all_rows.push(table.pad_row(row)?);
+            }
+            all_rows
+        };
+        // 没有过滤条件时返回全部数据
+        let Some(filter) = filter else {
+            return Ok(all_rows);
+        };
+
+        // nocase 列的比较忽略大小写：条件中和这些列比较的字符串、行中这些列的值都转为小写再求值
+        // char 列读出时已经去掉了末尾的空格，条件中和这些列比较的字符串也去掉末尾的空格
+        let cols: Vec<String> = table.columns.iter().map(|c| c.name.clone()).collect();
+        let nocase_cols = table.nocase_columns();
+        let char_cols = table.char_columns();
+        let expression = bind_expression(
+            &collate_condition(&filter, &nocase_cols, &char_cols),
+            &cols,
+            &cols,
+        )?;
+
+        let mut rows = Vec::new();
+        for row in all_rows {
+            // 根据filter过滤数据
+            let collated_row = if nocase_cols.is_empty() {
+                None
+            } else {
+                Some(table.collate_row(&row))
+            };
+            let eval_row = collated_row.as_ref().unwrap_or(&row);
+            match expression.evaluate(eval_row, eval_row)? {
+                Value::Null => {}
+                Value::Boolean(false) => {}
+                Value::Boolean(true) => {
+                    rows.push(row);
+                }
+                _ => {
+                    return Err(Error::Internal(
+                        "[KV Engine Scan] Unexpected expression".into(),
+                    ))
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    // 从索引中删除一行，历史数据中的数值可能以另一种形式存储在索引中，每种可能的key都要清理
+    fn remove_from_index(
+        &mut self,
+        table_name: &str,
+        col: &Column,
+        value: &Value,
+        primary_key: &Value,
+    ) -> Result<()> {
+        for key in col.index_lookup_keys(value) {
+            let mut index = self.load_index(table_name, &col.name, &key)?;
+            if index.remove(primary_key) {
+                self.save_index(table_name, &col.name, &key, index)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -55,40 +206,24 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
     }
 
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()> {
+        catalog::check_writable(&table_name)?;
         let table = self.must_get_table(table_name.clone())?;
         // 插入行数据的数据类型检查
-        for (i, col) in table.columns.iter().enumerate() {
-            match row[i].get_datatype() {
-                None if col.nullable => continue,
-                None => {
-                    return Err(Error::Internal(format!(
-                        "[Insert Table] Column \" {} \" cannot be null",
-                        col.name
-                    )))
-                }
-                Some(datatype) if datatype != col.datatype => {
-                    return Err(Error::Internal(format!(
-                        "[Insert Table] Column \" {} \" mismatched data type",
-                        col.name
-                    )))
-                }
-                _ => continue,
-            }
-        }
+        table.check_row(&row)?;
 
         let primary_key = table.get_primary_key(&row)?;
         let key = Key::Row(table.name.clone(), primary_key.clone()).encode()?;
 
         // 如果主键已经存在，则报冲突
         if self.transaction.get(key.clone())?.is_some() {
-            return Err(Error::Internal(format!(
+            return Err(Error::Schema(format!(
                 "[Insert Table] Primary Key \" {} \" conflicted in table \" {} \"",
                 primary_key, table_name
             )));
         }
 
         // 存放数据
-        let value = bincode::serialize(&row)?;
+        let value = table.encode_row(&row)?;
         self.transaction.set(key, value)?;
 
         // 维护索引
@@ -100,14 +235,73 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
             .filter(|(_, c)| c.is_index)
             .collect::<Vec<_>>();
         for (i, index_col) in index_cols {
-            let mut index = self.load_index(&table_name, &index_col.name, &row[i])?;
+            // nocase 列的索引按小写存储
+            let col_value = index_col.index_key(&row[i]);
+            let mut index = self.load_index(&table_name, &index_col.name, &col_value)?;
             index.insert(primary_key.clone());
-            self.save_index(&table_name, &index_col.name, &row[i], index)?
+            self.save_index(&table_name, &index_col.name, &col_value, index)?
+        }
+        Ok(())
+    }
+
+    fn create_rows(&mut self, table_name: String, rows: Vec<Row>) -> Result<()> {
+        catalog::check_writable(&table_name)?;
+        let table = self.must_get_table(table_name.clone())?;
+
+        // 1. 先校验所有行，全部通过之后再写入
+        // 主键冲突：逐个查找表中是否已有该主键，再检查本批次内是否已经出现过
+        // 不扫描整张表，可串行化事务中只有这些主键进入读集合
+        let mut batch_keys = HashSet::new();
+        // 出错时在错误信息中标明是第几行
+        let mut keys = Vec::with_capacity(rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let checked = table.check_row(row).and_then(|_| {
+                let primary_key = table.get_primary_key(row)?;
+                let key = Key::Row(table.name.clone(), primary_key.clone()).encode()?;
+                if !batch_keys.insert(key.clone()) || self.transaction.get(key.clone())?.is_some() {
+                    return Err(Error::Schema(format!(
+                        "[Insert Table] Primary Key \" {} \" conflicted in table \" {} \"",
+                        primary_key, table_name
+                    )));
+                }
+                Ok((key, primary_key))
+            });
+            keys.push(checked.map_err(|e| error_at_row(e, i + 1, rows.len()))?);
+        }
+
+        // 2. 写入数据，索引先在内存中合并，每个索引值只读写一次
+        let index_cols = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_index)
+            .collect::<Vec<_>>();
+        let mut indexes: HashMap<(usize, Value), HashSet<Value>> = HashMap::new();
+        for (row, (key, primary_key)) in rows.into_iter().zip(keys) {
+            for (i, index_col) in index_cols.iter() {
+                let col_value = index_col.index_key(&row[*i]);
+                let index = match indexes.entry((*i, col_value)) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let index =
+                            self.load_index(&table_name, &index_col.name, &entry.key().1)?;
+                        entry.insert(index)
+                    }
+                };
+                index.insert(primary_key.clone());
+            }
+            self.transaction.set(key, table.encode_row(&row)?)?;
+        }
+        for ((i, col_value), index) in indexes {
+            self.save_index(&table_name, &table.columns[i].name, &col_value, index)?;
         }
         Ok(())
     }
 
     fn update_row(&mut self, table: &Table, primary_key: &Value, row: Row) -> Result<()> {
+        catalog::check_writable(&table.name)?;
+        // 更新后的行同样要满足列的定义：类型、是否为空、char 的长度
+        table.check_row(&row)?;
         // 传入的是新row
         // 对比主键是否修改，是则删除原key，建立新key
         let new_primary_key = table.get_primary_key(&row)?;
@@ -127,29 +321,30 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
         for (i, index_col) in index_cols {
             // 加载旧row
             if let Some(old_row) = self.read_row_by_pk(&table.name, primary_key)? {
-                if old_row[i] == row[i] {
+                let old_value = index_col.index_key(&old_row[i]);
+                let new_value = index_col.index_key(&row[i]);
+                if old_value == new_value {
                     continue;
                 } // 没有更新索引列
 
                 // 更新了索引列
                 // 需要先从旧集合中删除，再加入新集合
-                let mut old_index = self.load_index(&table.name, &index_col.name, &old_row[i])?;
-                old_index.remove(primary_key);
-                self.save_index(&table.name, &index_col.name, &old_row[i], old_index)?;
+                self.remove_from_index(&table.name, index_col, &old_row[i], primary_key)?;
 
-                let mut new_index = self.load_index(&table.name, &index_col.name, &row[i])?;
+                let mut new_index = self.load_index(&table.name, &index_col.name, &new_value)?;
                 new_index.insert(primary_key.clone());
-                self.save_index(&table.name, &index_col.name, &row[i], new_index)?;
+                self.save_index(&table.name, &index_col.name, &new_value, new_index)?;
             }
         }
 
         let key = Key::Row(table.name.clone(), new_primary_key.clone()).encode()?;
-        let value = bincode::serialize(&row)?;
+        let value = table.encode_row(&row)?;
         self.transaction.set(key, value)?;
         Ok(())
     }
 
     fn delete_row(&mut self, table: &Table, primary_key: &Value) -> Result<()> {
+        catalog::check_writable(&table.name)?;
         // 删除数据之前先删索引
         let index_cols = table
             .columns
@@ -159,9 +354,7 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
             .collect::<Vec<_>>();
         for (i, index_col) in index_cols {
             if let Some(row) = self.read_row_by_pk(&table.name, primary_key)? {
-                let mut index = self.load_index(&table.name, &index_col.name, &row[i])?;
-                index.remove(primary_key);
-                self.save_index(&table.name, &index_col.name, &row[i], index)?; // 修改后的索引重新存储
+                self.remove_from_index(&table.name, index_col, &row[i], primary_key)?;
             }
         }
 
@@ -170,41 +363,18 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
     }
 
     fn scan(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>> {
-        let table = self.must_get_table(table_name.clone())?;
-        // 根据前缀扫描表
-        let prefix = PrefixKey::Row(table_name.clone()).encode()?;
-        let results = self.transaction.prefix_scan(prefix)?;
+        self.scan_rows(table_name, filter, false)
+    }
 
-        let mut rows = Vec::new();
-        for res in results {
-            // 根据filter过滤数据
-            let row: Row = bincode::deserialize(&res.value)?;
-            if let Some(expression) = &filter {
-                let cols = table.columns.iter().map(|c| c.name.clone()).collect();
-                match parse_expression(expression, &cols, &row, &cols, &row)? {
-                    Value::Null => {}
-                    Value::Boolean(false) => {}
-                    Value::Boolean(true) => {
-                        rows.push(row);
-                    }
-                    _ => {
-                        return Err(Error::Internal(
-                            "[KV Engine Scan] Unexpected expression".into(),
-                        ))
-                    }
-                }
-            } else {
-                // filter不存在，查找所有数据
-                rows.push(row);
-            }
-        }
-        Ok(rows)
+    fn scan_reverse(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>> {
+        self.scan_rows(table_name, filter, true)
     }
 
     fn create_table(&mut self, table: Table) -> Result<()> {
+        catalog::check_writable(&table.name)?;
         // 判断表是否存在
         if self.get_table(table.name.clone())?.is_some() {
-            return Err(Error::Internal(format!(
+            return Err(Error::Schema(format!(
                 "[CreateTable] Failed, Table \" {} \" already exists",
                 table.name.clone()
             )));
@@ -217,11 +387,13 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
         let key = Key::Table(table.name.clone()).encode()?;
         let value = bincode::serialize(&table)?;
         self.transaction.set(key, value)?;
+        self.table_cache.lock()?.insert(table.name.clone(), table);
 
         Ok(())
     }
 
     fn drop_table(&mut self, name: String) -> Result<()> {
+        catalog::check_writable(&name)?;
         // 获取表
         let table = self.must_get_table(name.clone())?;
         // 获取表的数据
@@ -231,17 +403,37 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
             self.delete_row(&table, &table.get_primary_key(&row)?)?;
         }
         // 删除表结构定义
+        self.table_cache.lock()?.remove(&table.name);
         let key = Key::Table(table.name).encode()?;
         self.transaction.delete(key)
     }
 
     fn get_table(&self, table_name: String) -> Result<Option<Table>> {
-        let key = Key::Table(table_name).encode()?;
-        let value = self
+        // 优先查找系统目录表
+        if let Some(table) = catalog::get_table(&table_name) {
+            return Ok(Some(table));
+        }
+        if let Some(table) = self.table_cache.lock()?.get(&table_name) {
+            return Ok(Some(table.clone()));
+        }
+        let key = Key::Table(table_name.clone()).encode()?;
+        let value: Option<Table> = self
             .transaction
-            .get(key)?
-            .map(|value| bincode::deserialize(&value))
+            .get(key.clone())?
+            .map(|value| {
+                decode_with_context(&value, || {
+                    format!(
+                        "schema of table \" {} \" at key {}",
+                        table_name,
+                        hex_key(&key)
+                    )
+                })
+            })
             .transpose()?;
+        // 只缓存存在的表，不存在的表可能在之后被创建
+        if let Some(table) = &value {
+            self.table_cache.lock()?.insert(table_name, table.clone());
+        }
         Ok(value)
     }
 
@@ -250,7 +442,9 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
         let results = self.transaction.prefix_scan(prefix)?;
         let mut names = Vec::new();
         for result in results {
-            let table: Table = bincode::deserialize(&result.value)?;
+            let table: Table = decode_with_context(&result.value, || {
+                format!("table schema at key {}", hex_key(&result.key))
+            })?;
             names.push(table.name);
         }
         Ok(names)
@@ -266,8 +460,17 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
         let key = Key::Index(table_name.into(), col_name.into(), col_value.clone()).encode()?;
         Ok(self
             .transaction
-            .get(key)?
-            .map(|v| bincode::deserialize(&v))
+            .get(key.clone())?
+            .map(|v| {
+                decode_with_context(&v, || {
+                    format!(
+                        "index of column \" {} \" in table \" {} \" at key {}",
+                        col_name,
+                        table_name,
+                        hex_key(&key)
+                    )
+                })
+            })
             .transpose()?
             .unwrap_or_default())
     }
@@ -288,14 +491,108 @@ impl<E: storageEngine> Transaction for KVTransaction<E> {
         }
     }
 
+    fn scan_index(&self, table_name: &str, col_name: &str) -> Result<Vec<(Value, HashSet<Value>)>> {
+        let prefix = PrefixKey::Index(table_name.into(), col_name.into()).encode()?;
+        let mut entries = Vec::new();
+        for res in self.transaction.prefix_scan(prefix)? {
+            let value = match decode_key(&res.key)? {
+                Key::Index(_, _, value) => value,
+                _ => {
+                    return Err(Error::Storage(
+                        "[KV Engine Scan Index] Unexpected key".into(),
+                    ))
+                }
+            };
+            let index = decode_with_context(&res.value, || {
+                format!(
+                    "index of column \" {} \" in table \" {} \" at key {}",
+                    col_name,
+                    table_name,
+                    hex_key(&res.key)
+                )
+            })?;
+            entries.push((value, index));
+        }
+        Ok(entries)
+    }
+
     fn read_row_by_pk(&self, table_name: &str, pk: &Value) -> Result<Option<Row>> {
+        let key = Key::Row(table_name.into(), pk.clone()).encode()?;
         let res = self
             .transaction
-            .get(Key::Row(table_name.into(), pk.clone()).encode()?)?
-            .map(|v| bincode::deserialize(&v))
+            .get(key.clone())?
+            .map(|v| {
+                decode_with_context(&v, || {
+                    format!("row of table \" {} \" at key {}", table_name, hex_key(&key))
+                })
+            })
             .transpose()?;
         Ok(res)
     }
+
+    fn check_database(&self) -> Result<(usize, Vec<String>)> {
+        let results = self.transaction.prefix_scan(Vec::new())?;
+        let mut problems = Vec::new();
+        for res in results.iter() {
+            self.check_cancelled()?;
+            if let Err(e) = check_entry(&res.key, &res.value) {
+                problems.push(e.to_string());
+            }
+        }
+        Ok((results.len(), problems))
+    }
+
+    fn lock_row(&self, table_name: &str, pk: &Value) -> Result<()> {
+        self.transaction
+            .lock_key(Key::Row(table_name.into(), pk.clone()).encode()?)
+    }
+
+    fn meta_get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.transaction.get(Key::Meta(key.into()).encode()?)
+    }
+
+    fn meta_set(&mut self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.transaction.set(Key::Meta(key.into()).encode()?, value)
+    }
+
+    fn meta_delete(&mut self, key: &str) -> Result<()> {
+        self.transaction.delete(Key::Meta(key.into()).encode()?)
+    }
+
+    fn savepoint(&self, name: &str) -> Result<()> {
+        self.transaction.savepoint(name)
+    }
+
+    fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        self.transaction.rollback_to_savepoint(name)?;
+        // 保存点之后的建表、删表已被撤销，缓存中的表结构可能已经失效
+        self.table_cache.lock()?.clear();
+        Ok(())
+    }
+
+    fn release_savepoint(&self, name: &str) -> Result<()> {
+        self.transaction.release_savepoint(name)
+    }
+
+    fn has_written_table(&self, table_name: &str) -> Result<bool> {
+        Ok(self
+            .transaction
+            .has_written_prefix(&Key::Table(table_name.into()).encode()?)?
+            || self
+                .transaction
+                .has_written_prefix(&PrefixKey::Row(table_name.into()).encode()?)?)
+    }
+
+    fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel_token = token;
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel_token {
+            Some(token) => token.check(),
+            None => Ok(()),
+        }
+    }
 }
 
 // 辅助方法：由于底层的存储的传入参数都是 u8, 用户给的字符串需要进行转换
@@ -304,32 +601,204 @@ enum Key {
     Table(String),
     Row(String, Value),           // (table_name, primary_key)
     Index(String, String, Value), // [2, table_name, index_col_name, index_col_value]
+    Meta(String),                 // 应用自定义的元数据，和表、行、索引的key互不冲突
+    FormatVersion,                // 存储格式的版本号
 }
 
 impl Key {
     pub fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            Key::Table(table_name) | Key::Row(table_name, _) | Key::Index(table_name, _, _) => {
+                check_table_name(table_name)?
+            }
+            Key::Meta(_) | Key::FormatVersion => {}
+        }
         serialize_key(self)
     }
 }
 
+// 解码存储中的 key，失败时在错误信息中附带 key 的内容
+fn decode_key(key: &[u8]) -> Result<Key> {
+    deserialize_key(key).map_err(|e| {
+        e.map_message(|msg| format!("[KV Engine] Failed to decode key {}: {}", hex_key(key), msg))
+    })
+}
+
+// 解码存储中的 value，失败时在错误信息中附带正在解码的内容和 value 的长度，便于定位损坏的数据
+fn decode_with_context<T: DeserializeOwned>(
+    bytes: &[u8],
+    context: impl Fn() -> String,
+) -> Result<T> {
+    bincode::deserialize(bytes).map_err(|e| {
+        Error::Storage(format!(
+            "[KV Engine] Failed to decode {} ({} bytes): {}",
+            context(),
+            bytes.len(),
+            e
+        ))
+    })
+}
+
+// key 的十六进制表示，过长的 key 只保留开头的部分
+fn hex_key(key: &[u8]) -> String {
+    const MAX_BYTES: usize = 32;
+    let hex = key
+        .iter()
+        .take(MAX_BYTES)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    match key.len() > MAX_BYTES {
+        true => format!("{}...", hex),
+        false => hex,
+    }
+}
+
+// 按 key 的类型解码一条数据，check database 使用
+fn check_entry(key: &[u8], value: &[u8]) -> Result<()> {
+    match decode_key(key)? {
+        Key::Table(table_name) => {
+            decode_with_context::<Table>(value, || {
+                format!(
+                    "schema of table \" {} \" at key {}",
+                    table_name,
+                    hex_key(key)
+                )
+            })?;
+        }
+        Key::Row(table_name, _) => {
+            decode_with_context::<Row>(value, || {
+                format!("row of table \" {} \" at key {}", table_name, hex_key(key))
+            })?;
+        }
+        Key::Index(table_name, col_name, _) => {
+            decode_with_context::<HashSet<Value>>(value, || {
+                format!(
+                    "index of column \" {} \" in table \" {} \" at key {}",
+                    col_name,
+                    table_name,
+                    hex_key(key)
+                )
+            })?;
+        }
+        // 元数据的内容由应用决定，不需要解码
+        Key::Meta(_) => {}
+        Key::FormatVersion => {
+            decode_with_context::<u32>(value, || "format version".into())?;
+        }
+    }
+    Ok(())
+}
+
+// 空表名编码之后只剩 0 0 结尾，它的前缀会和其他表的数据混在一起，在编码之前拒绝
+fn check_table_name(table_name: &str) -> Result<()> {
+    if table_name.is_empty() {
+        return Err(Error::Schema(
+            "[KVEngine] Table name cannot be empty".into(),
+        ));
+    }
+    Ok(())
+}
+
 // 辅助枚举，用于前缀扫描
 #[derive(Debug, Serialize, Deserialize)]
 enum PrefixKey {
     Table, // 存的时候Table是第0个枚举，Row是第一个枚举，如果这里没有Table的话，扫描的时候是对不上的，所以要Table进行占位
     Row(String),
+    Index(String, String), // 某一列的全部索引
 }
 
 impl PrefixKey {
     pub fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            PrefixKey::Row(table_name) | PrefixKey::Index(table_name, _) => {
+                check_table_name(table_name)?
+            }
+            PrefixKey::Table => {}
+        }
         serialize_key(self)
     }
 }
 
 // new方法定义
+// 存储格式的版本号，表结构、行、索引或 key 的编码发生不兼容的变化时递增
+// 没有记录版本号的存储是最初的格式：整数 key 直接按大端编码，表结构中没有注释、排序规则和精度
+const FORMAT_VERSION: u32 = 1;
+
 impl<E: storageEngine> KVEngine<E> {
-    pub fn new(engine: E) -> Self {
-        Self {
+    // 打开存储，空的存储记录当前的格式版本号，其他格式的存储拒绝打开，避免按错误的格式读出数据
+    pub fn new(engine: E) -> Result<Self> {
+        let engine = Self {
             kv: storage::mvcc::Mvcc::new(engine),
+        };
+        engine.check_format_version()?;
+        Ok(engine)
+    }
+
+    fn check_format_version(&self) -> Result<()> {
+        let key = Key::FormatVersion.encode()?;
+        let transaction = self.kv.begin_read_only()?;
+        let version = transaction.get(key.clone())?;
+        let is_empty = transaction
+            .prefix_scan(PrefixKey::Table.encode()?)?
+            .is_empty();
+        transaction.commit()?;
+
+        let version: u32 = match version {
+            Some(value) => decode_with_context(&value, || "format version".into())?,
+            None if is_empty => {
+                let mut transaction = self.kv.begin()?;
+                transaction.set(key, bincode::serialize(&FORMAT_VERSION)?)?;
+                return transaction.commit();
+            }
+            None => 0,
+        };
+        if version != FORMAT_VERSION {
+            return Err(Error::Storage(format!(
+                "[KVEngine] Store format version {} is not supported, expected {}, export the data with the version that wrote it and import it again",
+                version, FORMAT_VERSION
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<E: storageEngine + Send + 'static> KVEngine<E> {
+    // 在后台线程中每隔 interval 刷一次盘，供嵌入使用的场景在提交性能和宕机丢失的数据之间折中
+    // 返回的句柄被丢弃时停止后台线程，并在停止前最后刷一次盘
+    pub fn start_periodic_flush(&self, interval: Duration) -> PeriodicFlush {
+        let engine = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || loop {
+            let stopping = !matches!(
+                stopped.recv_timeout(interval),
+                Err(RecvTimeoutError::Timeout)
+            );
+            if let Err(e) = engine.sync() {
+                error!("[KVEngine] Periodic flush failed: {}", e);
+            }
+            if stopping {
+                break;
+            }
+        });
+        PeriodicFlush {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+// 定时刷盘的后台线程句柄
+pub struct PeriodicFlush {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for PeriodicFlush {
+    fn drop(&mut self) {
+        // 关闭通道即通知后台线程停止
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
         }
     }
 }
@@ -337,18 +806,23 @@ impl<E: storageEngine> KVEngine<E> {
 #[cfg(test)]
 mod tests {
 
-    use super::KVEngine;
+    use super::{KVEngine, Key};
+    use crate::sql::engine::database::{Databases, DEFAULT_DATABASE};
     use crate::storage::engine::Engine as StorageEngine;
     use crate::{
-        error::Result,
+        error::{Error, Result},
         sql::{
-            engine::{Engine, Session},
+            engine::{Engine, Session, Transaction},
             executor::ResultSet,
+            parser::{ast::Sentence, Parser},
             types::{Row, Value},
         },
         storage::disk::DiskEngine,
+        test_util::{assert_rows_unordered, assert_table, explain_plan, populate, query_rows},
     };
+    use std::collections::HashMap;
 
+    // 执行查询，返回结果中的全部行
     fn setup_table<E: StorageEngine + 'static>(s: &mut Session<KVEngine<E>>) -> Result<()> {
         s.execute(
             "create table t1 (
@@ -423,7 +897,7 @@ mod tests {
     #[test]
     fn test_create_table() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         setup_table(&mut s)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
@@ -433,7 +907,7 @@ mod tests {
     #[test]
     fn test_insert() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         setup_table(&mut s)?;
 
@@ -441,70 +915,27 @@ mod tests {
         s.execute("insert into t1 (a) values (1);")?;
         s.execute("insert into t1 values (2, 'a', 2);")?;
         s.execute("insert into t1(b,a) values ('b', 3);")?;
-
-        scan_table_and_compare(
+        assert_table(
             &mut s,
             "t1",
-            vec![
-                vec![
-                    Value::Integer(1),
-                    Value::String("vv".to_string()),
-                    Value::Integer(100),
-                ],
-                vec![
-                    Value::Integer(2),
-                    Value::String("a".to_string()),
-                    Value::Integer(2),
-                ],
-                vec![
-                    Value::Integer(3),
-                    Value::String("b".to_string()),
-                    Value::Integer(100),
-                ],
-            ],
+            "
+            1, vv, 100
+            2, a, 2
+            3, b, 100
+            ",
         )?;
 
         // t2
         s.execute("insert into t2 (a) values (1);")?;
-        scan_table_and_compare(
-            &mut s,
-            "t2",
-            vec![vec![
-                Value::Integer(1),
-                Value::Integer(100),
-                Value::Float(1.1),
-                Value::Boolean(false),
-                Value::Boolean(true),
-                Value::String("v1".to_string()),
-                Value::String("v2".to_string()),
-                Value::String("v3".to_string()),
-            ]],
-        )?;
+        assert_table(&mut s, "t2", "1, 100, 1.1, false, true, v1, v2, v3")?;
 
         // t3
         s.execute("insert into t3 (a, d) values (1, 1.1);")?;
-        scan_table_and_compare(
-            &mut s,
-            "t3",
-            vec![vec![
-                Value::Integer(1),
-                Value::Integer(12),
-                Value::Null,
-                Value::Float(1.1),
-            ]],
-        )?;
+        assert_table(&mut s, "t3", "1, 12, NULL, 1.1")?;
 
         // t4
         s.execute("insert into t4 (a) values (true);")?;
-        scan_table_and_compare(
-            &mut s,
-            "t4",
-            vec![vec![
-                Value::Boolean(true),
-                Value::Integer(12),
-                Value::Boolean(true),
-            ]],
-        )?;
+        assert_table(&mut s, "t4", "true, 12, true")?;
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
@@ -513,7 +944,7 @@ mod tests {
     #[test]
     fn test_update() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         setup_table(&mut s)?;
 
@@ -527,53 +958,51 @@ mod tests {
         let res = s.execute("update t2 set d = false where d = true;")?;
         assert_eq!(res, ResultSet::Update { count: 2 });
 
-        scan_table_and_compare(
+        assert_table(
             &mut s,
             "t2",
-            vec![
-                vec![
-                    Value::Integer(1),
-                    Value::Integer(100),
-                    Value::Float(1.1),
-                    Value::Boolean(false),
-                    Value::Boolean(true),
-                    Value::String("v1".to_string()),
-                    Value::String("v2".to_string()),
-                    Value::String("v3".to_string()),
-                ],
-                vec![
-                    Value::Integer(2),
-                    Value::Integer(2),
-                    Value::Float(2.2),
-                    Value::Boolean(false),
-                    Value::Boolean(false),
-                    Value::String("v4".to_string()),
-                    Value::String("v5".to_string()),
-                    Value::String("v6".to_string()),
-                ],
-                vec![
-                    Value::Integer(3),
-                    Value::Integer(3),
-                    Value::Float(3.3),
-                    Value::Boolean(false),
-                    Value::Boolean(false),
-                    Value::String("v7".to_string()),
-                    Value::String("v8".to_string()),
-                    Value::String("v9".to_string()),
-                ],
-                vec![
-                    Value::Integer(4),
-                    Value::Integer(4),
-                    Value::Float(4.4),
-                    Value::Boolean(false),
-                    Value::Boolean(true),
-                    Value::String("v10".to_string()),
-                    Value::String("v11".to_string()),
-                    Value::String("v12".to_string()),
-                ],
-            ],
+            "
+            1, 100, 1.1, false, true, v1, v2, v3
+            2, 2, 2.2, false, false, v4, v5, v6
+            3, 3, 3.3, false, false, v7, v8, v9
+            4, 4, 4.4, false, true, v10, v11, v12
+            ",
+        )?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_default() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int default 10, c text, d int not null);")?;
+        s.execute("insert into t values (1, 20, 'x', 30);")?;
+
+        // 有默认值的列重置为默认值，可为空且无默认值的列重置为 NULL
+        let res = s.execute("update t set b = default, c = default where a = 1;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
+        scan_table_and_compare(
+            &mut s,
+            "t",
+            vec![vec![
+                Value::Integer(1),
+                Value::Integer(10),
+                Value::Null,
+                Value::Integer(30),
+            ]],
         )?;
 
+        // 不可为空且无默认值的列报错
+        assert_eq!(
+            s.execute("update t set d = default where a = 1;"),
+            Err(Error::Schema(
+                "[Update Table] Column \" d \" has no default value".into()
+            ))
+        );
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
@@ -581,7 +1010,7 @@ mod tests {
     #[test]
     fn test_delete() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         setup_table(&mut s)?;
 
@@ -592,63 +1021,23 @@ mod tests {
 
         let res = s.execute("delete from t2 where a = 1;")?;
         assert_eq!(res, ResultSet::Delete { count: 1 });
-        scan_table_and_compare(
+        assert_table(
             &mut s,
             "t2",
-            vec![
-                vec![
-                    Value::Integer(2),
-                    Value::Integer(2),
-                    Value::Float(2.2),
-                    Value::Boolean(false),
-                    Value::Boolean(false),
-                    Value::String("v4".to_string()),
-                    Value::String("v5".to_string()),
-                    Value::String("v6".to_string()),
-                ],
-                vec![
-                    Value::Integer(3),
-                    Value::Integer(3),
-                    Value::Float(3.3),
-                    Value::Boolean(true),
-                    Value::Boolean(false),
-                    Value::String("v7".to_string()),
-                    Value::String("v8".to_string()),
-                    Value::String("v9".to_string()),
-                ],
-                vec![
-                    Value::Integer(4),
-                    Value::Integer(4),
-                    Value::Float(4.4),
-                    Value::Boolean(false),
-                    Value::Boolean(true),
-                    Value::String("v10".to_string()),
-                    Value::String("v11".to_string()),
-                    Value::String("v12".to_string()),
-                ],
-            ],
+            "
+            2, 2, 2.2, false, false, v4, v5, v6
+            3, 3, 3.3, true, false, v7, v8, v9
+            4, 4, 4.4, false, true, v10, v11, v12
+            ",
         )?;
 
         let res = s.execute("delete from t2 where d = false;")?;
         assert_eq!(res, ResultSet::Delete { count: 2 });
-        scan_table_and_compare(
-            &mut s,
-            "t2",
-            vec![vec![
-                Value::Integer(3),
-                Value::Integer(3),
-                Value::Float(3.3),
-                Value::Boolean(true),
-                Value::Boolean(false),
-                Value::String("v7".to_string()),
-                Value::String("v8".to_string()),
-                Value::String("v9".to_string()),
-            ]],
-        )?;
+        assert_table(&mut s, "t2", "3, 3, 3.3, true, false, v7, v8, v9")?;
 
         let res = s.execute("delete from t2;")?;
         assert_eq!(res, ResultSet::Delete { count: 1 });
-        scan_table_and_compare(&mut s, "t2", vec![])?;
+        assert_table(&mut s, "t2", "")?;
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
@@ -657,7 +1046,7 @@ mod tests {
     #[test]
     fn test_sort() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         setup_table(&mut s)?;
 
@@ -670,14 +1059,14 @@ mod tests {
 
         match s.execute("select a, b as col2 from t3 order by c, a desc limit 100;")? {
             ResultSet::Scan { columns, rows } => {
-                for col in columns {
-                    print!("{} ", col);
-                }
-                println!();
-                println!("-----------");
-                for r in rows {
-                    println!("{:?}", r);
-                }
+                assert_eq!(columns, vec!["a", "col2"]);
+                assert_eq!(
+                    rows,
+                    [(5, 87), (3, 56), (1, 34), (2, 87), (4, 23), (7, 87)]
+                        .iter()
+                        .map(|&(a, b)| vec![Value::Integer(a), Value::Integer(b)])
+                        .collect::<Vec<_>>()
+                );
             }
             _ => unreachable!(),
         }
@@ -689,7 +1078,7 @@ mod tests {
     #[test]
     fn test_cross_join() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t1 (a int primary key);")?;
         s.execute("create table t2 (b int primary key);")?;
@@ -699,16 +1088,18 @@ mod tests {
         s.execute("insert into t2 values (4), (5), (6);")?;
         s.execute("insert into t3 values (7), (8), (9);")?;
 
-        match s.execute("select * from t1 cross join t2 cross join t3;")? {
-            ResultSet::Scan { columns, rows } => {
-                assert_eq!(3, columns.len());
-                assert_eq!(27, rows.len());
-                for row in rows {
-                    println!("{:?}", row);
+        let mut expected = Vec::new();
+        for a in [1, 2, 3] {
+            for b in [4, 5, 6] {
+                for c in [7, 8, 9] {
+                    expected.push(vec![a.into(), b.into(), c.into()]);
                 }
             }
-            _ => unreachable!(),
         }
+        assert_rows_unordered(
+            &s.execute("select * from t1 cross join t2 cross join t3;")?,
+            expected,
+        );
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
@@ -717,7 +1108,7 @@ mod tests {
     #[test]
     fn test_join() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t1 (a int primary key);")?;
         s.execute("create table t2 (b int primary key);")?;
@@ -745,7 +1136,7 @@ mod tests {
     #[test]
     fn test_agg() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t1 (a int primary key, b text, c float);")?;
 
@@ -796,18 +1187,52 @@ mod tests {
     }
 
     #[test]
-    fn test_group_by() -> Result<()> {
+    fn test_agg_filter() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
-        s.execute("create table t1 (a int primary key, b text, c float);")?;
+        s.execute("create table t1 (a int primary key, b text, c int);")?;
 
-        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
-        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
-        s.execute("insert into t1 values (3, null, NULL);")?;
-        s.execute("insert into t1 values (4, null, 4.6);")?;
-        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
-        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
+        s.execute("insert into t1 values (1, 'aa', 3);")?;
+        s.execute("insert into t1 values (2, 'aa', 0);")?;
+        s.execute("insert into t1 values (3, 'bb', 0);")?;
+        s.execute("insert into t1 values (4, 'bb', 5);")?;
+        s.execute("insert into t1 values (5, 'bb', NULL);")?;
+
+        match s.execute(
+            "select count(a) as total, count(a) filter (where c > 0) as positive, sum(c) filter (where b = 'bb') as bb_sum from t1;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["total", "positive", "bb_sum"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(5),
+                        Value::Integer(2),
+                        Value::Integer(5)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c float);")?;
+
+        s.execute("insert into t1 values (1, 'aa', 3.1);")?;
+        s.execute("insert into t1 values (2, 'bb', 5.3);")?;
+        s.execute("insert into t1 values (3, null, NULL);")?;
+        s.execute("insert into t1 values (4, null, 4.6);")?;
+        s.execute("insert into t1 values (5, 'bb', 5.8);")?;
+        s.execute("insert into t1 values (6, 'dd', 1.4);")?;
 
         match s.execute("select b, min(c), max(a), avg(c) from t1 group by b order by avg;")? {
             ResultSet::Scan { columns, rows } => {
@@ -852,7 +1277,7 @@ mod tests {
     #[test]
     fn test_filter() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t1 (a int primary key, b text, c float, d bool);")?;
 
@@ -865,9 +1290,6 @@ mod tests {
 
         match s.execute("select * from t1 where d < true;")? {
             ResultSet::Scan { columns, rows } => {
-                // for row in rows {
-                //     println!("{:?}", row);
-                // }
                 assert_eq!(4, columns.len());
                 assert_eq!(3, rows.len());
             }
@@ -882,6 +1304,35 @@ mod tests {
             _ => unreachable!(),
         }
 
+        // having 可以直接使用布尔表达式
+        match s.execute("select b, max(d) as any_d from t1 group by b having any_d order by b;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["b", "any_d"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("aa".into()), Value::Boolean(true)],
+                        vec![Value::String("bb".into()), Value::Boolean(true)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        match s.execute("select a from t1 where d;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(1)],
+                        vec![Value::Integer(2)],
+                        vec![Value::Integer(5)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
@@ -889,7 +1340,7 @@ mod tests {
     #[test]
     fn test_index() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
         s.execute("insert into t values (1, 'a', 1.1, true);")?;
@@ -915,7 +1366,7 @@ mod tests {
     #[test]
     fn test_primary_key_scan() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t (a int primary key, b text index, c float index, d bool);")?;
         s.execute("insert into t values (1, 'a', 1.1, true);")?;
@@ -937,7 +1388,7 @@ mod tests {
     #[test]
     fn test_hash_join() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut s = kvengine.session()?;
         s.execute("create table t1 (a int primary key);")?;
         s.execute("create table t2 (b int primary key);")?;
@@ -947,13 +1398,4535 @@ mod tests {
         s.execute("insert into t2 values (2), (3), (4);")?;
         s.execute("insert into t3 values (3), (8), (9);")?;
 
-        match s.execute("select * from t1 join t2 on a = b join t3 on a = c;")? {
+        let res = s.execute("select * from t1 join t2 on a = b join t3 on a = c;")?;
+        assert_rows_unordered(&res, vec![vec![3.into(), 3.into(), 3.into()]]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_types() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, x int);")?;
+        s.execute("create table t2 (b int primary key, y int);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("insert into t2 values (2, 200), (3, 300), (4, 400);")?;
+
+        let i = |v: i64| Value::Integer(v);
+        let null = || Value::Null;
+        let columns = vec!["t1.a", "t1.x", "t2.b", "t2.y"];
+        let matched = vec![
+            vec![i(2), i(20), i(2), i(200)],
+            vec![i(3), i(30), i(3), i(300)],
+        ];
+
+        let mut left = vec![vec![i(1), i(10), null(), null()]];
+        left.extend(matched.clone());
+        let mut right = matched.clone();
+        right.push(vec![null(), null(), i(4), i(400)]);
+        let mut cross = Vec::new();
+        for (a, x) in [(1, 10), (2, 20), (3, 30)] {
+            for (b, y) in [(2, 200), (3, 300), (4, 400)] {
+                cross.push(vec![i(a), i(x), i(b), i(y)]);
+            }
+        }
+
+        let cases = vec![
+            ("select * from t1 join t2 on a = b;", matched.clone()),
+            ("select * from t1 join t2 on b = a;", matched.clone()),
+            ("select * from t1 left join t2 on a = b;", left.clone()),
+            ("select * from t1 right join t2 on a = b;", right.clone()),
+            (
+                "select * from t1 right join t2 on t2.b = t1.a;",
+                right.clone(),
+            ),
+            ("select * from t1 cross join t2;", cross),
+        ];
+        for (sql, expect) in cases {
+            let res = s.execute(sql)?;
+            match &res {
+                ResultSet::Scan { columns: cols, .. } => assert_eq!(cols, &columns, "{}", sql),
+                _ => unreachable!(),
+            }
+            // 不依赖连接算法输出的行顺序
+            assert_rows_unordered(&res, expect);
+        }
+
+        // right join 之后按列名投影、过滤
+        match s.execute("select y, a from t1 right join t2 on a = b where y > 250;")? {
             ResultSet::Scan { columns, rows } => {
-                assert_eq!(columns.len(), 3);
-                assert_eq!(rows.len(), 1);
+                assert_eq!(columns, vec!["y", "a"]);
+                assert_eq!(rows, vec![vec![i(300), i(3)], vec![i(400), null()]]);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_aggregate() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, name varchar);")?;
+        s.execute("create table t2 (id int primary key, a int, b int);")?;
+
+        s.execute("insert into t1 values (1, 'x'), (2, 'y'), (3, 'z');")?;
+        s.execute(
+            "insert into t2 values (1, 1, 10), (2, 1, 20), (3, 1, null), (4, 2, 5), (5, 4, 7);",
+        )?;
+
+        // 每组的计数：a=1 有两个非空的b，a=2 有一个，a=3 和 a=4 不在连接结果中
+        match s.execute(
+            "select t1.a, count(t2.b), sum(t2.b) from t1 join t2 on t1.a = t2.a group by t1.a order by t1.a;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["t1.a", "count", "sum"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(1), Value::Integer(2), Value::Integer(30)],
+                        vec![Value::Integer(2), Value::Integer(1), Value::Integer(5)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 没有歧义的列可以不带表名
+        match s.execute(
+            "select name, count(b) as cnt from t1 join t2 on t1.a = t2.a group by name order by name;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["name", "cnt"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("x".into()), Value::Integer(2)],
+                        vec![Value::String("y".into()), Value::Integer(1)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 两张表都有a列，不带表名有歧义
+        assert!(s
+            .execute("select a, count(b) from t1 join t2 on t1.a = t2.a group by a;")
+            .is_err());
+        assert!(s
+            .execute("select t1.a, count(a) from t1 join t2 on t1.a = t2.a group by t1.a;")
+            .is_err());
+        // 列不存在
+        assert!(s
+            .execute("select t1.a, count(t1.b) from t1 join t2 on t1.a = t2.a group by t1.a;")
+            .is_err());
+
+        // 单表查询：列名中的表名必须是查询的表
+        assert_eq!(
+            query_rows(
+                &mut s,
+                "select t1.name from t1 where t1.a = 2 group by t1.name;"
+            )?,
+            vec![vec![Value::String("y".into())]]
+        );
+        for sql in [
+            "select t2.a from t1;",
+            "select zz.a from t1;",
+            "select * from t1 where zz.a = 1;",
+            "select count(zz.a) from t1;",
+            "select name from t1 group by zz.name;",
+            "select * from t1 order by zz.a;",
+            "update t1 set name = 'w' where zz.a = 1;",
+            "delete from t1 where t2.a = 1;",
+        ] {
+            match s.execute(sql) {
+                Err(Error::Internal(msg)) => assert!(
+                    msg.contains("does not exist in table \" t1 \""),
+                    "{}: {}",
+                    sql,
+                    msg
+                ),
+                res => panic!("{}: unexpected {:?}", sql, res),
+            }
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_where() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, x int);")?;
+        s.execute("create table t2 (b int primary key, y int);")?;
+
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("insert into t2 values (1, 5), (2, 25), (4, 40);")?;
+
+        // 条件同时引用两张表的列，只能在连接之后过滤
+        match s.execute("select * from t1 join t2 on a = b where x > y;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["t1.a", "t1.x", "t2.b", "t2.y"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(1),
+                        Value::Integer(10),
+                        Value::Integer(1),
+                        Value::Integer(5)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("explain select * from t1 join t2 on a = b where t1.x > t2.y;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Filter: t1.x > t2.y")),
+            _ => unreachable!(),
+        }
+
+        // 只涉及一张表的条件下推到扫描节点
+        match s.execute("explain select * from t1 join t2 on a = b where t2.y = 25;")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Sequence Scan On Table t2 ( Filter: y = 25 )"));
+                assert!(!plan.contains("Sequence Scan On Table t1 ("));
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t1 cross join t2 where t2.y = 25;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 3),
+            _ => unreachable!(),
+        }
+
+        // 左连接中右表的条件不能下推，否则补NULL的行会被错误地保留
+        match s.execute("select a from t1 left join t2 on a = b where y = 25;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_catalog() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, b text default 'x', c int index);")?;
+        s.execute("create table t2 (id int primary key, d bool not null);")?;
+        s.execute("create table t3 (e int primary key);")?;
+        s.execute("create table wanted (col text primary key);")?;
+        s.execute("insert into wanted values ('id');")?;
+
+        match s.execute("select name, column_count from __tables__ where column_count > 1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["name", "column_count"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("t1".into()), Value::Integer(3)],
+                        vec![Value::String("t2".into()), Value::Integer(2)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        match s.execute("select * from __columns__ where table_name = 't1';")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(
+                    columns,
+                    vec![
+                        "table_name",
+                        "column_name",
+                        "datatype",
+                        "nullable",
+                        "default",
+                        "primary_key",
+                        "indexed"
+                    ]
+                );
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("t1".into()),
+                            Value::String("id".into()),
+                            Value::String("INTEGER".into()),
+                            Value::Boolean(false),
+                            Value::Null,
+                            Value::Boolean(true),
+                            Value::Boolean(false),
+                        ],
+                        vec![
+                            Value::String("t1".into()),
+                            Value::String("b".into()),
+                            Value::String("STRING".into()),
+                            Value::Boolean(true),
+                            Value::String("x".into()),
+                            Value::Boolean(false),
+                            Value::Boolean(false),
+                        ],
+                        vec![
+                            Value::String("t1".into()),
+                            Value::String("c".into()),
+                            Value::String("INTEGER".into()),
+                            Value::Boolean(true),
+                            Value::Null,
+                            Value::Boolean(false),
+                            Value::Boolean(true),
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 找出所有包含 id 列的表
+        match s.execute(
+            "select table_name from __columns__ join wanted on column_name = col order by table_name;",
+        )? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("t1".into())],
+                        vec![Value::String("t2".into())],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 系统目录表只读
+        for sql in [
+            "insert into __tables__ values ('t4', 1, 'a');",
+            "update __columns__ set nullable = false;",
+            "delete from __tables__;",
+            "drop table __columns__;",
+            "create table __tables__ (a int primary key);",
+        ] {
+            match s.execute(sql) {
+                Err(Error::Schema(msg)) => assert!(msg.contains("read-only system catalog")),
+                _ => unreachable!(),
+            }
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, oncall bool);")?;
+        s.execute("insert into t1 values (1, true), (2, true);")?;
+
+        // 两个值班的人各自确认还有其他人值班后下班
+        let write_skew =
+            |begin: &str, id1: i64, id2: i64| -> Result<(Result<ResultSet>, Result<ResultSet>)> {
+                let mut s1 = kvengine.session()?;
+                let mut s2 = kvengine.session()?;
+                s1.execute(begin)?;
+                s2.execute(begin)?;
+                s1.execute("select * from t1 where oncall = true;")?;
+                s2.execute("select * from t1 where oncall = true;")?;
+                s1.execute(&format!("update t1 set oncall = false where id = {};", id1))?;
+                s2.execute(&format!("update t1 set oncall = false where id = {};", id2))?;
+                Ok((s1.execute("commit;"), s2.execute("commit;")))
+            };
+
+        // 快照隔离下两个事务都提交成功，没有人值班了
+        let (r1, r2) = write_skew("begin;", 1, 2)?;
+        assert!(r1.is_ok() && r2.is_ok());
+        match s.execute("select * from t1 where oncall = true;")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+
+        // 可串行化事务中，后提交的事务失败并回滚
+        s.execute("update t1 set oncall = true;")?;
+        let (r1, r2) = write_skew("begin serializable;", 1, 2)?;
+        assert!(r1.is_ok());
+        assert_eq!(r2, Err(Error::SerializationFailure));
+        match s.execute("select id from t1 where oncall = true;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        // 只读的可串行化事务不会失败
+        let mut s1 = kvengine.session()?;
+        s1.execute("begin serializable;")?;
+        s1.execute("select * from t1;")?;
+        s.execute("update t1 set oncall = false;")?;
+        s1.execute("commit;")?;
+
+        // 并发插入不同主键的多行，只检查插入的主键是否存在，两个事务都能提交
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("begin serializable;")?;
+        s2.execute("begin serializable;")?;
+        s1.execute("insert into t1 values (3, true), (4, true);")?;
+        s2.execute("insert into t1 values (5, true), (6, true);")?;
+        s1.execute("commit;")?;
+        s2.execute("commit;")?;
+        assert_eq!(
+            query_rows(&mut s, "select id from t1 where id > 2;")?.len(),
+            4
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_insert_rows() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text default 'x');")?;
+
+        s.set_max_insert_rows(Some(3));
+        assert_eq!(
+            s.execute("insert into t1 values (1), (2), (3);")?,
+            ResultSet::Insert { count: 3 }
+        );
+        match s.execute("insert into t1 (b, a) values ('a', 4), ('b', 5), ('c', 6), ('d', 7);") {
+            Err(Error::Internal(msg)) => {
+                assert!(msg.contains("Too many rows in one statement (4 > 3)"))
+            }
+            _ => unreachable!(),
+        }
+        // 超出限制的语句没有插入任何数据
+        scan_table_and_compare(
+            &mut s,
+            "t1",
+            vec![
+                vec![Value::Integer(1), Value::String("x".into())],
+                vec![Value::Integer(2), Value::String("x".into())],
+                vec![Value::Integer(3), Value::String("x".into())],
+            ],
+        )?;
+
+        s.set_max_insert_rows(None);
+        assert_eq!(
+            s.execute("insert into t1 (b, a) values ('a', 4), ('b', 5), ('c', 6), ('d', 7);")?,
+            ResultSet::Insert { count: 4 }
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_meta() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+            let mut s = kvengine.session()?;
+            s.execute("create table t1 (a int primary key);")?;
+            s.execute("insert into t1 values (1);")?;
+
+            // 通过事务接口读写
+            let mut txn = kvengine.begin()?;
+            txn.meta_set("schema_version", b"3".to_vec())?;
+            assert_eq!(txn.meta_get("schema_version")?, Some(b"3".to_vec()));
+            txn.commit()?;
+
+            // 回滚后写入不可见
+            let mut txn = kvengine.begin()?;
+            txn.meta_set("schema_version", b"4".to_vec())?;
+            txn.meta_set("tmp", b"x".to_vec())?;
+            txn.rollback()?;
+            let txn = kvengine.begin()?;
+            assert_eq!(txn.meta_get("schema_version")?, Some(b"3".to_vec()));
+            assert_eq!(txn.meta_get("tmp")?, None);
+            txn.commit()?;
+
+            // 通过sql读写
+            s.execute("kv put 'theme' 'dark';")?;
+            s.execute("begin;")?;
+            s.execute("kv delete 'theme';")?;
+            s.execute("kv put 'lang' 'en';")?;
+            s.execute("rollback;")?;
+
+            // 元数据不是表，不会出现在 show tables 和表扫描中
+            match s.execute("show tables;")? {
+                ResultSet::TableNames { names } => assert_eq!(names, vec!["t1"]),
+                _ => unreachable!(),
+            }
+            scan_table_and_compare(&mut s, "t1", vec![vec![Value::Integer(1)]])?;
+        }
+
+        // 重启之后仍然存在
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        match s.execute("kv get 'theme';")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["key", "value"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::String("theme".into()),
+                        Value::String("dark".into())
+                    ]]
+                );
             }
             _ => unreachable!(),
         }
+        match s.execute("kv get 'lang';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][1], Value::Null),
+            _ => unreachable!(),
+        }
+        let txn = kvengine.begin()?;
+        assert_eq!(txn.meta_get("schema_version")?, Some(b"3".to_vec()));
+        txn.commit()?;
+
+        s.execute("kv delete 'theme';")?;
+        match s.execute("kv get 'theme';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][1], Value::Null),
+            _ => unreachable!(),
+        }
+        assert!(s.execute("kv get theme;").is_err());
+        assert!(s.execute("kv set 'theme' 'dark';").is_err());
+        match s.execute("KV Get 'theme';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][1], Value::Null),
+            _ => unreachable!(),
+        }
+
+        // kv、get、put 只在 kv 命令中有特殊含义，其他位置可以用作表名、列名
+        s.execute("create table get (kv int primary key, get text, put text);")?;
+        s.execute("insert into get (kv, get, put) values (1, 'a', 'b');")?;
+        s.execute("create table kv (put int primary key);")?;
+        assert_eq!(
+            query_rows(&mut s, "select get, put from get where kv = 1;")?,
+            vec![vec![Value::String("a".into()), Value::String("b".into())]]
+        );
+        assert_eq!(query_rows(&mut s, "select * from kv;")?, Vec::<Row>::new());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_for_update() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int primary key, oncall bool);")?;
+        s.execute("insert into t1 values (1, true), (2, true);")?;
+
+        // 和 test_serializable 中的写偏斜相同，但读的时候锁定了行，后提交的事务失败
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("select * from t1 where oncall = true for update;")?;
+        s2.execute("select * from t1 where oncall = true for update;")?;
+        s1.execute("update t1 set oncall = false where id = 1;")?;
+        s2.execute("update t1 set oncall = false where id = 2;")?;
+        assert!(s1.execute("commit;").is_ok());
+        assert_eq!(s2.execute("commit;"), Err(Error::SerializationFailure));
+        match s.execute("select id from t1 where oncall = true;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+
+        // 只锁定读到的行：锁定 id = 2，另一个事务修改 id = 1 不冲突
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        match s1.execute("select * from t1 where id = 2 for update;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        s2.execute("update t1 set oncall = true where id = 1;")?;
+        s2.execute("commit;")?;
+        s1.execute("update t1 set oncall = false where id = 2;")?;
+        assert!(s1.execute("commit;").is_ok());
+
+        match s.execute("explain select * from t1 where id = 1 for update;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Lock Rows On Table t1")),
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select * from __tables__ for update;").is_err());
+        assert!(s.execute("select * from t1 for;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_string() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a varchar primary key, b varchar index, c int);")?;
+        s.execute(
+            "insert into t values ('', '', 1), ('a', null, 2), ('a ', 'a', 3), (' ', '', 4);",
+        )?;
+
+        let str = |v: &str| Value::String(v.into());
+        let i = |v: i64| Value::Integer(v);
+
+        // 空字符串作为主键，走主键索引
+        match s.execute("select c from t where a = '';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![i(1)]]),
+            _ => unreachable!(),
+        }
+        // 空字符串作为索引列的值，走二级索引，null 不等于空字符串
+        match s.execute("explain select * from t where b = '';")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Index Scan")),
+            _ => unreachable!(),
+        }
+        match s.execute("select c from t where b = '' order by c;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![i(1)], vec![i(4)]]),
+            _ => unreachable!(),
+        }
+        // 比较时不会去掉空格
+        match s.execute("select c from t where a = 'a';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![i(2)]]),
+            _ => unreachable!(),
+        }
+        match s.execute("select c from t where a = 'a ';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![i(3)]]),
+            _ => unreachable!(),
+        }
+
+        // 排序：NULL < '' < ' ' < 'a'
+        match s.execute("select b, c from t order by b, c;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Null, i(2)],
+                    vec![str(""), i(1)],
+                    vec![str(""), i(4)],
+                    vec![str("a"), i(3)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute("select a from t order by a;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![str("")],
+                    vec![str(" ")],
+                    vec![str("a")],
+                    vec![str("a ")]
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // group by 中空字符串和 null 是不同的分组
+        match s.execute("select b, count(c) as cnt from t group by b order by b;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Null, i(1)],
+                    vec![str(""), i(2)],
+                    vec![str("a"), i(1)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 输出时空字符串为空，null 显示为 NULL
+        let res = s.execute("select a, b from t where c = 1;")?.to_string();
+        assert!(!res.contains("NULL"));
+        let res = s.execute("select a, b from t where c = 2;")?.to_string();
+        assert!(res.contains("NULL"));
+
+        // 空字符串主键不能重复，更新和删除也能定位到
+        assert!(s.execute("insert into t values ('', 'x', 5);").is_err());
+        s.execute("update t set b = 'x' where a = '';")?;
+        match s.execute("select b from t where a = '';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![str("x")]]),
+            _ => unreachable!(),
+        }
+        s.execute("delete from t where a = '';")?;
+        match s.execute("select c from t where b = '';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![i(4)]]),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_insert() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute("insert into t values (1, 'x', 10);")?;
+
+        // 批次内多行共享同一个索引值，索引合并之后写入
+        s.execute("insert into t values (2, 'x', 20), (3, 'y', 30), (4, 'x', 40);")?;
+        match s.execute("select a from t where b = 'x' order by a;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1)],
+                    vec![Value::Integer(2)],
+                    vec![Value::Integer(4)]
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 和表中已有的主键冲突、批次内主键重复、类型错误，整条语句都不会写入
+        assert!(s
+            .execute("insert into t values (5, 'z', 50), (1, 'z', 60);")
+            .is_err());
+        assert!(s
+            .execute("insert into t values (6, 'z', 50), (6, 'z', 60);")
+            .is_err());
+        assert!(s
+            .execute("insert into t values (7, 'z', 50), (8, 'z', 'c');")
+            .is_err());
+        match s.execute("select * from t where b = 'z';")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+
+        // 事务内的批量插入可以看到本事务之前写入的数据
+        s.execute("begin;")?;
+        s.execute("insert into t values (9, 'z', 90);")?;
+        assert!(s
+            .execute("insert into t values (10, 'z', 100), (9, 'z', 90);")
+            .is_err());
+        s.execute("insert into t values (10, 'z', 100), (11, 'z', 110);")?;
+        s.execute("commit;")?;
+        match s.execute("select a from t where b = 'z' order by a;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(9)],
+                    vec![Value::Integer(10)],
+                    vec![Value::Integer(11)]
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text index);")?;
+
+        // 保存点只能在显式事务中使用
+        assert!(s.execute("savepoint s1;").is_err());
+        assert!(s.execute("rollback to s1;").is_err());
+        assert!(s.execute("release s1;").is_err());
+
+        s.execute("begin;")?;
+        s.execute("insert into t1 values (1, 'a');")?;
+        assert_eq!(
+            s.execute("savepoint s1;")?.to_string(),
+            "SAVEPOINT s1".to_string()
+        );
+        s.execute("insert into t1 values (2, 'b');")?;
+        s.execute("update t1 set b = 'x' where a = 1;")?;
+        s.execute("savepoint s2;")?;
+        s.execute("insert into t1 values (3, 'c');")?;
+        s.execute("rollback to savepoint s2;")?;
+        match s.execute("select a from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        s.execute("rollback to s1;")?;
+        // 已回滚的保存点之后的保存点不存在了
+        assert!(s.execute("rollback to s2;").is_err());
+        // 事务仍然活跃，可以继续写入
+        s.execute("insert into t1 values (4, 'a');")?;
+        s.execute("release savepoint s1;")?;
+        assert!(s.execute("rollback to s1;").is_err());
+        assert!(s.execute("rollback to unknown;").is_err());
+        s.execute("commit;")?;
+
+        // 只有保存点之前以及回滚之后的写入被提交，索引也一并回滚
+        match s.execute("select * from t1 order by a;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::String("a".into())],
+                    vec![Value::Integer(4), Value::String("a".into())],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute("select a from t1 where b = 'x';")? {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+        match s.execute("select a from t1 where b = 'a';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_create_table() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        setup_table(&mut s)?;
+        s.execute("create table t5 (a text primary key, b float default 2.0 index, c int not null default 0, d float default 3.14159265, e float default 0.0000001);")?;
+
+        match s.execute("show create table t3;")? {
+            ResultSet::ShowCreateTable { sql } => assert_eq!(
+                sql,
+                "CREATE TABLE t3 (
+  a INTEGER PRIMARY KEY,
+  b INTEGER NULL DEFAULT 12,
+  c INTEGER NULL DEFAULT NULL,
+  d FLOAT NOT NULL
+);"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 浮点数默认值完整输出，不按查询结果的格式舍入
+        match s.execute("show create table t5;")? {
+            ResultSet::ShowCreateTable { sql } => assert_eq!(
+                sql,
+                "CREATE TABLE t5 (
+  a STRING PRIMARY KEY,
+  b FLOAT NULL DEFAULT 2.0 INDEX,
+  c INTEGER NOT NULL DEFAULT 0,
+  d FLOAT NULL DEFAULT 3.14159265,
+  e FLOAT NULL DEFAULT 0.0000001
+);"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 字符串默认值和表注释中的引号写作两个引号
+        s.execute(
+            "create table t6 (a int primary key, b text default 'it''s \"x\"') comment 'Bob''s table';",
+        )?;
+        match s.execute("show create table t6;")? {
+            ResultSet::ShowCreateTable { sql } => assert_eq!(
+                sql,
+                "CREATE TABLE t6 (
+  a INTEGER PRIMARY KEY,
+  b STRING NULL DEFAULT 'it''s \"x\"'
+) COMMENT 'Bob''s table';"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 导出的建表语句可以被重新解析，重新建表之后表结构不变
+        for name in ["t1", "t2", "t3", "t4", "t5", "t6"] {
+            let sql = match s.execute(&format!("show create table {};", name))? {
+                ResultSet::ShowCreateTable { sql } => sql,
+                _ => unreachable!(),
+            };
+            match Parser::new(&sql).parse()? {
+                Sentence::CreateTable {
+                    name: table_name, ..
+                } => assert_eq!(table_name, name),
+                _ => unreachable!(),
+            }
+
+            let table = kvengine.begin()?.must_get_table(name.into())?;
+            s.execute(&format!("drop table {};", name))?;
+            s.execute(&sql)?;
+            assert_eq!(kvengine.begin()?.must_get_table(name.into())?, table);
+        }
+
+        assert!(s.execute("show create table t7;").is_err());
+        assert!(s.execute("show create t1;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_stddev_variance() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c integer);")?;
+        s.execute("insert into t1 values (1, 'aa', 2), (2, 'aa', 4), (3, 'aa', NULL), (4, 'bb', 6), (5, 'cc', NULL);")?;
+
+        match s.execute(
+            "select b, sum(c), stddev(c), variance(c) as var from t1 group by b order by b;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["b", "sum", "stddev", "var"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("aa".into()),
+                            Value::Integer(6),
+                            Value::Float(2.0f64.sqrt()),
+                            Value::Float(2.0)
+                        ],
+                        // 单个值和全部是null时样本方差为null
+                        vec![
+                            Value::String("bb".into()),
+                            Value::Integer(6),
+                            Value::Null,
+                            Value::Null
+                        ],
+                        vec![
+                            Value::String("cc".into()),
+                            Value::Null,
+                            Value::Null,
+                            Value::Null
+                        ],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        match s.execute("select variance(c) from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Float(4.0)]]),
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select stddev(b) from t1;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_comment() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text) comment 'user accounts';")?;
+        s.execute("create table t2 (a int primary key);")?;
+
+        let sql = match s.execute("show create table t1;")? {
+            ResultSet::ShowCreateTable { sql } => sql,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            sql,
+            "CREATE TABLE t1 (
+  a INTEGER PRIMARY KEY,
+  b STRING NULL
+) COMMENT 'user accounts';"
+        );
+        match Parser::new(&sql).parse()? {
+            Sentence::CreateTable { comment, .. } => {
+                assert_eq!(comment, Some("user accounts".to_string()))
+            }
+            _ => unreachable!(),
+        }
+
+        // 注释随表结构一起持久化，导出后重新建表注释不变
+        let table = kvengine.begin()?.must_get_table("t1".into())?;
+        assert_eq!(table.comment, Some("user accounts".to_string()));
+        s.execute("drop table t1;")?;
+        s.execute(&sql)?;
+        assert_eq!(kvengine.begin()?.must_get_table("t1".into())?, table);
+
+        match s.execute("show table t1;")? {
+            ResultSet::TableSchema { schema } => {
+                assert!(schema.ends_with("\nCOMMENT: user accounts"))
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("show table t2;")? {
+            ResultSet::TableSchema { schema } => assert!(!schema.contains("COMMENT")),
+            _ => unreachable!(),
+        }
+
+        assert!(s
+            .execute("create table t3 (a int primary key) comment;")
+            .is_err());
+        assert!(s
+            .execute("create table t3 (a int primary key) comment 1;")
+            .is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_collate_nocase() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t1 (id int primary key, name text collate nocase index, dept text collate nocase, tag text);",
+        )?;
+        s.execute(
+            "insert into t1 values (1, 'Alice', 'Eng', 'x'), (2, 'bob', 'eng', 'X'), (3, 'ALICE', 'Ops', 'x'), (4, 'Carol', 'ENG', 'x'), (5, 'alice', NULL, 'x');",
+        )?;
+
+        let ids = |s: &mut Session<KVEngine<DiskEngine>>, sql: &str| -> Result<Vec<Value>> {
+            Ok(query_rows(s, sql)?
+                .into_iter()
+                .map(|r| r[0].clone())
+                .collect())
+        };
+        let ints = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+
+        // 索引按小写存储，任意大小写都可以走索引找到
+        match s.execute("explain select * from t1 where name = 'aLiCe';")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Index Scan On Table t1.name")),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            ids(&mut s, "select id from t1 where name = 'aLiCe';")?,
+            ints(vec![1, 3, 5])
+        );
+        // 全表扫描的等值和范围比较
+        assert_eq!(
+            ids(&mut s, "select id from t1 where dept = 'ENG';")?,
+            ints(vec![1, 2, 4])
+        );
+        assert_eq!(
+            ids(&mut s, "select id from t1 where dept > 'f';")?,
+            ints(vec![3])
+        );
+        assert_eq!(
+            ids(&mut s, "select id from t1 where dept != 'eng';")?,
+            ints(vec![3])
+        );
+        // 普通列仍然区分大小写
+        assert_eq!(
+            ids(&mut s, "select id from t1 where tag = 'x';")?,
+            ints(vec![1, 3, 4, 5])
+        );
+
+        // 忽略大小写排序，大小写不同的值按原值排序
+        assert_eq!(
+            ids(&mut s, "select id from t1 order by name;")?,
+            ints(vec![3, 1, 5, 2, 4])
+        );
+        assert_eq!(
+            ids(&mut s, "select id from t1 order by name desc;")?,
+            ints(vec![4, 2, 5, 1, 3])
+        );
+
+        // group by 合并大小写不同的值，输出组内第一行的原值
+        match s.execute("select dept, count(id) from t1 group by dept order by dept;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Null, Value::Integer(1)],
+                    vec![Value::String("Eng".into()), Value::Integer(3)],
+                    vec![Value::String("Ops".into()), Value::Integer(1)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 更新和删除同步维护小写索引
+        s.execute("update t1 set name = 'BOB' where id = 5;")?;
+        s.execute("update t1 set name = 'aLICE' where id = 1;")?;
+        assert_eq!(
+            ids(&mut s, "select id from t1 where name = 'Bob';")?,
+            ints(vec![2, 5])
+        );
+        s.execute("delete from t1 where id = 3;")?;
+        assert_eq!(
+            ids(&mut s, "select id from t1 where name = 'alice';")?,
+            ints(vec![1])
+        );
+
+        match s.execute("show create table t1;")? {
+            ResultSet::ShowCreateTable { sql } => {
+                assert!(sql.contains("name STRING NULL COLLATE NOCASE INDEX"))
+            }
+            _ => unreachable!(),
+        }
+
+        // 只有非主键的字符串列可以不区分大小写
+        assert!(s
+            .execute("create table t2 (a int primary key, b int collate nocase);")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a text primary key collate nocase);")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a int primary key, b text collate foo);")
+            .is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_precision() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t1 (a int primary key, price decimal(5, 2), qty numeric(3), fee decimal(4, 1) default 2);",
+        )?;
+
+        // 小数部分四舍五入到 scale 位，整数转为浮点数
+        s.execute("insert into t1 values (1, 12.346, 7);")?;
+        s.execute("insert into t1 values (2, 999.994, 12.6, 0.04), (3, 3, 999, 999.94);")?;
+        scan_table_and_compare(
+            &mut s,
+            "t1",
+            vec![
+                vec![
+                    Value::Integer(1),
+                    Value::Float(12.35),
+                    Value::Float(7.0),
+                    Value::Float(2.0),
+                ],
+                vec![
+                    Value::Integer(2),
+                    Value::Float(999.99),
+                    Value::Float(13.0),
+                    Value::Float(0.0),
+                ],
+                vec![
+                    Value::Integer(3),
+                    Value::Float(3.0),
+                    Value::Float(999.0),
+                    Value::Float(999.9),
+                ],
+            ],
+        )?;
+
+        // 整数部分超出 precision - scale 位则报错，包括四舍五入之后进位超出的情况
+        assert!(s.execute("insert into t1 values (4, 1000, 1);").is_err());
+        assert!(s.execute("insert into t1 values (4, 999.999, 1);").is_err());
+        assert!(s.execute("insert into t1 values (4, 1.0, 1000);").is_err());
+        assert!(s
+            .execute("insert into t1 values (4, 1.0, 1, 1000);")
+            .is_err());
+        assert!(s
+            .execute("insert into t1 values (4, 1.0, 1), (5, 1.0, 1234);")
+            .is_err());
+
+        // 更新同样校验
+        assert!(s
+            .execute("update t1 set price = 1234.5 where a = 1;")
+            .is_err());
+        s.execute("update t1 set price = 5.678 where a = 1;")?;
+        match s.execute("select price from t1 where a = 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Float(5.68)]]),
+            _ => unreachable!(),
+        }
+
+        // 按十进制的写法舍入：1.005 在二进制中略小于 1.005，仍然进位为 1.01
+        s.execute("insert into t1 values (6, 1.005, 2.5, 0.15), (7, 2.675, 0.5, 99.95);")?;
+        assert_eq!(
+            query_rows(
+                &mut s,
+                "select price, qty, fee from t1 where a >= 6 order by a;"
+            )?,
+            vec![
+                vec![Value::Float(1.01), Value::Float(3.0), Value::Float(0.2)],
+                vec![Value::Float(2.68), Value::Float(1.0), Value::Float(100.0)],
+            ]
+        );
+        s.execute("delete from t1 where a >= 6;")?;
+
+        match s.execute("show create table t1;")? {
+            ResultSet::ShowCreateTable { sql } => {
+                assert!(sql.contains("price DECIMAL(5, 2) NULL"));
+                assert!(sql.contains("qty DECIMAL(3, 0) NULL"));
+                assert!(sql.contains("fee DECIMAL(4, 1) NULL DEFAULT 2.0"));
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(s
+            .execute("create table t2 (a int primary key, b decimal(0));")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a int primary key, b decimal(16, 2));")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a int primary key, b decimal(3, 4));")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a int primary key, b decimal(a));")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (a int primary key, b decimal(5, 2) default 1000);")
+            .is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_binding() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b float, c text);")?;
+        s.execute("create table t2 (a int primary key, d int);")?;
+
+        // 不存在的列在绑定时报错，空表也一样
+        let err = s.execute("select * from t1 where x = 1;").unwrap_err();
+        assert!(err.to_string().contains("available columns: a, b, c"));
+
+        s.execute("insert into t1 values (1, 1.5, 'x'), (2, 2.0, 'y'), (3, NULL, 'z');")?;
+        s.execute("insert into t2 values (1, 2), (2, 3), (3, 1);")?;
+
+        let count = |s: &mut Session<KVEngine<DiskEngine>>, sql: &str| -> Result<usize> {
+            Ok(query_rows(s, sql)?.len())
+        };
+        // 整数和浮点数比较、NULL 不满足任何比较
+        assert_eq!(count(&mut s, "select * from t1 where b = 2;")?, 1);
+        assert_eq!(count(&mut s, "select * from t1 where b >= 1;")?, 2);
+        assert_eq!(count(&mut s, "select * from t1 where b != 2;")?, 1);
+        assert_eq!(count(&mut s, "select * from t1 where 'y' <= c;")?, 2);
+        // 连接之后按两张表的列过滤
+        assert_eq!(
+            count(&mut s, "select * from t1 cross join t2 where t1.a > t2.d;")?,
+            3
+        );
+        assert!(s.execute("select * from t1 where c > 1;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_equi_join() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        s.execute("create table t2 (b int primary key);")?;
+        s.execute("insert into t1 values (1), (2), (3);")?;
+        s.execute("insert into t2 values (1), (2);")?;
+
+        let ints = |v: Vec<Vec<Option<i64>>>| -> Vec<Row> {
+            v.into_iter()
+                .map(|r| {
+                    r.into_iter()
+                        .map(|v| v.map(Value::Integer).unwrap_or(Value::Null))
+                        .collect()
+                })
+                .collect()
+        };
+
+        // 非等值连接走嵌套循环
+        match s.execute("explain select * from t1 join t2 on t1.a > t2.b;")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Nested Loop Join"));
+                assert!(!plan.contains("Hash Join"));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            query_rows(&mut s, "select * from t1 join t2 on t1.a > t2.b;")?,
+            ints(vec![
+                vec![Some(2), Some(1)],
+                vec![Some(3), Some(1)],
+                vec![Some(3), Some(2)],
+            ])
+        );
+        // 条件两侧写反时结果一致
+        assert_eq!(
+            query_rows(&mut s, "select * from t1 join t2 on t2.b < t1.a;")?,
+            query_rows(&mut s, "select * from t1 join t2 on t1.a > t2.b;")?
+        );
+        // 外连接中未匹配的行补 NULL
+        assert_eq!(
+            query_rows(&mut s, "select * from t1 left join t2 on t1.a <= t2.b;")?,
+            ints(vec![
+                vec![Some(1), Some(1)],
+                vec![Some(1), Some(2)],
+                vec![Some(2), Some(2)],
+                vec![Some(3), None],
+            ])
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t2 right join t1 on t2.b >= t1.a;")?,
+            ints(vec![
+                vec![Some(1), Some(1)],
+                vec![Some(2), Some(1)],
+                vec![Some(2), Some(2)],
+                vec![None, Some(3)],
+            ])
+        );
+        // 等值连接仍然走 HashJoin
+        match s.execute("explain select * from t1 join t2 on t1.a = t2.b;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Hash Join")),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_indexes() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text index, c int index, d int);")?;
+        s.execute("create table t2 (a int primary key, b text);")?;
+        s.execute("insert into t1 values (1, 'x', 1, 1), (2, 'x', 2, 2), (3, 'y', NULL, 3);")?;
+
+        let index_rows = |s: &mut Session<KVEngine<DiskEngine>>, table: &str| -> Result<Vec<Row>> {
+            match s.execute(&format!("show indexes from {};", table))? {
+                ResultSet::Scan { columns, rows } => {
+                    assert_eq!(columns, vec!["column", "type", "entry_count"]);
+                    Ok(rows)
+                }
+                _ => unreachable!(),
+            }
+        };
+        let index_row = |col: &str, count: i64| {
+            vec![
+                Value::String(col.into()),
+                Value::String("HASH".into()),
+                Value::Integer(count),
+            ]
+        };
+
+        assert_eq!(
+            index_rows(&mut s, "t1")?,
+            vec![index_row("b", 3), index_row("c", 3)]
+        );
+        s.execute("delete from t1 where a = 1;")?;
+        assert_eq!(
+            index_rows(&mut s, "t1")?,
+            vec![index_row("b", 2), index_row("c", 2)]
+        );
+        s.execute("delete from t1;")?;
+        assert_eq!(
+            index_rows(&mut s, "t1")?,
+            vec![index_row("b", 0), index_row("c", 0)]
+        );
+
+        // 没有索引的表返回空结果
+        assert_eq!(index_rows(&mut s, "t2")?, Vec::<Row>::new());
+        assert!(s.execute("show index from t3;").is_err());
+
+        // show table 标出索引列
+        match s.execute("show table t1;")? {
+            ResultSet::TableSchema { schema } => {
+                let indexed = schema
+                    .lines()
+                    .filter(|l| l.contains(" INDEX"))
+                    .map(|l| l.split_whitespace().next().unwrap())
+                    .collect::<Vec<_>>();
+                assert_eq!(indexed, vec!["b", "c"]);
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_residual_condition() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, k int, c int);")?;
+        s.execute("create table t2 (b int primary key, k int, d int);")?;
+        s.execute("insert into t1 values (1, 1, 10), (2, 1, 20), (3, 2, 5), (4, 3, 1);")?;
+        s.execute("insert into t2 values (1, 1, 15), (2, 1, 5), (3, 2, 10), (4, NULL, 0);")?;
+
+        // 等值条件走 HashJoin，其余条件作为剩余条件
+        let sql = "select * from t1 join t2 on t1.k = t2.k and t1.c > t2.d;";
+        let p1 = explain_plan(&mut s, sql)?;
+        assert!(p1.contains("Hash Join( t1.k = t2.k ) Residual( t1.c > t2.d )"));
+        // 不含等值条件时走 NestedLoopJoin，作为对照
+        let baseline = "select * from t1 join t2 on t1.k >= t2.k and t1.k <= t2.k and t1.c > t2.d;";
+        assert!(explain_plan(&mut s, baseline)?.contains("Nested Loop Join"));
+
+        let res = query_rows(&mut s, sql)?;
+        assert_rows_unordered(&s.execute(baseline)?, res.clone());
+        let ints = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Row>();
+        assert_rows_unordered(
+            &s.execute(sql)?,
+            vec![
+                ints(vec![1, 1, 10, 2, 1, 5]),
+                ints(vec![2, 1, 20, 1, 1, 15]),
+                ints(vec![2, 1, 20, 2, 1, 5]),
+            ],
+        );
+
+        // 外连接中剩余条件不满足的行补 NULL，条件写反也一样
+        let sql = "select * from t1 left join t2 on t2.k = t1.k and t2.d < t1.c;";
+        let baseline =
+            "select * from t1 left join t2 on t1.k >= t2.k and t1.k <= t2.k and t1.c > t2.d;";
+        assert!(explain_plan(&mut s, sql)?.contains("Hash Join"));
+        let res = query_rows(&mut s, sql)?;
+        assert_rows_unordered(&s.execute(baseline)?, res.clone());
+        assert_eq!(res.len(), 5);
+        assert!(res.contains(&vec![
+            Value::Integer(3),
+            Value::Integer(2),
+            Value::Integer(5),
+            Value::Null,
+            Value::Null,
+            Value::Null
+        ]));
+
+        // 右连接
+        let sql = "select * from t1 right join t2 on t1.k = t2.k and t1.c > t2.d;";
+        let baseline =
+            "select * from t1 right join t2 on t1.k >= t2.k and t1.k <= t2.k and t1.c > t2.d;";
+        let res = query_rows(&mut s, sql)?;
+        assert_rows_unordered(&s.execute(baseline)?, res);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_multi_column_key() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, x int, y text, c int);")?;
+        s.execute("create table t2 (b int primary key, x int, y text, d int);")?;
+        s.execute(
+            "insert into t1 values (1, 1, 'p', 10), (2, 1, 'q', 20), (3, 2, 'p', 5), (4, NULL, 'p', 1), (5, 2, NULL, 7);",
+        )?;
+        s.execute(
+            "insert into t2 values (1, 1, 'p', 15), (2, 1, 'p', 5), (3, 1, 'q', 30), (4, 2, 'q', 0), (5, NULL, 'p', 2), (6, 2, NULL, 3);",
+        )?;
+
+        let ints = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Row>();
+
+        // 两列的等值比较都作为 HashJoin 的连接键，和 NestedLoopJoin 的结果一致
+        let sql = "select t1.a, t2.b from t1 join t2 on t1.x = t2.x and t2.y = t1.y;";
+        let baseline = "select t1.a, t2.b from t1 join t2 on t1.x >= t2.x and t1.x <= t2.x and t1.y >= t2.y and t1.y <= t2.y;";
+        assert!(explain_plan(&mut s, sql)?.contains("Hash Join( t1.x = t2.x AND t1.y = t2.y )"));
+        assert!(explain_plan(&mut s, baseline)?.contains("Nested Loop Join"));
+        let expected = vec![ints(vec![1, 1]), ints(vec![1, 2]), ints(vec![2, 3])];
+        assert_rows_unordered(&s.execute(sql)?, expected.clone());
+        assert_rows_unordered(&s.execute(baseline)?, expected);
+
+        // 等值条件和不等条件混合：等值的部分作为连接键，其余作为剩余条件
+        let sql =
+            "select t1.a, t2.b from t1 join t2 on t1.x = t2.x and t1.c > t2.d and t1.y = t2.y;";
+        assert!(explain_plan(&mut s, sql)?
+            .contains("Hash Join( t1.x = t2.x AND t1.y = t2.y ) Residual( t1.c > t2.d )"));
+        assert_rows_unordered(&s.execute(sql)?, vec![ints(vec![1, 2])]);
+
+        // <=> 的连接列 NULL 之间互相匹配，= 的连接列为 NULL 时不匹配
+        let sql = "select t1.a, t2.b from t1 join t2 on t1.x <=> t2.x and t1.y = t2.y;";
+        assert!(explain_plan(&mut s, sql)?.contains("Hash Join"));
+        assert_rows_unordered(
+            &s.execute(sql)?,
+            vec![
+                ints(vec![1, 1]),
+                ints(vec![1, 2]),
+                ints(vec![2, 3]),
+                ints(vec![4, 5]),
+            ],
+        );
+
+        // 外连接中匹配不上的行补 NULL
+        let sql = "select t1.a, t2.b from t1 left join t2 on t1.x = t2.x and t1.y = t2.y;";
+        let baseline = "select t1.a, t2.b from t1 left join t2 on t1.x >= t2.x and t1.x <= t2.x and t1.y >= t2.y and t1.y <= t2.y;";
+        let res = query_rows(&mut s, sql)?;
+        assert_rows_unordered(&s.execute(baseline)?, res.clone());
+        assert_eq!(res.len(), 6);
+        assert!(res.contains(&vec![Value::Integer(5), Value::Null]));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_statements() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        s.execute("create table t1 (a int primary key, b int index);")?;
+        s.execute("insert into t1 values (1, 1), (2, 2), (3, 2);")?;
+
+        let begin = |s: &mut Session<KVEngine<DiskEngine>>| -> Result<u64> {
+            let version = match s.execute("begin;")? {
+                ResultSet::Begin { version } => version,
+                _ => unreachable!(),
+            };
+            s.execute("commit;")?;
+            Ok(version)
+        };
+
+        // 100 条无显式事务的只读语句不消耗版本号，也不写入日志
+        let before = begin(&mut s)?;
+        let log_size = std::fs::metadata(&p)?.len();
+        for _ in 0..20 {
+            s.execute("select * from t1 where b = 2;")?;
+            s.execute("show table t1;")?;
+            s.execute("show tables;")?;
+            s.execute("show indexes from t1;")?;
+            s.execute("explain select * from t1;")?;
+        }
+        assert_eq!(std::fs::metadata(&p)?.len(), log_size);
+        let after = begin(&mut s)?;
+        assert_eq!(after, before + 1);
+
+        // select ... for update 需要锁定行，仍然使用普通事务
+        s.execute("select * from t1 for update;")?;
+        assert_eq!(begin(&mut s)?, after + 2);
+
+        // 只读语句能看到之前提交的数据
+        s.execute("update t1 set b = 3 where a = 1;")?;
+        match s.execute("select * from t1 where b = 3;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(3)]])
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_without_semicolon() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        s.execute("create table t1 (a int primary key, b text)")?;
+        s.execute("insert into t1 values (1, 'a'), (2, 'b') -- comment")?;
+        s.execute("begin")?;
+        s.execute("update t1 set b = 'c' where a = 2")?;
+        s.execute("commit")?;
+        assert_eq!(
+            s.execute("select * from t1 where a = 2")?,
+            s.execute("select * from t1 where a = 2;")?
+        );
+        match s.execute("select b from t1 where a = 2")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::String("c".into())]])
+            }
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select * from t1; select * from t1").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_star() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        s.execute("create table t1 (a int primary key, b int, c text);")?;
+        s.execute(
+            "insert into t1 values (1, 1, 'x'), (2, null, 'y'), (3, 1, null), (4, null, null);",
+        )?;
+
+        // count(*) 统计所有行，count(col) 只统计非null的值
+        match s.execute("select count(*), count(b), count(c) as n from t1;")? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["count", "count", "n"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(4),
+                        Value::Integer(2),
+                        Value::Integer(2)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select b, count(*) as n from t1 group by b order by b;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Null, Value::Integer(2)],
+                        vec![Value::Integer(1), Value::Integer(2)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        match s.execute("select count(*) filter (where b = 1) from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+        assert!(s.execute("select *, count(*) from t1;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_output_order() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b varchar, c int);")?;
+        s.execute("create table t2 (x varchar primary key, y int);")?;
+        // 乱序插入
+        s.execute("insert into t1 values (5, 'e', 1), (12, null, 2), (1, 'b', 3), (30, 'a', 4), (7, 'b', 5), (2, null, 6);")?;
+        s.execute("insert into t2 values ('pear', 1), ('apple', 3), ('fig', 7), ('kiwi', 3);")?;
+
+        let values = |rs: ResultSet| match rs {
+            ResultSet::Scan { rows, .. } => rows,
+            _ => unreachable!(),
+        };
+        let ints = |v: &[i64]| v.iter().map(|&i| Value::Integer(i)).collect::<Vec<_>>();
+
+        // 没有 order by 的表扫描按主键升序返回
+        let rows = values(s.execute("select a from t1;")?);
+        assert_eq!(
+            rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>(),
+            ints(&[1, 2, 5, 7, 12, 30])
+        );
+        let rows = values(s.execute("select x from t2 where y > 1;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String("apple".into())],
+                vec![Value::String("fig".into())],
+                vec![Value::String("kiwi".into())],
+            ]
+        );
+
+        // 多次执行，输出顺序保持不变（每次执行 hash map 的随机种子都不同）
+        for _ in 0..20 {
+            // group by 按分组值升序输出，NULL 在最前
+            let rows = values(s.execute("select b, min(c) from t1 group by b;")?);
+            assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Null, Value::Integer(2)],
+                    vec![Value::String("a".into()), Value::Integer(4)],
+                    vec![Value::String("b".into()), Value::Integer(3)],
+                    vec![Value::String("e".into()), Value::Integer(1)],
+                ]
+            );
+
+            // hash join 保持左表的顺序，同一左行的匹配保持右表的顺序
+            let rows = values(s.execute("select a, x from t1 join t2 on c = y;")?);
+            assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::String("apple".into())],
+                    vec![Value::Integer(1), Value::String("kiwi".into())],
+                    vec![Value::Integer(5), Value::String("pear".into())],
+                ]
+            );
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_pads_short_rows() -> Result<()> {
+        use crate::sql::schema::Column;
+        use crate::sql::types::{Collation, DataType};
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 10), (2, 20);")?;
+
+        // 模拟表结构演进：直接改写表结构，在末尾增加两列，已有的行仍然是旧的两列
+        let mut txn = kvengine.begin()?;
+        let mut table = txn.must_get_table("t".into())?;
+        let column = |name: &str, default: Option<Value>| Column {
+            name: name.into(),
+            datatype: DataType::Integer,
+            nullable: true,
+            default,
+            is_primary_key: false,
+            is_index: false,
+            collation: Collation::Binary,
+            precision: None,
+        };
+        let (c, d) = (column("c", Some(Value::Integer(7))), column("d", None));
+        table.columns.push(c);
+        table.columns.push(d);
+        txn.transaction.set(
+            super::Key::Table("t".into()).encode()?,
+            bincode::serialize(&table)?,
+        )?;
+        txn.commit()?;
+        s.execute("insert into t values (3, 30, 8, 9);")?;
+
+        let rows = |rs: ResultSet| match rs {
+            ResultSet::Scan { rows, .. } => rows,
+            _ => unreachable!(),
+        };
+        let row = |v: [Value; 4]| v.to_vec();
+        let (i, null) = (Value::Integer, Value::Null);
+
+        // 全表扫描、主键查询、索引查询读出的旧行都补齐为四列
+        assert_eq!(
+            rows(s.execute("select * from t;")?),
+            vec![
+                row([i(1), i(10), i(7), null.clone()]),
+                row([i(2), i(20), i(7), null.clone()]),
+                row([i(3), i(30), i(8), i(9)]),
+            ]
+        );
+        assert_eq!(
+            rows(s.execute("select * from t where a = 1;")?),
+            vec![row([i(1), i(10), i(7), null.clone()])]
+        );
+        assert_eq!(
+            rows(s.execute("select * from t where b = 20;")?),
+            vec![row([i(2), i(20), i(7), null.clone()])]
+        );
+        assert_eq!(
+            rows(s.execute("select d, a from t where c = 7 order by a desc;")?),
+            vec![vec![null.clone(), i(2)], vec![null.clone(), i(1)]]
+        );
+        assert_eq!(
+            rows(s.execute("select c, count(a) from t group by c;")?),
+            vec![vec![i(7), i(2)], vec![i(8), i(1)]]
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_batch_and_allowed_statements() -> Result<()> {
+        use crate::sql::parser::ast::StatementKind;
+        use std::collections::HashSet;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (name varchar primary key);")?;
+        s.execute("insert into users values ('a');")?;
+
+        // 拼接了用户输入的 sql，execute 只允许一条语句
+        let injected = "select * from users where name = 'a'; drop table users; --'";
+        assert_eq!(
+            s.execute(injected),
+            Err(Error::Parse(
+                "[Parser] Multiple statements are not allowed, use execute_batch to run them"
+                    .into()
+            ))
+        );
+        assert!(s.execute("select * from users;").is_ok());
+
+        // 限制只能执行查询和 dml，drop table 被拒绝，批量中的语句一条都不执行
+        s.set_allowed_statements(Some(HashSet::from([
+            StatementKind::Query,
+            StatementKind::Dml,
+        ])));
+        assert_eq!(
+            s.execute_batch("insert into users values ('b'); drop table users;"),
+            Err(Error::Internal(
+                "[Session] Ddl statements are not allowed in this session".into()
+            ))
+        );
+        assert!(s.execute("drop table users;").is_err());
+        assert!(s.execute("begin;").is_err());
+        match s.execute("select * from users;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+
+        // 取消限制后，execute_batch 依次执行每条语句
+        s.set_allowed_statements(None);
+        let results = s.execute_batch(injected)?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[1],
+            ResultSet::DropTable {
+                table_name: "users".into()
+            }
+        );
+        assert!(s.execute("select * from users;").is_err());
+
+        // 遇到错误停止，之前的语句已经生效
+        assert!(s
+            .execute_batch("create table t (a int primary key); insert into t values (1), (1); create table t2 (a int primary key);")
+            .is_err());
+        match s.execute("show tables;")? {
+            ResultSet::TableNames { names } => assert_eq!(names, vec!["t".to_string()]),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_statements() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key);")?;
+
+        assert!(explain_plan(&mut s, "show table t;")?.ends_with("\nShow Table Schema: t"));
+        assert!(explain_plan(&mut s, "show tables;")?.ends_with("\nShow Table Names"));
+
+        // 事务命令在解析时被拒绝，不影响会话的事务状态
+        assert_eq!(
+            s.execute("explain begin;"),
+            Err(Error::Parse(
+                "[Parser] Cannot EXPLAIN BEGIN statements".into()
+            ))
+        );
+        assert!(s.transaction_version().is_none());
+        s.execute("begin;")?;
+        assert_eq!(
+            s.execute("explain commit;"),
+            Err(Error::Parse(
+                "[Parser] Cannot EXPLAIN COMMIT statements".into()
+            ))
+        );
+        assert!(s.transaction_version().is_some());
+        s.execute("commit;")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_null_and_no_default() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b int not null, c int, d int default null, e int not null default 5);",
+        )?;
+
+        // 不可为空且没有默认值的列，插入时不能省略
+        let no_default = Err(Error::Schema(
+            "[Insert Table] Column \" b \" has no default value".into(),
+        ));
+        assert_eq!(s.execute("insert into t (a) values (1);"), no_default);
+        assert_eq!(s.execute("insert into t values (1);"), no_default);
+        assert_eq!(
+            s.execute("insert into t (a, c, d, e) values (1, 2, 3, 4);"),
+            no_default
+        );
+
+        // 可为空的列不管有没有写 default null，省略时都是 NULL
+        s.execute("insert into t (a, b) values (1, 2);")?;
+        s.execute("insert into t values (2, 3);")?;
+        match s.execute("select * from t;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![
+                        Value::Integer(1),
+                        Value::Integer(2),
+                        Value::Null,
+                        Value::Null,
+                        Value::Integer(5)
+                    ],
+                    vec![
+                        Value::Integer(2),
+                        Value::Integer(3),
+                        Value::Null,
+                        Value::Null,
+                        Value::Integer(5)
+                    ],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 多行插入时每行省略的末尾列同样处理：可为空的列补 NULL，不可为空的列必须给出
+        s.execute("insert into t values (3, 4), (4, 5, 6);")?;
+        assert_eq!(
+            s.execute("insert into t values (5, 6, 7), (6);"),
+            Err(Error::Schema(
+                "[Insert Table] Row 2 of 2: Column \" b \" has no default value".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("insert into t (a, c) values (5, 1), (6, 2);"),
+            Err(Error::Schema(
+                "[Insert Table] Row 1 of 2: Column \" b \" has no default value".into()
+            ))
+        );
+        assert_table(
+            &mut s,
+            "t",
+            "
+            1, 2, NULL, NULL, 5
+            2, 3, NULL, NULL, 5
+            3, 4, NULL, NULL, 5
+            4, 5, 6, NULL, 5
+            ",
+        )?;
+
+        // 显式的 default null 在建表语句中保留，没写 default 的不输出
+        match s.execute("show create table t;")? {
+            ResultSet::ShowCreateTable { sql } => assert_eq!(
+                sql,
+                "CREATE TABLE t (
+  a INTEGER PRIMARY KEY,
+  b INTEGER NOT NULL,
+  c INTEGER NULL,
+  d INTEGER NULL DEFAULT NULL,
+  e INTEGER NOT NULL DEFAULT 5
+);"
+            ),
+            _ => unreachable!(),
+        }
+
+        // 不可为空的列不能以 NULL 作为默认值
+        assert_eq!(
+            s.execute("create table t2 (a int primary key, b int not null default null);"),
+            Err(Error::Schema(
+                "[CreateTable] Failed, column \" b \" is NOT NULL but has DEFAULT NULL in table \" t2 \"".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge_table() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("create table u (a int primary key);")?;
+        s.execute("insert into u values (1);")?;
+
+        // 表还存在时不能清理，事务中也不能清理
+        assert_eq!(
+            s.execute("purge table t;"),
+            Err(Error::Schema(
+                "[Purge Table] Table \" t \" still exists, drop it before purging".into()
+            ))
+        );
+        s.execute("begin;")?;
+        assert_eq!(
+            s.execute("purge table t;"),
+            Err(Error::Internal(
+                "[Exec Purge Table] Cannot purge a table inside a transaction".into()
+            ))
+        );
+        s.execute("commit;")?;
+
+        // s2 在删除表之前开启事务，s3 在删除表的事务提交之前开启事务，两者都还能看到表
+        let mut s2 = kvengine.session()?;
+        s2.execute("begin;")?;
+        s.execute("begin;")?;
+        s.execute("drop table t;")?;
+        let mut s3 = kvengine.session()?;
+        s3.execute("begin;")?;
+        s.execute("commit;")?;
+
+        let count = |s: &mut Session<_>, sql: &str| -> Result<usize> {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                ResultSet::PurgeTable { count, .. } => Ok(count),
+                _ => unreachable!(),
+            }
+        };
+        // 持有旧快照的事务结束前，数据不会被清理
+        assert_eq!(count(&mut s, "purge table t;")?, 0);
+        assert_eq!(count(&mut s2, "select * from t;")?, 3);
+        assert_eq!(count(&mut s3, "select * from t where b = 20;")?, 1);
+        s2.execute("commit;")?;
+        assert_eq!(count(&mut s, "purge table t;")?, 0);
+        assert_eq!(count(&mut s3, "select * from t;")?, 3);
+        s3.execute("commit;")?;
+
+        // 表结构、3 行数据、3 条索引各有创建和删除两个版本
+        let size = std::fs::metadata(&p)?.len();
+        assert_eq!(
+            s.execute("purge table t;")?.to_string(),
+            "PURGE TABLE t, 14 entries reclaimed"
+        );
+        assert!(std::fs::metadata(&p)?.len() < size);
+        assert_eq!(count(&mut s, "purge table t;")?, 0);
+
+        // 其他表不受影响，同名的新表可以正常使用
+        assert_eq!(count(&mut s, "select * from u;")?, 1);
+        s.execute("create table t (a int primary key);")?;
+        assert_eq!(count(&mut s, "select * from t;")?, 0);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_boolean_predicates() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, d boolean);")?;
+        s.execute("insert into t values (1, true), (2, false), (3, null), (4, true);")?;
+
+        let select = |s: &mut Session<_>, sql: &str| -> Result<Vec<Value>> {
+            Ok(query_rows(s, sql)?
+                .into_iter()
+                .map(|r| r[0].clone())
+                .collect())
+        };
+        // NULL 既不满足条件，也不满足取反后的条件
+        for (sql, expected) in [
+            ("select a from t where d;", vec![1, 4]),
+            ("select a from t where d = true;", vec![1, 4]),
+            ("select a from t where d != false;", vec![1, 4]),
+            ("select a from t where not d;", vec![2]),
+            ("select a from t where not d = true;", vec![2]),
+            ("select a from t where not not d;", vec![1, 4]),
+            ("select a from t where not d and a > 1;", vec![2]),
+            ("select a from t where d and not a = 1;", vec![4]),
+        ] {
+            assert_eq!(
+                select(&mut s, sql)?,
+                expected.into_iter().map(Value::Integer).collect::<Vec<_>>(),
+                "{}",
+                sql
+            );
+        }
+
+        // not 只能作用于布尔值
+        assert_eq!(
+            s.execute("select a from t where not a;"),
+            Err(Error::Type(
+                "[Executor] Can not apply NOT to expression 1".into()
+            ))
+        );
+        match s.execute("explain select a from t where not d;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Filter: NOT d"), "{}", plan),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 测试用的日志：只记录开启了捕获的线程产生的日志，每个测试在自己的线程中执行，互不干扰
+    struct CaptureLogger;
+
+    thread_local! {
+        static CAPTURED_LOGS: std::cell::RefCell<Option<Vec<String>>> = const { std::cell::RefCell::new(None) };
+    }
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            CAPTURED_LOGS.with(|logs| {
+                if let Some(logs) = logs.borrow_mut().as_mut() {
+                    logs.push(record.args().to_string());
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        static LOGGER: CaptureLogger = CaptureLogger;
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Debug);
+        });
+        CAPTURED_LOGS.with(|logs| *logs.borrow_mut() = Some(Vec::new()));
+        let res = f();
+        (
+            res,
+            CAPTURED_LOGS.with(|logs| logs.borrow_mut().take().unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_logging_events() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 10), (2, 20);")?;
+
+        // 索引列上的等值查询
+        let (res, logs) = capture_logs(|| s.execute("select * from t where b = 20;"));
+        res?;
+        assert!(logs.contains(&"[Parser] Parse sql: select * from t where b = 20;".to_string()));
+        assert!(logs.contains(&"[Planner] Index scan chosen on t.b".to_string()));
+        assert!(logs.iter().any(|l| l.starts_with("[Planner] Plan chosen:")));
+
+        // 写冲突
+        let mut s2 = kvengine.session()?;
+        s.execute("begin;")?;
+        s2.execute("begin;")?;
+        s.execute("update t set b = 11 where a = 1;")?;
+        let (res, logs) = capture_logs(|| s2.execute("update t set b = 12 where a = 1;"));
+        assert_eq!(res, Err(Error::WriteConflict));
+        let version = s.transaction_version().unwrap();
+        assert!(
+            logs.iter().any(|l| l.starts_with(&format!(
+                "[Mvcc] Write conflict in transaction {} on key",
+                s2.transaction_version().unwrap()
+            )) && l.ends_with(&format!("conflicting version {}", version))),
+            "{:?}",
+            logs
+        );
+        s2.execute("rollback;")?;
+
+        let (res, logs) = capture_logs(|| s.execute("commit;"));
+        res?;
+        assert!(logs.contains(&format!("[Mvcc] Commit transaction {}", version)));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_scalar() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b varchar);")?;
+        s.execute("insert into t values (1, 'x'), (2, 'y');")?;
+
+        assert_eq!(
+            s.query_scalar("select count(*) from t;")?,
+            Value::Integer(2)
+        );
+        assert_eq!(
+            s.query_scalar("select b from t where a = 2;")?,
+            Value::String("y".into())
+        );
+
+        assert_eq!(
+            s.query_scalar("select b from t;"),
+            Err(Error::Internal(
+                "[Session] Scalar query must return exactly one row and one column, got 2 rows and 1 columns".into()
+            ))
+        );
+        assert_eq!(
+            s.query_scalar("select * from t where a = 1;"),
+            Err(Error::Internal(
+                "[Session] Scalar query must return exactly one row and one column, got 1 rows and 2 columns".into()
+            ))
+        );
+        assert_eq!(
+            s.query_scalar("select b from t where a = 3;"),
+            Err(Error::Internal(
+                "[Session] Scalar query must return exactly one row and one column, got 0 rows and 1 columns".into()
+            ))
+        );
+        assert_eq!(
+            s.query_scalar("insert into t values (3, 'z');"),
+            Err(Error::Internal(
+                "[Session] Scalar query must be a query, got result: INSERT 1 rows".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_canonical_keys() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, f float index, g float);")?;
+        // 写入时按列类型规整：浮点数列中的整数存为浮点数，整数列中的 3.0 存为整数
+        s.execute("insert into t values (1, 2, 2), (2.0, 2.0, 2.5);")?;
+
+        // 模拟规整之前写入的历史数据：浮点数列中存的是整数，索引的key也是整数
+        let mut txn = kvengine.begin()?;
+        let row = vec![Value::Integer(3), Value::Integer(2), Value::Integer(2)];
+        txn.transaction.set(
+            super::Key::Row("t".into(), Value::Integer(3)).encode()?,
+            bincode::serialize(&row)?,
+        )?;
+        txn.save_index("t", "f", &Value::Integer(2), [Value::Integer(3)].into())?;
+        txn.commit()?;
+
+        assert_eq!(
+            query_rows(&mut s, "select * from t where a = 1;")?,
+            vec![vec![
+                Value::Integer(1),
+                Value::Float(2.0),
+                Value::Float(2.0)
+            ]]
+        );
+
+        // 索引查找时两种形式都能找到
+        for sql in [
+            "select a from t where f = 2;",
+            "select a from t where f = 2.0;",
+        ] {
+            assert_eq!(
+                query_rows(&mut s, sql)?,
+                vec![
+                    vec![Value::Integer(1)],
+                    vec![Value::Integer(2)],
+                    vec![Value::Integer(3)]
+                ],
+                "{}",
+                sql
+            );
+        }
+
+        // 2 和 2.0 归入同一组，输出组内第一行的值
+        assert_eq!(
+            query_rows(&mut s, "select g, count(a) from t group by g;")?,
+            vec![
+                vec![Value::Float(2.0), Value::Integer(2)],
+                vec![Value::Float(2.5), Value::Integer(1)],
+            ]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select f, count(a) from t group by f;")?,
+            vec![vec![Value::Float(2.0), Value::Integer(3)]]
+        );
+
+        // 更新、删除历史数据时，以旧形式存储的索引也会被清理
+        s.execute("update t set f = 5 where a = 3;")?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where f = 2;")?,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select a from t where f = 5;")?,
+            vec![vec![Value::Integer(3)]]
+        );
+        s.execute("delete from t where a = 2;")?;
+        assert_eq!(
+            query_rows(&mut s, "select a from t where f = 2;")?,
+            vec![vec![Value::Integer(1)]]
+        );
+
+        // 浮点数主键按规范形式查找
+        s.execute("create table p (k float primary key);")?;
+        s.execute("insert into p values (2);")?;
+        assert_eq!(
+            query_rows(&mut s, "select * from p where k = 2;")?,
+            vec![vec![Value::Float(2.0)]]
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_empty_table() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+
+        // 没有 group by 时总是输出一行
+        assert_eq!(
+            s.execute("select count(a), count(*), sum(b), min(b), max(b), avg(b) from t;")?,
+            ResultSet::Scan {
+                columns: vec![
+                    "count".into(),
+                    "count".into(),
+                    "sum".into(),
+                    "min".into(),
+                    "max".into(),
+                    "avg".into()
+                ],
+                rows: vec![vec![
+                    Value::Integer(0),
+                    Value::Integer(0),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                    Value::Null
+                ]],
+            }
+        );
+        // 有 group by 时空表没有任何分组
+        assert_eq!(
+            s.execute("select count(a) as n, a from t group by a;")?,
+            ResultSet::Scan {
+                columns: vec!["n".into(), "a".into()],
+                rows: vec![],
+            }
+        );
+
+        // 没有 group by 时不能单独选择列，不论表中有没有数据
+        for i in 0..2 {
+            assert_eq!(
+                s.execute("select count(a), a from t;"),
+                Err(Error::Internal(
+                    "[Executor] Column a must appear in GROUP BY or Aggregate function".into()
+                ))
+            );
+            s.execute(&format!("insert into t values ({}, 1);", i))?;
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_builder() -> Result<()> {
+        use crate::row;
+        use crate::sql::schema::{ColumnBuilder, RowBuilder, TableBuilder};
+        use crate::sql::types::Collation;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table parsed (
+                id int primary key index,
+                name varchar not null default 'anon',
+                email varchar collate nocase index,
+                score float default null,
+                price decimal(5, 2) default 1.23456,
+                active boolean not null
+            ) comment 'users';",
+        )?;
+
+        // 和解析建表语句得到的表结构完全一致
+        let columns = || {
+            [
+                ColumnBuilder::int("id").primary_key().index(),
+                ColumnBuilder::text("name").not_null().default("anon"),
+                ColumnBuilder::text("email")
+                    .collate(Collation::NoCase)
+                    .index(),
+                ColumnBuilder::float("score").default(None::<f64>),
+                ColumnBuilder::decimal("price", 5, 2).default(1.23456),
+                ColumnBuilder::boolean("active").not_null(),
+            ]
+        };
+        let mut builder = TableBuilder::new("built").comment("users");
+        for column in columns() {
+            builder = builder.column(column);
+        }
+        let mut table = builder.build()?;
+        let mut txn = kvengine.begin()?;
+        let parsed = txn.must_get_table("parsed".into())?;
+        table.name = "parsed".into();
+        assert_eq!(table, parsed);
+
+        // 构造的表可以直接建表
+        table.name = "built".into();
+        txn.create_table(table)?;
+        let table = txn.must_get_table("built".into())?;
+        let row = RowBuilder::new(&table)
+            .set("id", 1)
+            .set("email", "A@x.com")
+            .set("active", true)
+            .build()?;
+        assert_eq!(row, row![1, "anon", "A@x.com", None::<f64>, 1.23, true]);
+        txn.create_row("built".into(), row)?;
+        txn.commit()?;
+        assert_eq!(
+            s.execute("select id, price from built where email = 'a@X.com';")?,
+            ResultSet::Scan {
+                columns: vec!["id".into(), "price".into()],
+                rows: vec![row![1, 1.23]],
+            }
+        );
+
+        // 构造时按建表的规则校验
+        assert_eq!(
+            TableBuilder::new("t")
+                .column(ColumnBuilder::int("a"))
+                .build(),
+            Err(Error::Schema(
+                "[CreateTable] Failed, Table \" t \" has no primary key".into()
+            ))
+        );
+        assert_eq!(
+            TableBuilder::new("t")
+                .column(ColumnBuilder::int("a").primary_key())
+                .column(ColumnBuilder::int("b").not_null().default(None::<i64>))
+                .build(),
+            Err(Error::Schema(
+                "[CreateTable] Failed, column \" b \" is NOT NULL but has DEFAULT NULL in table \" t \"".into()
+            ))
+        );
+
+        // 按列的定义检查每个值
+        for (builder, err) in [
+            (
+                RowBuilder::new(&table).set("id", 2).set("active", "yes"),
+                Error::Type("[Insert Table] Column \" active \" mismatched data type".into()),
+            ),
+            (
+                RowBuilder::new(&table).set("id", 2),
+                Error::Schema("[Insert Table] Column \" active \" has no default value".into()),
+            ),
+            (
+                RowBuilder::new(&table)
+                    .set("id", 2)
+                    .set("active", None::<bool>),
+                Error::Schema(
+                    "[Insert Table] Column \" active \" BOOLEAN NOT NULL in table \" built \" cannot be null"
+                        .into(),
+                ),
+            ),
+            (
+                RowBuilder::new(&table).set("idx", 2),
+                Error::NotFound(
+                    "[Insert Table] Column \" idx \" does not exist in table \" built \"".into(),
+                ),
+            ),
+        ] {
+            assert_eq!(builder.build(), Err(err));
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_empty_groups() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("create table u (a int primary key);")?;
+
+        assert_eq!(
+            query_rows(&mut s, "select count(*) from t;")?,
+            vec![vec![Value::Integer(0)]]
+        );
+        for round in 0..2 {
+            // 不分组的聚集总是输出一行，不论输入是空表、被条件全部过滤还是连接后为空
+            for sql in [
+                "select count(*) from t where b > 100;",
+                "select count(*) from t join u on t.a = u.a;",
+            ] {
+                assert_eq!(
+                    query_rows(&mut s, sql)?,
+                    vec![vec![Value::Integer(0)]],
+                    "{}",
+                    sql
+                );
+            }
+            assert_eq!(
+                query_rows(&mut s, "select count(*), sum(b) from t where b > 100;")?,
+                vec![vec![Value::Integer(0), Value::Null]]
+            );
+            // 分组的聚集在没有输入时没有任何一组
+            assert_eq!(
+                s.execute("select b, count(*) from t where b > 100 group by b;")?,
+                ResultSet::Scan {
+                    columns: vec!["b".into(), "count".into()],
+                    rows: vec![],
+                }
+            );
+            // 第二轮：表中有数据，但都不满足条件
+            if round == 0 {
+                s.execute("insert into t values (1, 1), (2, 2);")?;
+            }
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_string_concat() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, first_name text, last_name text);")?;
+        s.execute(
+            "insert into users values (1, 'ada', 'lovelace'), (2, 'alan', 'turing'), (3, 'grace', null);",
+        )?;
+
+        // 投影中的拼接和别名，没有别名时以表达式作为列名
+        match s.execute(
+            "select first_name || ' ' || last_name as full_name, concat(last_name, ',', first_name) from users;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns[0], "full_name");
+                assert_eq!(columns[1], "last_name || , || first_name");
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![
+                            Value::String("ada lovelace".into()),
+                            Value::String("lovelace,ada".into())
+                        ],
+                        vec![
+                            Value::String("alan turing".into()),
+                            Value::String("turing,alan".into())
+                        ],
+                        // 任意一侧为 NULL 时结果为 NULL
+                        vec![Value::Null, Value::Null],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // where 中和常量比较，order by 拼接表达式或者其别名
+        assert_eq!(
+            s.query_scalar(
+                "select id from users where first_name || last_name = 'alan' || 'turing';"
+            )?,
+            Value::Integer(2)
+        );
+        for sql in [
+            "select id from users order by last_name || first_name desc;",
+            "select id, last_name || first_name as k from users order by k desc;",
+        ] {
+            match s.execute(sql)? {
+                ResultSet::Scan { rows, .. } => assert_eq!(
+                    rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>(),
+                    vec![Value::Integer(2), Value::Integer(1), Value::Integer(3)],
+                    "{}",
+                    sql
+                ),
+                _ => unreachable!(),
+            }
+        }
+
+        // 只能拼接字符串
+        assert_eq!(
+            s.execute("select 1 || 'a' from users;"),
+            Err(Error::Type(
+                "[Executor] Can not concatenate expression 1 and a".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("select id from users where id || 'a' = '1a';"),
+            Err(Error::Type(
+                "[Executor] Can not concatenate expression 1 and a".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_identifiers() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        // 反引号括起来的标识符可以包含空格、大写字母、反引号，或者和关键字同名
+        s.execute(
+            "create table `my table` (`first name` string primary key, `Order` int, `a``b` int default 1, c int);",
+        )?;
+        s.execute("insert into `my table` (`first name`, `Order`) values ('ada', 2);")?;
+        assert_eq!(
+            s.query_scalar("select `Order` from `my table` where `first name` = 'ada';")?,
+            Value::Integer(2)
+        );
+
+        // 导出的建表语句中需要的标识符加上反引号，可以重新执行
+        let sql = match s.execute("show create table `my table`;")? {
+            ResultSet::ShowCreateTable { sql } => sql,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            sql,
+            "CREATE TABLE `my table` (
+  `first name` STRING PRIMARY KEY,
+  `Order` INTEGER NULL,
+  `a``b` INTEGER NULL DEFAULT 1,
+  c INTEGER NULL
+);"
+        );
+        let table = kvengine.begin()?.must_get_table("my table".into())?;
+        s.execute("drop table `my table`;")?;
+        s.execute(&sql)?;
+        assert_eq!(kvengine.begin()?.must_get_table("my table".into())?, table);
+
+        // 报错信息中的标识符同样加上反引号
+        assert_eq!(
+            s.execute("create table `No Key` (a int);"),
+            Err(Error::Schema(
+                "[CreateTable] Failed, Table \" `No Key` \" has no primary key".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("select * from `Other`;"),
+            Err(Error::NotFound(
+                "[Get Table] Table \" `Other` \" does not exist".into()
+            ))
+        );
+        assert!(s.execute("select * from `my table;").is_err());
+        assert!(s.execute("select * from ``;").is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_periodic_flush() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        let status = |s: &mut Session<_>| query_rows(s, "show status;");
+        assert_eq!(
+            status(&mut s)?[..2],
+            vec![
+                vec![Value::from("sync_count"), Value::Integer(0)],
+                vec![Value::from("last_sync"), Value::Null],
+            ]
+        );
+
+        // 后台线程定时刷盘
+        s.execute("create table t (a int primary key);")?;
+        let flush = kvengine.start_periodic_flush(std::time::Duration::from_millis(5));
+        let start = std::time::Instant::now();
+        while kvengine.sync_stats()?.count < 2 {
+            assert!(start.elapsed().as_secs() < 10, "periodic flush did not run");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        // 停止时最后刷一次盘，之后不再刷盘
+        drop(flush);
+        let count = kvengine.sync_stats()?.count;
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        assert_eq!(kvengine.sync_stats()?.count, count);
+
+        let rows = status(&mut s)?;
+        assert_eq!(rows[0][1], Value::Integer(count as i64));
+        assert!(matches!(rows[1][1], Value::Integer(t) if t > 0));
+        // status 不是保留字
+        s.execute("create table status (status int primary key);")?;
+        s.execute("insert into status values (1);")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_databases() -> Result<()> {
+        let dir = tempfile::tempdir()?.into_path();
+        let open_databases =
+            || Databases::new(dir.clone(), |path| KVEngine::new(DiskEngine::new(path)?));
+        let databases = open_databases();
+        databases.get_or_create(DEFAULT_DATABASE)?;
+        let mut s = databases.session(DEFAULT_DATABASE)?;
+        assert_eq!(s.database(), Some(DEFAULT_DATABASE));
+
+        s.execute("create database db1;")?;
+        s.execute("create database db2;")?;
+        assert_eq!(
+            s.execute("show databases;")?,
+            ResultSet::DatabaseNames {
+                names: vec!["db1".into(), "db2".into(), "default".into()]
+            }
+        );
+
+        // 各个数据库中的表互相隔离，同名的表可以同时存在
+        s.execute("use db1;")?;
+        assert_eq!(s.database(), Some("db1"));
+        s.execute("create table t (a int primary key);")?;
+        s.execute("insert into t values (1);")?;
+        s.execute("use database db2;")?;
+        assert_eq!(
+            s.execute("select * from t;"),
+            Err(Error::NotFound(
+                "[Get Table] Table \" t \" does not exist".into()
+            ))
+        );
+        s.execute("create table t (a int primary key);")?;
+        s.execute("insert into t values (2);")?;
+        s.execute("create table only_db2 (a int primary key);")?;
+
+        // 另一个会话看到的是同一份数据
+        let mut s2 = databases.session("db1")?;
+        assert_eq!(s2.query_scalar("select a from t;")?, Value::Integer(1));
+        assert_eq!(s.query_scalar("select a from t;")?, Value::Integer(2));
+        assert_eq!(
+            s2.execute("show tables;")?,
+            ResultSet::TableNames {
+                names: vec!["t".into()]
+            }
+        );
+
+        // 不能重复创建，不能切换到不存在的数据库，也不能在事务中切换
+        assert_eq!(
+            s.execute("create database db1;"),
+            Err(Error::Schema(
+                "[Create Database] Database \" db1 \" already exists".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("use db3;"),
+            Err(Error::NotFound(
+                "[Use Database] Database \" db3 \" does not exist".into()
+            ))
+        );
+        assert!(s.execute("create database `../db`;").is_err());
+        s.execute("begin;")?;
+        assert!(s.execute("use db1;").is_err());
+        s.execute("rollback;")?;
+        assert_eq!(s.database(), Some("db2"));
+
+        // 不是通过 Databases 创建的会话不能切换数据库
+        let p = dir.join("single").join("sqldb-log");
+        let mut single = KVEngine::new(DiskEngine::new(p)?)?.session()?;
+        assert!(single.execute("use db1;").is_err());
+        drop(single);
+
+        // 重新打开存储目录，数据仍然在各自的数据库中
+        drop((s, s2, databases));
+        let databases = open_databases();
+        let mut s = databases.session("db2")?;
+        assert_eq!(s.query_scalar("select a from t;")?, Value::Integer(2));
+        s.execute("use db1;")?;
+        assert_eq!(s.query_scalar("select a from t;")?, Value::Integer(1));
+        assert!(s.execute("select * from only_db2;").is_err());
+
+        drop((s, databases));
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_prefix_encoding() -> Result<()> {
+        use super::{Key, PrefixKey};
+        use crate::storage::keyencode::{serialize_key, serialize_key_prefix};
+        use crate::storage::mvcc::{MvccKey, MvccKeyPrefix};
+
+        // 空表名在编码时报错
+        let empty = Err(Error::Schema(
+            "[KVEngine] Table name cannot be empty".into(),
+        ));
+        assert_eq!(Key::Table("".into()).encode(), empty);
+        assert_eq!(Key::Row("".into(), Value::Integer(1)).encode(), empty);
+        assert_eq!(PrefixKey::Row("".into()).encode(), empty);
+        assert_eq!(PrefixKey::Index("".into(), "a".into()).encode(), empty);
+
+        // 单个字符、互为前缀、包含 0 和 0xFF 字节（ÿ 编码为 C3 BF）的表名
+        let tables = ["t", "t2", "a", "t\0", "ÿ", "ÿÿ", "\0"];
+        let values = [
+            Value::Null,
+            Value::Integer(0),
+            Value::Integer(-1),
+            Value::String("".into()),
+            Value::String("ÿ\0".into()),
+        ];
+        for table in tables {
+            let row_prefix = PrefixKey::Row(table.into()).encode()?;
+            let index_prefix =
+                serialize_key_prefix(&PrefixKey::Index(table.into(), String::new()))?;
+            let mvcc_row_prefix =
+                serialize_key_prefix(&MvccKeyPrefix::Version(row_prefix.clone()))?;
+            for other in tables {
+                for value in values.iter() {
+                    let row = Key::Row(other.into(), value.clone()).encode()?;
+                    let index = Key::Index(other.into(), "a".into(), value.clone()).encode()?;
+                    let mvcc_row = serialize_key(&MvccKey::Version(row.clone(), 3))?;
+                    // 只有本表的key以本表的前缀开头
+                    assert_eq!(row.starts_with(&row_prefix), table == other);
+                    assert_eq!(index.starts_with(&index_prefix), table == other);
+                    assert_eq!(mvcc_row.starts_with(&mvcc_row_prefix), table == other);
+                    // 行和索引的前缀不会互相匹配
+                    assert!(!index.starts_with(&row_prefix));
+                    assert!(!row.starts_with(&index_prefix));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_cache() -> Result<()> {
+        use crate::row;
+        use crate::sql::schema::{ColumnBuilder, TableBuilder};
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int);")?;
+
+        // 事务内删表后重新建表，缓存中不能留下旧的表结构
+        s.execute("begin;")?;
+        s.execute("insert into t values (1, 1);")?;
+        s.execute("drop table t;")?;
+        assert_eq!(
+            s.execute("select * from t;"),
+            Err(Error::NotFound(
+                "[Get Table] Table \" t \" does not exist".into()
+            ))
+        );
+        s.execute("create table t (a int primary key, c text, d int default 3);")?;
+        s.execute("insert into t (a, c) values (2, 'x');")?;
+        assert_eq!(
+            s.execute("select * from t;")?,
+            ResultSet::Scan {
+                columns: vec!["a".into(), "c".into(), "d".into()],
+                rows: vec![row![2, "x", 3]],
+            }
+        );
+        s.execute("rollback;")?;
+
+        // 回滚后缓存随事务一起丢弃，看到的还是原来的表
+        assert_eq!(
+            s.execute("select * from t;")?,
+            ResultSet::Scan {
+                columns: vec!["a".into(), "b".into()],
+                rows: vec![],
+            }
+        );
+
+        // 直接通过事务接口：建表后立即可见，删表后不可见
+        let mut txn = kvengine.begin()?;
+        let table = TableBuilder::new("u")
+            .column(ColumnBuilder::int("id").primary_key())
+            .build()?;
+        assert_eq!(txn.get_table("u".into())?, None);
+        txn.create_table(table.clone())?;
+        assert_eq!(txn.must_get_table("u".into())?, table);
+        assert_eq!(txn.must_get_table("u".into())?, table);
+        txn.drop_table("u".into())?;
+        assert_eq!(txn.get_table("u".into())?, None);
+        txn.commit()?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_cache_savepoint() -> Result<()> {
+        use crate::row;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        // 回滚到保存点后，保存点之后建的表不能再从缓存中读到
+        s.execute("begin;")?;
+        s.execute("savepoint s;")?;
+        s.execute("create table t (a int primary key);")?;
+        s.execute("rollback to s;")?;
+        assert!(s.execute("insert into t values (2);").is_err());
+        s.execute("commit;")?;
+        assert_eq!(
+            s.execute("select * from t;"),
+            Err(Error::NotFound(
+                "[Get Table] Table \" t \" does not exist".into()
+            ))
+        );
+
+        // 保存点之后删掉的表，回滚后重新可见
+        s.execute("create table u (a int primary key, b int);")?;
+        s.execute("begin;")?;
+        s.execute("insert into u values (1, 1);")?;
+        s.execute("savepoint s;")?;
+        s.execute("drop table u;")?;
+        s.execute("rollback to s;")?;
+        s.execute("insert into u values (2, 2);")?;
+        s.execute("commit;")?;
+        assert_eq!(
+            s.execute("select * from u;")?,
+            ResultSet::Scan {
+                columns: vec!["a".into(), "b".into()],
+                rows: vec![row![1, 1], row![2, 2]],
+            }
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_test_util() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c float, d bool index);")?;
+
+        // 直接写入行，索引同样会被维护
+        populate(
+            &mut s,
+            "t",
+            vec![
+                vec![1.into(), "x".into(), 1.5.into(), true.into()],
+                vec![2.into(), "a, b".into(), Value::Null, false.into()],
+                vec![3.into(), "NULL".into(), 0.25.into(), true.into()],
+                vec![4.into(), " it's ".into(), (-2.0).into(), Value::Null],
+            ],
+        )?;
+        assert_rows_unordered(
+            &s.execute("select a from t where d = true;")?,
+            vec![vec![3.into()], vec![1.into()]],
+        );
+        // 主键冲突时整批回滚
+        assert!(populate(
+            &mut s,
+            "t",
+            vec![
+                vec![5.into(), "y".into(), Value::Null, Value::Null],
+                vec![1.into(), "y".into(), Value::Null, Value::Null]
+            ]
+        )
+        .is_err());
+
+        assert_table(
+            &mut s,
+            "t",
+            "
+            4, ' it''s ', -2.0, NULL
+            3, 'NULL', 0.25, TRUE
+            2, 'a, b', null, false
+
+            1, x, 1.5, true
+            ",
+        )?;
+
+        // 显式事务中写入当前事务
+        s.execute("begin;")?;
+        populate(
+            &mut s,
+            "t",
+            vec![vec![6.into(), Value::Null, Value::Null, Value::Null]],
+        )?;
+        s.execute("rollback;")?;
+        assert_eq!(
+            s.query_scalar("select count(*) from t;")?,
+            Value::Integer(4)
+        );
+
+        // 不一致时列出缺少和多出的行
+        let res = s.execute("select a, b from t where a < 3;")?;
+        let err = std::panic::catch_unwind(|| {
+            assert_rows_unordered(
+                &res,
+                vec![vec![1.into(), "x".into()], vec![1.into(), "x".into()]],
+            )
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<String>().unwrap(),
+            "rows mismatch: got 2 rows, expected 2 rows\n\
+             missing (expected but not returned):\n  (1, 'x')\n\
+             unexpected (returned but not expected):\n  (2, 'a, b')\n"
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_list() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text collate nocase);")?;
+        s.execute("insert into t values (1, 10, 'X'), (2, NULL, 'y'), (3, 30, NULL);")?;
+
+        let ids = |s: &mut Session<_>, condition: &str| -> Result<Vec<Value>> {
+            Ok(query_rows(
+                s,
+                &format!("select a from t where {} order by a;", condition),
+            )?
+            .into_iter()
+            .map(|r| r[0].clone())
+            .collect())
+        };
+        let i = |v: i64| Value::Integer(v);
+        for (condition, expected) in [
+            ("a in (1, 3)", vec![i(1), i(3)]),
+            ("a in (3, 3, 4)", vec![i(3)]),
+            ("b in (10.0, 20)", vec![i(1)]),
+            ("c in ('x', 'Y')", vec![i(1), i(2)]),
+            ("a not in (1)", vec![i(2), i(3)]),
+            ("a in (1, 2) and b in (10, 30)", vec![i(1)]),
+            // 左侧为 NULL，或者都不相等但列表中有 NULL，结果为 NULL，两种写法都不满足条件
+            ("b in (10, 20)", vec![i(1)]),
+            ("b not in (10, 20)", vec![i(3)]),
+            ("a in (1, null)", vec![i(1)]),
+            ("a not in (1, null)", vec![]),
+            // 空列表恒为 false，取反恒为 true，左侧为 NULL 时也是如此
+            ("a in ()", vec![]),
+            ("b in ()", vec![]),
+            ("b not in ()", vec![i(1), i(2), i(3)]),
+        ] {
+            assert_eq!(ids(&mut s, condition)?, expected, "{}", condition);
+        }
+
+        // 空列表在计划时折叠，不需要扫描
+        match s.execute("explain select * from t where a in ();")? {
+            ResultSet::Explain { plan } => assert!(plan.ends_with("\nNothing")),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            s.execute("delete from t where b in ();")?,
+            ResultSet::Delete { count: 0 }
+        );
+        assert_eq!(
+            s.execute("update t set b = 0 where a in (2, 3);")?,
+            ResultSet::Update { count: 2 }
+        );
+        assert_eq!(
+            s.execute("select a from t where a in (1, 'x');"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression 2 and x of column a".into()
+            ))
+        );
+
+        // 空的值列表在解析时报错
+        assert_eq!(
+            s.execute("insert into t values ();"),
+            Err(Error::Parse(
+                "[Parser] Empty value list in VALUES, each row needs at least one value".into()
+            ))
+        );
+        assert_eq!(
+            s.query_scalar("select count(*) from t;")?,
+            Value::Integer(3)
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_own_ddl_and_dml() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        let not_exist = || {
+            Err(Error::NotFound(
+                "[Get Table] Table \" t \" does not exist".into(),
+            ))
+        };
+
+        // 同一个事务中建表、写入之后立即可以读到
+        s1.execute("begin;")?;
+        s1.execute("create table t (a int primary key, b text index);")?;
+        s1.execute("insert into t values (1, 'x'), (2, 'y'), (3, 'x');")?;
+        assert_table(&mut s1, "t", "1, x\n2, y\n3, x")?;
+        s1.execute("update t set b = 'z' where a = 2;")?;
+        s1.execute("delete from t where a = 3;")?;
+        assert_table(&mut s1, "t", "1, x\n2, z")?;
+        assert_rows_unordered(
+            &s1.execute("select a from t where b = 'x';")?,
+            vec![vec![1.into()]],
+        );
+        assert_eq!(
+            s1.query_scalar("select count(*) from t;")?,
+            Value::Integer(2)
+        );
+        assert_eq!(
+            s1.execute("show tables;")?,
+            ResultSet::TableNames {
+                names: vec!["t".into()]
+            }
+        );
+
+        // 其他会话看不到未提交的表和数据
+        assert_eq!(s2.execute("select * from t;"), not_exist());
+        assert_eq!(
+            s2.execute("show tables;")?,
+            ResultSet::TableNames { names: vec![] }
+        );
+        s2.execute("begin;")?;
+        s1.execute("commit;")?;
+        // 提交之前开启的事务仍然看不到
+        assert_eq!(s2.execute("select * from t;"), not_exist());
+        s2.execute("commit;")?;
+        assert_table(&mut s2, "t", "1, x\n2, z")?;
+
+        // 事务中删表之后重新建表，回滚之后原来的表和数据不变
+        s1.execute("begin;")?;
+        s1.execute("drop table t;")?;
+        assert_eq!(s1.execute("select * from t;"), not_exist());
+        s1.execute("create table t (a int primary key);")?;
+        s1.execute("insert into t values (9);")?;
+        assert_table(&mut s1, "t", "9")?;
+        assert_table(&mut s2, "t", "1, x\n2, z")?;
+        s1.execute("rollback;")?;
+        assert_table(&mut s1, "t", "1, x\n2, z")?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_table() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        let checksum =
+            |s: &mut Session<KVEngine<DiskEngine>>, name: &str| -> Result<(usize, u64)> {
+                match s.execute(&format!("checksum table {};", name))? {
+                    ResultSet::ChecksumTable { rows, checksum, .. } => Ok((rows, checksum)),
+                    other => panic!("unexpected result: {:?}", other),
+                }
+            };
+
+        // 两张表的数据相同，插入顺序不同，校验和相同
+        s.execute("create table t1 (a int primary key, b text, c float, d bool);")?;
+        s.execute("create table t2 (a int primary key, b text, c float, d bool);")?;
+        s.execute("insert into t1 values (1, 'x', 1.5, true), (2, null, 0.0, false), (3, 'z', null, null);")?;
+        s.execute("insert into t2 values (3, 'z', null, null);")?;
+        s.execute("insert into t2 values (2, null, 0.0, false), (1, 'x', 1.5, true);")?;
+        let (rows, sum) = checksum(&mut s, "t1")?;
+        assert_eq!(rows, 3);
+        assert_eq!(checksum(&mut s, "t2")?, (3, sum));
+        // 多次计算结果不变
+        assert_eq!(checksum(&mut s, "t1")?, (3, sum));
+
+        // 修改一行之后校验和改变，改回来之后恢复
+        s.execute("update t2 set b = 'y' where a = 1;")?;
+        let (_, changed) = checksum(&mut s, "t2")?;
+        assert_ne!(changed, sum);
+        s.execute("update t2 set b = 'x' where a = 1;")?;
+        assert_eq!(checksum(&mut s, "t2")?, (3, sum));
+
+        // 空值和空字符串、整数和浮点数不会得到相同的校验和
+        s.execute("update t2 set b = '' where a = 2;")?;
+        assert_ne!(checksum(&mut s, "t2")?.1, sum);
+        s.execute("update t2 set b = null, c = 0 where a = 2;")?;
+        assert_eq!(checksum(&mut s, "t2")?, (3, sum));
+
+        // 删除一行之后校验和改变
+        s.execute("delete from t2 where a = 3;")?;
+        assert_ne!(checksum(&mut s, "t2")?, (2, sum));
+
+        // 空表的校验和相同
+        s.execute("create table e1 (a int primary key);")?;
+        s.execute("create table e2 (a int primary key);")?;
+        assert_eq!(checksum(&mut s, "e1")?, checksum(&mut s, "e2")?);
+
+        // 事务中可以看到未提交的修改
+        s.execute("begin;")?;
+        s.execute("delete from t1 where a = 1;")?;
+        assert_eq!(checksum(&mut s, "t1")?.0, 2);
+        s.execute("rollback;")?;
+        assert_eq!(checksum(&mut s, "t1")?, (3, sum));
+
+        assert!(s.execute("checksum table missing;").is_err());
+        match s.execute("explain checksum table t1;")? {
+            ResultSet::Explain { plan } => assert!(plan.ends_with("Checksum Table: t1")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit_offset() -> Result<()> {
+        use crate::row;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');")?;
+
+        // limit 和 offset 的顺序不影响结果
+        let expected = vec![row![2, "b"], row![3, "c"]];
+        assert_eq!(
+            query_rows(&mut s, "select * from t order by a limit 2 offset 1;")?,
+            expected
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t order by a offset 1 limit 2;")?,
+            expected
+        );
+        assert!(s
+            .execute("select * from t offset 1 limit 2 offset 1;")
+            .is_err());
+
+        // limit all、offset 0 不生成节点
+        assert_eq!(query_rows(&mut s, "select * from t limit all;")?.len(), 4);
+        assert_eq!(query_rows(&mut s, "select * from t offset 0;")?.len(), 4);
+        for sql in [
+            "select * from t limit all;",
+            "select * from t offset 0;",
+            "select * from t limit all offset 0;",
+        ] {
+            let plan = explain_plan(&mut s, sql)?;
+            assert!(
+                !plan.contains("Limit") && !plan.contains("Offset"),
+                "{}",
+                plan
+            );
+        }
+        let plan = explain_plan(&mut s, "select * from t offset 0 limit 2;")?;
+        assert!(
+            plan.contains("Limit 2") && !plan.contains("Offset"),
+            "{}",
+            plan
+        );
+
+        // limit 0 不返回任何行
+        assert_eq!(
+            query_rows(&mut s, "select * from t limit 0;")?,
+            Vec::<Row>::new()
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t limit 0 offset 1;")?,
+            Vec::<Row>::new()
+        );
+
+        // offset 超过表的行数时返回空
+        assert_eq!(
+            query_rows(&mut s, "select * from t offset 4;")?,
+            Vec::<Row>::new()
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t order by a offset 100 limit 2;")?,
+            Vec::<Row>::new()
+        );
+        assert_eq!(
+            query_rows(&mut s, "select * from t order by a offset 3 limit 10;")?,
+            vec![row![4, "d"]]
+        );
+
+        // 行数必须是非负整数
+        assert_eq!(
+            s.execute("select * from t limit 1.5;"),
+            Err(Error::Type(
+                "[Planner] LIMIT must be a non-negative integer, got 1.5".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("select * from t offset 'x';"),
+            Err(Error::Type(
+                "[Planner] OFFSET must be a non-negative integer, got x".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_row() -> Result<()> {
+        use crate::row;
+        use crate::sql::parser::ast::StatementKind;
+        use std::collections::HashSet;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c int default 7);")?;
+        s.execute("insert into t (a, b) values (1, 'x'), (2, 'y');")?;
+        s.execute("create table f (a float primary key, b text);")?;
+        s.execute("insert into f values (1.5, 'x'), (3.0, 'y');")?;
+        s.execute("create table n (name text primary key);")?;
+        s.execute("insert into n values ('alice');")?;
+
+        // 命中和未命中，未指定的列补上默认值
+        assert_eq!(s.get_row("t", Value::Integer(2))?, Some(row![2, "y", 7]));
+        assert_eq!(s.get_row("t", Value::Integer(3))?, None);
+        assert_eq!(s.get_row("n", "alice".into())?, Some(row!["alice"]));
+        assert_eq!(s.get_row("n", "Alice".into())?, None);
+
+        // 主键值按列的类型转换
+        assert_eq!(s.get_row("t", Value::Float(1.0))?, Some(row![1, "x", 7]));
+        assert_eq!(s.get_row("t", Value::Float(1.5))?, None);
+        assert_eq!(s.get_row("f", Value::Integer(3))?, Some(row![3.0, "y"]));
+        assert_eq!(s.get_row("f", Value::Float(1.5))?, Some(row![1.5, "x"]));
+        assert_eq!(s.get_row("t", Value::Null)?, None);
+
+        // 事务中可以读到未提交的修改
+        s.execute("begin;")?;
+        s.execute("insert into t (a, b) values (3, 'z');")?;
+        s.execute("delete from t where a = 1;")?;
+        assert_eq!(s.get_row("t", Value::Integer(3))?, Some(row![3, "z", 7]));
+        assert_eq!(s.get_row("t", Value::Integer(1))?, None);
+        s.execute("rollback;")?;
+        assert_eq!(s.get_row("t", Value::Integer(3))?, None);
+        assert_eq!(s.get_row("t", Value::Integer(1))?, Some(row![1, "x", 7]));
+
+        assert_eq!(
+            s.get_row("missing", Value::Integer(1)),
+            Err(Error::NotFound(
+                "[Get Table] Table \" missing \" does not exist".into()
+            ))
+        );
+
+        // 和查询语句一样受会话允许的语句类别限制
+        s.set_allowed_statements(Some(HashSet::from([StatementKind::Dml])));
+        assert!(s.get_row("t", Value::Integer(1)).is_err());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_variants() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text not null, c int);")?;
+        s.execute("insert into t values (1, 'x', 1);")?;
+
+        let variant = |res: Result<ResultSet>| match res {
+            Err(Error::Parse(_)) => "Parse",
+            Err(Error::Schema(_)) => "Schema",
+            Err(Error::Type(_)) => "Type",
+            Err(Error::NotFound(_)) => "NotFound",
+            Err(Error::Storage(_)) => "Storage",
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(res) => panic!("unexpected result {:?}", res),
+        };
+        for (sql, expected) in [
+            ("select * frm t;", "Parse"),
+            ("select * from missing;", "NotFound"),
+            ("select d from t;", "NotFound"),
+            ("select * from t order by d;", "NotFound"),
+            ("select sum(d) from t;", "NotFound"),
+            ("rollback to s1;", "NotFound"),
+            ("create table t (a int primary key);", "Schema"),
+            ("create table u (a int);", "Schema"),
+            ("insert into t values (1, 'y', 1);", "Schema"),
+            ("insert into t (a, c) values (2, 1);", "Schema"),
+            ("insert into t values (2, 1, 1);", "Type"),
+            ("select * from t where b > 1;", "Type"),
+            ("select a || 1 from t;", "Type"),
+            ("select * from t limit 'x';", "Type"),
+        ] {
+            if sql.starts_with("rollback to") {
+                s.execute("begin;")?;
+                assert_eq!(variant(s.execute(sql)), expected, "{}", sql);
+                s.execute("rollback;")?;
+            } else {
+                assert_eq!(variant(s.execute(sql)), expected, "{}", sql);
+            }
+        }
+
+        // 存储文件无法打开时是存储层的错误
+        let err = DiskEngine::new(p.parent().unwrap().to_path_buf()).err();
+        assert!(matches!(err, Some(Error::Storage(_))), "{:?}", err);
+
+        // 错误信息带有类别，客户端可以据此区分
+        assert_eq!(
+            s.execute("select * from missing;").unwrap_err().to_string(),
+            "Not Found: [Get Table] Table \" missing \" does not exist"
+        );
+        assert!(s
+            .execute("insert into t values (1, 'y', 1);")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Schema Error: "));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_safe_equal() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        let i = |v: i64| Value::Integer(v);
+        let null = || Value::Null;
+
+        // 真值表：两侧都为 NULL 时为 true，只有一侧为 NULL 时为 false，否则和 = 相同
+        s.execute("create table p (id int primary key, l int, r float);")?;
+        s.execute(
+            "insert into p values (1, null, null), (2, 1, null), (3, null, 1), (4, 1, 1.0), (5, 1, 2);",
+        )?;
+        let ids = |s: &mut Session<KVEngine<DiskEngine>>, cond: &str| -> Result<Vec<Row>> {
+            query_rows(s, &format!("select id from p where {} order by id;", cond))
+        };
+        let expect = |v: &[i64]| v.iter().map(|&v| vec![i(v)]).collect::<Vec<_>>();
+        assert_eq!(ids(&mut s, "l <=> r")?, expect(&[1, 4]));
+        assert_eq!(ids(&mut s, "r <=> l")?, expect(&[1, 4]));
+        assert_eq!(ids(&mut s, "not l <=> r")?, expect(&[2, 3, 5]));
+        assert_eq!(ids(&mut s, "l = r")?, expect(&[4]));
+        assert_eq!(ids(&mut s, "not l = r")?, expect(&[5]));
+        // 常量之间的比较在计划时折叠
+        assert_eq!(ids(&mut s, "null <=> null")?, expect(&[1, 2, 3, 4, 5]));
+        assert_eq!(ids(&mut s, "1 <=> null")?, expect(&[]));
+        assert_eq!(ids(&mut s, "'a' <=> 'a' and l <=> null")?, expect(&[1, 3]));
+        assert!(s.execute("select * from p where l <=> 'a';").is_err());
+
+        s.execute("create table t1 (a int primary key, x int);")?;
+        s.execute("create table t2 (b int primary key, y int);")?;
+        s.execute("insert into t1 values (1, 10), (2, null), (3, null), (4, 40);")?;
+        s.execute("insert into t2 values (1, 10), (2, null), (3, 30);")?;
+
+        // 过滤条件
+        let res = s.execute("select a from t1 where x <=> null;")?;
+        assert_rows_unordered(&res, vec![vec![i(2)], vec![i(3)]]);
+        let res = s.execute("select a from t1 where x <=> 40;")?;
+        assert_rows_unordered(&res, vec![vec![i(4)]]);
+        let res = s.execute("select a from t1 where not x <=> 10;")?;
+        assert_rows_unordered(&res, vec![vec![i(2)], vec![i(3)], vec![i(4)]]);
+
+        // 连接：= 不匹配 NULL，<=> 时两侧的 NULL 互相匹配
+        let res = s.execute("select a, b from t1 join t2 on x = y;")?;
+        assert_rows_unordered(&res, vec![vec![i(1), i(1)]]);
+        let res = s.execute("select a, b from t1 join t2 on x <=> y;")?;
+        assert_rows_unordered(
+            &res,
+            vec![vec![i(1), i(1)], vec![i(2), i(2)], vec![i(3), i(2)]],
+        );
+        let res = s.execute("select a, b from t1 left join t2 on x <=> y;")?;
+        assert_rows_unordered(
+            &res,
+            vec![
+                vec![i(1), i(1)],
+                vec![i(2), i(2)],
+                vec![i(3), i(2)],
+                vec![i(4), null()],
+            ],
+        );
+        let res = s.execute("select a, b from t1 left join t2 on x = y;")?;
+        assert_rows_unordered(
+            &res,
+            vec![
+                vec![i(1), i(1)],
+                vec![i(2), null()],
+                vec![i(3), null()],
+                vec![i(4), null()],
+            ],
+        );
+        // 嵌套循环连接中作为剩余条件求值，结果相同
+        let res = s.execute("select a, b from t1 join t2 on x <=> y and a >= b;")?;
+        assert_rows_unordered(
+            &res,
+            vec![vec![i(1), i(1)], vec![i(2), i(2)], vec![i(3), i(2)]],
+        );
+        match s.execute("explain select * from t1 join t2 on y <=> x;")? {
+            ResultSet::Explain { plan } => assert!(plan.contains("Hash Join"), "{}", plan),
+            _ => unreachable!(),
+        }
+
+        // 去重：找出 x 的值（包括 NULL）和其他行相同的行
+        s.execute("create table t3 (c int primary key, z int);")?;
+        s.execute("insert into t3 values (1, 10), (2, null), (3, null), (4, 40);")?;
+        let res = s.execute("select a, c from t1 join t3 on x <=> z where a < c;")?;
+        assert_rows_unordered(&res, vec![vec![i(2), i(3)]]);
+        let res = s.execute("select a, c from t1 join t3 on x = z where a < c;")?;
+        assert_rows_unordered(&res, vec![]);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by_position() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c int);")?;
+        s.execute(
+            "insert into t values (1, 'x', 10), (2, 'y', 20), (3, 'x', 30), (4, null, 40), (5, 'y', 5);",
+        )?;
+
+        // 按位置分组和按列名分组的结果相同，位置指向带别名的列时同样解析为原列
+        for (by_position, by_name) in [
+            (
+                "select b, count(a), sum(c) from t group by 1 order by b;",
+                "select b, count(a), sum(c) from t group by b order by b;",
+            ),
+            (
+                "select max(c), b from t group by 2 order by b;",
+                "select max(c), b from t group by b order by b;",
+            ),
+            (
+                "select b as k, min(c) from t group by 1 order by k;",
+                "select b as k, min(c) from t group by b order by k;",
+            ),
+        ] {
+            let expected = s.execute(by_name)?;
+            match &expected {
+                ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 3),
+                _ => unreachable!(),
+            }
+            assert_eq!(s.execute(by_position)?, expected);
+        }
+
+        // 位置超出select列表、指向聚集函数或者不是整数时报错
+        for sql in [
+            "select b, count(a) from t group by 3;",
+            "select b, count(a) from t group by 0;",
+            "select * from t group by 1;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::NotFound(_))), "{}", sql);
+        }
+        assert!(matches!(
+            s.execute("select count(a), b from t group by 1;"),
+            Err(Error::Internal(_))
+        ));
+        assert!(matches!(
+            s.execute("select b, count(a) from t group by 1.5;"),
+            Err(Error::Parse(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_mixed_directions() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c text, d float);")?;
+        s.execute(
+            "insert into t values
+                 (1, 2, 'x', 1.5), (2, 1, 'y', null), (3, 2, 'y', 0.5),
+                 (4, null, 'x', 2.5), (6, 2, null, 1.5);",
+        )?;
+        // NaN 和其他数字不可比较，sql 中写不出来，直接写入
+        populate(
+            &mut s,
+            "t",
+            vec![
+                vec![5.into(), 1.into(), "x".into(), f64::NAN.into()],
+                vec![7.into(), Value::Null, "y".into(), f64::NAN.into()],
+            ],
+        )?;
+
+        let ids = |s: &mut Session<KVEngine<DiskEngine>>, order_by: &str| -> Result<Vec<i64>> {
+            Ok(
+                query_rows(s, &format!("select a from t order by {};", order_by))?
+                    .into_iter()
+                    .map(|row| match row[0] {
+                        Value::Integer(a) => a,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            )
+        };
+        // NULL 比其他值小，升序时排在最前面，降序时排在最后面
+        for (order_by, expected) in [
+            ("b asc, a desc", vec![7, 4, 5, 2, 6, 3, 1]),
+            ("b desc, a", vec![1, 3, 6, 2, 5, 4, 7]),
+            ("b desc, c asc, a asc", vec![6, 1, 3, 5, 2, 4, 7]),
+            ("c desc, b desc, a desc", vec![3, 2, 7, 1, 5, 4, 6]),
+            ("c, b, a desc", vec![6, 4, 5, 1, 7, 2, 3]),
+            // NaN 排在其他数字之后，相同的 NaN 之间按后面的排序列排序
+            ("d asc, a desc", vec![2, 3, 6, 1, 4, 7, 5]),
+            ("d desc, a asc", vec![5, 7, 4, 1, 6, 3, 2]),
+            ("d desc, b desc, c", vec![5, 7, 4, 6, 1, 3, 2]),
+        ] {
+            assert_eq!(ids(&mut s, order_by)?, expected, "order by {}", order_by);
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_result_types() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c float);")?;
+        s.execute("insert into t values (1, 4, 4.0), (2, null, 1.5), (3, 1, null), (4, 7, 0.5);")?;
+
+        match s.execute(
+            "select count(b), sum(b), min(b), max(b), avg(b), sum(c), min(c), avg(c) from t;",
+        )? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![
+                    Value::Integer(3),
+                    Value::Integer(12),
+                    Value::Integer(1),
+                    Value::Integer(7),
+                    Value::Float(4.0),
+                    Value::Float(6.0),
+                    Value::Float(0.5),
+                    Value::Float(2.0),
+                ]]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 整数之和超出范围时报错
+        s.execute(&format!("insert into t values (5, {}, 0.0);", i64::MAX))?;
+        assert!(matches!(
+            s.execute("select sum(b) from t;"),
+            Err(Error::Type(_))
+        ));
+        assert_eq!(
+            s.query_scalar("select sum(b) from t where a >= 5;")?,
+            Value::Integer(i64::MAX)
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by_primary_key_scan() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (name text primary key, score int);")?;
+        s.execute("create table n (id int primary key, v int);")?;
+        s.execute(
+            "insert into t values ('kiwi', 3), ('apple', 1), ('pear', 4), ('fig', 2), ('banana', 5);",
+        )?;
+        s.execute("insert into n values (3, 1), (10, 3), (0, 4);")?;
+        populate(&mut s, "n", vec![vec![(-2).into(), 2.into()]])?;
+
+        let first = |s: &mut Session<KVEngine<DiskEngine>>, sql: &str| -> Result<Vec<Value>> {
+            Ok(query_rows(s, sql)?
+                .into_iter()
+                .map(|r| r[0].clone())
+                .collect())
+        };
+        let strs = |v: &[&str]| {
+            v.iter()
+                .map(|&s| Value::String(s.into()))
+                .collect::<Vec<_>>()
+        };
+
+        // 按主键降序反向扫描，按主键升序直接扫描，都不需要排序节点
+        for (sql, expected) in [
+            (
+                "select name from t order by name desc;",
+                strs(&["pear", "kiwi", "fig", "banana", "apple"]),
+            ),
+            (
+                "select name from t order by t.name desc, score asc;",
+                strs(&["pear", "kiwi", "fig", "banana", "apple"]),
+            ),
+            (
+                "select name as k from t where score > 1 order by k desc limit 3;",
+                strs(&["pear", "kiwi", "fig"]),
+            ),
+            (
+                "select name from t order by name;",
+                strs(&["apple", "banana", "fig", "kiwi", "pear"]),
+            ),
+            // 整数主键的编码保序，负数排在正数之前
+            (
+                "select id from n order by id desc;",
+                vec![10.into(), 3.into(), 0.into(), (-2).into()],
+            ),
+            (
+                "select id from n order by id;",
+                vec![(-2).into(), 0.into(), 3.into(), 10.into()],
+            ),
+        ] {
+            let plan = explain_plan(&mut s, sql)?;
+            assert!(!plan.contains("Order By"), "{}", plan);
+            assert_eq!(plan.contains("Reverse Sequence Scan"), sql.contains("desc"));
+            assert_eq!(first(&mut s, sql)?, expected, "{}", sql);
+        }
+
+        // 事务中自己的写入和删除同样按主键降序返回
+        s.execute("begin;")?;
+        s.execute("insert into t values ('grape', 6), ('zucchini', 7);")?;
+        s.execute("delete from t where name = 'kiwi';")?;
+        s.execute("update t set score = 0 where name = 'apple';")?;
+        match s.execute("select * from t order by name desc;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                [
+                    ("zucchini", 7),
+                    ("pear", 4),
+                    ("grape", 6),
+                    ("fig", 2),
+                    ("banana", 5),
+                    ("apple", 0)
+                ]
+                .iter()
+                .map(|&(name, score)| vec![name.into(), score.into()])
+                .collect::<Vec<Row>>()
+            ),
+            _ => unreachable!(),
+        }
+        s.execute("rollback;")?;
+
+        // 按非主键列排序，仍然需要排序节点
+        for (sql, expected) in [
+            (
+                "select id from n order by v desc;",
+                vec![0.into(), 10.into(), (-2).into(), 3.into()],
+            ),
+            (
+                "select name from t order by score desc;",
+                strs(&["banana", "pear", "kiwi", "fig", "apple"]),
+            ),
+        ] {
+            assert!(explain_plan(&mut s, sql)?.contains("Order By"), "{}", sql);
+            assert_eq!(first(&mut s, sql)?, expected, "{}", sql);
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_table_errors() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int, c int);")?;
+        s.execute("create table u (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 10, 100);")?;
+        s.execute("insert into u values (1, 10);")?;
+
+        // 直接通过存储接口写入损坏的数据：t 中一行没有任何列，u 的表结构没有主键
+        let mut transaction = kvengine.begin()?;
+        transaction.transaction.set(
+            Key::Row("t".into(), Value::Integer(2)).encode()?,
+            bincode::serialize(&Row::new())?,
+        )?;
+        let mut table = transaction.must_get_table("u".into())?;
+        table.columns[0].is_primary_key = false;
+        transaction.transaction.set(
+            Key::Table("u".into()).encode()?,
+            bincode::serialize(&table)?,
+        )?;
+        transaction.commit()?;
+
+        // 报错而不是 panic，按主键读取完好的行不受影响
+        for sql in [
+            "select * from t;",
+            "select count(*) from t;",
+            "update t set b = 20;",
+            "delete from t where b = 10;",
+        ] {
+            match s.execute(sql) {
+                Err(Error::Storage(msg)) => assert!(msg.contains("missing primary key"), "{}", msg),
+                res => panic!("{}: unexpected result {:?}", sql, res),
+            }
+        }
+        match s.execute("select b from t where a = 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(10)]]),
+            _ => unreachable!(),
+        }
+        for sql in [
+            "select * from u;",
+            "insert into u values (2, 20);",
+            "update u set b = 20;",
+            "delete from u;",
+        ] {
+            match s.execute(sql) {
+                Err(Error::Schema(msg)) => {
+                    assert!(
+                        msg.contains("Table \" u \" has an invalid schema"),
+                        "{}",
+                        msg
+                    );
+                    assert!(msg.contains("has no primary key"), "{}", msg);
+                }
+                res => panic!("{}: unexpected result {:?}", sql, res),
+            }
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_error_column_names() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("create table u (c int primary key, d int);")?;
+        s.execute("insert into t values (1, 'x');")?;
+        s.execute("insert into u values (2, 3);")?;
+
+        // 列和常量比较出错，错误信息中带有列名
+        assert_eq!(
+            s.execute("select * from t where b > 1;"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression x and 1 of column b".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("delete from t where 1 = b;"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression x and 1 of column b".into()
+            ))
+        );
+        // 两侧都是列时带有两个列名
+        assert_eq!(
+            s.execute("select * from t join u on b > d;"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression x and 3 of columns b and d".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("select * from t cross join u where b <= d;"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression x and 3 of columns b and d".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_reports_all_problems() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b int not null, c text, d bool not null);")?;
+
+        // 一行中的所有问题一起报告，按列的顺序排列
+        assert_eq!(
+            s.execute("insert into t (a, c) values (1, 2);"),
+            Err(Error::Schema(
+                "[Insert Table] 3 problems: Column \" b \" has no default value; Column \" c \" mismatched data type; Column \" d \" has no default value".into()
+            ))
+        );
+        // 只有一个问题时和之前一样
+        assert_eq!(
+            s.execute("insert into t values (1, null, 'x', true);"),
+            Err(Error::Schema(
+                "[Insert Table] Column \" b \" INTEGER NOT NULL in table \" t \" cannot be null"
+                    .into()
+            ))
+        );
+
+        // 多行插入时标明出错的是第几行，一行都不会写入
+        assert_eq!(
+            s.execute(
+                "insert into t values (1, 1, 'x', true), (2, null, 'y', true), (3, 3, 'z', false);"
+            ),
+            Err(Error::Schema(
+                "[Insert Table] Row 2 of 3: Column \" b \" INTEGER NOT NULL in table \" t \" cannot be null".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("insert into t values (1, 1, 'x', true), (2, 2, 'y', 1), (3, 3, 4, false);"),
+            Err(Error::Type(
+                "[Insert Table] Row 2 of 3: Column \" d \" mismatched data type".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("insert into t (a, b, c) values (1, 1, 'x'), (2, 2, 3);"),
+            Err(Error::Schema(
+                "[Insert Table] Row 1 of 2: Column \" d \" has no default value".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("insert into t (a, b, d) values (1, 1, true), (2, 'y', null);"),
+            Err(Error::Type(
+                "[Insert Table] Row 2 of 2: 2 problems: Column \" b \" mismatched data type; Column \" d \" BOOLEAN NOT NULL in table \" t \" cannot be null".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("insert into t values (1, 1, 'x', true), (1, 2, 'y', false);"),
+            Err(Error::Schema(
+                "[Insert Table] Row 2 of 2: Primary Key \" 1 \" conflicted in table \" t \"".into()
+            ))
+        );
+        assert_table(&mut s, "t", "")?;
+
+        // 值的个数多于列数时报错，多出的值不会被丢弃
+        s.execute("create table c (a int primary key, b text, n int);")?;
+        let too_many =
+            Error::Schema("[Insert Table] Row has 4 values but table \" c \" has 3 columns".into());
+        assert_eq!(
+            s.execute("insert into c values (10, 'x', 1, 7);"),
+            Err(too_many.clone())
+        );
+        assert_eq!(
+            s.execute("insert into c values (1, 'x', 1), (2, 'y', 2, 7);"),
+            Err(Error::Schema(
+                "[Insert Table] Row 2 of 2: Row has 4 values but table \" c \" has 3 columns"
+                    .into()
+            ))
+        );
+        // 直接写入的行同样检查宽度，和其他问题一起报告
+        let mut txn = kvengine.begin()?;
+        let table = txn.must_get_table("c".into())?;
+        assert_eq!(
+            txn.create_row("c".into(), vec![1.into(), "x".into(), 1.into(), 7.into()]),
+            Err(too_many)
+        );
+        assert_eq!(
+            table.build_row(vec![Some(1.into()), Some(2.into()), None, Some(7.into())]),
+            Err(Error::Schema(
+                "[Insert Table] 2 problems: Row has 4 values but table \" c \" has 3 columns; Column \" b \" mismatched data type".into()
+            ))
+        );
+        txn.rollback()?;
+        assert_table(&mut s, "c", "")?;
+
+        // 最多列出 10 个问题
+        let columns = (1..=12)
+            .map(|i| format!("c{} int not null", i))
+            .collect::<Vec<_>>();
+        s.execute(&format!(
+            "create table w (id int primary key, {});",
+            columns.join(", ")
+        ))?;
+        match s.execute("insert into w (id) values (1);") {
+            Err(Error::Schema(msg)) => {
+                assert!(msg.starts_with(
+                    "[Insert Table] 12 problems: Column \" c1 \" has no default value;"
+                ));
+                assert!(msg.contains("Column \" c10 \" has no default value; and 2 more"));
+                assert!(!msg.contains("c11"));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_status_metrics() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        // 读取各项计数，show status 本身也算一条语句
+        let metrics = |s: &mut Session<_>| -> Result<HashMap<String, i64>> {
+            match s.execute("show status;")? {
+                ResultSet::Scan { rows, .. } => Ok(rows
+                    .into_iter()
+                    .skip(2)
+                    .map(|row| match (&row[0], &row[1]) {
+                        (Value::String(name), Value::Integer(v)) => (name.clone(), *v),
+                        _ => unreachable!(),
+                    })
+                    .collect()),
+                _ => unreachable!(),
+            }
+        };
+        let before = metrics(&mut s)?;
+        let mut names = before.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["bytes_written", "conflicts", "rows_scanned", "statements"]
+        );
+        assert_eq!(before["statements"], 1);
+        assert_eq!(before["conflicts"], 0);
+
+        s.execute("create table t (a int primary key, b int);")?;
+        s.execute("insert into t values (1, 10), (2, 20), (3, 30);")?;
+        let written = metrics(&mut s)?;
+        assert_eq!(written["statements"], 4);
+        assert!(written["bytes_written"] > before["bytes_written"]);
+
+        // 扫描时过滤之前读出的行都计算在内，只读的语句不写入数据
+        assert_eq!(
+            s.execute("select * from t where b > 10;")?
+                .to_string()
+                .lines()
+                .last(),
+            Some("(2 rows)")
+        );
+        let scanned = metrics(&mut s)?;
+        assert_eq!(scanned["rows_scanned"] - written["rows_scanned"], 3);
+        assert_eq!(scanned["bytes_written"], written["bytes_written"]);
+        assert_eq!(scanned["statements"], 6);
+
+        // 执行失败的语句同样计数，写冲突计入冲突次数
+        let mut s2 = kvengine.session()?;
+        s.execute("begin;")?;
+        s.execute("update t set b = 11 where a = 1;")?;
+        assert_eq!(
+            s2.execute("update t set b = 12 where a = 1;"),
+            Err(Error::WriteConflict)
+        );
+        s.execute("commit;")?;
+        let conflicted = metrics(&mut s)?;
+        assert_eq!(conflicted["conflicts"], 1);
+        assert_eq!(conflicted["statements"], scanned["statements"] + 5);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_char_padding() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (id int primary key, c char(4) index, v varchar, one char default 'y');",
+        )?;
+        s.execute("insert into t values (1, 'ab  ', 'ab  ', 'x'), (2, ' cd', ' cd ', null);")?;
+        s.execute("insert into t (id, c, v) values (3, 'abcd   ', 'abcd   ');")?;
+
+        // char 列读出时去掉末尾的空格，开头的空格保留；varchar 列原样保存
+        let string = |v: &str| Value::String(v.into());
+        assert_eq!(
+            query_rows(&mut s, "select c, v, one from t;")?,
+            vec![
+                vec![string("ab"), string("ab  "), string("x")],
+                vec![string(" cd"), string(" cd "), Value::Null],
+                vec![string("abcd"), string("abcd   "), string("y")],
+            ]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select id from t where c = 'ab';")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select id from t where v = 'ab';")?,
+            Vec::<Row>::new()
+        );
+
+        // 存储中的值补齐了空格
+        let raw = |id: i64| -> Result<Row> {
+            let txn = kvengine.begin()?;
+            let value = txn
+                .transaction
+                .get(super::Key::Row("t".into(), Value::Integer(id)).encode()?)?
+                .unwrap();
+            Ok(bincode::deserialize(&value)?)
+        };
+        assert_eq!(
+            raw(1)?,
+            vec![
+                Value::Integer(1),
+                string("ab  "),
+                string("ab  "),
+                string("x")
+            ]
+        );
+        assert_eq!(raw(2)?[1], string(" cd "));
+
+        // 更新其他列之后，没有修改的 char 列仍然补齐空格存储；按索引查找时忽略末尾的空格
+        s.execute("update t set v = 'z' where id = 2;")?;
+        assert_eq!(raw(2)?[1], string(" cd "));
+        s.execute("update t set c = 'e' where id = 3;")?;
+        assert_eq!(raw(3)?[1], string("e   "));
+        assert_eq!(
+            query_rows(&mut s, "select id, c from t where c = 'e';")?,
+            vec![vec![Value::Integer(3), string("e")]]
+        );
+
+        // 超过长度的值报错，末尾的空格不计入长度
+        assert_eq!(
+            s.execute("insert into t values (4, 'abcde', 'abcde', 'x');"),
+            Err(Error::Type(
+                "[Insert Table] Value abcde is too long for column \" c \" Char(4)".into()
+            ))
+        );
+        assert!(s.execute("update t set one = 'xy' where id = 1;").is_err());
+        assert!(s
+            .execute("create table t2 (id int primary key, c char(2) default 'abc');")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (id int primary key, c char(0));")
+            .is_err());
+        assert!(s
+            .execute("create table t2 (id int primary key, c char(256));")
+            .is_err());
+
+        // char 主键按去掉末尾空格之后的值查找
+        s.execute("create table codes (code char(3) primary key, name text);")?;
+        s.execute("insert into codes values ('a ', 'first'), ('b', 'second');")?;
+        assert_eq!(
+            query_rows(&mut s, "select name from codes where code = 'a';")?,
+            vec![vec![string("first")]]
+        );
+        assert!(s
+            .execute("insert into codes values ('a', 'again');")
+            .is_err());
+
+        // 导出的建表语句保留 char 的长度
+        match s.execute("show create table t;")? {
+            ResultSet::ShowCreateTable { sql } => assert_eq!(
+                sql,
+                "CREATE TABLE t (
+  id INTEGER PRIMARY KEY,
+  c CHAR(4) NULL INDEX,
+  v STRING NULL,
+  one CHAR(1) NULL DEFAULT 'y'
+);"
+            ),
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_char_comparison() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        // c 有索引，d 没有索引，两列的数据相同
+        s.execute("create table t (id char(3) primary key, c char(4) index, d char(4));")?;
+        s.execute("insert into t values ('a', 'ab', 'ab'), ('b', 'abc', 'abc'), ('c', 'b', 'b');")?;
+
+        // 条件中的字符串去掉末尾的空格之后再比较，结果和是否走索引、主键无关
+        let ids = |s: &mut Session<KVEngine<DiskEngine>>, cond: &str| -> Result<Vec<Row>> {
+            query_rows(s, &format!("select id from t where {} order by id;", cond))
+        };
+        let expect = |v: &[&str]| {
+            v.iter()
+                .map(|&v| vec![Value::String(v.into())])
+                .collect::<Vec<_>>()
+        };
+        for col in ["c", "d"] {
+            let cond = |c: &str| c.replace("col", col);
+            assert_eq!(ids(&mut s, &cond("col = 'ab  '"))?, expect(&["a"]));
+            assert_eq!(ids(&mut s, &cond("'ab  ' = col"))?, expect(&["a"]));
+            assert_eq!(
+                ids(&mut s, &cond("col >= 'ab  '"))?,
+                expect(&["a", "b", "c"])
+            );
+            assert_eq!(ids(&mut s, &cond("col != 'ab '"))?, expect(&["b", "c"]));
+            assert_eq!(
+                ids(&mut s, &cond("col in ('ab ', 'b  ')"))?,
+                expect(&["a", "c"])
+            );
+        }
+        // 主键上的等值查找和范围条件一致
+        assert_eq!(ids(&mut s, "id = 'a  '")?, expect(&["a"]));
+        assert_eq!(ids(&mut s, "id >= 'a  '")?, expect(&["a", "b", "c"]));
+        assert_eq!(ids(&mut s, "id <= 'b  '")?, expect(&["a", "b"]));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_database() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("create table u (a int primary key, b int index);")?;
+        s.execute("insert into t values (1, 'x'), (2, 'y');")?;
+        s.execute("insert into u values (1, 10), (2, 20);")?;
+        s.execute("kv put 'app.version' '3';")?;
+
+        let check = |s: &mut Session<_>| -> Result<(usize, Vec<String>)> {
+            match s.execute("check database;")? {
+                ResultSet::CheckDatabase { entries, problems } => Ok((entries, problems)),
+                _ => unreachable!(),
+            }
+        };
+        // 2 个表结构、4 行、2 个索引项、1 条元数据和格式版本号
+        assert_eq!(check(&mut s)?, (10, vec![]));
+
+        // 通过底层存储写入无法解码的表结构、行、索引和 key
+        let mut txn = kvengine.begin()?;
+        txn.transaction
+            .set(super::Key::Table("t".into()).encode()?, vec![1, 2, 3])?;
+        txn.transaction.set(
+            super::Key::Row("u".into(), Value::Integer(2)).encode()?,
+            vec![9],
+        )?;
+        txn.transaction.set(
+            super::Key::Index("u".into(), "b".into(), Value::Integer(10)).encode()?,
+            vec![],
+        )?;
+        txn.transaction.set(vec![0xff, 0x01], vec![0])?;
+        txn.commit()?;
+
+        // 错误信息中带有表名、key 和 value 的长度
+        match s.execute("select * from t;") {
+            Err(Error::Storage(msg)) => assert!(
+                msg.starts_with("[KV Engine] Failed to decode schema of table \" t \" at key ")
+                    && msg.contains("(3 bytes)"),
+                "{}",
+                msg
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match s.execute("select * from u where a = 2;") {
+            Err(Error::Storage(msg)) => {
+                assert!(msg.contains("row of table \" u \"") && msg.contains("(1 bytes)"))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match s.execute("select a from u where b = 10;") {
+            Err(Error::Storage(msg)) => assert!(
+                msg.contains("index of column \" b \" in table \" u \""),
+                "{}",
+                msg
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // check database 列出全部无法解码的数据，不会在第一条上停下
+        let (entries, problems) = check(&mut s)?;
+        assert_eq!(entries, 11);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("schema of table \" t \""));
+        assert!(problems[1].contains("row of table \" u \""));
+        assert!(problems[2].contains("index of column \" b \" in table \" u \""));
+        assert!(problems[3].starts_with("Storage Error: [KV Engine] Failed to decode key ff01"));
+        let output = s.execute("check database;")?.to_string();
+        assert!(output.starts_with("CHECK DATABASE: 11 entries checked, 4 undecodable\n"));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_null() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute(
+            "create table t (a int primary key, b text null, c char(3) not null, d decimal(5, 2) not null);",
+        )?;
+
+        // 可以为空的列写入 NULL
+        s.execute("insert into t values (1, null, 'x', 1.5);")?;
+        match s.execute("select b from t where a = 1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Null]]),
+            _ => unreachable!(),
+        }
+
+        // 不可为空的列写入 NULL，错误中给出表名、出错的列和列的定义
+        for (sql, message) in [
+            (
+                "insert into t values (null, 'x', 'y', 1);",
+                "[Insert Table] Column \" a \" INTEGER PRIMARY KEY in table \" t \" cannot be null",
+            ),
+            (
+                "insert into t values (2, 'x', null, 1);",
+                "[Insert Table] Column \" c \" CHAR(3) NOT NULL in table \" t \" cannot be null",
+            ),
+            (
+                "insert into t (a, c, d) values (2, 'y', null);",
+                "[Insert Table] Column \" d \" DECIMAL(5, 2) NOT NULL in table \" t \" cannot be null",
+            ),
+            (
+                "update t set c = null where a = 1;",
+                "[Insert Table] Column \" c \" CHAR(3) NOT NULL in table \" t \" cannot be null",
+            ),
+        ] {
+            assert_eq!(s.execute(sql), Err(Error::Schema(message.into())), "{}", sql);
+        }
+
+        // 出错的语句没有写入任何数据
+        assert_eq!(
+            s.query_scalar("select count(*) from t;")?,
+            Value::Integer(1)
+        );
+        match s.execute("select c from t;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::String("x".into())]])
+            }
+            _ => unreachable!(),
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_ddl_autocommit() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        let not_exist = |name: &str| {
+            Err(Error::NotFound(format!(
+                "[Get Table] Table \" {} \" does not exist",
+                name
+            )))
+        };
+        s1.execute("create table t (a int primary key, b text index);")?;
+        s1.execute("insert into t values (1, 'x'), (2, 'y');")?;
+
+        // 默认情况下 DDL 属于当前事务：建表、写入之后回滚，什么都不留下
+        s1.execute("begin;")?;
+        s1.execute("create table u (a int primary key);")?;
+        s1.execute("insert into u values (1);")?;
+        s1.execute("rollback;")?;
+        assert_eq!(s1.execute("select * from u;"), not_exist("u"));
+        assert_eq!(
+            s1.execute("show tables;")?,
+            ResultSet::TableNames {
+                names: vec!["t".into()]
+            }
+        );
+
+        // 删表之后回滚，表结构、行和索引都恢复
+        s1.execute("begin;")?;
+        s1.execute("drop table t;")?;
+        assert_eq!(s1.execute("select * from t;"), not_exist("t"));
+        assert_table(&mut s2, "t", "1, x\n2, y")?;
+        s1.execute("rollback;")?;
+        for s in [&mut s1, &mut s2] {
+            assert_table(s, "t", "1, x\n2, y")?;
+            assert_rows_unordered(
+                &s.execute("select a from t where b = 'y';")?,
+                vec![vec![2.into()]],
+            );
+        }
+
+        // 两个事务同时创建同名的表，后写入的一方冲突
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("create table u (a int primary key);")?;
+        assert_eq!(
+            s2.execute("create table u (b int primary key);"),
+            Err(Error::WriteConflict)
+        );
+        s2.execute("rollback;")?;
+        s1.execute("rollback;")?;
+
+        // 打开 ddl_autocommit 之后，事务中的 DDL 另开事务立即提交，不随当前事务回滚
+        s1.set_ddl_autocommit(true);
+        s1.execute("begin;")?;
+        s1.execute("create table u (a int primary key);")?;
+        assert_table(&mut s2, "u", "")?;
+        // 当前事务开始时表还不存在，之后开启的事务才能使用
+        assert_eq!(s1.execute("insert into u values (1);"), not_exist("u"));
+        s1.execute("rollback;")?;
+        s1.execute("insert into u values (1);")?;
+        assert_table(&mut s2, "u", "1")?;
+
+        // 当前事务写过的表不能另开事务删除
+        s1.execute("begin;")?;
+        s1.execute("insert into t values (3, 'z');")?;
+        assert_eq!(
+            s1.execute("drop table t;"),
+            Err(Error::Internal(
+                "[Exec Transaction] Cannot auto-commit DDL on table \" t \" which the current transaction has written, commit or roll back first".into()
+            ))
+        );
+        // 没有写过的表不受影响
+        s1.execute("drop table u;")?;
+        assert_eq!(s2.execute("select * from u;"), not_exist("u"));
+        s1.execute("commit;")?;
+        assert_table(&mut s2, "t", "1, x\n2, y\n3, z")?;
+        assert_eq!(s1.execute("select * from u;"), not_exist("u"));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_column_order() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text, c int);")?;
+        s.execute(
+            "insert into t values (1, 'x', 10), (2, 'y', null), (3, 'x', 30), (4, 'z', 40), (5, 'y', 50);",
+        )?;
+
+        // 输出的列和每组的值都按 select 列表的顺序排列，分组列可以出现在任意位置，同一个函数可以出现多次
+        // 第一组 x 中 c 没有 NULL，第二组 y 中 count(c) 少于 count(*)，各组的列都保持一致
+        let (i, st) = (Value::Integer, |v: &str| Value::String(v.into()));
+        for (sql, columns, rows) in [
+            (
+                "select count(*), b, sum(c) as total, max(c) from t group by b;",
+                vec!["count", "b", "total", "max"],
+                vec![
+                    vec![i(2), st("x"), i(40), i(30)],
+                    vec![i(2), st("y"), i(50), i(50)],
+                    vec![i(1), st("z"), i(40), i(40)],
+                ],
+            ),
+            (
+                "select max(a) as m, count(c) as n, count(a), b as g from t group by b;",
+                vec!["m", "n", "count", "g"],
+                vec![
+                    vec![i(3), i(2), i(2), st("x")],
+                    vec![i(5), i(1), i(2), st("y")],
+                    vec![i(4), i(1), i(1), st("z")],
+                ],
+            ),
+            (
+                "select min(c), b from t where a = 2 group by b;",
+                vec!["min", "b"],
+                vec![vec![Value::Null, st("y")]],
+            ),
+        ] {
+            assert_eq!(
+                s.execute(sql)?,
+                ResultSet::Scan {
+                    columns: columns.into_iter().map(String::from).collect(),
+                    rows,
+                },
+                "{}",
+                sql
+            );
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_select_alias() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text index, c int);")?;
+        s.execute("create table u (a int primary key, d int);")?;
+        s.execute("insert into t values (1, 'x', 3), (2, 'y', 9), (7, 'y', 5);")?;
+        s.execute("insert into u values (1, 10), (7, 70);")?;
+
+        let (i, st) = (Value::Integer, |v: &str| Value::String(v.into()));
+
+        // where 中的别名还原为 select 中的原表达式
+        for (sql, expected) in [
+            (
+                "select a as x from t where x > 1;",
+                vec![vec![i(2)], vec![i(7)]],
+            ),
+            (
+                "select a as x, c from t where x > 1 and c < 6;",
+                vec![vec![i(7), i(5)]],
+            ),
+            (
+                "select b || '!' as e from t where e = 'x!';",
+                vec![vec![st("x!")]],
+            ),
+            (
+                "select a as x from t where x in (1, 7);",
+                vec![vec![i(1)], vec![i(7)]],
+            ),
+            (
+                "select t.a as x, d from t join u on t.a = u.a where x > 1;",
+                vec![vec![i(7), i(70)]],
+            ),
+            // 原表中有同名的列时使用原表的列
+            ("select a as c from t where c = 3;", vec![vec![i(1)]]),
+        ] {
+            assert_eq!(query_rows(&mut s, sql)?, expected, "{}", sql);
+        }
+
+        // 还原之后仍然可以走索引
+        match s.execute("explain select b as k from t where k = 'y';")? {
+            ResultSet::Explain { plan } => {
+                assert!(plan.contains("Index Scan On Table t"), "{}", plan)
+            }
+            _ => unreachable!(),
+        }
+
+        // 聚集函数的别名不能用在 where 中，和不存在的列的错误区分开
+        assert_eq!(
+            s.execute("select b, count(a) as n from t where n > 1 group by b;"),
+            Err(Error::Internal(
+                "[Planner] Alias \" n \" of aggregate function count cannot be used in WHERE, use HAVING instead".into()
+            ))
+        );
+        assert_eq!(
+            s.execute("select a as x from t where y > 1;"),
+            Err(Error::NotFound(
+                "[Executor] Column \" y \" does not exist, available columns: a, b, c".into()
+            ))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_version() -> Result<()> {
+        use crate::storage::mvcc::Mvcc;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        // 新建的存储记录当前的格式版本号，之后可以正常重新打开
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        kvengine
+            .session()?
+            .execute("create table t (a int primary key);")?;
+        drop(kvengine);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        kvengine.session()?.execute("insert into t values (1);")?;
+        drop(kvengine);
+
+        // 其他版本的格式拒绝打开
+        let mvcc = Mvcc::new(DiskEngine::new(p.clone())?);
+        let mut txn = mvcc.begin()?;
+        txn.set(Key::FormatVersion.encode()?, bincode::serialize(&2u32)?)?;
+        txn.commit()?;
+        drop((txn, mvcc));
+        assert_eq!(
+            KVEngine::new(DiskEngine::new(p.clone())?).err(),
+            Some(Error::Storage("[KVEngine] Store format version 2 is not supported, expected 1, export the data with the version that wrote it and import it again".into()))
+        );
+
+        // 没有记录版本号但已经有表的存储是最初的格式，同样拒绝打开
+        let old = p.with_file_name("sqldb-old");
+        let mvcc = Mvcc::new(DiskEngine::new(old.clone())?);
+        let mut txn = mvcc.begin()?;
+        txn.set(Key::Table("t".into()).encode()?, Vec::new())?;
+        txn.commit()?;
+        drop((txn, mvcc));
+        assert_eq!(
+            KVEngine::new(DiskEngine::new(old)?).err(),
+            Some(Error::Storage("[KVEngine] Store format version 0 is not supported, expected 1, export the data with the version that wrote it and import it again".into()))
+        );
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_non_constant() -> Result<()> {
+        use crate::row;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int primary key, b text);")?;
+        s.execute("insert into t values (1, 'x');")?;
+
+        // values、set 和 default 中只能是常量，函数、列名和聚集函数都报错，不会让会话崩溃
+        let non_constant = |msg: &str| Err(Error::Parse(msg.into()));
+        assert_eq!(
+            s.execute("insert into t values (2, concat('x','y'));"),
+            non_constant("[Insert Table] Only constant values are allowed, got x || y")
+        );
+        assert_eq!(
+            s.execute("insert into t values (4, a);"),
+            non_constant("[Insert Table] Only constant values are allowed, got a")
+        );
+        assert_eq!(
+            s.execute("insert into t values (3, count(a));"),
+            non_constant("[Insert Table] Only constant values are allowed, got count(a)")
+        );
+        assert_eq!(
+            s.execute("insert into t values (3, 'z'), (4, b);"),
+            non_constant("[Insert Table] Row 2 of 2: Only constant values are allowed, got b")
+        );
+        assert_eq!(
+            s.execute("update t set b = a;"),
+            non_constant("[Update Table] Only constant values are allowed, got a")
+        );
+        assert_eq!(
+            s.execute("create table u (a int primary key, b text default concat('a','b'));"),
+            non_constant("[CreateTable] Failed, default of column \" b \": Only constant values are allowed, got a || b")
+        );
+
+        // 出错之后会话仍然可用，数据没有变化
+        assert_eq!(
+            s.execute("select * from t;")?,
+            ResultSet::Scan {
+                columns: vec!["a".into(), "b".into()],
+                rows: vec![row![1, "x"]],
+            }
+        );
 
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
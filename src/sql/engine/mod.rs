@@ -1,14 +1,22 @@
+pub mod catalog;
+pub mod database;
 pub mod kv;
 
 use crate::error::Error::Internal;
 use crate::error::{Error, Result};
+use crate::sql::engine::database::Databases;
 use crate::sql::executor::ResultSet;
-use crate::sql::parser::ast::Expression;
+use crate::sql::parser::ast::{Expression, StatementKind};
+use crate::sql::parser::lexer::quote_identifier;
 use crate::sql::parser::{ast, Parser};
-use crate::sql::planner::Plan;
+use crate::sql::planner::{Node, Plan};
 use crate::sql::schema::Table;
 use crate::sql::types::{Row, Value};
+use crate::storage::mvcc::{Metrics, SyncStats};
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
 // 定义sql引擎的抽象接口
 pub trait Engine: Clone {
@@ -17,11 +25,29 @@ pub trait Engine: Clone {
 
     fn begin(&self) -> Result<Self::Transaction>; // 每个sql语句，我们都会将其封装在一个事务中运行，所以执行sql时需要先开启事务
 
+    fn begin_serializable(&self) -> Result<Self::Transaction>; // 显式开启可串行化事务
+
+    fn begin_read_only(&self) -> Result<Self::Transaction>; // 无显式事务的只读语句使用，不消耗版本号，写入会报错
+
+    fn purge_table(&self, table_name: &str) -> Result<usize>; // 物理清理已删除表的残留数据，返回清理的条目数
+
+    fn sync(&self) -> Result<()>; // 把已提交的数据刷到磁盘上
+
+    fn sync_stats(&self) -> Result<SyncStats>; // 刷盘次数和最近一次刷盘的时间
+
+    fn metrics(&self) -> &Metrics; // 执行的语句数、扫描的行数等运行计数
+
     fn session(&self) -> Result<Session<Self>> {
         // 客户端与sql服务端的连接靠session来维持
         Ok(Session {
             engine: self.clone(), // 确保 Session 拥有当前引擎的一个副本
             transaction: None,    // 初始化为None，直到有显式事务
+            max_insert_rows: None,
+            cancel_token: None,
+            allowed_statements: None,
+            databases: None,
+            database: None,
+            ddl_autocommit: false,
         })
     }
 }
@@ -38,14 +64,24 @@ pub trait Transaction {
     // 创建行
     fn create_row(&mut self, table: String, row: Row) -> Result<()>;
 
+    // 批量创建行，默认逐行插入，存储层可以实现更快的批量写入
+    fn create_rows(&mut self, table: String, rows: Vec<Row>) -> Result<()> {
+        for row in rows {
+            self.create_row(table.clone(), row)?;
+        }
+        Ok(())
+    }
+
     // 更新行
     fn update_row(&mut self, table: &Table, primary_key: &Value, row: Row) -> Result<()>;
 
     // 删除行
     fn delete_row(&mut self, table: &Table, primary_key: &Value) -> Result<()>;
 
-    // 扫描表
+    // 扫描表，返回的行按主键升序排列（存储按编码后的 key 有序遍历）
     fn scan(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>>;
+    // 反向扫描表，返回的行和 scan 的顺序相反，即按编码后的主键降序排列
+    fn scan_reverse(&self, table_name: String, filter: Option<Expression>) -> Result<Vec<Row>>;
 
     // DDL
     fn create_table(&mut self, table: Table) -> Result<()>;
@@ -57,10 +93,28 @@ pub trait Transaction {
     // 获取所有表名
     fn get_all_table_names(&self) -> Result<Vec<String>>;
 
+    // 逐条解码数据库中的表结构、行和索引，返回检查的条数和无法解码的数据的错误信息
+    fn check_database(&self) -> Result<(usize, Vec<String>)>;
+
     // 必须获取表
     fn must_get_table(&self, table_name: String) -> Result<Table> {
-        self.get_table(table_name.clone())?.  // ok_or : Option -> Result
-            ok_or(Error::Internal(format!("[Get Table] Table \" {} \" does not exist",table_name)))
+        let table = self.get_table(table_name.clone())?.  // ok_or : Option -> Result
+            ok_or(Error::NotFound(format!("[Get Table] Table \" {} \" does not exist",quote_identifier(&table_name))))?;
+        // 存储中的表结构可能已经损坏（如没有主键），加载时就报错，而不是在执行器中 panic
+        // 系统目录表是没有主键的虚拟表，不需要校验
+        if !catalog::is_catalog_table(&table_name) {
+            table.is_valid().map_err(|e| {
+                Error::Schema(format!(
+                    "[Get Table] Table \" {} \" has an invalid schema: {}",
+                    quote_identifier(&table_name),
+                    match e {
+                        Error::Schema(msg) => msg,
+                        e => e.to_string(),
+                    }
+                ))
+            })?;
+        }
+        Ok(table)
     }
 
     // 索引相关方法
@@ -77,28 +131,325 @@ pub trait Transaction {
         col_value: &Value,
         index: HashSet<Value>,
     ) -> Result<()>;
+    // 扫描某一列的全部索引项：(列值, 主键集合)
+    fn scan_index(&self, table_name: &str, col_name: &str) -> Result<Vec<(Value, HashSet<Value>)>>;
     fn read_row_by_pk(&self, table_name: &str, pk: &Value) -> Result<Option<Row>>;
+
+    // 锁定一行（select ... for update），提交时如果该行已被其他事务修改并提交，则提交失败
+    fn lock_row(&self, table_name: &str, pk: &Value) -> Result<()>;
+
+    // 元数据读写，如应用的 schema 版本、配置等，无需建表，和数据一样受事务保护
+    fn meta_get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    fn meta_set(&mut self, key: &str, value: Vec<u8>) -> Result<()>;
+    fn meta_delete(&mut self, key: &str) -> Result<()>;
+
+    // 保存点：回滚到保存点时撤销其后的写入，事务保持活跃；同名保存点以最近的为准
+    fn savepoint(&self, name: &str) -> Result<()>;
+    fn rollback_to_savepoint(&self, name: &str) -> Result<()>;
+    fn release_savepoint(&self, name: &str) -> Result<()>;
+
+    // 本事务是否写过这张表的表结构或行，索引总是随行一起写入
+    fn has_written_table(&self, table_name: &str) -> Result<bool>;
+
+    // 设置当前语句的取消标记
+    fn set_cancel_token(&mut self, token: Option<CancelToken>);
+
+    // 当前语句已被取消时返回错误，执行器在循环中定期检查
+    fn check_cancelled(&self) -> Result<()>;
+}
+
+// 语句的取消标记，克隆后共享同一个标记，可以在其他线程中取消正在执行的语句
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
 }
 
 pub struct Session<E: Engine> {
-    engine: E,                           // 存储当前的 SQL 引擎实例
-    transaction: Option<E::Transaction>, // 显式事务命令
+    engine: E,                                          // 存储当前的 SQL 引擎实例
+    transaction: Option<E::Transaction>,                // 显式事务命令
+    max_insert_rows: Option<usize>, // 单条 insert 语句最多插入的行数，None表示不限制
+    cancel_token: Option<CancelToken>, // 之后执行的语句使用的取消标记
+    allowed_statements: Option<HashSet<StatementKind>>, // 允许执行的语句类别，None表示不限制
+    databases: Option<Databases<E>>, // 可以切换的数据库，None表示只能使用当前引擎
+    database: Option<String>,       // 当前使用的数据库
+    ddl_autocommit: bool,           // 显式事务中的建表、删表是否另开事务立即提交
 }
 
 impl<E: Engine + 'static> Session<E> {
-    // 执行客户端传来的sql语句
+    // 设置单条 insert 语句最多插入的行数
+    pub fn set_max_insert_rows(&mut self, max_insert_rows: Option<usize>) {
+        self.max_insert_rows = max_insert_rows;
+    }
+
+    // 默认情况下建表、删表和其他语句一样属于当前的显式事务，随事务提交或回滚
+    // 打开之后，显式事务中的建表、删表另开一个事务执行并立即提交，不受当前事务回滚的影响
+    pub fn set_ddl_autocommit(&mut self, ddl_autocommit: bool) {
+        self.ddl_autocommit = ddl_autocommit;
+    }
+
+    // 设置之后执行的语句使用的取消标记，每条语句可以使用不同的标记
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel_token = token;
+    }
+
+    // 限制之后只能执行哪些类别的语句，在解析之后、执行之前检查，None表示不限制
+    pub fn set_allowed_statements(&mut self, kinds: Option<HashSet<StatementKind>>) {
+        self.allowed_statements = kinds;
+    }
+
+    fn check_allowed(&self, sentence: &ast::Sentence) -> Result<()> {
+        self.check_allowed_kind(sentence.kind())
+    }
+
+    fn check_allowed_kind(&self, kind: StatementKind) -> Result<()> {
+        match &self.allowed_statements {
+            Some(allowed) if !allowed.contains(&kind) => Err(Internal(format!(
+                "[Session] {:?} statements are not allowed in this session",
+                kind
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn databases(&self) -> Result<&Databases<E>> {
+        self.databases.as_ref().ok_or(Internal(
+            "[Exec Database] Multiple databases are not configured for this session".into(),
+        ))
+    }
+
+    // 当前使用的数据库，会话不是通过 Databases 创建的时候返回None
+    pub fn database(&self) -> Option<&str> {
+        self.database.as_deref()
+    }
+
+    // 当前显式事务的版本号，不在事务中时返回None
+    pub fn transaction_version(&self) -> Option<u64> {
+        self.transaction.as_ref().map(|t| t.get_version())
+    }
+
+    // 回滚当前的显式事务，返回被回滚的事务版本号，不在事务中时什么都不做
+    // 用于连接断开等会话要结束的场景，不受允许执行的语句类别的限制
+    pub fn rollback_transaction(&mut self) -> Result<Option<u64>> {
+        match self.transaction.take() {
+            Some(transaction) => {
+                let version = transaction.get_version();
+                transaction.rollback()?;
+                Ok(Some(version))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // 不经过 sql 解析，直接在事务中调用引擎接口
+    // 处在显式事务中时使用当前事务，否则开启一个新事务，成功则提交，失败则回滚
+    pub fn with_transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut E::Transaction) -> Result<T>,
+    ) -> Result<T> {
+        if let Some(transaction) = self.transaction.as_mut() {
+            return f(transaction);
+        }
+        let mut transaction = self.engine.begin()?;
+        match f(&mut transaction) {
+            Ok(res) => {
+                transaction.commit()?;
+                Ok(res)
+            }
+            Err(e) => {
+                transaction.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    // 是否是只读语句，select ... for update 需要锁定行，不算只读
+    fn is_read_only(sentence: &ast::Sentence) -> bool {
+        matches!(
+            sentence,
+            ast::Sentence::Select {
+                for_update: false,
+                ..
+            } | ast::Sentence::TableSchema { .. }
+                | ast::Sentence::ShowCreateTable { .. }
+                | ast::Sentence::ShowIndexes { .. }
+                | ast::Sentence::ChecksumTable { .. }
+                | ast::Sentence::CheckDatabase {}
+                | ast::Sentence::TableNames {}
+                | ast::Sentence::KvGet { .. }
+        )
+    }
+
+    // 执行客户端传来的sql语句，只能包含一条语句，多条语句需要使用 execute_batch
     pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
-        match Parser::new(sql).parse()? {
-            // 传进来的sql直接扔给parser解析
-            ast::Sentence::Begin {} if self.transaction.is_some() => {
+        // 传进来的sql直接扔给parser解析
+        self.execute_sentence(Parser::new(sql).parse()?)
+    }
+
+    // 依次执行以分号分隔的多条语句，遇到错误则停止，之前的语句已经执行的结果不会撤销
+    // 所有语句都会在执行之前先检查是否允许执行，有不允许的语句则一条都不执行
+    pub fn execute_batch(&mut self, sql: &str) -> Result<Vec<ResultSet>> {
+        let sentences = Parser::new(sql).parse_batch()?;
+        for sentence in &sentences {
+            self.check_allowed(sentence)?;
+        }
+        sentences
+            .into_iter()
+            .map(|sentence| self.execute_sentence(sentence))
+            .collect()
+    }
+
+    // 执行只返回一行一列的查询，直接取出这个值，如 select count(*) from t
+    pub fn query_scalar(&mut self, sql: &str) -> Result<Value> {
+        match self.execute(sql)? {
+            ResultSet::Scan { columns, mut rows } => {
+                if columns.len() != 1 || rows.len() != 1 {
+                    return Err(Internal(format!(
+                        "[Session] Scalar query must return exactly one row and one column, got {} rows and {} columns",
+                        rows.len(),
+                        columns.len()
+                    )));
+                }
+                Ok(rows.remove(0).remove(0))
+            }
+            res => Err(Internal(format!(
+                "[Session] Scalar query must be a query, got result: {}",
+                res.to_string()
+            ))),
+        }
+    }
+
+    // 按主键读取一行，不经过 sql 解析，和 where 主键 = 值 的查询一样直接按主键查找
+    // 主键值按列的类型转换，整数主键可以用 2.0 查找，行不存在时返回None
+    pub fn get_row(&mut self, table_name: &str, pk: Value) -> Result<Option<Row>> {
+        self.check_allowed_kind(StatementKind::Query)?;
+        let plan = Plan(Node::PkIndex {
+            table_name: table_name.into(),
+            value: pk,
+        });
+        let result = match self.transaction.as_mut() {
+            Some(transaction) => plan.execute(transaction)?,
+            None => {
+                let mut transaction = self.engine.begin_read_only()?;
+                let result = plan.execute(&mut transaction);
+                transaction.commit()?;
+                result?
+            }
+        };
+        match result {
+            ResultSet::Scan { mut rows, .. } => Ok(rows.pop()),
+            res => Err(Internal(format!(
+                "[Session] Unexpected result of primary key lookup: {}",
+                res.to_string()
+            ))),
+        }
+    }
+
+    // 执行一条已经解析好的语句，调用方可以自行解析多条语句后逐条执行
+    pub fn execute_sentence(&mut self, sentence: ast::Sentence) -> Result<ResultSet> {
+        self.check_allowed(&sentence)?;
+        Metrics::add(&self.engine.metrics().statements, 1);
+        match sentence {
+            ast::Sentence::Begin { .. } if self.transaction.is_some() => {
                 return Err(Internal("[Exec Transaction] Already in transaction".into()))
             }
-            ast::Sentence::Commit {} | ast::Sentence::Rollback {} if self.transaction.is_none() => {
+            ast::Sentence::Commit {}
+            | ast::Sentence::Rollback {}
+            | ast::Sentence::Savepoint { .. }
+            | ast::Sentence::RollbackTo { .. }
+            | ast::Sentence::Release { .. }
+                if self.transaction.is_none() =>
+            {
                 return Err(Internal("[Exec Transaction] Not in transaction".into()))
             }
-            ast::Sentence::Begin {} => {
+            ast::Sentence::Insert { ref values, .. }
+                if self.max_insert_rows.is_some_and(|max| values.len() > max) =>
+            {
+                let max = self.max_insert_rows.unwrap();
+                Err(Internal(format!(
+                    "[Insert Table] Too many rows in one statement ({} > {}), please insert in batches of at most {} rows",
+                    values.len(),
+                    max,
+                    max
+                )))
+            }
+            ast::Sentence::PurgeTable { .. } if self.transaction.is_some() => Err(Internal(
+                "[Exec Purge Table] Cannot purge a table inside a transaction".into(),
+            )),
+            ast::Sentence::PurgeTable { name } => {
+                let count = self.engine.purge_table(&name)?;
+                Ok(ResultSet::PurgeTable {
+                    table_name: name,
+                    count,
+                })
+            }
+            // 数据库不是事务性的，也不能在事务中切换
+            ast::Sentence::CreateDatabase { .. } | ast::Sentence::UseDatabase { .. }
+                if self.transaction.is_some() =>
+            {
+                Err(Internal(
+                    "[Exec Database] Cannot create or switch database inside a transaction".into(),
+                ))
+            }
+            ast::Sentence::CreateDatabase { name } => {
+                self.databases()?.create(&name)?;
+                Ok(ResultSet::CreateDatabase { name })
+            }
+            ast::Sentence::UseDatabase { name } => {
+                self.engine = self.databases()?.get(&name)?;
+                self.database = Some(name.clone());
+                Ok(ResultSet::UseDatabase { name })
+            }
+            ast::Sentence::DatabaseNames {} => Ok(ResultSet::DatabaseNames {
+                names: self.databases()?.names()?,
+            }),
+            // 引擎的运行状态，每行是一项指标
+            ast::Sentence::ShowStatus {} => {
+                let stats = self.engine.sync_stats()?;
+                let last_sync = match stats.last_sync {
+                    Some(time) => Value::Integer(
+                        time.duration_since(UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs() as i64),
+                    ),
+                    None => Value::Null,
+                };
+                let mut rows = vec![
+                    vec!["sync_count".into(), Value::Integer(stats.count as i64)],
+                    vec!["last_sync".into(), last_sync], // 最近一次刷盘的 unix 时间戳（秒）
+                ];
+                for (name, value) in self.engine.metrics().snapshot() {
+                    rows.push(vec![name.into(), Value::Integer(value as i64)]);
+                }
+                Ok(ResultSet::Scan {
+                    columns: vec!["name".into(), "value".into()],
+                    rows,
+                })
+            }
+            ast::Sentence::Begin { serializable } => {
                 // 处理事务命令
-                let transaction = self.engine.begin()?;
+                let transaction = if serializable {
+                    self.engine.begin_serializable()?
+                } else {
+                    self.engine.begin()?
+                };
                 let version = transaction.get_version();
                 self.transaction = Some(transaction);
                 Ok(ResultSet::Begin { version })
@@ -120,6 +471,24 @@ impl<E: Engine + 'static> Session<E> {
                 transaction.rollback()?;
                 Ok(ResultSet::Rollback { version })
             }
+            ast::Sentence::Savepoint { name } => {
+                self.transaction.as_ref().unwrap().savepoint(&name)?;
+                Ok(ResultSet::Savepoint { name })
+            }
+            ast::Sentence::RollbackTo { name } => {
+                self.transaction
+                    .as_ref()
+                    .unwrap()
+                    .rollback_to_savepoint(&name)?;
+                Ok(ResultSet::RollbackTo { name })
+            }
+            ast::Sentence::Release { name } => {
+                self.transaction
+                    .as_ref()
+                    .unwrap()
+                    .release_savepoint(&name)?;
+                Ok(ResultSet::Release { name })
+            }
             ast::Sentence::Explain { sentence } => {
                 let plan = match self.transaction.as_ref() {
                     Some(_) => {
@@ -128,8 +497,8 @@ impl<E: Engine + 'static> Session<E> {
                         Plan::build(*sentence, self.transaction.as_mut().unwrap())?
                     }
                     None => {
-                        // 手动构建事务
-                        let mut transaction = self.engine.begin()?;
+                        // 手动构建事务，explain 只生成执行计划，不会写入数据
+                        let mut transaction = self.engine.begin_read_only()?;
                         let plan = Plan::build(*sentence, &mut transaction)?;
                         transaction.commit()?;
                         plan
@@ -139,28 +508,49 @@ impl<E: Engine + 'static> Session<E> {
                     plan: plan.0.to_string(),
                 }) // to_string 实际上就是 node 的Display方法
             }
+            // 当前事务已经写过这张表时，另开的事务看不到这些写入，删表也会和它们冲突，直接拒绝
+            ast::Sentence::CreateTable { ref name, .. } | ast::Sentence::DropTable { ref name }
+                if self.ddl_autocommit && self.transaction.is_some() =>
+            {
+                if self.transaction.as_ref().unwrap().has_written_table(name)? {
+                    return Err(Internal(format!(
+                        "[Exec Transaction] Cannot auto-commit DDL on table \" {} \" which the current transaction has written, commit or roll back first",
+                        name
+                    )));
+                }
+                self.execute_autocommit(sentence)
+            }
             sentence if self.transaction.is_some() => {
                 // 在事务内的sql
-                Plan::build(sentence, self.transaction.as_mut().unwrap())?
-                    .execute(self.transaction.as_mut().unwrap())
-            }
-            sentence => {
-                //  获取到了一句无显式事务的sql
-                let mut transaction = self.engine.begin()?; // 开启事务
-
-                // 开始构建plan
-                match Plan::build(sentence, &mut transaction)?.    // 这里获得一个node
-                    execute(&mut transaction)
-                {
-                    Ok(res) => {
-                        transaction.commit()?; // 成功，事务提交
-                        Ok(res)
-                    }
-                    Err(e) => {
-                        transaction.rollback()?; // 失败，事务回滚
-                        Err(e)
-                    }
-                }
+                let transaction = self.transaction.as_mut().unwrap();
+                transaction.set_cancel_token(self.cancel_token.clone());
+                Plan::build(sentence, transaction)?.execute(transaction)
+            }
+            sentence => self.execute_autocommit(sentence),
+        }
+    }
+
+    // 在一个新事务中执行一条语句，成功则提交，失败则回滚
+    fn execute_autocommit(&mut self, sentence: ast::Sentence) -> Result<ResultSet> {
+        // 只读语句使用只读事务，避免每次查询都递增版本号、写入活跃事务列表
+        let mut transaction = if Self::is_read_only(&sentence) {
+            self.engine.begin_read_only()?
+        } else {
+            self.engine.begin()? // 开启事务
+        };
+        transaction.set_cancel_token(self.cancel_token.clone());
+
+        // 开始构建plan
+        match Plan::build(sentence, &mut transaction)?.    // 这里获得一个node
+            execute(&mut transaction)
+        {
+            Ok(res) => {
+                transaction.commit()?; // 成功，事务提交
+                Ok(res)
+            }
+            Err(e) => {
+                transaction.rollback()?; // 失败，事务回滚
+                Err(e)
             }
         }
     }
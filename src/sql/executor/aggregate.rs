@@ -1,16 +1,18 @@
 use crate::error::Error::Internal;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::sql::engine::Transaction;
 use crate::sql::executor::calculate::Calculate;
 use crate::sql::executor::{Executor, ResultSet};
-use crate::sql::parser::ast::Expression;
-use crate::sql::types::{Row, Value};
+use crate::sql::parser::ast::{bind_expression, find_column, Expression};
+use crate::sql::types::{Collation, DataType, Row, Value};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
 pub struct Aggregate<T: Transaction> {
     source: Box<dyn Executor<T>>,
     expressions: Vec<(Expression, Option<String>)>,
     group_by: Option<Expression>,
+    group_collation: Collation,
 }
 
 impl<T: Transaction> Aggregate<T> {
@@ -18,11 +20,13 @@ impl<T: Transaction> Aggregate<T> {
         source: Box<dyn Executor<T>>,
         expressions: Vec<(Expression, Option<String>)>,
         group_by: Option<Expression>,
+        group_collation: Collation,
     ) -> Box<Self> {
         Box::new(Self {
             source,
             expressions,
             group_by,
+            group_collation,
         })
     }
 }
@@ -31,81 +35,81 @@ impl<T: Transaction> Executor<T> for Aggregate<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
         if let ResultSet::Scan { columns, rows } = self.source.execute(transaction)? {
             let mut new_rows = Vec::new();
-            let mut new_cols = Vec::new();
+
+            // group by 的列在结果集中的下标，列名可以带表名，如 group by t1.a
+            let group_pos = match &self.group_by {
+                Some(Expression::Field(col_name)) => match find_column(&columns, col_name)? {
+                    Some(pos) => Some(pos),
+                    None => {
+                        return Err(Error::NotFound(format!(
+                            "The group by column {} does not exist",
+                            col_name
+                        )))
+                    }
+                },
+                _ => None,
+            };
+
+            // 输出的列名在处理任何一组之前按 select 列表的顺序确定，空表分组后没有任何一组，也要返回列名
+            let new_cols = output_columns(&self.expressions, &columns, group_pos)?;
 
             // 为了方便，我们将之前计算聚集函数的过程写为一个闭包函数，供本execute方法内调用
-            let mut calc = |col_value: Option<&Value>, rows: &Vec<Row>| -> Result<Row> {
+            let calc = |col_value: Option<&Value>, rows: &Vec<Row>| -> Result<Row> {
                 let mut new_row = Vec::new();
 
-                for (expr, nick_name) in &self.expressions {
+                for (expr, _) in &self.expressions {
                     match expr {
-                        Expression::Function(func_name, col_name) => {
+                        Expression::Function(func_name, col_name, filter) => {
                             // 聚集函数
                             let calculator = <dyn Calculate>::build(&func_name)?;
-                            let value = calculator.calculate(&col_name, &columns, rows)?;
-
-                            if new_cols.len() < self.expressions.len() {
-                                // 这里需要限制输出的列以select表达式的长度为限
-                                new_cols.push(if let Some(name) = nick_name {
-                                    name.clone()
-                                } else {
-                                    func_name.clone()
-                                }); // 没有别名，默认给agg函数名
-                            }
-                            new_row.push(value);
-                        }
-                        Expression::Field(col_name) => {
-                            // group by的列名
-                            // 需要判断，不可以 select c2 , min(c1) from t group by c3;
-                            if let Some(Expression::Field(group_col)) = &self.group_by {
-                                if *group_col != *col_name {
-                                    return Err(Internal(format!("[Executor] Column {} must appear in GROUP BY or Aggregate function", col_name)));
+                            let value = match filter {
+                                // 有 filter 条件，则只对满足条件的行进行计算
+                                Some(condition) => {
+                                    let rows = filter_rows(condition, &columns, rows)?;
+                                    calculator.calculate(col_name, &columns, &rows)?
                                 }
-                            }
-
-                            if new_cols.len() < self.expressions.len() {
-                                new_cols.push(if let Some(name) = nick_name {
-                                    name.clone()
-                                } else {
-                                    col_name.clone()
-                                });
-                            }
-                            new_row.push(col_value.unwrap().clone());
-                        }
-                        _ => {
-                            return Err(Internal(
-                                "[Executor] Aggregate unexpected expression".into(),
-                            ))
+                                None => calculator.calculate(&col_name, &columns, rows)?,
+                            };
+                            new_row.push(value);
                         }
+                        // group by的列，上面已经校验过，有 group by 时才会出现
+                        _ => new_row.push(col_value.unwrap().clone()),
                     }
                 }
                 Ok(new_row)
             };
 
             // 有无group by是两套不同的处理逻辑
-            if let Some(Expression::Field(col_name)) = &self.group_by {
+            if let Some(pos) = group_pos {
                 // 有group by，则需要对数据进行分组，并进行每组的统计
-                let pos = match columns.iter().position(|c| *c == *col_name) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Internal(format!(
-                            "The group by column {} does not exist",
-                            col_name
-                        )))
-                    }
-                };
-
-                // 创建hash map存储每个分组中不同的数据
-                let mut groups = HashMap::new();
+                // hash map 记录每个分组在 groups 中的下标，groups 按分组首次出现的顺序存储每组的数据
+                // nocase 列按小写分组，大小写不同的值归入同一组，输出组内第一行的原值
+                // 2 和 2.0 相等，应归入同一组：列中出现过浮点数时，整数按浮点数的规范形式分组
+                let group_datatype =
+                    match rows.iter().any(|row| matches!(row[pos], Value::Float(_))) {
+                        true => DataType::Float,
+                        false => DataType::Integer,
+                    };
+                let mut group_index = HashMap::new();
+                let mut groups: Vec<(Value, Vec<Row>)> = Vec::new();
                 for row in rows.iter() {
-                    let key = &row[pos];
-                    let value = groups.entry(key).or_insert(Vec::new());
-                    value.push(row.clone());
+                    let key = self
+                        .group_collation
+                        .normalize(&row[pos].canonical_for(&group_datatype));
+                    let index = *group_index.entry(key.clone()).or_insert_with(|| {
+                        groups.push((key, Vec::new()));
+                        groups.len() - 1
+                    });
+                    groups[index].1.push(row.clone());
                 }
 
+                // 没有 order by 时分组的输出顺序也要确定，不能依赖 hash map 的遍历顺序：
+                // 按分组的值升序输出，NULL 排在最前，不可比较的值保持首次出现的顺序
+                groups.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
                 // 进行计算
-                for (key, row) in groups {
-                    let row = calc(Some(key), &row)?;
+                for (_, row) in groups {
+                    let row = calc(Some(&row[0][pos]), &row)?;
                     new_rows.push(row);
                 }
             } else {
@@ -125,3 +129,55 @@ impl<T: Transaction> Executor<T> for Aggregate<T> {
         ))
     }
 }
+
+// 按 select 列表的顺序生成输出的列名，有别名用别名，聚集函数默认用函数名
+// 同时校验非聚集的列必须是 group by 的列
+fn output_columns(
+    expressions: &[(Expression, Option<String>)],
+    columns: &[String],
+    group_pos: Option<usize>,
+) -> Result<Vec<String>> {
+    let mut new_cols = Vec::with_capacity(expressions.len());
+    for (expr, nick_name) in expressions {
+        let name = match expr {
+            Expression::Function(func_name, _, _) => func_name, // 没有别名，默认给agg函数名
+            Expression::Field(col_name) => {
+                // 需要判断，不可以 select c2 , min(c1) from t group by c3;
+                // 比较的是列的下标，这样 a 和 t1.a 可以指同一列
+                let pos = find_column(columns, col_name)?;
+                if group_pos.is_none() || pos != group_pos {
+                    return Err(Internal(format!(
+                        "[Executor] Column {} must appear in GROUP BY or Aggregate function",
+                        col_name
+                    )));
+                }
+                col_name
+            }
+            _ => {
+                return Err(Internal(
+                    "[Executor] Aggregate unexpected expression".into(),
+                ))
+            }
+        };
+        new_cols.push(nick_name.as_ref().unwrap_or(name).clone());
+    }
+    Ok(new_cols)
+}
+
+// 过滤出满足聚集函数 filter 条件的行
+fn filter_rows(condition: &Expression, columns: &[String], rows: &[Row]) -> Result<Vec<Row>> {
+    let condition = bind_expression(condition, columns, columns)?;
+    let mut res = Vec::new();
+    for row in rows {
+        match condition.evaluate(row, row)? {
+            Value::Null | Value::Boolean(false) => {}
+            Value::Boolean(true) => res.push(row.clone()),
+            _ => {
+                return Err(Internal(
+                    "[Executor Aggregate] Unexpected filter expression".into(),
+                ))
+            }
+        }
+    }
+    Ok(res)
+}
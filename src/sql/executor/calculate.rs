@@ -1,7 +1,13 @@
 use crate::error::*;
+use crate::sql::parser::ast::find_column;
 use crate::sql::types::{Row, Value};
+use std::cmp::Ordering;
 
 // 通用计算接口，供聚集函数使用
+// 聚集函数结果的类型：
+// count 总是整数；avg、stddev、variance 总是浮点数；
+// sum 的输入全部是整数时为整数，超出 i64 范围时报错，含有浮点数时为浮点数；
+// min、max 保持输入的类型，输入中整数和浮点数混合时按数值比较，结果统一为浮点数（和 sum 一致）
 pub trait Calculate {
     fn new(&self) -> Box<dyn Calculate>;
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value>;
@@ -16,8 +22,10 @@ impl dyn Calculate {
             "MIN" => Min::new(&Min),
             "MAX" => Max::new(&Max),
             "AVG" => Avg::new(&Avg),
+            "STDDEV" => Stddev::new(&Stddev),
+            "VARIANCE" | "VAR" => Variance::new(&Variance),
             _ => {
-                return Err(Error::Internal(
+                return Err(Error::NotFound(
                     "[Executor] Unknown aggregate function".into(),
                 ))
             }
@@ -25,6 +33,26 @@ impl dyn Calculate {
     }
 }
 
+// 取出某列所有非null的值
+// 所有聚集函数都忽略null：count 只统计非null的值，没有非null值（全部是null或者没有行）时返回0；
+// 其余函数（sum、min、max、avg、stddev、variance）没有非null值时统一返回null
+fn non_null_values<'a>(col_name: &str, cols: &[String], rows: &'a [Row]) -> Result<Vec<&'a Value>> {
+    let pos = match find_column(cols, col_name)? {
+        Some(pos) => pos,
+        None => {
+            return Err(Error::NotFound(format!(
+                "[Executor] Column {} does not exist",
+                col_name
+            )))
+        }
+    };
+    Ok(rows
+        .iter()
+        .map(|row| &row[pos])
+        .filter(|v| **v != Value::Null)
+        .collect())
+}
+
 // 接下来是agg常见函数定义
 // count
 pub struct Count;
@@ -35,26 +63,43 @@ impl Calculate for Count {
     }
 
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(Error::Internal(format!(
-                    "[Executor] Column {} does not exist",
+        // count(*) 统计所有行，包括含有null的行
+        if col_name == "*" {
+            return Ok(Value::Integer(rows.len() as i64));
+        }
+        // 只统计非null的值，没有非null值时为0
+        let values = non_null_values(col_name, cols, rows)?;
+        Ok(Value::Integer(values.len() as i64))
+    }
+}
+
+// min、max 共用：单次遍历找到最小（或最大）的非null值
+// 整数和浮点数之间按数值大小比较，只要出现浮点数，结果就统一为浮点数，和 sum 的类型规则一致，
+// 这样结果的类型不会因为最值恰好落在整数上而变化；其他不同类型的值之间不可比，报错
+fn extremum(col_name: &str, cols: &[String], rows: &[Row], target: Ordering) -> Result<Value> {
+    let mut res: Option<&Value> = None;
+    let mut has_float = false;
+    for value in non_null_values(col_name, cols, rows)? {
+        has_float |= matches!(value, Value::Float(_));
+        match res.map(|r| value.partial_cmp(r)) {
+            None => res = Some(value),
+            Some(Some(o)) if o == target => res = Some(value),
+            Some(Some(_)) => {}
+            Some(None) => {
+                return Err(Error::Type(format!(
+                    "[Executor] Can not compare values {} and {} of column {}",
+                    res.unwrap(),
+                    value,
                     col_name
                 )))
             }
-        };
-
-        // 找到row[pos]，进行计数，如果是null则不予统计
-        let mut cnt = 0;
-        for row in rows.iter() {
-            if row[pos] != Value::Null {
-                cnt += 1;
-            }
         }
-
-        Ok(Value::Integer(cnt))
     }
+    Ok(match res {
+        None => Value::Null, // 全部是null，没有最值
+        Some(Value::Integer(v)) if has_float => Value::Float(*v as f64),
+        Some(v) => v.clone(),
+    })
 }
 
 // min
@@ -66,30 +111,7 @@ impl Calculate for Min {
     }
 
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(Error::Internal(format!(
-                    "[Executor] Column {} does not exist",
-                    col_name
-                )))
-            }
-        };
-
-        // 如果是null则跳过，如果全部是null则无最小值，返回null
-        let mut min = Value::Null;
-        let mut values = Vec::new();
-        for row in rows.iter() {
-            if row[pos] != Value::Null {
-                values.push(&row[pos]);
-            }
-        }
-        if !values.is_empty() {
-            values.sort_by(|a, b| a.partial_cmp(b).unwrap()); // 和之前的order by排序逻辑一致
-            min = values[0].clone();
-        }
-
-        Ok(min)
+        extremum(col_name, cols, rows, Ordering::Less)
     }
 }
 
@@ -102,31 +124,103 @@ impl Calculate for Max {
     }
 
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(Error::Internal(format!(
-                    "[Executor] Column {} does not exist",
+        extremum(col_name, cols, rows, Ordering::Greater)
+    }
+}
+
+// sum、avg、stddev、variance 共用的数值累加器，单次遍历即可得到所有结果，可以流式地逐个加入值
+// 同时记录输入是否全部是整数：全部是整数时 sum 返回整数（溢出时报错），否则返回浮点数
+// 方差使用 Welford 算法，避免先求平方和再相减带来的精度损失
+#[derive(Default)]
+pub struct NumericAccumulator {
+    count: i64,
+    int_sum: i128, // 整数和，用 i128 累加，中间结果不会溢出，只在输出时检查范围
+    float_sum: f64,
+    all_integer: bool,
+    mean: f64,
+    m2: f64, // 与均值之差的平方和
+}
+
+impl NumericAccumulator {
+    pub fn new() -> Self {
+        Self {
+            all_integer: true,
+            ..Default::default()
+        }
+    }
+
+    // 加入一个值，null 被忽略，非数值类型报错
+    pub fn add(&mut self, col_name: &str, value: &Value) -> Result<()> {
+        let v = match *value {
+            Value::Null => return Ok(()),
+            Value::Integer(v) => {
+                self.int_sum += v as i128;
+                v as f64
+            }
+            Value::Float(v) => {
+                self.all_integer = false;
+                v
+            }
+            _ => {
+                return Err(Error::Type(format!(
+                    "[Executor] Can not calculate sum of column {}",
                     col_name
                 )))
             }
         };
+        self.count += 1;
+        self.float_sum += v;
+        let delta = v - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (v - self.mean);
+        Ok(())
+    }
 
-        // 如果是null则跳过，如果全部是null则无最小值，返回null
-        let mut max = Value::Null;
-        let mut values = Vec::new();
-        for row in rows.iter() {
-            if row[pos] != Value::Null {
-                values.push(&row[pos]);
-            }
+    // 整数之和超出 i64 范围时报错，不能静默地退化为有精度损失的浮点数
+    pub fn sum(&self, col_name: &str) -> Result<Value> {
+        Ok(match (self.count, self.all_integer) {
+            (0, _) => Value::Null,
+            (_, true) => Value::Integer(i64::try_from(self.int_sum).map_err(|_| {
+                Error::Type(format!(
+                    "[Executor] Integer overflow in sum of column {}",
+                    col_name
+                ))
+            })?),
+            _ => Value::Float(self.float_sum),
+        })
+    }
+
+    pub fn avg(&self) -> Value {
+        match self.count {
+            0 => Value::Null,
+            count => Value::Float(self.float_sum / count as f64),
         }
-        if !values.is_empty() {
-            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            max = values[values.len() - 1].clone();
+    }
+
+    // 样本方差，分母为 n - 1，少于两个值时返回null
+    pub fn variance(&self) -> Value {
+        match self.count {
+            0 | 1 => Value::Null,
+            count => Value::Float(self.m2 / (count - 1) as f64),
+        }
+    }
+
+    // 样本标准差
+    pub fn stddev(&self) -> Value {
+        match self.variance() {
+            Value::Float(v) => Value::Float(v.sqrt()),
+            v => v,
         }
+    }
+}
 
-        Ok(max)
+// 对某列的所有值做累加
+fn accumulate(col_name: &str, cols: &[String], rows: &[Row]) -> Result<NumericAccumulator> {
+    let mut acc = NumericAccumulator::new();
+    for value in non_null_values(col_name, cols, rows)? {
+        acc.add(col_name, value)?;
     }
+    Ok(acc)
 }
 
 // sum
@@ -138,46 +232,8 @@ impl Calculate for Sum {
     }
 
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
-        let pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(Error::Internal(format!(
-                    "[Executor] Column {} does not exist",
-                    col_name
-                )))
-            }
-        };
-
-        let mut sum = None;
-        for row in rows.iter() {
-            // 如果是整数或浮点数，统一按浮点数求和。其他类型不可求和
-            match row[pos] {
-                Value::Null => continue,
-                Value::Integer(v) => {
-                    if sum == None {
-                        sum = Some(0.0)
-                    }
-                    sum = Some(sum.unwrap() + v as f64)
-                }
-                Value::Float(v) => {
-                    if sum == None {
-                        sum = Some(0.0)
-                    }
-                    sum = Some(sum.unwrap() + v)
-                }
-                _ => {
-                    return Err(Error::Internal(format!(
-                        "[Executor] Can not calculate sum of column {}",
-                        col_name
-                    )))
-                }
-            }
-        }
-
-        Ok(match sum {
-            Some(sum) => Value::Float(sum),
-            None => Value::Null,
-        })
+        // 全部是整数时结果为整数，含有浮点数时结果为浮点数
+        accumulate(col_name, cols, rows)?.sum(col_name)
     }
 }
 
@@ -190,23 +246,352 @@ impl Calculate for Avg {
     }
 
     fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
-        let _pos = match cols.iter().position(|c| *c == *col_name) {
-            Some(pos) => pos,
-            None => {
-                return Err(Error::Internal(format!(
-                    "[Executor] Column {} does not exist",
-                    col_name
-                )))
-            }
-        };
+        // avg = sum / count，只计算非null的值，结果总是浮点数
+        Ok(accumulate(col_name, cols, rows)?.avg())
+    }
+}
+
+// 样本方差 variance（别名 var）
+pub struct Variance;
+
+impl Calculate for Variance {
+    fn new(&self) -> Box<dyn Calculate> {
+        Box::new(Variance)
+    }
+
+    fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
+        Ok(accumulate(col_name, cols, rows)?.variance())
+    }
+}
+
+// 样本标准差 stddev
+pub struct Stddev;
+
+impl Calculate for Stddev {
+    fn new(&self) -> Box<dyn Calculate> {
+        Box::new(Stddev)
+    }
+
+    fn calculate(&self, col_name: &String, cols: &Vec<String>, rows: &Vec<Row>) -> Result<Value> {
+        Ok(accumulate(col_name, cols, rows)?.stddev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Calculate;
+    use crate::error::{Error, Result};
+    use crate::sql::types::{Row, Value};
+
+    fn calc(func_name: &str, values: Vec<Value>) -> Result<Value> {
+        let cols = vec!["c".to_string()];
+        let rows: Vec<Row> = values.into_iter().map(|v| vec![v]).collect();
+        <dyn Calculate>::build(&func_name.to_string())?.calculate(&"c".to_string(), &cols, &rows)
+    }
+
+    #[test]
+    fn test_null_contract() -> Result<()> {
+        let null = || Value::Null;
+        // (函数, 列值, 期望结果)
+        let cases = vec![
+            // 没有行
+            ("count", vec![], Value::Integer(0)),
+            ("sum", vec![], Value::Null),
+            ("min", vec![], Value::Null),
+            ("max", vec![], Value::Null),
+            ("avg", vec![], Value::Null),
+            // 全部是null
+            ("count", vec![null(), null()], Value::Integer(0)),
+            ("sum", vec![null(), null()], Value::Null),
+            ("min", vec![null(), null()], Value::Null),
+            ("max", vec![null(), null()], Value::Null),
+            ("avg", vec![null(), null()], Value::Null),
+            // 整数列中的null被忽略，avg 的分母不包含null
+            (
+                "count",
+                vec![Value::Integer(1), null(), Value::Integer(4)],
+                Value::Integer(2),
+            ),
+            (
+                "sum",
+                vec![Value::Integer(1), null(), Value::Integer(4)],
+                Value::Integer(5),
+            ),
+            (
+                "min",
+                vec![null(), Value::Integer(4), Value::Integer(1)],
+                Value::Integer(1),
+            ),
+            (
+                "max",
+                vec![Value::Integer(1), Value::Integer(4), null()],
+                Value::Integer(4),
+            ),
+            (
+                "avg",
+                vec![Value::Integer(1), null(), Value::Integer(4)],
+                Value::Float(2.5),
+            ),
+            // 浮点数列
+            ("count", vec![null(), Value::Float(1.5)], Value::Integer(1)),
+            ("sum", vec![null(), Value::Float(1.5)], Value::Float(1.5)),
+            (
+                "min",
+                vec![Value::Float(1.5), null(), Value::Float(-0.5)],
+                Value::Float(-0.5),
+            ),
+            (
+                "max",
+                vec![Value::Float(1.5), null(), Value::Float(-0.5)],
+                Value::Float(1.5),
+            ),
+            (
+                "avg",
+                vec![Value::Float(1.5), null(), Value::Float(0.5)],
+                Value::Float(1.0),
+            ),
+            // 字符串列
+            (
+                "count",
+                vec![Value::String("b".into()), null()],
+                Value::Integer(1),
+            ),
+            ("sum", vec![null()], Value::Null),
+            (
+                "min",
+                vec![Value::String("b".into()), null(), Value::String("a".into())],
+                Value::String("a".into()),
+            ),
+            (
+                "max",
+                vec![Value::String("b".into()), null(), Value::String("a".into())],
+                Value::String("b".into()),
+            ),
+            // 布尔列
+            (
+                "count",
+                vec![Value::Boolean(false), null(), Value::Boolean(true)],
+                Value::Integer(2),
+            ),
+            (
+                "min",
+                vec![Value::Boolean(true), null(), Value::Boolean(false)],
+                Value::Boolean(false),
+            ),
+            (
+                "max",
+                vec![Value::Boolean(false), null(), Value::Boolean(true)],
+                Value::Boolean(true),
+            ),
+        ];
+
+        for (func_name, values, expect) in cases {
+            assert_eq!(
+                calc(func_name, values.clone())?,
+                expect,
+                "{}({:?})",
+                func_name,
+                values
+            );
+        }
 
-        // avg = sum / count
-        let sum = Sum::new(&Sum).calculate(col_name, cols, rows)?;
-        let count = Count::new(&Count).calculate(col_name, cols, rows)?;
-        let avg = match (sum, count) {
-            (Value::Float(s), Value::Integer(c)) => Value::Float(s / c as f64),
-            _ => Value::Null,
+        // 非数值类型不能求和，null 不影响报错
+        assert!(calc("sum", vec![null(), Value::String("a".into())]).is_err());
+        assert!(calc("avg", vec![Value::Boolean(true), null()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_accumulator() -> Result<()> {
+        let null = || Value::Null;
+        let ints = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+        let float = |v: Value| match v {
+            Value::Float(f) => f,
+            v => panic!("expect float, got {:?}", v),
         };
-        Ok(avg)
+
+        // 2,4,4,4,5,5,7,9：均值5，离差平方和32，样本方差 32/7
+        let mut values = ints(vec![2, 4, 4, 4, 5, 5, 7, 9]);
+        values.insert(3, null());
+        values.push(null());
+        assert_eq!(calc("sum", values.clone())?, Value::Integer(40));
+        assert_eq!(calc("avg", values.clone())?, Value::Float(5.0));
+        assert!((float(calc("variance", values.clone())?) - 32.0 / 7.0).abs() < 1e-9);
+        assert!((float(calc("var", values.clone())?) - 32.0 / 7.0).abs() < 1e-9);
+        assert!((float(calc("stddev", values)?) - (32.0f64 / 7.0).sqrt()).abs() < 1e-9);
+
+        // 含有浮点数时 sum 为浮点数
+        let values = vec![
+            Value::Integer(1),
+            Value::Float(2.5),
+            null(),
+            Value::Float(4.5),
+        ];
+        assert_eq!(calc("sum", values.clone())?, Value::Float(8.0));
+        // 均值 8/3，离差平方和 (1-8/3)^2 + (2.5-8/3)^2 + (4.5-8/3)^2 = 37/6，样本方差 37/12
+        assert!((float(calc("variance", values)?) - 37.0 / 12.0).abs() < 1e-9);
+
+        // 整数溢出时报错，中间结果溢出但最终结果在范围内时不报错
+        assert!(matches!(
+            calc("sum", ints(vec![i64::MAX, 1])),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(
+            calc("sum", ints(vec![i64::MIN, -1])),
+            Err(Error::Type(_))
+        ));
+        assert_eq!(
+            calc("sum", ints(vec![i64::MAX, 1, -2]))?,
+            Value::Integer(i64::MAX - 1)
+        );
+        // 出现浮点数之后按浮点数求和，不会溢出
+        assert_eq!(
+            calc("sum", vec![Value::Integer(i64::MAX), Value::Float(1.0)])?,
+            Value::Float(i64::MAX as f64 + 1.0)
+        );
+
+        // 只有一个非null值时样本方差和标准差没有意义，返回null
+        for func_name in ["stddev", "variance"] {
+            assert_eq!(
+                calc(func_name, vec![Value::Integer(3), null()])?,
+                Value::Null
+            );
+            assert_eq!(calc(func_name, vec![null(), null()])?, Value::Null);
+            assert_eq!(calc(func_name, vec![])?, Value::Null);
+            assert!(calc(
+                func_name,
+                vec![Value::String("a".into()), Value::Integer(1)]
+            )
+            .is_err());
+        }
+        assert_eq!(
+            calc("stddev", vec![Value::Float(1.5), Value::Float(1.5)])?,
+            Value::Float(0.0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_min_max_mixed_numeric() -> Result<()> {
+        let null = || Value::Null;
+        // 整数和浮点数混合时按数值比较，结果统一为浮点数
+        let values = vec![
+            Value::Integer(3),
+            Value::Float(2.5),
+            null(),
+            Value::Integer(-1),
+            Value::Float(10.5),
+        ];
+        assert_eq!(calc("min", values.clone())?, Value::Float(-1.0));
+        assert_eq!(calc("max", values)?, Value::Float(10.5));
+
+        let values = vec![Value::Float(1.5), Value::Integer(7), Value::Integer(0)];
+        assert_eq!(calc("min", values.clone())?, Value::Float(0.0));
+        assert_eq!(calc("max", values)?, Value::Float(7.0));
+
+        // 数值相等时结果与顺序无关
+        for values in [
+            vec![Value::Integer(2), Value::Float(2.0)],
+            vec![Value::Float(2.0), Value::Integer(2)],
+        ] {
+            assert_eq!(calc("min", values.clone())?, Value::Float(2.0));
+            assert_eq!(calc("max", values)?, Value::Float(2.0));
+        }
+
+        // 没有浮点数时保持整数
+        let values = vec![Value::Integer(3), null(), Value::Integer(-1)];
+        assert_eq!(calc("min", values.clone())?, Value::Integer(-1));
+        assert_eq!(calc("max", values)?, Value::Integer(3));
+
+        // 不可比的类型报错，而不是panic
+        assert!(calc("min", vec![Value::Integer(1), Value::String("a".into())]).is_err());
+        assert!(calc("max", vec![Value::Boolean(true), Value::Float(1.0)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_result_types() -> Result<()> {
+        let null = || Value::Null;
+        let int_col = vec![
+            Value::Integer(4),
+            null(),
+            Value::Integer(1),
+            Value::Integer(7),
+        ];
+        let float_col = vec![
+            Value::Float(4.0),
+            Value::Float(1.5),
+            null(),
+            Value::Float(0.5),
+        ];
+        let mixed_col = vec![
+            Value::Integer(4),
+            Value::Float(1.5),
+            Value::Integer(1),
+            null(),
+        ];
+        // (函数, 整数列的结果, 浮点数列的结果, 混合列的结果)
+        let cases = vec![
+            (
+                "count",
+                Value::Integer(3),
+                Value::Integer(3),
+                Value::Integer(3),
+            ),
+            (
+                "sum",
+                Value::Integer(12),
+                Value::Float(6.0),
+                Value::Float(6.5),
+            ),
+            (
+                "min",
+                Value::Integer(1),
+                Value::Float(0.5),
+                Value::Float(1.0),
+            ),
+            (
+                "max",
+                Value::Integer(7),
+                Value::Float(4.0),
+                Value::Float(4.0),
+            ),
+            (
+                "avg",
+                Value::Float(4.0),
+                Value::Float(2.0),
+                Value::Float(6.5 / 3.0),
+            ),
+            (
+                "variance",
+                Value::Float(9.0),
+                Value::Float(3.25),
+                Value::Float(31.0 / 12.0),
+            ),
+        ];
+        for (func_name, int_res, float_res, mixed_res) in cases {
+            for (values, expect) in [
+                (&int_col, int_res),
+                (&float_col, float_res),
+                (&mixed_col, mixed_res),
+            ] {
+                let res = calc(func_name, values.clone())?;
+                // Value 的比较中 Integer(1) 和 Float(1.0) 不相等，这里同时校验了类型
+                let same = match (&res, &expect) {
+                    (Value::Float(a), Value::Float(b)) => (a - b).abs() < 1e-9,
+                    (a, b) => a == b,
+                };
+                assert!(
+                    same,
+                    "{}({:?}) = {:?}, expect {:?}",
+                    func_name, values, res, expect
+                );
+            }
+        }
+        assert!(matches!(
+            calc("stddev", int_col)?,
+            Value::Float(v) if (v - 3.0).abs() < 1e-9
+        ));
+        Ok(())
     }
 }
@@ -1,9 +1,11 @@
 use crate::error::Error::Internal;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::sql::engine::Transaction;
 use crate::sql::executor::{Executor, ResultSet};
-use crate::sql::parser::ast::{parse_expression, Expression, Operation};
-use crate::sql::types::Value;
+use crate::sql::parser::ast::{
+    bind_expression, find_column, split_conjuncts, Expression, Operation,
+};
+use crate::sql::types::{Row, Value};
 use std::collections::HashMap;
 
 pub struct NestedLoopJoin<T: Transaction> {
@@ -48,20 +50,23 @@ impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
                 // NestedLoopJoin 即遍历连接
                 new_columns.extend(right_cols.clone());
 
+                // 连接条件只绑定一次
+                let condition = self
+                    .condition
+                    .as_ref()
+                    .map(|condition| bind_expression(condition, &left_cols, &right_cols))
+                    .transpose()?;
+
                 for left_row in &left_rows {
                     let mut flag = false; // 表示左表的数据是否在右表匹配到
                     for right_row in &right_rows {
+                        // 连接的行数可能非常多，每一对行都检查语句是否已被取消
+                        transaction.check_cancelled()?;
                         let mut row = left_row.clone();
 
                         // 如果有Join条件，需要查看是否满足条件，否则不予连接
-                        if let Some(condition) = &self.condition {
-                            match parse_expression(
-                                condition,
-                                &left_cols,
-                                left_row,
-                                &right_cols,
-                                right_row,
-                            )? {
+                        if let Some(condition) = &condition {
+                            match condition.evaluate(left_row, right_row)? {
                                 Value::Null => continue, // 本次连接不匹配
                                 Value::Boolean(false) => continue,
                                 Value::Boolean(true) => {
@@ -108,6 +113,7 @@ pub struct HashJoin<T: Transaction> {
     left: Box<dyn Executor<T>>,
     right: Box<dyn Executor<T>>,
     condition: Option<Expression>,
+    residual: Option<Expression>,
     outer: bool,
 }
 
@@ -116,12 +122,14 @@ impl<T: Transaction> HashJoin<T> {
         left: Box<dyn Executor<T>>,
         right: Box<dyn Executor<T>>,
         condition: Option<Expression>,
+        residual: Option<Expression>,
         outer: bool,
     ) -> Box<Self> {
         Box::new(Self {
             left,
             right,
             condition,
+            residual,
             outer,
         })
     }
@@ -145,65 +153,100 @@ impl<T: Transaction> Executor<T> for HashJoin<T> {
             {
                 new_cols.extend(right_cols.clone());
 
-                // 解析HashJoin条件，即拿到左右两列的列名
-                let (lcol, rcol) = match parse_join_condition(self.condition) {
-                    Some(res) => res,
+                // 解析HashJoin条件，即拿到每一对连接列的列名，以及是否是 NULL 安全的等值比较
+                let keys = match parse_join_condition(self.condition) {
+                    Some(keys) => keys,
                     None => return Err(Internal(
                         "[Executor] Failed to parse join condition, please recheck column names"
                             .into(),
                     )),
                 };
 
-                // 拿到连接列在表中的位置
-                let left_pos = match left_cols.iter().position(|c| *c == lcol) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Internal(format!(
-                            "[Executor] Column {} does not exist",
-                            lcol
-                        )))
-                    }
-                };
-
-                let right_pos = match right_cols.iter().position(|c| *c == rcol) {
-                    Some(pos) => pos,
-                    None => {
-                        return Err(Internal(format!(
-                            "[Executor] Column {} does not exist",
-                            rcol
-                        )))
-                    }
+                // 拿到每一对连接列在表中的位置
+                // 条件可能写成 右表列 = 左表列（如 right join 交换了左右表），此时交换一下再找
+                let mut key_pos = Vec::with_capacity(keys.len());
+                for (lcol, rcol, null_safe) in &keys {
+                    let pos = match (
+                        find_column(&left_cols, lcol)?,
+                        find_column(&right_cols, rcol)?,
+                    ) {
+                        (Some(l), Some(r)) => (l, r),
+                        _ => match (
+                            find_column(&left_cols, rcol)?,
+                            find_column(&right_cols, lcol)?,
+                        ) {
+                            (Some(l), Some(r)) => (l, r),
+                            _ => {
+                                return Err(Error::NotFound(format!(
+                                    "[Executor] Column {} or {} does not exist",
+                                    lcol, rcol
+                                )))
+                            }
+                        },
+                    };
+                    key_pos.push((pos.0, pos.1, *null_safe));
+                }
+                // 一行的连接键：按顺序取出每个连接列的值
+                // = 比较时 NULL 和任何值都不相等，有这样的连接列为 NULL 时这一行不会匹配，返回 None；<=> 时 NULL 之间互相匹配
+                let join_key = |row: &Row, left: bool| -> Option<Vec<Value>> {
+                    key_pos
+                        .iter()
+                        .map(|&(l, r, null_safe)| {
+                            let value = &row[if left { l } else { r }];
+                            match !null_safe && *value == Value::Null {
+                                true => None,
+                                false => Some(value.clone()),
+                            }
+                        })
+                        .collect()
                 };
 
                 // 构建hash表（右），key 为 连接列的值， value为对应的一行数据
                 // 可能一个key有不止一行数据，所以用列表存
                 let mut map = HashMap::new();
                 for row in &right_rows {
-                    let rows = map.entry(row[right_pos].clone()).or_insert(Vec::new());
-                    rows.push(row.clone());
+                    if let Some(key) = join_key(row, false) {
+                        map.entry(key).or_insert(Vec::new()).push(row.clone());
+                    }
                 }
 
-                // 扫描左表进行匹配
+                // 等值条件之外的其他条件，对等值匹配上的每一对行求值
+                let residual = self
+                    .residual
+                    .as_ref()
+                    .map(|residual| bind_expression(residual, &left_cols, &right_cols))
+                    .transpose()?;
+
+                // 扫描左表进行匹配，结果保持左表的顺序，同一左行匹配到的多行保持右表的顺序
                 for row in left_rows {
-                    match map.get(&row[left_pos]) {
-                        // 尝试与右表数据匹配
-                        Some(rows) => {
-                            for a_row in rows {
-                                let mut row = row.clone();
-                                row.extend(a_row.clone());
-                                new_rows.push(row);
-                            }
-                        }
-                        None => {
-                            // 未匹配到，如果是外连接需要展示为null
-                            if self.outer {
-                                let mut row = row.clone();
-                                for _ in 0..right_cols.len() {
-                                    row.push(Value::Null);
+                    transaction.check_cancelled()?;
+                    let mut matched = false;
+                    // 尝试与右表数据匹配
+                    let matches = join_key(&row, true).and_then(|key| map.get(&key));
+                    for a_row in matches.into_iter().flatten() {
+                        if let Some(residual) = &residual {
+                            match residual.evaluate(&row, a_row)? {
+                                Value::Null | Value::Boolean(false) => continue,
+                                Value::Boolean(true) => {}
+                                _ => {
+                                    return Err(Internal(
+                                        "[Executor] Unexpected expression".to_string(),
+                                    ))
                                 }
-                                new_rows.push(row);
                             }
                         }
+                        matched = true;
+                        let mut new_row = row.clone();
+                        new_row.extend(a_row.clone());
+                        new_rows.push(new_row);
+                    }
+                    // 未匹配到，如果是外连接需要展示为null
+                    if !matched && self.outer {
+                        let mut row = row.clone();
+                        for _ in 0..right_cols.len() {
+                            row.push(Value::Null);
+                        }
+                        new_rows.push(row);
                     }
                 }
                 return Ok(ResultSet::Scan {
@@ -219,30 +262,50 @@ impl<T: Transaction> Executor<T> for HashJoin<T> {
     }
 }
 
-// 解析join条件，获取左右两列
+// 解析join条件，条件是一个或者多个用 and 连接的两列的等值比较，获取每一对左右两列，以及是否是 <=> 比较
 // 思路和index的条件判断一致
-fn parse_join_condition(condition: Option<Expression>) -> Option<(String, String)> {
-    match condition {
-        Some(expr) => {
-            match expr {
-                // 解析列名
-                Expression::Field(col) => Some((col, "".into())),
-                Expression::Operation(operation) => {
-                    match operation {
-                        Operation::Equal(col1, col2) => {
-                            // 递归调用进行解析
-                            let left = parse_join_condition(Some(*col1));
-                            let right = parse_join_condition(Some(*col2));
-
-                            // 左右均为为(col, "")，现在进行组合
-                            Some((left.unwrap().0, right.unwrap().0))
-                        }
-                        _ => None,
-                    }
-                }
+fn parse_join_condition(condition: Option<Expression>) -> Option<Vec<(String, String, bool)>> {
+    split_conjuncts(condition?)
+        .into_iter()
+        .map(|condition| {
+            let (l, r, null_safe) = match condition {
+                Expression::Operation(Operation::Equal(l, r)) => (l, r, false),
+                Expression::Operation(Operation::NullSafeEqual(l, r)) => (l, r, true),
+                _ => return None,
+            };
+            match (*l, *r) {
+                (Expression::Field(lcol), Expression::Field(rcol)) => Some((lcol, rcol, null_safe)),
                 _ => None,
             }
+        })
+        .collect()
+}
+
+// 给输入的列名加上表名，如 a -> t1.a
+pub struct Qualify<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    table_name: String,
+}
+
+impl<T: Transaction> Qualify<T> {
+    pub fn new(source: Box<dyn Executor<T>>, table_name: String) -> Box<Self> {
+        Box::new(Self { source, table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Qualify<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
+                columns: columns
+                    .into_iter()
+                    .map(|c| format!("{}.{}", self.table_name, c))
+                    .collect(),
+                rows,
+            }),
+            _ => Err(Internal(
+                "[Executor] Unexpected ResultSet, expected Scan Node".to_string(),
+            )),
         }
-        None => None,
     }
 }
@@ -0,0 +1,65 @@
+use crate::error::Result;
+use crate::sql::engine::Transaction;
+use crate::sql::executor::{Executor, ResultSet};
+use crate::sql::types::Value;
+
+// kv get 'key'，返回一行 key, value，不存在时 value 为 NULL
+pub struct KvGet {
+    key: String,
+}
+
+impl KvGet {
+    pub fn new(key: String) -> Box<Self> {
+        Box::new(Self { key })
+    }
+}
+
+impl<T: Transaction> Executor<T> for KvGet {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        let value = match transaction.meta_get(&self.key)? {
+            Some(value) => Value::String(String::from_utf8_lossy(&value).into_owned()),
+            None => Value::Null,
+        };
+        Ok(ResultSet::Scan {
+            columns: vec!["key".into(), "value".into()],
+            rows: vec![vec![Value::String(self.key), value]],
+        })
+    }
+}
+
+// kv put 'key' 'value'，已存在则覆盖
+pub struct KvPut {
+    key: String,
+    value: String,
+}
+
+impl KvPut {
+    pub fn new(key: String, value: String) -> Box<Self> {
+        Box::new(Self { key, value })
+    }
+}
+
+impl<T: Transaction> Executor<T> for KvPut {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        transaction.meta_set(&self.key, self.value.into_bytes())?;
+        Ok(ResultSet::KvPut { key: self.key })
+    }
+}
+
+// kv delete 'key'
+pub struct KvDelete {
+    key: String,
+}
+
+impl KvDelete {
+    pub fn new(key: String) -> Box<Self> {
+        Box::new(Self { key })
+    }
+}
+
+impl<T: Transaction> Executor<T> for KvDelete {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        transaction.meta_delete(&self.key)?;
+        Ok(ResultSet::KvDelete { key: self.key })
+    }
+}
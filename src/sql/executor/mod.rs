@@ -1,6 +1,7 @@
 mod aggregate;
 mod calculate;
 mod join;
+mod meta;
 mod mutation;
 mod query;
 mod schema;
@@ -9,22 +10,26 @@ mod show;
 use crate::error::Result;
 use crate::sql::engine::Transaction;
 use crate::sql::executor::aggregate::Aggregate;
-use crate::sql::executor::join::{HashJoin, NestedLoopJoin};
+use crate::sql::executor::join::{HashJoin, NestedLoopJoin, Qualify};
+use crate::sql::executor::meta::{KvDelete, KvGet, KvPut};
 use crate::sql::executor::mutation::{Delete, Insert, Update};
 use crate::sql::executor::query::{
-    Having, Limit, Offset, Order, PkIndex, Projection, Scan, ScanIndex,
+    Filter, Limit, LockRows, Nothing, Offset, Order, PkIndex, Projection, Scan, ScanIndex,
 };
 use crate::sql::executor::schema::{CreateTable, DropTable};
-use crate::sql::executor::show::{TableNames, TableSchema};
+use crate::sql::executor::show::{
+    CheckDatabase, ChecksumTable, ShowCreateTable, ShowIndexes, TableNames, TableSchema,
+};
 use crate::sql::planner::Node;
 use crate::sql::types::Row;
+use serde::{Deserialize, Serialize};
 
 pub trait Executor<T: Transaction> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet>;
 }
 
-// 执行结果集的定义
-#[derive(Debug, PartialEq, Clone)]
+// 执行结果集的定义，可以序列化之后通过网络传输或者缓存
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum ResultSet {
     CreateTable {
         table_name: String, // 创建表成功，则返回表名
@@ -32,6 +37,10 @@ pub enum ResultSet {
     DropTable {
         table_name: String,
     },
+    PurgeTable {
+        table_name: String,
+        count: usize, // 物理删除的数据条目数
+    },
     Insert {
         count: usize, // 插入表成功，则返回插入数
     },
@@ -48,9 +57,30 @@ pub enum ResultSet {
     TableSchema {
         schema: String,
     },
+    ShowCreateTable {
+        sql: String,
+    },
+    ChecksumTable {
+        table_name: String,
+        rows: usize,   // 参与计算的行数
+        checksum: u64, // 表中数据的校验和
+    },
+    CheckDatabase {
+        entries: usize,        // 检查的数据条数
+        problems: Vec<String>, // 无法解码的数据，每条一个错误信息
+    },
     TableNames {
         names: Vec<String>,
     },
+    CreateDatabase {
+        name: String,
+    },
+    UseDatabase {
+        name: String,
+    },
+    DatabaseNames {
+        names: Vec<String>,
+    },
     Begin {
         version: u64,
     },
@@ -60,34 +90,67 @@ pub enum ResultSet {
     Rollback {
         version: u64,
     },
+    Savepoint {
+        name: String,
+    },
+    RollbackTo {
+        name: String,
+    },
+    Release {
+        name: String,
+    },
     Explain {
         plan: String,
     },
+    KvPut {
+        key: String,
+    },
+    KvDelete {
+        key: String,
+    },
 }
 
 impl ResultSet {
     pub fn to_string(&self) -> String {
+        self.to_string_with_width(None)
+    }
+
+    // 以表格形式展示结果，max_width 限制扫描结果每列内容的最大宽度，超出的部分用 ... 代替
+    pub fn to_string_with_width(&self, max_width: Option<usize>) -> String {
         match self {
             ResultSet::CreateTable { table_name } => format!("CREATE TABLE {}", table_name), // 创建成功提示
             ResultSet::DropTable { table_name } => format!("DROP TABLE {}", table_name),
+            ResultSet::PurgeTable { table_name, count } => {
+                format!("PURGE TABLE {}, {} entries reclaimed", table_name, count)
+            }
             ResultSet::Insert { count } => format!("INSERT {} rows", count), // 插入成功提示
             ResultSet::Scan { columns, rows } => {
                 // 返回扫描结果
                 let rows_len = rows.len(); // 一共多少行
 
+                // 先把每个值转为要展示的字符串，超出最大宽度的截断
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|v| truncate_cell(v.to_string(), max_width))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+
                 // 先找到列名的长度
                 let mut max_len = columns.iter().map(|c| c.len()).collect::<Vec<usize>>();
                 // 然后将列名和行数据进行比较，选出最长的那个
-                for a_row in rows {
+                for a_row in &rows {
                     for (i, v) in a_row.iter().enumerate() {
                         // 确保 i 在 max_len.len() 范围内
                         if i < max_len.len() {
-                            if v.to_string().len() > max_len[i] {
-                                max_len[i] = v.to_string().len();
+                            if v.len() > max_len[i] {
+                                max_len[i] = v.len();
                             }
                         } else {
                             // 如果发现列数不匹配，扩展 max_len
-                            max_len.push(v.to_string().len());
+                            max_len.push(v.len());
                         }
                     }
                 }
@@ -113,7 +176,7 @@ impl ResultSet {
                     .map(|row| {
                         row.iter()
                             .zip(max_len.iter())
-                            .map(|(v, &len)| format!("{:width$}", v.to_string(), width = len))
+                            .map(|(v, &len)| format!("{:width$}", v, width = len))
                             .collect::<Vec<_>>()
                             .join(" |")
                     })
@@ -125,6 +188,24 @@ impl ResultSet {
             ResultSet::Update { count } => format!("UPDATE {} rows", count), // 更新成功提示
             ResultSet::Delete { count } => format!("DELETE {} rows", count), // 删除成功提示
             ResultSet::TableSchema { schema } => format!("{}", schema),
+            ResultSet::ShowCreateTable { sql } => sql.clone(),
+            ResultSet::ChecksumTable {
+                table_name,
+                rows,
+                checksum,
+            } => format!(
+                "CHECKSUM TABLE {}: {:016x} ({} rows)",
+                table_name, checksum, rows
+            ),
+            ResultSet::CheckDatabase { entries, problems } => {
+                let mut lines = vec![format!(
+                    "CHECK DATABASE: {} entries checked, {} undecodable",
+                    entries,
+                    problems.len()
+                )];
+                lines.extend(problems.iter().cloned());
+                lines.join("\n")
+            }
             ResultSet::TableNames { names } => {
                 if names.is_empty() {
                     "No tables found.".to_string()
@@ -132,11 +213,39 @@ impl ResultSet {
                     names.join("\n")
                 }
             }
+            ResultSet::CreateDatabase { name } => format!("CREATE DATABASE {}", name),
+            ResultSet::UseDatabase { name } => format!("USE DATABASE {}", name),
+            ResultSet::DatabaseNames { names } => {
+                if names.is_empty() {
+                    "No databases found.".to_string()
+                } else {
+                    names.join("\n")
+                }
+            }
             ResultSet::Begin { version } => format!("TRANSACTION {} BEGIN", version),
             ResultSet::Commit { version } => format!("TRANSACTION {} COMMIT", version),
             ResultSet::Rollback { version } => format!("TRANSACTION {} ROLLBACK", version),
+            ResultSet::Savepoint { name } => format!("SAVEPOINT {}", name),
+            ResultSet::RollbackTo { name } => format!("ROLLBACK TO SAVEPOINT {}", name),
+            ResultSet::Release { name } => format!("RELEASE SAVEPOINT {}", name),
             ResultSet::Explain { plan } => plan.to_string(),
+            ResultSet::KvPut { key } => format!("KV PUT {}", key),
+            ResultSet::KvDelete { key } => format!("KV DELETE {}", key),
+        }
+    }
+}
+
+// 截断超出最大宽度的单元格内容，截断后的内容（包括 ...）不超过最大宽度
+fn truncate_cell(cell: String, max_width: Option<usize>) -> String {
+    const ELLIPSIS: &str = "...";
+    match max_width {
+        Some(max) if cell.chars().count() > max => {
+            let keep = max.saturating_sub(ELLIPSIS.len());
+            let mut truncated = cell.chars().take(keep).collect::<String>();
+            truncated.push_str(&ELLIPSIS[..max.min(ELLIPSIS.len())]);
+            truncated
         }
+        _ => cell,
     }
 }
 
@@ -150,14 +259,23 @@ impl<T: Transaction + 'static> dyn Executor<T> {
                 columns,
                 values,
             } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name, filter } => Scan::new(table_name, filter),
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                reverse,
+            } => Scan::new(table_name, filter, columns, reverse),
             Node::Update {
                 table_name,
                 scan,
                 columns,
             } => Update::new(table_name, Self::build(*scan), columns),
             Node::Delete { table_name, scan } => Delete::new(table_name, Self::build(*scan)),
-            Node::OrderBy { scan, order_by } => Order::new(Self::build(*scan), order_by),
+            Node::OrderBy {
+                scan,
+                order_by,
+                collations,
+            } => Order::new(Self::build(*scan), order_by, collations),
             Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
             Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
             Node::Projection {
@@ -169,27 +287,155 @@ impl<T: Transaction + 'static> dyn Executor<T> {
                 right,
                 condition,
                 outer,
-            } => NestedLoopJoin::new(Self::build(*left), Self::build(*right), condition, outer),
+            } => NestedLoopJoin::new(
+                Self::build_join_input(*left),
+                Self::build_join_input(*right),
+                condition,
+                outer,
+            ),
             Node::Aggregate {
                 source,
                 expression,
                 group_by,
-            } => Aggregate::new(Self::build(*source), expression, group_by),
-            Node::Having { source, condition } => Having::new(Self::build(*source), condition),
+                group_collation,
+            } => Aggregate::new(Self::build(*source), expression, group_by, group_collation),
+            Node::Having { source, condition } | Node::Filter { source, condition } => {
+                Filter::new(Self::build(*source), condition)
+            }
             Node::TableSchema { name } => TableSchema::new(&name),
+            Node::ShowCreateTable { name } => ShowCreateTable::new(&name),
+            Node::ShowIndexes { name } => ShowIndexes::new(&name),
+            Node::ChecksumTable { name } => ChecksumTable::new(&name),
+            Node::CheckDatabase {} => CheckDatabase::new(),
             Node::TableNames {} => TableNames::new(),
+            Node::LockRows { source, table_name } => {
+                LockRows::new(Self::build(*source), table_name)
+            }
+            Node::KvGet { key } => KvGet::new(key),
+            Node::KvPut { key, value } => KvPut::new(key, value),
+            Node::KvDelete { key } => KvDelete::new(key),
             Node::ScanIndex {
                 table_name,
                 col_name,
                 value,
-            } => ScanIndex::new(table_name, col_name, value),
+                columns,
+            } => ScanIndex::new(table_name, col_name, value, columns),
             Node::PkIndex { table_name, value } => PkIndex::new(table_name, value),
+            Node::Nothing { columns } => Nothing::new(columns),
             Node::HashJoin {
                 left,
                 right,
                 condition,
+                residual,
                 outer,
-            } => HashJoin::new(Self::build(*left), Self::build(*right), condition, outer),
+            } => HashJoin::new(
+                Self::build_join_input(*left),
+                Self::build_join_input(*right),
+                condition,
+                residual,
+                outer,
+            ),
+        }
+    }
+
+    // 连接的输入如果是直接扫描的表，需要给输出列加上表名，这样才能用 t1.a 区分不同表的同名列
+    fn build_join_input(node: Node) -> Box<dyn Executor<T>> {
+        match node.scan_table().cloned() {
+            Some(table_name) => Qualify::new(Self::build(node), table_name),
+            None => Self::build(node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResultSet;
+    use crate::error::Result;
+    use crate::sql::types::Value;
+
+    fn round_trip(result: ResultSet) -> Result<()> {
+        let bytes = bincode::serialize(&result)?;
+        assert_eq!(bincode::deserialize::<ResultSet>(&bytes)?, result);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_round_trip() -> Result<()> {
+        let name = || "t".to_string();
+        for result in [
+            ResultSet::CreateTable { table_name: name() },
+            ResultSet::DropTable { table_name: name() },
+            ResultSet::PurgeTable {
+                table_name: name(),
+                count: 3,
+            },
+            ResultSet::Insert { count: 1 },
+            ResultSet::Scan {
+                columns: vec![],
+                rows: vec![],
+            },
+            ResultSet::Update { count: 2 },
+            ResultSet::Delete { count: 0 },
+            ResultSet::TableSchema {
+                schema: "TABLE t (\n    a INTEGER PRIMARY KEY\n)".into(),
+            },
+            ResultSet::ShowCreateTable {
+                sql: "CREATE TABLE t (a INTEGER PRIMARY KEY);".into(),
+            },
+            ResultSet::ChecksumTable {
+                table_name: name(),
+                rows: 2,
+                checksum: u64::MAX,
+            },
+            ResultSet::CheckDatabase {
+                entries: 3,
+                problems: vec!["[KV Engine] Failed to decode".into()],
+            },
+            ResultSet::TableNames {
+                names: vec![name(), "u".into()],
+            },
+            ResultSet::CreateDatabase { name: name() },
+            ResultSet::UseDatabase { name: name() },
+            ResultSet::DatabaseNames { names: vec![] },
+            ResultSet::Begin { version: 1 },
+            ResultSet::Commit { version: u64::MAX },
+            ResultSet::Rollback { version: 0 },
+            ResultSet::Savepoint { name: name() },
+            ResultSet::RollbackTo { name: name() },
+            ResultSet::Release { name: name() },
+            ResultSet::Explain {
+                plan: "Sequence Scan On Table t".into(),
+            },
+            ResultSet::KvPut { key: "k".into() },
+            ResultSet::KvDelete { key: "".into() },
+        ] {
+            round_trip(result)?;
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_serde_scan_mixed_types() -> Result<()> {
+        round_trip(ResultSet::Scan {
+            columns: vec!["a".into(), "b".into(), "c".into(), "d".into(), "e".into()],
+            rows: vec![
+                vec![
+                    Value::Integer(i64::MIN),
+                    Value::Float(-0.5),
+                    Value::String("中文 'quoted'".into()),
+                    Value::Boolean(true),
+                    Value::Null,
+                ],
+                vec![
+                    Value::Null,
+                    Value::Float(f64::INFINITY),
+                    Value::String("".into()),
+                    Value::Boolean(false),
+                    Value::Integer(0),
+                ],
+                // 行的长度可以不同
+                vec![Value::Integer(1)],
+            ],
+        })
     }
 }
@@ -3,9 +3,9 @@ use crate::error::{Error, Result};
 use crate::sql::engine::Transaction;
 use crate::sql::executor::{Executor, ResultSet};
 use crate::sql::parser::ast::Expression;
-use crate::sql::schema::Table;
+use crate::sql::schema::{error_at_row, Table};
 use crate::sql::types::{Row, Value};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashSet};
 
 pub struct Insert {
     table_name: String,
@@ -32,84 +32,128 @@ impl<T: Transaction> Executor<T> for Insert {
         // 插入表之前，表必须是存在的
         let table = transaction.must_get_table(self.table_name.clone())?;
 
-        // ResultSet成功结果返回插入行数
-        let mut count = 0;
+        // 多行插入走批量写入，单行插入直接写入
+        let count = self.values.len();
+        let mut rows = Vec::with_capacity(count);
+
+        // 插入列和表中列的对应关系只和语句有关，每条语句计算一次
+        let layout = RowLayout::new(&table, &self.columns);
 
         // 现在手上表的数据类型是values:Vec<Vec<Expression>>,我们需要进行一些操作
-        for exprs in self.values {
+        for (i, exprs) in self.values.into_iter().enumerate() {
             // 1. 先将 Vec<Expression> 转换为 Row，即Vec<Value>
-            let row = exprs
+            // 2. 按表中列的顺序排列，补全未给出的列并检查，多行插入时错误信息中标明是第几行
+            let insert_row = exprs
                 .into_iter()
-                .map(|e| Value::from_expression_to_value(e))
-                .collect::<Vec<Value>>();
-
-            // 2. 可选项：是否指定了插入的列
-            let insert_row = if self.columns.is_empty() {
-                // 未指定插入列
-                complete_row(&table, &row)?
-            } else {
-                // 指定插入列
-                modify_row(&table, &self.columns, &row)?
-            };
-            transaction.create_row(self.table_name.clone(), insert_row)?;
-            count += 1;
+                .map(Value::from_expression_to_value)
+                .collect::<Result<Vec<Value>>>()
+                .map_err(|e| e.map_message(|msg| format!("[Insert Table] {}", msg)))
+                .and_then(|row| layout.build(row))
+                .and_then(|values| table.build_row(values))
+                .map_err(|e| match count {
+                    1 => e,
+                    _ => error_at_row(e, i + 1, count),
+                })?;
+            rows.push(insert_row);
+        }
+        if count == 1 {
+            transaction.create_row(self.table_name, rows.pop().unwrap())?;
+        } else {
+            transaction.create_rows(self.table_name, rows)?;
         }
         Ok(ResultSet::Insert { count })
     }
 }
 
 // 辅助判断方法
-// 1. 补全列，即列对齐
-fn complete_row(table: &Table, row: &Row) -> Result<Row> {
-    let mut res = row.clone();
-    for column in table.columns.iter().skip(row.len()) {
-        // 跳过已经给定数据的列
-        if let Some(default) = &column.default {
-            // 有默认值
-            res.push(default.clone());
-        } else {
-            // 建表时没有默认值但是insert时又没给数据
-            return Err(Error::Internal(format!(
-                "[Insert Table] Column \" {} \" has no default value",
-                column.name
-            )));
-        }
-    }
-    Ok(res)
+// 1. 插入行的布局：由 insert 语句指定的列计算出表中每一列的值从哪里来
+struct RowLayout<'a> {
+    table: &'a Table,
+    // 指定了插入列时，表中每一列对应的值在插入行中的下标，None 表示使用默认值
+    positions: Option<Vec<Option<usize>>>,
+    num_values: usize, // 指定的插入列数，每行的值的个数必须与之一致
 }
 
-// 2. 调整列信息并补全
-fn modify_row(table: &Table, columns: &Vec<String>, values: &Row) -> Result<Row> {
-    // 首先先判断给的列数和values的数量是否是一致的：
-    if columns.len() != values.len() {
-        return Err(Error::Internal(
-            "[Insert Table] Mismatch num of columns and values".to_string(),
-        ));
+impl<'a> RowLayout<'a> {
+    fn new(table: &'a Table, columns: &[String]) -> Self {
+        let positions = if columns.is_empty() {
+            None
+        } else {
+            // 同一列被指定多次时，使用最后一次给出的值
+            Some(
+                table
+                    .columns
+                    .iter()
+                    .map(|col| columns.iter().rposition(|c| *c == col.name))
+                    .collect(),
+            )
+        };
+        Self {
+            table,
+            positions,
+            num_values: columns.len(),
+        }
     }
 
-    // 有可能顺序是乱的，但是返回时顺序不能乱，这里考虑使用hash
-    let mut inputs = HashMap::new();
-    for (i, col_name) in columns.iter().enumerate() {
-        // enumerate()用于为迭代中的每个元素附加一个索引值
-        inputs.insert(col_name, values[i].clone());
+    // 按表中列的顺序排列插入行的值，None 表示没有给出值的列
+    fn build(&self, row: Row) -> Result<Vec<Option<Value>>> {
+        match &self.positions {
+            // 未指定插入列，给出的值依次对应表中的列，没有给出的列在最后
+            None => {
+                if row.len() > self.table.columns.len() {
+                    self.table.check_width(row.len())?;
+                }
+                let missing = self.table.columns.len() - row.len();
+                Ok(row
+                    .into_iter()
+                    .map(Some)
+                    .chain(std::iter::repeat_n(None, missing))
+                    .collect())
+            }
+            Some(positions) => self.modify_row(positions, row),
+        }
     }
 
-    // 现在inputs就是顺序正常的插入行，之后和complete_row()思路差不多了
-    let mut res = Vec::new();
-    for col in table.columns.iter() {
-        if let Some(value) = inputs.get(&col.name) {
-            res.push(value.clone());
-        } else if let Some(default) = &col.default {
-            res.push(default.clone());
-        } else {
-            return Err(Error::Internal(format!(
-                "[Insert Table] Column \" {} \" has no default value",
-                col.name
-            )));
+    // 调整列信息
+    fn modify_row(
+        &self,
+        positions: &[Option<usize>],
+        mut values: Row,
+    ) -> Result<Vec<Option<Value>>> {
+        // 首先先判断给的列数和values的数量是否是一致的：
+        if self.num_values != values.len() {
+            return Err(Error::Schema(
+                "[Insert Table] Mismatch num of columns and values".to_string(),
+            ));
         }
+
+        // 按表中列的顺序，从插入行中按下标取值，每个下标最多被取一次
+        Ok(positions
+            .iter()
+            .map(|pos| pos.map(|i| std::mem::replace(&mut values[i], Value::Null)))
+            .collect())
     }
+}
 
-    Ok(res)
+// 规整一行数据：数值转为列类型的规范形式（浮点数列存浮点数，整数列存整数），decimal 列按精度规整
+fn normalize_row(table: &Table, row: Row) -> Result<Row> {
+    table
+        .columns
+        .iter()
+        .zip(row)
+        .map(|(col, value)| col.apply_precision(value.canonical_for(&col.datatype)))
+        .collect()
+}
+
+// 获取列的默认值，用于 update 的 set col = default
+fn column_default(table: &Table, col_name: &str) -> Result<Value> {
+    let column = &table.columns[table.get_col_index(col_name)?];
+    column.omitted_value().ok_or_else(|| {
+        Error::Schema(format!(
+            "[Update Table] Column \" {} \" has no default value",
+            column.name
+        ))
+    })
 }
 
 pub struct Update<T: Transaction> {
@@ -140,19 +184,34 @@ impl<T: Transaction> Executor<T> for Update<T> {
             ResultSet::Scan { columns, rows } => {
                 // 处理更新流程
                 let table = transaction.must_get_table(self.table_name.clone())?;
+                // 本语句已经处理过的主键，包括更新前和更新后的主键
+                // 扫描结果目前是一次性读出的，被更新过的行不会再次出现；但如果扫描改为流式的，
+                // 修改了主键的行可能在扫描后面的位置被再次读到，这里跳过这些行，保证每行只更新一次
+                let mut processed = HashSet::new();
                 // 遍历每行，更新列数据
                 for row in rows {
-                    let mut new_row = row.clone();
                     let primary_key = table.get_primary_key(&row)?;
+                    if processed.contains(&primary_key) {
+                        continue;
+                    }
+                    let mut new_row = row.clone();
                     for (i, col) in columns.iter().enumerate() {
                         if let Some(expression) = self.columns.get(col) {
                             // 如果本列需要修改
-                            new_row[i] = Value::from_expression_to_value(expression.clone());
+                            new_row[i] = match expression {
+                                Expression::Default => column_default(&table, col)?,
+                                _ => Value::from_expression_to_value(expression.clone()).map_err(
+                                    |e| e.map_message(|msg| format!("[Update Table] {}", msg)),
+                                )?,
+                            };
                         }
                     }
                     // 如果涉及了主键的更新，由于我们存储时用的是表名和主键一起作为key，所以这里需要删了重新建key
                     // 否则，key部分(table_name, primary_key) 不动，直接变value即可
+                    let new_row = normalize_row(&table, new_row)?;
+                    processed.insert(table.get_primary_key(&new_row)?);
                     transaction.update_row(&table, &primary_key, new_row)?;
+                    processed.insert(primary_key);
                     count += 1;
                 }
             }
@@ -199,3 +258,90 @@ impl<T: Transaction> Executor<T> for Delete<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Update;
+    use crate::error::Result;
+    use crate::sql::engine::kv::KVEngine;
+    use crate::sql::engine::{Engine, Transaction};
+    use crate::sql::executor::{Executor, ResultSet};
+    use crate::sql::parser::ast::{Consts, Expression};
+    use crate::sql::types::{Row, Value};
+    use crate::storage::memory::MemoryEngine;
+    use std::collections::BTreeMap;
+
+    // 按给定顺序返回行的扫描，用来模拟流式扫描再次读到已经被更新过的行
+    struct ScriptedScan {
+        columns: Vec<String>,
+        rows: Vec<Row>,
+    }
+
+    impl<T: Transaction> Executor<T> for ScriptedScan {
+        fn execute(self: Box<Self>, _: &mut T) -> Result<ResultSet> {
+            Ok(ResultSet::Scan {
+                columns: self.columns,
+                rows: self.rows,
+            })
+        }
+    }
+
+    #[test]
+    fn test_update_each_row_once() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        s.execute("insert into t values (1, 0), (2, 0), (3, 0), (5, 0);")?;
+
+        let row = |id: i64, v: i64| vec![Value::Integer(id), Value::Integer(v)];
+        let update = |rows: Vec<Row>, col: &str, value: i64| -> Result<ResultSet> {
+            let mut transaction = kvengine.begin()?;
+            let scan = Box::new(ScriptedScan {
+                columns: vec!["id".into(), "v".into()],
+                rows,
+            });
+            let columns =
+                BTreeMap::from([(col.to_string(), Expression::Consts(Consts::Integer(value)))]);
+            let res = Update::new("t".into(), scan, columns).execute(&mut transaction)?;
+            transaction.commit()?;
+            Ok(res)
+        };
+
+        // 同一行被扫描到两次，只更新一次
+        assert_eq!(
+            update(vec![row(1, 0), row(2, 0), row(1, 0)], "v", 7)?,
+            ResultSet::Update { count: 2 }
+        );
+        // 主键被改为 10 之后，扫描在后面的位置又读到了新主键的行，不能再处理一次
+        assert_eq!(
+            update(vec![row(3, 0), row(10, 0)], "id", 10)?,
+            ResultSet::Update { count: 1 }
+        );
+
+        match s.execute("select * from t order by id;")? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![row(1, 7), row(2, 7), row(5, 0), row(10, 0)])
+            }
+            _ => unreachable!(),
+        }
+
+        // 当前一次性读出的扫描：修改主键的行只更新一次
+        assert_eq!(
+            s.execute("update t set id = 20 where id < 2;")?,
+            ResultSet::Update { count: 1 }
+        );
+        match s.execute("select id from t order by id;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(2)],
+                    vec![Value::Integer(5)],
+                    vec![Value::Integer(10)],
+                    vec![Value::Integer(20)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
@@ -1,33 +1,70 @@
 use crate::error::Error::Internal;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::sql::engine::Transaction;
 use crate::sql::executor::{Executor, ResultSet};
 use crate::sql::parser::ast::OrderBy::Asc;
-use crate::sql::parser::ast::{parse_expression, Expression, OrderBy};
-use crate::sql::types::Value;
+use crate::sql::parser::ast::{bind_expression, find_column, BoundExpression, Expression, OrderBy};
+use crate::sql::schema::Table;
+use crate::sql::types::{Collation, Row, Value};
 use std::cmp::Ordering;
 use std::cmp::Ordering::Equal;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+// 扫描节点只输出列裁剪之后需要的列，columns 为None时输出全部列
+fn prune_columns(table: Table, columns: Option<Vec<String>>, rows: Vec<Row>) -> Result<ResultSet> {
+    let columns = match columns {
+        Some(columns) => columns,
+        None => {
+            return Ok(ResultSet::Scan {
+                columns: table.columns.into_iter().map(|c| c.name).collect(),
+                rows,
+            })
+        }
+    };
+    let positions = columns
+        .iter()
+        .map(|c| table.get_col_index(c))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(ResultSet::Scan {
+        columns,
+        rows: rows
+            .into_iter()
+            .map(|row| positions.iter().map(|i| row[*i].clone()).collect())
+            .collect(),
+    })
+}
 
 pub struct Scan {
     table_name: String,
     filter: Option<Expression>,
+    columns: Option<Vec<String>>,
+    reverse: bool,
 }
 
 impl Scan {
-    pub fn new(table_name: String, filter: Option<Expression>) -> Box<Self> {
-        Box::new(Self { table_name, filter })
+    pub fn new(
+        table_name: String,
+        filter: Option<Expression>,
+        columns: Option<Vec<String>>,
+        reverse: bool,
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            filter,
+            columns,
+            reverse,
+        })
     }
 }
 
 impl<T: Transaction> Executor<T> for Scan {
     fn execute(self: Box<Self>, trasaction: &mut T) -> Result<ResultSet> {
         let table = trasaction.must_get_table(self.table_name.clone())?;
-        let rows = trasaction.scan(self.table_name.clone(), self.filter)?;
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
-            rows,
-        })
+        let rows = match self.reverse {
+            true => trasaction.scan_reverse(self.table_name.clone(), self.filter)?,
+            false => trasaction.scan(self.table_name.clone(), self.filter)?,
+        };
+        prune_columns(table, self.columns, rows)
     }
 }
 
@@ -35,14 +72,21 @@ pub struct ScanIndex {
     table_name: String,
     col_name: String,
     value: Value,
+    columns: Option<Vec<String>>,
 }
 
 impl ScanIndex {
-    pub fn new(table_name: String, col_name: String, value: Value) -> Box<Self> {
+    pub fn new(
+        table_name: String,
+        col_name: String,
+        value: Value,
+        columns: Option<Vec<String>>,
+    ) -> Box<Self> {
         Box::new(Self {
             table_name,
             col_name,
             value,
+            columns,
         })
     }
 }
@@ -51,8 +95,13 @@ impl<T: Transaction> Executor<T> for ScanIndex {
     fn execute(self: Box<Self>, trasaction: &mut T) -> Result<ResultSet> {
         let table = trasaction.must_get_table(self.table_name.clone())?;
 
-        // 加载 col_name, value 对应的索引情况
-        let index = trasaction.load_index(&self.table_name, &self.col_name, &self.value)?;
+        // 加载 col_name, value 对应的索引情况，nocase 列的索引按小写存储
+        // 数值按规范形式查找，历史数据中以另一种形式存储的索引也一并查找
+        let col = &table.columns[table.get_col_index(&self.col_name)?];
+        let mut index = HashSet::new();
+        for value in col.index_lookup_keys(&self.value) {
+            index.extend(trasaction.load_index(&self.table_name, &self.col_name, &value)?);
+        }
 
         // 由于拿到的是Set，是无序的，我们尽量让它有序
         // 先转为列表
@@ -62,14 +111,10 @@ impl<T: Transaction> Executor<T> for ScanIndex {
         let mut rows = Vec::new();
         for pk in pks {
             if let Some(row) = trasaction.read_row_by_pk(&self.table_name, &pk)? {
-                rows.push(row);
+                rows.push(table.pad_row(row)?);
             }
         }
-        // println!("index scan");
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
-            rows,
-        })
+        prune_columns(table, self.columns, rows)
     }
 }
 
@@ -88,51 +133,68 @@ impl<T: Transaction> Executor<T> for PkIndex {
     fn execute(self: Box<Self>, trasaction: &mut T) -> Result<ResultSet> {
         let table = trasaction.must_get_table(self.table_name.clone())?;
         let mut rows = Vec::new();
-        let mut pk_value = self.value.clone();
-        if let Value::Float(f) = self.value {
-            // 我们查看小数部分是否为0，如果为0说明是整数，需要进行转换
-            if f.fract() == 0.0 {
-                pk_value = Value::Integer(f as i64);
-            }
-        }
+        // 主键按列类型的规范形式查找：整数主键用 2.0 查找时转为 2，浮点数主键用 2 查找时转为 2.0
+        let pk_col = &table.columns[table.primary_key_index()?];
+        let pk_value = self.value.canonical_for(&pk_col.datatype);
         if let Some(row) = trasaction.read_row_by_pk(&self.table_name, &pk_value)? {
-            rows.push(row);
+            rows.push(table.pad_row(row)?);
         }
 
-        // println!("pk index");
-
         Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
+            columns: table.columns.into_iter().map(|c| c.name).collect(),
             rows,
         })
     }
 }
 
-pub struct Having<T: Transaction> {
+// 恒为空的结果，不访问存储
+pub struct Nothing {
+    columns: Vec<String>,
+}
+
+impl Nothing {
+    pub fn new(columns: Vec<String>) -> Box<Self> {
+        Box::new(Self { columns })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Nothing {
+    fn execute(self: Box<Self>, _: &mut T) -> Result<ResultSet> {
+        Ok(ResultSet::Scan {
+            columns: self.columns,
+            rows: Vec::new(),
+        })
+    }
+}
+
+// 按布尔条件过滤行，having 和连接之后的 where 共用这一个执行器
+pub struct Filter<T: Transaction> {
     source: Box<dyn Executor<T>>,
     condition: Expression,
 }
 
-impl<T: Transaction> Having<T> {
+impl<T: Transaction> Filter<T> {
     pub fn new(source: Box<dyn Executor<T>>, condition: Expression) -> Box<Self> {
         Box::new(Self { source, condition })
     }
 }
 
-impl<T: Transaction> Executor<T> for Having<T> {
+impl<T: Transaction> Executor<T> for Filter<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
-        match self.source.execute(transaction) {
-            Ok(ResultSet::Scan { columns, rows }) => {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => {
+                let condition = bind_expression(&self.condition, &columns, &columns)?;
                 let mut new_rows = Vec::new();
                 for row in rows {
-                    match parse_expression(&self.condition, &columns, &row, &columns, &row)? {
+                    transaction.check_cancelled()?;
+                    match condition.evaluate(&row, &row)? {
                         Value::Null => {}
                         Value::Boolean(false) => {}
                         Value::Boolean(true) => {
                             new_rows.push(row);
                         }
                         _ => {
-                            return Err(Internal("[Executor Having] Unexpected expression".into()))
+                            return Err(Internal("[Executor Filter] Unexpected expression".into()))
                         }
                     }
                 }
@@ -150,6 +212,35 @@ impl<T: Transaction> Executor<T> for Having<T> {
     }
 }
 
+// select ... for update，锁定扫描到的每一行，数据原样返回
+pub struct LockRows<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    table_name: String,
+}
+
+impl<T: Transaction> LockRows<T> {
+    pub fn new(source: Box<dyn Executor<T>>, table_name: String) -> Box<Self> {
+        Box::new(Self { source, table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for LockRows<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => {
+                let table = transaction.must_get_table(self.table_name.clone())?;
+                for row in rows.iter() {
+                    transaction.lock_row(&self.table_name, &table.get_primary_key(row)?)?;
+                }
+                Ok(ResultSet::Scan { columns, rows })
+            }
+            _ => Err(Internal(
+                "[Executor] Unexpected ResultSet, expected Scan Node".to_string(),
+            )),
+        }
+    }
+}
+
 pub struct Projection<T: Transaction> {
     source: Box<dyn Executor<T>>,
     expressions: Vec<(Expression, Option<String>)>,
@@ -169,39 +260,44 @@ impl<T: Transaction> Projection<T> {
 
 impl<T: Transaction> Executor<T> for Projection<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
-        match self.source.execute(transaction) {
-            Ok(ResultSet::Scan { columns, rows }) => {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => {
                 // 处理投影逻辑，我们需要根据expressions构建新的“表”
-                let mut select_index = Vec::new(); // 选择的列的下标
+                let mut select_exprs = Vec::new(); // 选择的列的下标，或者需要对每行求值的表达式
                 let mut new_columns = Vec::new(); // 选择的列
 
                 for (expr, nick_name) in self.expressions {
-                    if let Expression::Field(col_name) = expr {
+                    let bound = match &expr {
                         // 找到col_name在原表中的下标
-                        let position = match columns.iter().position(|c| *c == col_name) {
-                            Some(position) => position,
+                        Expression::Field(col_name) => match find_column(&columns, col_name)? {
+                            Some(position) => BoundExpression::Field(position, col_name.clone()),
                             None => {
-                                return Err(Internal(format!(
+                                return Err(Error::NotFound(format!(
                                     "[Executor] Projection column {} does not exist",
                                     col_name
                                 )))
                             }
-                        };
-                        select_index.push(position);
-                        new_columns.push(if nick_name.is_some() {
-                            nick_name.unwrap()
-                        } else {
-                            col_name
-                        });
+                        },
+                        // 拼接等表达式，没有别名时以表达式本身作为列名
+                        expr => bind_expression(expr, &columns, &columns)?,
                     };
+                    select_exprs.push(bound);
+                    new_columns.push(match nick_name {
+                        Some(nick_name) => nick_name,
+                        None => match expr {
+                            Expression::Field(col_name) => col_name,
+                            expr => expr.to_string(),
+                        },
+                    });
                 }
 
                 // 根据选择的列，对每行内容进行过滤
                 let mut new_rows = Vec::new();
                 for row in rows {
+                    transaction.check_cancelled()?;
                     let mut new_row = Vec::new();
-                    for i in select_index.iter() {
-                        new_row.push(row[*i].clone());
+                    for expr in select_exprs.iter() {
+                        new_row.push(expr.evaluate(&row, &row)?);
                     }
                     new_rows.push(new_row);
                 }
@@ -222,50 +318,85 @@ impl<T: Transaction> Executor<T> for Projection<T> {
 
 pub struct Order<T: Transaction> {
     scan: Box<dyn Executor<T>>,
-    order_by: Vec<(String, OrderBy)>,
+    order_by: Vec<(Expression, OrderBy)>,
+    collations: Vec<Collation>,
 }
 
 impl<T: Transaction> Order<T> {
-    pub fn new(scan: Box<dyn Executor<T>>, order_by: Vec<(String, OrderBy)>) -> Box<Self> {
-        Box::new(Self { scan, order_by })
+    pub fn new(
+        scan: Box<dyn Executor<T>>,
+        order_by: Vec<(Expression, OrderBy)>,
+        collations: Vec<Collation>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            scan,
+            order_by,
+            collations,
+        })
     }
 }
 
 impl<T: Transaction> Executor<T> for Order<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
         // 首先和update一样，先需要拿到scan节点，否则报错
-        match self.scan.execute(transaction) {
-            Ok(ResultSet::Scan { columns, mut rows }) => {
+        match self.scan.execute(transaction)? {
+            ResultSet::Scan { columns, mut rows } => {
                 // 处理排序逻辑
                 // 首先我们要拿到排序列在整张表里的下标，比如有abcd四列，要对bd两列排序，下标就是b-1,d-3
                 // 而在order by 的排序条件里，下标是 b-0,d-1 需要修改
                 let mut order_col_index = HashMap::new();
-                for (i, (col_name, _)) in self.order_by.iter().enumerate() {
+                let mut computed = Vec::new(); // 需要对每行求值的排序表达式
+                for (i, (expr, _)) in self.order_by.iter().enumerate() {
+                    let col_name = match expr {
+                        Expression::Field(col_name) => col_name,
+                        // 拼接等表达式的结果追加在每行的末尾，按追加的列排序
+                        expr => {
+                            order_col_index.insert(i, columns.len() + computed.len());
+                            computed.push(bind_expression(expr, &columns, &columns)?);
+                            continue;
+                        }
+                    };
                     // 这里需要判断，有可能用户指定的排序列不在表中，需要报错
-                    match columns.iter().position(|c| *c == *col_name) {
+                    match find_column(&columns, col_name)? {
                         Some(position) => order_col_index.insert(i, position),
                         None => {
-                            return Err(Internal(format!(
+                            return Err(Error::NotFound(format!(
                                 "order by column {} is not in table",
                                 col_name
                             )))
                         }
                     };
                 }
+                if !computed.is_empty() {
+                    for row in rows.iter_mut() {
+                        let values = computed
+                            .iter()
+                            .map(|expr| expr.evaluate(row, row))
+                            .collect::<Result<Vec<_>>>()?;
+                        row.extend(values);
+                    }
+                }
 
                 rows.sort_by(|row1, row2| {
                     for (i, (_, condition)) in self.order_by.iter().enumerate() {
                         let col_index = order_col_index.get(&i).unwrap(); // 拿到实际的表中列下标
                         let x = &row1[*col_index]; // row1_value
                         let y = &row2[*col_index]; // row2_value
-                        match x.partial_cmp(y) {
-                            Some(Equal) => continue,
-                            Some(o) => return if *condition == Asc { o } else { o.reverse() },
-                            None => continue,
+                                                   // nocase 列忽略大小写排序，只有大小写不同时再按原值排序
+                                                   // 不可比较的值（如 NaN）也要给出确定的顺序，跳过会使比较不满足传递性，排序结果错乱
+                        match self.collations[i]
+                            .compare(x, y)
+                            .unwrap_or_else(|| x.sort_cmp(y))
+                        {
+                            Equal => continue,
+                            o => return if *condition == Asc { o } else { o.reverse() },
                         }
                     }
                     Equal // 其余情况认为相等
                 });
+                if !computed.is_empty() {
+                    rows.iter_mut().for_each(|row| row.truncate(columns.len()));
+                }
                 Ok(ResultSet::Scan { columns, rows })
             }
             _ => {
@@ -290,8 +421,8 @@ impl<T: Transaction> Limit<T> {
 
 impl<T: Transaction> Executor<T> for Limit<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
-        match self.source.execute(transaction) {
-            Ok(ResultSet::Scan { columns, rows }) => {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => {
                 // 对输出的rows截断即可
                 Ok(ResultSet::Scan {
                     columns,
@@ -320,8 +451,8 @@ impl<T: Transaction> Offset<T> {
 
 impl<T: Transaction> Executor<T> for Offset<T> {
     fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
-        match self.source.execute(transaction) {
-            Ok(ResultSet::Scan { columns, rows }) => {
+        match self.source.execute(transaction)? {
+            ResultSet::Scan { columns, rows } => {
                 // 对输出rows跳过即可
                 Ok(ResultSet::Scan {
                     columns,
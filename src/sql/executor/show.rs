@@ -1,6 +1,7 @@
 use crate::error::Result;
 use crate::sql::engine::Transaction;
 use crate::sql::executor::{Executor, ResultSet};
+use crate::sql::types::{combine_hashes, hash_row, Value};
 use std::marker::PhantomData;
 
 pub struct TableSchema<T: Transaction> {
@@ -26,6 +27,117 @@ impl<T: Transaction> Executor<T> for TableSchema<T> {
     }
 }
 
+pub struct ShowCreateTable<T: Transaction> {
+    name: String,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Transaction> ShowCreateTable<T> {
+    pub fn new(name: &str) -> Box<Self> {
+        Box::new(ShowCreateTable {
+            name: name.into(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for ShowCreateTable<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        let table = transaction.must_get_table(self.name.clone())?;
+        Ok(ResultSet::ShowCreateTable {
+            sql: table.to_create_sql(),
+        })
+    }
+}
+
+// 列出表上的索引，每个索引列一行：列名、索引类型、索引中的行数
+pub struct ShowIndexes<T: Transaction> {
+    name: String,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Transaction> ShowIndexes<T> {
+    pub fn new(name: &str) -> Box<Self> {
+        Box::new(ShowIndexes {
+            name: name.into(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for ShowIndexes<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        let table = transaction.must_get_table(self.name.clone())?;
+        let mut rows = Vec::new();
+        for col in table.columns.iter().filter(|c| c.is_index) {
+            let entry_count: usize = transaction
+                .scan_index(&table.name, &col.name)?
+                .iter()
+                .map(|(_, pks)| pks.len())
+                .sum();
+            rows.push(vec![
+                Value::String(col.name.clone()),
+                Value::String("HASH".into()),
+                Value::Integer(entry_count as i64),
+            ]);
+        }
+        Ok(ResultSet::Scan {
+            columns: vec!["column".into(), "type".into(), "entry_count".into()],
+            rows,
+        })
+    }
+}
+
+// 计算表中全部数据的校验和，两张表的数据相同时校验和一定相同，和行的存储顺序无关
+pub struct ChecksumTable<T: Transaction> {
+    name: String,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Transaction> ChecksumTable<T> {
+    pub fn new(name: &str) -> Box<Self> {
+        Box::new(ChecksumTable {
+            name: name.into(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for ChecksumTable<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        let table = transaction.must_get_table(self.name.clone())?;
+        let rows = transaction.scan(table.name.clone(), None)?;
+        // 先对每一行求哈希再排序，得到规范的顺序之后合并
+        let mut hashes = rows.iter().map(hash_row).collect::<Vec<_>>();
+        hashes.sort_unstable();
+        Ok(ResultSet::ChecksumTable {
+            table_name: table.name,
+            rows: hashes.len(),
+            checksum: combine_hashes(hashes),
+        })
+    }
+}
+
+// 逐条解码数据库中的数据，一条数据无法解码时记录下来继续检查，不会中途报错
+pub struct CheckDatabase<T: Transaction> {
+    _marker: PhantomData<T>,
+}
+
+impl<T: Transaction> CheckDatabase<T> {
+    pub fn new() -> Box<Self> {
+        Box::new(CheckDatabase {
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CheckDatabase<T> {
+    fn execute(self: Box<Self>, transaction: &mut T) -> Result<ResultSet> {
+        let (entries, problems) = transaction.check_database()?;
+        Ok(ResultSet::CheckDatabase { entries, problems })
+    }
+}
+
 pub struct TableNames<T: Transaction> {
     _marker: PhantomData<T>,
 }
@@ -1,5 +1,8 @@
+use crate::error::Error;
 use crate::error::Error::Internal;
-use crate::sql::types::{DataType, Value};
+use crate::sql::types::{Collation, DataType, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 // 本模块是抽象语法树的定义
@@ -8,24 +11,29 @@ use std::fmt::{Display, Formatter};
 #[derive(Debug, PartialEq)]
 pub struct Column {
     // 列的各种属性
-    pub name: String,                // 列名
-    pub datatype: DataType,          // 列数据类型
-    pub nullable: Option<bool>,      // 列是否为空
-    pub default: Option<Expression>, // 列的默认值
-    pub is_primary_key: bool,        // 本列是否为主键
-    pub is_index: bool,              // 本列是否为索引
+    pub name: String,                  // 列名
+    pub datatype: DataType,            // 列数据类型
+    pub nullable: Option<bool>,        // 列是否为空
+    pub default: Option<Expression>,   // 列的默认值
+    pub is_primary_key: bool,          // 本列是否为主键
+    pub is_index: bool,                // 本列是否为索引
+    pub collation: Collation,          // 字符串比较规则
+    pub precision: Option<(u32, u32)>, // decimal(precision, scale)
 }
 
 // 目前表达式为了简单，仅支持常量，不支持：insert into Table_A value(11 * 11 + 2) 等
 // 更新：select的列名算作Expression
 // 更新：join的条件——列相等算作Expression
 // 更新：聚集函数算作表达式
+// 更新：update 的 set col = default 算作表达式，执行时再根据表结构解析
+// 更新：聚集函数可以带 filter (where ...) 条件，仅统计满足条件的行
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
     Consts(Consts),
     Field(String),
     Operation(Operation),
-    Function(String, String),
+    Function(String, String, Option<Box<Expression>>), // 函数名，列名，filter条件
+    Default,
 }
 
 // join的类型定义
@@ -75,6 +83,153 @@ pub enum Operation {
     Less(Box<Expression>, Box<Expression>),
     LessEqual(Box<Expression>, Box<Expression>),
     NotEqual(Box<Expression>, Box<Expression>),
+    NullSafeEqual(Box<Expression>, Box<Expression>), // a <=> b，两侧都为 NULL 时也相等
+    And(Box<Expression>, Box<Expression>), // 用于连接条件和 where 条件：on a = b and c > d
+    Not(Box<Expression>),                  // 对布尔条件取反：where not d
+    Concat(Box<Expression>, Box<Expression>), // 字符串拼接：a || b
+    In(Box<Expression>, Vec<Expression>),  // a in (1, 2, 3)，列表可以为空
+}
+
+impl Operation {
+    // 交换比较的左右两侧，比较方向随之翻转：a > b 等价于 b < a
+    pub fn reverse(self) -> Operation {
+        match self {
+            Operation::Equal(l, r) => Operation::Equal(r, l),
+            Operation::Greater(l, r) => Operation::Less(r, l),
+            Operation::GreaterEqual(l, r) => Operation::LessEqual(r, l),
+            Operation::Less(l, r) => Operation::Greater(r, l),
+            Operation::LessEqual(l, r) => Operation::GreaterEqual(r, l),
+            Operation::NotEqual(l, r) => Operation::NotEqual(r, l),
+            Operation::NullSafeEqual(l, r) => Operation::NullSafeEqual(r, l),
+            // and 的两侧、not 的条件分别交换
+            Operation::And(l, r) => Operation::And(Self::reverse_expr(*l), Self::reverse_expr(*r)),
+            Operation::Not(e) => Operation::Not(Self::reverse_expr(*e)),
+            // 拼接不是比较，两侧的顺序不能交换
+            Operation::Concat(l, r) => Operation::Concat(l, r),
+            Operation::In(e, list) => Operation::In(e, list),
+        }
+    }
+
+    fn reverse_expr(e: Expression) -> Box<Expression> {
+        match e {
+            Expression::Operation(op) => Box::new(Expression::Operation(op.reverse())),
+            e => Box::new(e),
+        }
+    }
+}
+
+// 把 and 连接的条件拆成多个条件
+pub fn split_conjuncts(expr: Expression) -> Vec<Expression> {
+    match expr {
+        Expression::Operation(Operation::And(l, r)) => {
+            let mut conjuncts = split_conjuncts(*l);
+            conjuncts.extend(split_conjuncts(*r));
+            conjuncts
+        }
+        expr => vec![expr],
+    }
+}
+
+// 把多个条件用 and 连接起来，没有条件时返回None
+pub fn join_conjuncts(conjuncts: Vec<Expression>) -> Option<Expression> {
+    conjuncts
+        .into_iter()
+        .reduce(|l, r| Expression::Operation(Operation::And(Box::new(l), Box::new(r))))
+}
+
+// 把表达式中的每一列替换为 f 返回的表达式，聚集函数的参数不替换
+pub fn replace_fields(
+    expr: Expression,
+    f: &mut impl FnMut(String) -> crate::error::Result<Expression>,
+) -> crate::error::Result<Expression> {
+    let operation = match expr {
+        Expression::Field(col) => return f(col),
+        Expression::Operation(operation) => operation,
+        expr => return Ok(expr),
+    };
+    let mut replace = |e: Box<Expression>| replace_fields(*e, f).map(Box::new);
+    Ok(Expression::Operation(match operation {
+        Operation::Equal(l, r) => Operation::Equal(replace(l)?, replace(r)?),
+        Operation::Greater(l, r) => Operation::Greater(replace(l)?, replace(r)?),
+        Operation::GreaterEqual(l, r) => Operation::GreaterEqual(replace(l)?, replace(r)?),
+        Operation::Less(l, r) => Operation::Less(replace(l)?, replace(r)?),
+        Operation::LessEqual(l, r) => Operation::LessEqual(replace(l)?, replace(r)?),
+        Operation::NotEqual(l, r) => Operation::NotEqual(replace(l)?, replace(r)?),
+        Operation::NullSafeEqual(l, r) => Operation::NullSafeEqual(replace(l)?, replace(r)?),
+        Operation::And(l, r) => Operation::And(replace(l)?, replace(r)?),
+        Operation::Concat(l, r) => Operation::Concat(replace(l)?, replace(r)?),
+        Operation::Not(e) => Operation::Not(replace(e)?),
+        Operation::In(e, list) => Operation::In(
+            replace(e)?,
+            list.into_iter()
+                .map(|e| replace_fields(e, f))
+                .collect::<crate::error::Result<_>>()?,
+        ),
+    }))
+}
+
+// 常量折叠：两侧都是常量的比较、拼接、and、not 直接求值为一个常量，如 1 = 2 即 false
+// 四则运算在解析时已经计算过了，这里处理的是剩下的运算，求值规则和执行时一致
+// 求值出错（如比较不同类型的常量）时保持原样，留到执行时再报错
+// 另外，常量写在比较左侧时交换到右侧（2 = a 即 a = 2），便于选择索引
+pub fn fold_constants(expr: Expression) -> Expression {
+    let fold = |e: Box<Expression>| Box::new(fold_constants(*e));
+    let operation = match expr {
+        Expression::Operation(operation) => match operation {
+            Operation::Equal(l, r) => Operation::Equal(fold(l), fold(r)),
+            Operation::Greater(l, r) => Operation::Greater(fold(l), fold(r)),
+            Operation::GreaterEqual(l, r) => Operation::GreaterEqual(fold(l), fold(r)),
+            Operation::Less(l, r) => Operation::Less(fold(l), fold(r)),
+            Operation::LessEqual(l, r) => Operation::LessEqual(fold(l), fold(r)),
+            Operation::NotEqual(l, r) => Operation::NotEqual(fold(l), fold(r)),
+            Operation::NullSafeEqual(l, r) => Operation::NullSafeEqual(fold(l), fold(r)),
+            Operation::Concat(l, r) => Operation::Concat(fold(l), fold(r)),
+            Operation::Not(e) => Operation::Not(fold(e)),
+            // 空列表中没有任何值，结果恒为 false，即使左侧为 NULL
+            Operation::In(_, list) if list.is_empty() => {
+                return Expression::Consts(Consts::Boolean(false))
+            }
+            Operation::In(e, list) => {
+                Operation::In(fold(e), list.into_iter().map(fold_constants).collect())
+            }
+            // 一侧恒为 true 时等价于另一侧，一侧恒为 false 时整体为 false
+            Operation::And(l, r) => match (fold_constants(*l), fold_constants(*r)) {
+                (Expression::Consts(Consts::Boolean(true)), e)
+                | (e, Expression::Consts(Consts::Boolean(true))) => return e,
+                (Expression::Consts(Consts::Boolean(false)), _)
+                | (_, Expression::Consts(Consts::Boolean(false))) => {
+                    return Expression::Consts(Consts::Boolean(false))
+                }
+                (l, r) => Operation::And(Box::new(l), Box::new(r)),
+            },
+        },
+        expr => return expr,
+    };
+
+    let is_const = |e: &Expression| matches!(e, Expression::Consts(_));
+    let (operands_const, swap) = match &operation {
+        Operation::Equal(l, r)
+        | Operation::Greater(l, r)
+        | Operation::GreaterEqual(l, r)
+        | Operation::Less(l, r)
+        | Operation::LessEqual(l, r)
+        | Operation::NotEqual(l, r)
+        | Operation::NullSafeEqual(l, r) => (
+            is_const(l) && is_const(r),
+            is_const(l) && matches!(**r, Expression::Field(_)),
+        ),
+        Operation::And(l, r) | Operation::Concat(l, r) => (is_const(l) && is_const(r), false),
+        Operation::Not(e) => (is_const(e), false),
+        Operation::In(e, list) => (is_const(e) && list.iter().all(is_const), false),
+    };
+    let expr = Expression::Operation(if swap { operation.reverse() } else { operation });
+    if !operands_const {
+        return expr;
+    }
+    match bind_expression(&expr, &[], &[]).and_then(|e| e.evaluate(&[], &[])) {
+        Ok(value) => value.into(),
+        Err(_) => expr,
+    }
 }
 
 // 定义 Consts -> Expression 的类型转换
@@ -87,11 +242,7 @@ impl From<Consts> for Expression {
 impl Display for Expression {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expression::Consts(c) => write!(
-                f,
-                "{}",
-                Value::from_expression_to_value(Expression::Consts(c.clone()))
-            ),
+            Expression::Consts(c) => write!(f, "{}", Value::from_consts(c.clone())),
             Expression::Field(col_name) => write!(f, "{}", col_name),
             Expression::Operation(op) => match op {
                 Operation::Equal(l, r) => write!(f, "{} = {}", l, r),
@@ -100,8 +251,28 @@ impl Display for Expression {
                 Operation::Less(l, r) => write!(f, "{} < {}", l, r),
                 Operation::LessEqual(l, r) => write!(f, "{} <= {}", l, r),
                 Operation::NotEqual(l, r) => write!(f, "{} != {}", l, r),
+                Operation::NullSafeEqual(l, r) => write!(f, "{} <=> {}", l, r),
+                Operation::And(l, r) => write!(f, "{} AND {}", l, r),
+                Operation::Not(e) => write!(f, "NOT {}", e),
+                Operation::Concat(l, r) => write!(f, "{} || {}", l, r),
+                Operation::In(e, list) => write!(
+                    f,
+                    "{} IN ({})",
+                    e,
+                    list.iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
             },
-            Expression::Function(func_name, col_name) => write!(f, "{}({})", func_name, col_name),
+            Expression::Function(func_name, col_name, filter) => {
+                write!(f, "{}({})", func_name, col_name)?;
+                if let Some(filter) = filter {
+                    write!(f, " FILTER (WHERE {})", filter)?;
+                }
+                Ok(())
+            }
+            Expression::Default => write!(f, "DEFAULT"),
         }
     }
 }
@@ -110,12 +281,26 @@ impl Display for Expression {
 #[derive(Debug, PartialEq)]
 pub enum Sentence {
     CreateTable {
-        name: String,         // 表名
-        columns: Vec<Column>, // 表的列
+        name: String,            // 表名
+        columns: Vec<Column>,    // 表的列
+        comment: Option<String>, // 表注释：create table t (...) comment 'desc';
     },
     DropTable {
         name: String,
     },
+    PurgeTable {
+        name: String, // 已删除的表名，物理清理其残留的旧版本数据
+    },
+    // create database db1; 每个数据库的数据存放在各自的存储文件中
+    CreateDatabase {
+        name: String,
+    },
+    // use db1; 切换当前会话使用的数据库，也可以写作 use database db1
+    UseDatabase {
+        name: String,
+    },
+    // show databases;
+    DatabaseNames {},
     Insert {
         table_name: String,           // 目标表名
         columns: Option<Vec<String>>, // 目标列，可以为空
@@ -127,9 +312,10 @@ pub enum Sentence {
         where_condition: Option<Expression>,
         group_by: Option<Expression>,
         having: Option<Expression>,
-        order_by: Vec<(String, OrderBy)>, // 例如，order by col_a desc
+        order_by: Vec<(Expression, OrderBy)>, // 例如，order by col_a desc，也可以是聚集函数 order by count(b)
         limit: Option<Expression>,
         offset: Option<Expression>,
+        for_update: bool, // select ... for update，锁定读到的行
     },
     Update {
         table_name: String,
@@ -143,197 +329,443 @@ pub enum Sentence {
     TableSchema {
         table_name: String,
     },
+    // show create table t1;
+    ShowCreateTable {
+        table_name: String,
+    },
+    // show indexes from t1;
+    ShowIndexes {
+        table_name: String,
+    },
+    // checksum table t1; 计算表中全部数据的校验和，用于比较两份数据是否一致
+    ChecksumTable {
+        table_name: String,
+    },
     TableNames {
         // 没有参数，因为是全体表
     },
+    // check database; 逐条解码数据库中的表结构、行和索引，报告无法解码的数据
+    CheckDatabase {},
+    // show status; 查看引擎的运行状态：刷盘统计，以及执行的语句数、扫描的行数等运行计数
+    ShowStatus {},
     Begin {
-        //  没有参数，因为事务号是底层mvcc自动增加的
+        //  事务号是底层mvcc自动增加的
+        serializable: bool, // begin serializable 开启可串行化事务
     },
     Commit {},
     Rollback {},
+    // 保存点，只能在显式事务中使用：savepoint s1; rollback to s1; release s1;
+    Savepoint {
+        name: String,
+    },
+    RollbackTo {
+        name: String,
+    },
+    Release {
+        name: String,
+    },
     Explain {
         sentence: Box<Sentence>,
     },
+    // 元数据的读写：kv get 'key'; kv put 'key' 'value'; kv delete 'key';
+    KvGet {
+        key: String,
+    },
+    KvPut {
+        key: String,
+        value: String,
+    },
+    KvDelete {
+        key: String,
+    },
+}
+
+// 语句的类别，Session 可以只允许执行部分类别的语句
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatementKind {
+    Query,       // select、show、explain、kv get、use 等只读语句
+    Dml,         // insert、update、delete、kv put、kv delete
+    Ddl,         // create table、drop table、purge table、create database
+    Transaction, // begin、commit、rollback 和保存点
 }
 
-// 解析表达式
-pub fn parse_expression(
+impl Sentence {
+    pub fn kind(&self) -> StatementKind {
+        match self {
+            Sentence::CreateTable { .. }
+            | Sentence::DropTable { .. }
+            | Sentence::PurgeTable { .. }
+            | Sentence::CreateDatabase { .. } => StatementKind::Ddl,
+            Sentence::Insert { .. }
+            | Sentence::Update { .. }
+            | Sentence::Delete { .. }
+            | Sentence::KvPut { .. }
+            | Sentence::KvDelete { .. } => StatementKind::Dml,
+            Sentence::Select { .. }
+            | Sentence::TableSchema { .. }
+            | Sentence::ShowCreateTable { .. }
+            | Sentence::ShowIndexes { .. }
+            | Sentence::ChecksumTable { .. }
+            | Sentence::CheckDatabase {}
+            | Sentence::TableNames {}
+            | Sentence::ShowStatus {}
+            | Sentence::DatabaseNames {}
+            | Sentence::UseDatabase { .. }
+            | Sentence::Explain { .. }
+            | Sentence::KvGet { .. } => StatementKind::Query,
+            Sentence::Begin { .. }
+            | Sentence::Commit {}
+            | Sentence::Rollback {}
+            | Sentence::Savepoint { .. }
+            | Sentence::RollbackTo { .. }
+            | Sentence::Release { .. } => StatementKind::Transaction,
+        }
+    }
+}
+
+// 根据列名找到列在结果集中的下标，找不到返回None
+// 连接查询中，扫描节点输出的列会被加上表名，形如 t1.a，此时：
+// 1. t1.a 精确匹配
+// 2. 不带表名的 a 匹配所有表中名为 a 的列，如果有多个表都有 a 列，则有歧义，需要报错
+// 单表查询的列不带表名，planner 已经去掉了列名中的表名
+pub fn find_column(columns: &[String], col_name: &str) -> crate::error::Result<Option<usize>> {
+    let mut matched = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| *c == col_name)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if matched.is_empty() && !col_name.contains('.') {
+        matched = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.split_once('.').map(|(_, name)| name) == Some(col_name))
+            .map(|(i, _)| i)
+            .collect();
+    }
+    match matched.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matched[0])),
+        _ => Err(Internal(format!(
+            "[Column] Column reference \" {} \" is ambiguous, please qualify it with a table name",
+            col_name
+        ))),
+    }
+}
+
+// 按列的比较规则改写条件：和 nocase 列比较的字符串常量转为小写
+// 配合按比较规则归一化之后的行数据一起求值，比较时即可忽略大小写
+// 和 char 列比较的字符串常量去掉末尾的空格，和读出的值以及按索引、主键查找时的规则一致
+pub fn collate_condition(
     expr: &Expression,
-    left_cols: &Vec<String>,
-    left_row: &Vec<Value>,
-    right_cols: &Vec<String>,
-    right_row: &Vec<Value>,
-) -> crate::error::Result<Value> {
+    nocase_cols: &[String],
+    char_cols: &[String],
+) -> Expression {
+    let is_col =
+        |e: &Expression, cols: &[String]| matches!(e, Expression::Field(col) if cols.contains(col));
+    let collate = |l: &Expression, r: &Expression| -> (Box<Expression>, Box<Expression>) {
+        let nocase = is_col(l, nocase_cols) || is_col(r, nocase_cols);
+        let char = is_col(l, char_cols) || is_col(r, char_cols);
+        let normalize = |e: &Expression| match e {
+            Expression::Consts(Consts::String(s)) => {
+                let s = if char { s.trim_end_matches(' ') } else { s };
+                Expression::Consts(Consts::String(match nocase {
+                    true => s.to_lowercase(),
+                    false => s.to_string(),
+                }))
+            }
+            e => e.clone(),
+        };
+        (Box::new(normalize(l)), Box::new(normalize(r)))
+    };
     match expr {
-        Expression::Field(col_name) => {
-            // 根据列名，取对应行的数据
-            let pos = match left_cols.iter().position(|col| *col == *col_name) {
-                Some(pos) => pos,
-                None => {
-                    return Err(Internal(format!(
-                        "[Executor] Column {} does not exist",
-                        col_name
-                    )))
-                }
-            };
-            Ok(left_row[pos].clone())
-        }
-        Expression::Consts(c) => {
-            // 解析诸如 a = 3 中的常量
-            let value = match c {
-                Consts::Null => Value::Null,
-                Consts::Boolean(v) => Value::Boolean(*v),
-                Consts::Integer(v) => Value::Integer(*v),
-                Consts::Float(v) => Value::Float(*v),
-                Consts::String(v) => Value::String(v.clone()),
+        Expression::Operation(op) => Expression::Operation(match op {
+            Operation::Equal(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::Equal(l, r)
+            }
+            Operation::Greater(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::Greater(l, r)
+            }
+            Operation::GreaterEqual(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::GreaterEqual(l, r)
+            }
+            Operation::Less(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::Less(l, r)
+            }
+            Operation::LessEqual(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::LessEqual(l, r)
+            }
+            Operation::NotEqual(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::NotEqual(l, r)
+            }
+            Operation::NullSafeEqual(l, r) => {
+                let (l, r) = collate(l, r);
+                Operation::NullSafeEqual(l, r)
+            }
+            Operation::And(l, r) => Operation::And(
+                Box::new(collate_condition(l, nocase_cols, char_cols)),
+                Box::new(collate_condition(r, nocase_cols, char_cols)),
+            ),
+            Operation::Not(e) => {
+                Operation::Not(Box::new(collate_condition(e, nocase_cols, char_cols)))
+            }
+            Operation::Concat(l, r) => Operation::Concat(l.clone(), r.clone()),
+            // 和 nocase、char 列比较时，列表中的字符串常量同样归一化
+            Operation::In(e, list) => {
+                let list = list.iter().map(|item| *collate(e, item).1).collect();
+                Operation::In(e.clone(), list)
+            }
+        }),
+        e => e.clone(),
+    }
+}
+
+// 绑定之后的表达式：列名已经解析为列在行中的位置，求值时直接按下标取数据
+// 每次扫描、连接、过滤之前绑定一次，避免对每一行都按列名查找
+#[derive(Debug, PartialEq, Clone)]
+pub enum BoundExpression {
+    Value(Value),
+    Field(usize, String), // 列在行中的下标和列名，列名只用于报错
+    Operation(BoundOperation, Box<BoundExpression>, Box<BoundExpression>),
+    And(Box<BoundExpression>, Box<BoundExpression>),
+    Not(Box<BoundExpression>),
+    Concat(Box<BoundExpression>, Box<BoundExpression>),
+    In(Box<BoundExpression>, Vec<BoundExpression>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BoundOperation {
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    NotEqual,
+    NullSafeEqual,
+}
+
+// 绑定表达式，列名在 left_cols 中查找，不存在的列在这里报错
+// 比较运算的右侧在 right_cols 中查找，连接条件 a.x = b.y 即左侧取左表、右侧取右表
+pub fn bind_expression(
+    expr: &Expression,
+    left_cols: &[String],
+    right_cols: &[String],
+) -> crate::error::Result<BoundExpression> {
+    match expr {
+        Expression::Field(col_name) => match find_column(left_cols, col_name)? {
+            Some(pos) => Ok(BoundExpression::Field(pos, col_name.clone())),
+            None => Err(Error::NotFound(format!(
+                "[Executor] Column \" {} \" does not exist, available columns: {}",
+                col_name,
+                left_cols.join(", ")
+            ))),
+        },
+        Expression::Consts(c) => Ok(BoundExpression::Value(Value::from_consts(c.clone()))),
+        // and 的两侧都是独立的条件，按相同的列绑定
+        Expression::Operation(Operation::And(l, r)) => Ok(BoundExpression::And(
+            Box::new(bind_expression(l, left_cols, right_cols)?),
+            Box::new(bind_expression(r, left_cols, right_cols)?),
+        )),
+        Expression::Operation(Operation::Not(e)) => Ok(BoundExpression::Not(Box::new(
+            bind_expression(e, left_cols, right_cols)?,
+        ))),
+        // 拼接的两侧取自同一行
+        Expression::Operation(Operation::Concat(l, r)) => Ok(BoundExpression::Concat(
+            Box::new(bind_expression(l, left_cols, right_cols)?),
+            Box::new(bind_expression(r, left_cols, right_cols)?),
+        )),
+        // 列表中的值和左侧取自同一行
+        Expression::Operation(Operation::In(e, list)) => Ok(BoundExpression::In(
+            Box::new(bind_expression(e, left_cols, right_cols)?),
+            list.iter()
+                .map(|item| bind_expression(item, left_cols, right_cols))
+                .collect::<crate::error::Result<_>>()?,
+        )),
+        Expression::Operation(operation) => {
+            let (op, l, r) = match operation {
+                Operation::Equal(l, r) => (BoundOperation::Equal, l, r),
+                Operation::Greater(l, r) => (BoundOperation::Greater, l, r),
+                Operation::GreaterEqual(l, r) => (BoundOperation::GreaterEqual, l, r),
+                Operation::Less(l, r) => (BoundOperation::Less, l, r),
+                Operation::LessEqual(l, r) => (BoundOperation::LessEqual, l, r),
+                Operation::NotEqual(l, r) => (BoundOperation::NotEqual, l, r),
+                Operation::NullSafeEqual(l, r) => (BoundOperation::NullSafeEqual, l, r),
+                Operation::And(..)
+                | Operation::Not(_)
+                | Operation::Concat(..)
+                | Operation::In(..) => unreachable!(),
             };
-            Ok(value)
+            Ok(BoundExpression::Operation(
+                op,
+                Box::new(bind_expression(l, left_cols, right_cols)?),
+                Box::new(bind_expression(r, right_cols, left_cols)?),
+            ))
         }
-        Expression::Operation(operation) => match operation {
-            Operation::Equal(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
+        _ => Err(Internal(format!(
+            "[Executor] Unexpected Expression {:?}",
+            expr
+        ))),
+    }
+}
 
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l == r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l == r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 == r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l == r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l == r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l == r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
-                        )))
-                    }
-                })
-            }
-            Operation::Greater(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
+impl BoundExpression {
+    // 对一行（连接时为左右两行）求值，和绑定时一样，比较运算的右侧在 right_row 中取值
+    pub fn evaluate(&self, left_row: &[Value], right_row: &[Value]) -> crate::error::Result<Value> {
+        self.evaluate_ref(left_row, right_row)
+            .map(|value| value.into_owned())
+    }
 
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l > r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l > r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 > r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l > r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l > r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l > r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
-                        )))
-                    }
-                })
+    // 列和常量直接借用，不必为每一行复制数据
+    fn evaluate_ref<'a>(
+        &'a self,
+        left_row: &'a [Value],
+        right_row: &'a [Value],
+    ) -> crate::error::Result<Cow<'a, Value>> {
+        match self {
+            BoundExpression::Value(value) => Ok(Cow::Borrowed(value)),
+            BoundExpression::Field(pos, _) => Ok(Cow::Borrowed(&left_row[*pos])),
+            BoundExpression::Operation(op, l, r) => {
+                let left_value = l.evaluate_ref(left_row, right_row)?;
+                let right_value = r.evaluate_ref(right_row, left_row)?;
+                Ok(Cow::Owned(
+                    compare(*op, &left_value, &right_value)
+                        .map_err(|e| with_column_names(e, [l.as_ref(), r.as_ref()]))?,
+                ))
             }
-            Operation::GreaterEqual(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
-
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l >= r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l >= r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 >= r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l >= r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l >= r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l >= r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
-                        )))
-                    }
-                })
+            // 任意一侧为 false 结果即为 false，否则有 NULL 时结果为 NULL
+            BoundExpression::And(l, r) => {
+                let left_value = l.evaluate_ref(left_row, right_row)?;
+                let right_value = r.evaluate_ref(left_row, right_row)?;
+                Ok(Cow::Owned(
+                    match (left_value.as_ref(), right_value.as_ref()) {
+                        (Value::Boolean(false), _) | (_, Value::Boolean(false)) => {
+                            Value::Boolean(false)
+                        }
+                        (Value::Boolean(true), Value::Boolean(true)) => Value::Boolean(true),
+                        (
+                            Value::Null | Value::Boolean(true),
+                            Value::Null | Value::Boolean(true),
+                        ) => Value::Null,
+                        (l, r) => {
+                            return Err(Error::Type(format!(
+                                "[Executor] Can not apply AND to expression {} and {}",
+                                l, r
+                            )))
+                        }
+                    },
+                ))
             }
-            Operation::Less(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
-
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l < r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l < r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean((l as f64) < r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l < r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l < r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l < r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
+            // NULL 取反仍为 NULL
+            BoundExpression::Not(e) => Ok(Cow::Owned(
+                match e.evaluate_ref(left_row, right_row)?.as_ref() {
+                    Value::Boolean(b) => Value::Boolean(!b),
+                    Value::Null => Value::Null,
+                    v => {
+                        return Err(Error::Type(format!(
+                            "[Executor] Can not apply NOT to expression {}",
+                            v
                         )))
                     }
-                })
+                },
+            )),
+            // 只能拼接字符串，任意一侧为 NULL 时结果为 NULL
+            BoundExpression::Concat(l, r) => {
+                let left_value = l.evaluate_ref(left_row, right_row)?;
+                let right_value = r.evaluate_ref(left_row, right_row)?;
+                Ok(Cow::Owned(
+                    match (left_value.as_ref(), right_value.as_ref()) {
+                        (Value::Null, _) | (_, Value::Null) => Value::Null,
+                        (Value::String(l), Value::String(r)) => {
+                            Value::String(format!("{}{}", l, r))
+                        }
+                        (l, r) => {
+                            return Err(Error::Type(format!(
+                                "[Executor] Can not concatenate expression {} and {}",
+                                l, r
+                            )))
+                        }
+                    },
+                ))
             }
-            Operation::LessEqual(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
-
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l <= r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l <= r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 <= r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l <= r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l <= r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l <= r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
-                        )))
+            // 和列表中的任意一个值相等即为 true；都不相等时，左侧或者列表中有 NULL 则为 NULL，否则为 false
+            // 空列表恒为 false
+            BoundExpression::In(e, list) => {
+                if list.is_empty() {
+                    return Ok(Cow::Owned(Value::Boolean(false)));
+                }
+                let value = e.evaluate_ref(left_row, right_row)?;
+                let mut res = Value::Boolean(false);
+                for item in list {
+                    let item_value = item.evaluate_ref(left_row, right_row)?;
+                    match compare(BoundOperation::Equal, &value, &item_value)
+                        .map_err(|err| with_column_names(err, [e.as_ref(), item]))?
+                    {
+                        Value::Boolean(true) => return Ok(Cow::Owned(Value::Boolean(true))),
+                        Value::Null => res = Value::Null,
+                        _ => {}
                     }
-                })
+                }
+                Ok(Cow::Owned(res))
             }
-            Operation::NotEqual(left_expr, right_expr) => {
-                let left_value =
-                    parse_expression(&left_expr, left_cols, left_row, right_cols, right_row)?;
-                let right_value =
-                    parse_expression(&right_expr, right_cols, right_row, left_cols, left_row)?;
+        }
+    }
+}
 
-                Ok(match (left_value, right_value) {
-                    (Value::Boolean(l), Value::Boolean(r)) => Value::Boolean(l != r),
-                    (Value::Integer(l), Value::Integer(r)) => Value::Boolean(l != r),
-                    (Value::Integer(l), Value::Float(r)) => Value::Boolean(l as f64 != r),
-                    (Value::Float(l), Value::Integer(r)) => Value::Boolean(l != r as f64),
-                    (Value::Float(l), Value::Float(r)) => Value::Boolean(l != r),
-                    (Value::String(l), Value::String(r)) => Value::Boolean(l != r),
-                    (Value::Null, _) => Value::Null,
-                    (_, Value::Null) => Value::Null,
-                    (l, r) => {
-                        return Err(Internal(format!(
-                            "[Executor] Can not compare expression {} and {}",
-                            l, r
-                        )))
-                    }
-                })
-            }
-        },
-        _ => {
-            return Err(Internal(format!(
-                "[Executor] Unexpected Expression {:?}",
-                expr
+// 比较出错时在错误信息中附上参与比较的列名，方便定位是哪一列的类型不匹配
+fn with_column_names(err: Error, operands: [&BoundExpression; 2]) -> Error {
+    let names = operands
+        .iter()
+        .filter_map(|operand| match operand {
+            BoundExpression::Field(_, name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    match (err, names.as_slice()) {
+        (Error::Type(msg), [name]) => Error::Type(format!("{} of column {}", msg, name)),
+        (Error::Type(msg), [l, r]) => Error::Type(format!("{} of columns {} and {}", msg, l, r)),
+        (err, _) => err,
+    }
+}
+
+// 比较两个值，任意一侧为NULL时结果为NULL
+// <=> 例外：两侧都为 NULL 时为 true，只有一侧为 NULL 时为 false
+fn compare(op: BoundOperation, l: &Value, r: &Value) -> crate::error::Result<Value> {
+    let ordering = match (l, r) {
+        (Value::Null, Value::Null) if op == BoundOperation::NullSafeEqual => {
+            return Ok(Value::Boolean(true))
+        }
+        (Value::Null, _) | (_, Value::Null) if op == BoundOperation::NullSafeEqual => {
+            return Ok(Value::Boolean(false))
+        }
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        (Value::Boolean(l), Value::Boolean(r)) => l.partial_cmp(r),
+        (Value::Integer(l), Value::Integer(r)) => l.partial_cmp(r),
+        (Value::Integer(l), Value::Float(r)) => (*l as f64).partial_cmp(r),
+        (Value::Float(l), Value::Integer(r)) => l.partial_cmp(&(*r as f64)),
+        (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+        (Value::String(l), Value::String(r)) => l.partial_cmp(r),
+        (l, r) => {
+            return Err(Error::Type(format!(
+                "[Executor] Can not compare expression {} and {}",
+                l, r
             )))
         }
-    }
+    };
+    // NaN 和任何值比较都不相等
+    Ok(Value::Boolean(match op {
+        BoundOperation::Equal | BoundOperation::NullSafeEqual => ordering == Some(Ordering::Equal),
+        BoundOperation::NotEqual => ordering != Some(Ordering::Equal),
+        BoundOperation::Greater => ordering == Some(Ordering::Greater),
+        BoundOperation::GreaterEqual => {
+            matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+        BoundOperation::Less => ordering == Some(Ordering::Less),
+        BoundOperation::LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+    }))
 }
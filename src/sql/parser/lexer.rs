@@ -1,9 +1,12 @@
 use crate::error::Error::Parse;
 use crate::error::{Error, Result}; //自定义result
-use crate::sql::parser::ast::{Consts, Expression};
+use crate::sql::parser::ast::{Consts, Expression, Operation};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
-use std::iter::Peekable;
 use std::str::Chars;
+use std::sync::OnceLock;
+use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 // 对token和Keyword的定义
@@ -17,6 +20,7 @@ pub enum Token {
     OpenParen,        // (
     CloseParen,       // )
     Comma,            // ,
+    Period,           // .
     Semicolon,        // ;
     Asterisk,         // *
     Plus,             // +
@@ -28,29 +32,58 @@ pub enum Token {
     Less,             // <
     LessEqual,        // <=
     NotEqual,         // !=
+    NullSafeEqual,    // <=>
     Hat,              // ^
+    Concat,           // ||
 }
 
 impl Token {
     // 判断是否是数学运算符
     pub fn is_operator(&self) -> bool {
         match self {
-            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash | Token::Hat => true,
+            Token::Plus
+            | Token::Minus
+            | Token::Asterisk
+            | Token::Slash
+            | Token::Hat
+            | Token::Concat => true,
             _ => false,
         }
     }
 
-    // 获取优先级
+    // 判断是否是比较运算符
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Token::Equal
+                | Token::Greater
+                | Token::GreaterEqual
+                | Token::Less
+                | Token::LessEqual
+                | Token::NotEqual
+                | Token::NullSafeEqual
+        )
+    }
+
+    // 获取优先级，字符串拼接的优先级最低：'a' || 1 + 2 即 'a' || (1 + 2)
     pub fn get_priority(&self) -> i32 {
         match self {
-            Token::Plus | Token::Minus => 1,
-            Token::Asterisk | Token::Slash => 2,
-            Token::Hat => 3,
+            Token::Concat => 1,
+            Token::Plus | Token::Minus => 2,
+            Token::Asterisk | Token::Slash => 3,
+            Token::Hat => 4,
             _ => 0,
         }
     }
 
     pub fn calculate_expr(&self, left: Expression, right: Expression) -> Result<Expression> {
+        // 字符串拼接可能涉及列，在执行时对每一行求值
+        if *self == Token::Concat {
+            return Ok(Expression::Operation(Operation::Concat(
+                Box::new(left),
+                Box::new(right),
+            )));
+        }
         let val = match (left, right) {
             (Expression::Consts(c1), Expression::Consts(c2)) => match (c1, c2) {
                 // 只能计算常数的计算
@@ -88,6 +121,7 @@ impl Display for Token {
             Token::OpenParen => "(",
             Token::CloseParen => ")",
             Token::Comma => ",",
+            Token::Period => ".",
             Token::Semicolon => ";",
             Token::Asterisk => "*",
             Token::Plus => "+",
@@ -99,7 +133,9 @@ impl Display for Token {
             Token::Less => "<",
             Token::LessEqual => "<=",
             Token::NotEqual => "!=",
+            Token::NullSafeEqual => "<=>",
             Token::Hat => "^",
+            Token::Concat => "||",
         })
     }
 }
@@ -115,6 +151,7 @@ pub enum Keyword {
     String,
     Text,
     Varchar,
+    Char,
     Float,
     Double,
     Select,
@@ -155,69 +192,69 @@ pub enum Keyword {
     Index,
     Drop,
     Explain,
+    Filter,
+    Serializable,
+    For,
+    Savepoint,
+    Release,
+    To,
+    Comment,
+    Collate,
+    Decimal,
+    Numeric,
+    Indexes,
+    And,
+    Purge,
+    Database,
+    Databases,
+    Use,
+    In,
+    Checksum,
+    All,
+}
+
+// 关键字的最大长度（SERIALIZABLE）
+const MAX_KEYWORD_LEN: usize = 12;
+
+// 所有关键字，按 (长度, 字符串) 排序，进程内只构建一次
+fn keyword_table() -> &'static [(&'static str, Keyword)] {
+    static TABLE: OnceLock<Vec<(&'static str, Keyword)>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = Keyword::iter()
+            .map(|kw| (kw.to_str(), kw))
+            .collect::<Vec<_>>();
+        table.sort_by(|(a, _), (b, _)| cmp_keyword(a, b));
+        table
+    })
+}
+
+fn cmp_keyword(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
 }
 
 // word -> Keyword
 impl Keyword {
     pub fn transfer(input: &str) -> Option<Self> {
-        Some(match input.to_uppercase().as_ref() {
-            // as_ref() 将值转换为引用
-            "CREATE" => Keyword::Create,
-            "TABLE" => Keyword::Table,
-            "INT" => Keyword::Int,
-            "INTEGER" => Keyword::Integer,
-            "BOOLEAN" => Keyword::Boolean,
-            "BOOL" => Keyword::Bool,
-            "STRING" => Keyword::String,
-            "TEXT" => Keyword::Text,
-            "VARCHAR" => Keyword::Varchar,
-            "FLOAT" => Keyword::Float,
-            "DOUBLE" => Keyword::Double,
-            "SELECT" => Keyword::Select,
-            "FROM" => Keyword::From,
-            "INSERT" => Keyword::Insert,
-            "INTO" => Keyword::Into,
-            "VALUES" => Keyword::Values,
-            "TRUE" => Keyword::True,
-            "FALSE" => Keyword::False,
-            "DEFAULT" => Keyword::Default,
-            "NOT" => Keyword::Not,
-            "NULL" => Keyword::Null,
-            "PRIMARY" => Keyword::Primary,
-            "KEY" => Keyword::Key,
-            "UPDATE" => Keyword::Update,
-            "SET" => Keyword::Set,
-            "WHERE" => Keyword::Where,
-            "DELETE" => Keyword::Delete,
-            "ORDER" => Keyword::Order,
-            "BY" => Keyword::By,
-            "ASC" => Keyword::Asc,
-            "DESC" => Keyword::Desc,
-            "LIMIT" => Keyword::Limit,
-            "OFFSET" => Keyword::Offset,
-            "AS" => Keyword::As,
-            "CROSS" => Keyword::Cross,
-            "JOIN" => Keyword::Join,
-            "LEFT" => Keyword::Left,
-            "RIGHT" => Keyword::Right,
-            "ON" => Keyword::On,
-            "GROUP" => Keyword::Group,
-            "HAVING" => Keyword::Having,
-            "SHOW" => Keyword::Show,
-            "TABLES" => Keyword::Tables,
-            "BEGIN" => Keyword::Begin,
-            "COMMIT" => Keyword::Commit,
-            "ROLLBACK" => Keyword::Rollback,
-            "INDEX" => Keyword::Index,
-            "DROP" => Keyword::Drop,
-            "EXPLAIN" => Keyword::Explain,
-            _ => return None,
-        })
+        // 关键字都是较短的 ASCII 单词，在栈上转为大写后查表，不必为每个单词分配内存
+        if input.len() > MAX_KEYWORD_LEN || !input.is_ascii() {
+            return None;
+        }
+        let mut buf = [0u8; MAX_KEYWORD_LEN];
+        let upper = &mut buf[..input.len()];
+        upper.copy_from_slice(input.as_bytes());
+        upper.make_ascii_uppercase();
+        let upper = std::str::from_utf8(upper).ok()?;
+
+        let table = keyword_table();
+        table
+            .binary_search_by(|(kw, _)| cmp_keyword(kw, upper))
+            .ok()
+            .map(|i| table[i].1.clone())
     }
 }
 
 impl Keyword {
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(&self) -> &'static str {
         match self {
             Keyword::Create => "CREATE",
             Keyword::Table => "TABLE",
@@ -228,6 +265,7 @@ impl Keyword {
             Keyword::String => "STRING",
             Keyword::Text => "TEXT",
             Keyword::Varchar => "VARCHAR",
+            Keyword::Char => "CHAR",
             Keyword::Float => "FLOAT",
             Keyword::Double => "DOUBLE",
             Keyword::Select => "SELECT",
@@ -268,6 +306,25 @@ impl Keyword {
             Keyword::Index => "INDEX",
             Keyword::Drop => "DROP",
             Keyword::Explain => "EXPLAIN",
+            Keyword::Filter => "FILTER",
+            Keyword::Serializable => "SERIALIZABLE",
+            Keyword::For => "FOR",
+            Keyword::Savepoint => "SAVEPOINT",
+            Keyword::Release => "RELEASE",
+            Keyword::To => "TO",
+            Keyword::Comment => "COMMENT",
+            Keyword::Collate => "COLLATE",
+            Keyword::Decimal => "DECIMAL",
+            Keyword::Numeric => "NUMERIC",
+            Keyword::Indexes => "INDEXES",
+            Keyword::And => "AND",
+            Keyword::Purge => "PURGE",
+            Keyword::Checksum => "CHECKSUM",
+            Keyword::All => "ALL",
+            Keyword::Database => "DATABASE",
+            Keyword::Databases => "DATABASES",
+            Keyword::Use => "USE",
+            Keyword::In => "IN",
         }
     }
 }
@@ -279,48 +336,64 @@ impl Display for Keyword {
 }
 
 // 实现简单的词法分析Lexer
-// lexer 结构体包含 iter 元素，实现了peekable接口（非消耗地提前查看下一个字符），指定接收泛型为chars，生命周期为a
+// lexer 结构体包含 iter 元素，可以通过 clone 非消耗地提前查看下一个字符，生命周期为a
+// Chars 可以随时取出剩余未扫描的字符串，标识符、数字等直接从原字符串中截取，不必逐个字符拼接
 pub struct Lexer<'a> {
-    iter: Peekable<Chars<'a>>, // chars 包含对多个 token 的引用，所以需要生命周期
+    iter: Chars<'a>, // chars 包含对原字符串的引用，所以需要生命周期
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        // 将传入的字符串 input 初始化为带 peekable 功能的字符迭代器 iter
+        // 将传入的字符串 input 初始化为字符迭代器 iter
         Self {
-            iter: input.chars().peekable(),
+            iter: input.chars(),
         }
     }
 
     // 隔离一些小方法，比如消除空格等
     // 消除空格，例如 select    *     from   t; 这也是有效的sql，我们的思路是利用迭代器一直查找下个字符，直到不为空格
+    // 同时跳过 -- 开头的单行注释，注释一直到行尾
     fn move_whitespace(&mut self) {
-        self.next_while(|c| c.is_whitespace()); // 注：这里的whitespace包括 空格,\n,\t等
-                                                // 传参仅传condition闭包即可，&mut self 是隐式调用的
+        loop {
+            self.next_while(|c| c.is_whitespace()); // 注：这里的whitespace包括 空格,\n,\t等
+                                                    // 传参仅传condition闭包即可，&mut self 是隐式调用的
+            if !self.iter.as_str().starts_with("--") {
+                break;
+            }
+            self.next_while(|c| c != '\n');
+        }
     }
 
     // 辅助方法
+    // 查看下一个字符，不消耗
+    fn peek(&self) -> Option<char> {
+        self.iter.clone().next()
+    }
+
     // 判断当前字符a[i]是否满足条件，是则跳转到下一个字符a[i+1]，并返回该字符a[i]，否则返回None
     fn next_if<F: Fn(char) -> bool>(&mut self, condition: F) -> Option<char> {
         // 接收参数condition：condition是F类型的函数或闭包
-        self.iter.peek().filter(|&c| condition(*c))?; // 先探测 a[i] 是否满足条件（仅查看，不消耗）
+        self.peek().filter(|&c| condition(c))?; // 先探测 a[i] 是否满足条件（仅查看，不消耗）
         self.iter.next() // 第一行代码执行成功，就执行这行代码。这里是iter不是peek，所以还会消耗该字符，返回a[i]
     }
 
-    // 连续获取满足条件的字符，直到不满足为止
-    fn next_while<F: Fn(char) -> bool>(&mut self, condition: F) -> Option<String> {
-        let mut value = String::new();
-        while let Some(c) = self.next_if(&condition) {
-            value.push(c);
-        }
-        Some(value).filter(|s| !s.is_empty()) // 过滤空值
+    // 连续获取满足条件的字符，直到不满足为止，返回原字符串中对应的部分
+    fn next_while<F: Fn(char) -> bool>(&mut self, condition: F) -> Option<&'a str> {
+        let start = self.iter.as_str();
+        while self.next_if(&condition).is_some() {}
+        Some(self.scanned_since(start)).filter(|s| !s.is_empty()) // 过滤空值
+    }
+
+    // 从 start 位置到当前位置之间已经扫描过的字符串
+    fn scanned_since(&self, start: &'a str) -> &'a str {
+        &start[..start.len() - self.iter.as_str().len()]
     }
 
     // 只有是token，才会返回当前token，并跳到下一个字符
     // 这里我们需要理解，如果遇到 +，那么 next_if 会返回 +，next_if_token会返回Token::Plus
     fn next_if_token<F: Fn(char) -> Option<Token>>(&mut self, condition: F) -> Option<Token> {
-        let token = self.iter.peek().and_then(|c| condition(*c))?;
-        // and_then 的效果是：如果 peek() 返回 Some(&char)，则对字符应用 condition，并尝试将其转换为 Option<Token>
+        let token = self.peek().and_then(condition)?;
+        // and_then 的效果是：如果 peek() 返回 Some(char)，则对字符应用 condition，并尝试将其转换为 Option<Token>
         self.iter.next();
         Some(token)
     }
@@ -331,27 +404,30 @@ impl<'a> Lexer<'a> {
         self.move_whitespace(); // 先消除多余空格，即变为 select * from t;
 
         // 由扫描到的第一个字符进行判断：
-        match self.iter.peek() {
+        match self.peek() {
             Some('\'') => self.scan_string(),
             Some('"') => self.scan_string(), // 以单引号或者双引号打头的是字符串
+            Some('`') => self.scan_quoted_ident().map(Some), // 反引号括起来的是标识符
             Some(c) if c.is_ascii_digit() => Ok(self.scan_number()), // 数字
-            Some(c) if c.is_alphabetic() => Ok(self.scan_word()), // Ident、Keyword
-            Some(_) => Ok(self.scan_symbol()), // 符号
+            Some(c) if c.is_alphabetic() || c == '_' => Ok(self.scan_word()), // Ident、Keyword，标识符可以以下划线开头
+            Some(_) => Ok(self.scan_symbol()),                                // 符号
             None => Ok(None),
         }
     }
 
     fn scan_string(&mut self) -> Result<Option<Token>> {
         // 不是单/双引号号开头
-        if self.next_if(|c| c == '\'' || c == '"').is_none() {
+        let Some(quote) = self.next_if(|c| c == '\'' || c == '"') else {
             return Ok(None);
-        }
+        };
 
         let mut value = String::new();
         loop {
             match self.iter.next() {
-                Some('\'') => break, // 匹配结束
-                Some('"') => break,
+                // 字符串中的引号写作两个引号：'it''s' 即 it's
+                Some(c) if c == quote && self.next_if(|c| c == quote).is_some() => value.push(c),
+                // 遇到和开头相同的引号时结束，另一种引号是普通字符
+                Some(c) if c == quote => return Ok(Some(Token::String(value))),
                 Some(c) => value.push(c),
                 None => {
                     return Err(Error::Parse(
@@ -360,43 +436,64 @@ impl<'a> Lexer<'a> {
                 }
             }
         }
-        Ok(Some(Token::String(value)))
+    }
+
+    // 反引号括起来的标识符原样保留，可以包含空格、大写字母，或者和关键字同名
+    // 标识符中的反引号写作两个反引号：`a``b` 即 a`b
+    fn scan_quoted_ident(&mut self) -> Result<Token> {
+        self.iter.next(); // 消费开头的 '`'
+        let mut ident = String::new();
+        loop {
+            match self.iter.next() {
+                Some('`') if self.next_if(|c| c == '`').is_some() => ident.push('`'),
+                Some('`') if ident.is_empty() => {
+                    return Err(Error::Parse("[Lexer] Empty quoted identifier".to_string()))
+                }
+                Some('`') => return Ok(Token::Ident(ident)),
+                Some(c) => ident.push(c),
+                None => {
+                    return Err(Error::Parse(
+                        "[Lexer] Unexpected EOF of (Identifier)".to_string(),
+                    ))
+                }
+            }
+        }
     }
 
     fn scan_number(&mut self) -> Option<Token> {
         // 分部分扫描
-        let mut num = self.next_while(|c| c.is_ascii_digit())?; // ? 解包Option
+        let start = self.iter.as_str();
+        self.next_while(|c| c.is_ascii_digit())?; // ? 解包Option
 
-        if let Some(sep) = self.next_if(|c| c == '.') {
-            // 小数点
-            num.push(sep);
+        if self.next_if(|c| c == '.').is_some() {
             // 小数点之后接着扫描
-            while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
-                num.push(c);
-            }
+            self.next_while(|c| c.is_ascii_digit());
         }
-        Some(Token::Number(num))
+        Some(Token::Number(self.scanned_since(start).to_string()))
     }
 
     fn scan_word(&mut self) -> Option<Token> {
-        let mut val = self.next_if(|c| c.is_alphabetic())?.to_string();
-        while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
-            // alphanumeric是字母或数字
-            val.push(c)
-        }
+        let start = self.iter.as_str();
+        self.next_if(|c| c.is_alphabetic() || c == '_')?;
+        self.next_while(|c| c.is_alphanumeric() || c == '_'); // alphanumeric是字母或数字
+        let val = self.scanned_since(start);
 
         // 如果word是关键字，那么要转成关键字类型，否则为Ident类型
-        Some(Keyword::transfer(&val).map_or(
-            Token::Ident(val.to_lowercase()), // map_or返回None
-            Token::Keyword,
-        )) // map_or返回Some
+        // 标识符统一转为小写，已经是小写时直接复制
+        Some(match Keyword::transfer(val) {
+            Some(keyword) => Token::Keyword(keyword),
+            None if val.chars().flat_map(char::to_lowercase).eq(val.chars()) => {
+                Token::Ident(val.to_string())
+            }
+            None => Token::Ident(val.to_lowercase()),
+        })
     }
 
     fn scan_symbol(&mut self) -> Option<Token> {
-        match self.iter.peek()? {
+        match self.peek()? {
             '>' => {
                 self.iter.next(); // 消费 '>'
-                if self.iter.peek() == Some(&'=') {
+                if self.peek() == Some('=') {
                     self.iter.next(); // 消费 '='
                     Some(Token::GreaterEqual)
                 } else {
@@ -405,27 +502,39 @@ impl<'a> Lexer<'a> {
             }
             '<' => {
                 self.iter.next(); // 消费 '<'
-                if self.iter.peek() == Some(&'=') {
+                if self.peek() == Some('=') {
                     self.iter.next(); // 消费 '='
-                    Some(Token::LessEqual)
+                                      // <=> 是 NULL 安全的等于，否则是 <=
+                    if self.peek() == Some('>') {
+                        self.iter.next();
+                        Some(Token::NullSafeEqual)
+                    } else {
+                        Some(Token::LessEqual)
+                    }
                 } else {
                     Some(Token::Less)
                 }
             }
             '!' => {
                 self.iter.next();
-                if self.iter.peek() == Some(&'=') {
+                if self.peek() == Some('=') {
                     self.iter.next();
                     Some(Token::NotEqual)
                 } else {
                     None
                 }
             }
+            // 只支持 || 拼接，单独的 | 不是合法字符
+            '|' if self.iter.as_str().starts_with("||") => {
+                self.iter.nth(1);
+                Some(Token::Concat)
+            }
             _ => self.next_if_token(|c| match c {
                 '*' => Some(Token::Asterisk),
                 '(' => Some(Token::OpenParen),
                 ')' => Some(Token::CloseParen),
                 ',' => Some(Token::Comma),
+                '.' => Some(Token::Period),
                 ';' => Some(Token::Semicolon),
                 '+' => Some(Token::Plus),
                 '-' => Some(Token::Minus),
@@ -438,6 +547,26 @@ impl<'a> Lexer<'a> {
     }
 }
 
+// 生成 sql 或者报错信息时引用标识符：和不加引号时解析结果不同的标识符需要加上反引号
+// 比如包含空格、大写字母，或者和关键字同名，普通的标识符原样输出
+pub fn quote_identifier(ident: &str) -> Cow<'_, str> {
+    let mut chars = ident.chars();
+    let plain = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && ident.chars().flat_map(char::to_lowercase).eq(ident.chars())
+        && Keyword::transfer(ident).is_none();
+    if plain {
+        Cow::Borrowed(ident)
+    } else {
+        Cow::Owned(format!("`{}`", ident.replace('`', "``")))
+    }
+}
+
+// 生成 sql 时引用字符串常量：加上单引号，字符串中的单引号写作两个单引号
+pub fn quote_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
 // 标准迭代器接口
 impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Token>; // 每次返回token/err
@@ -449,8 +578,7 @@ impl<'a> Iterator for Lexer<'a> {
             Ok(None) =>
             // 解析返回None，但是确实有字符，说明字符不合法
             {
-                self.iter
-                    .peek()
+                self.peek()
                     .map(|c| Err(Parse(format!("[Lexer] Unexpected character {}", c))))
             }
             Err(e) => Some(Err(e)),
@@ -607,4 +735,122 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_keyword_case_insensitive() -> Result<()> {
+        for sql in ["SELECT", "select", "SeLeCt", "sELECT"] {
+            assert_eq!(
+                Lexer::new(sql).collect::<Result<Vec<_>>>()?,
+                vec![Token::Keyword(Keyword::Select)]
+            );
+        }
+
+        // 所有关键字的大写、小写都能识别，并且和 to_str 一致
+        for keyword in Keyword::iter() {
+            let word = keyword.to_str();
+            assert!(word.len() <= MAX_KEYWORD_LEN);
+            assert_eq!(Keyword::transfer(word), Some(keyword.clone()));
+            assert_eq!(Keyword::transfer(&word.to_lowercase()), Some(keyword));
+        }
+
+        // 标识符统一转为小写，非 ASCII 字符和超长的单词不是关键字
+        let tokens = Lexer::new("SeLeCt Col_A, colB, 列名 FROM T1_serializable_x;")
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("col_a".to_string()),
+                Token::Comma,
+                Token::Ident("colb".to_string()),
+                Token::Comma,
+                Token::Ident("列名".to_string()),
+                Token::Keyword(Keyword::From),
+                Token::Ident("t1_serializable_x".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        assert_eq!(Keyword::transfer("selectx"), None);
+        assert_eq!(Keyword::transfer("sel"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_concat() -> Result<()> {
+        assert_eq!(
+            Lexer::new("a||'b' || c").collect::<Result<Vec<_>>>()?,
+            vec![
+                Token::Ident("a".to_string()),
+                Token::Concat,
+                Token::String("b".to_string()),
+                Token::Concat,
+                Token::Ident("c".to_string()),
+            ]
+        );
+        // 单独的 | 不是合法字符
+        assert_eq!(
+            Lexer::new("a | b").collect::<Result<Vec<_>>>(),
+            Err(Parse("[Lexer] Unexpected character |".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_null_safe_equal() -> Result<()> {
+        let tokens = |sql: &str| Lexer::new(sql).collect::<Result<Vec<_>>>();
+        let a = || Token::Ident("a".into());
+        let one = || Token::Number("1".into());
+
+        assert_eq!(tokens("a<=>1")?, vec![a(), Token::NullSafeEqual, one()]);
+        assert_eq!(tokens("a <=> 1")?, vec![a(), Token::NullSafeEqual, one()]);
+        // 和 <=、<、> 区分开
+        assert_eq!(tokens("a<=1")?, vec![a(), Token::LessEqual, one()]);
+        assert_eq!(
+            tokens("a<= >1")?,
+            vec![a(), Token::LessEqual, Token::Greater, one()]
+        );
+        assert_eq!(
+            tokens("a< =>1")?,
+            vec![a(), Token::Less, Token::Equal, Token::Greater, one()]
+        );
+        assert_eq!(
+            tokens("a<=>=1")?,
+            vec![a(), Token::NullSafeEqual, Token::Equal, one()]
+        );
+        assert_eq!(Token::NullSafeEqual.to_string(), "<=>");
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_string_quotes() -> Result<()> {
+        let tokens = |sql: &str| Lexer::new(sql).collect::<Result<Vec<_>>>();
+        // 字符串在和开头相同的引号处结束，另一种引号是普通字符
+        assert_eq!(
+            tokens("'say \"hi\"; x'")?,
+            vec![Token::String("say \"hi\"; x".into())]
+        );
+        assert_eq!(
+            tokens("\"it's\" 'a'")?,
+            vec![Token::String("it's".into()), Token::String("a".into())]
+        );
+        assert_eq!(
+            tokens("'a\""),
+            Err(Parse("[Lexer] Unexpected EOF of (String)".into()))
+        );
+        // 两个引号表示字符串中的一个引号，和 quote_string 的输出互逆
+        assert_eq!(
+            tokens("'it''s' \"a\"\"b\" ''''")?,
+            vec![
+                Token::String("it's".into()),
+                Token::String("a\"b".into()),
+                Token::String("'".into()),
+            ]
+        );
+        assert_eq!(quote_string("it's"), "'it''s'");
+        assert_eq!(
+            tokens(&quote_string("'a'' b'"))?,
+            vec![Token::String("'a'' b'".into())]
+        );
+        Ok(())
+    }
 }
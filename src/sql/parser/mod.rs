@@ -7,7 +7,8 @@ use crate::sql::parser::ast::{
     Column, Expression, FromItem, JoinType, Operation, OrderBy, Sentence,
 };
 use crate::sql::parser::lexer::{Keyword, Lexer, Token};
-use crate::sql::types::DataType;
+use crate::sql::types::{Collation, DataType};
+use log::debug;
 use std::collections::BTreeMap;
 use std::iter::Peekable;
 
@@ -16,12 +17,14 @@ pub mod lexer; // lexer模块仅parser文件内部可使用
 
 // 定义Parser
 pub struct Parser<'a> {
-    lexer: Peekable<Lexer<'a>>, // parser的属性只有lexer，因为parser的数据来源仅是lexer
+    sql: &'a str,               // 原始sql，用于日志
+    lexer: Peekable<Lexer<'a>>, // parser的数据来源是lexer
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Parser {
+            sql: input,
             lexer: Lexer::new(input).peekable(), // 初始化
         }
     }
@@ -31,22 +34,45 @@ impl<'a> Parser<'a> {
 impl<'a> Parser<'a> {
     // 解析获的sql
     pub fn parse(&mut self) -> Result<Sentence> {
+        debug!("[Parser] Parse sql: {}", self.sql);
         let sentence = self.parse_sentence()?; // 获取解析得的语句
 
-        self.expect_next_token_is(Token::Semicolon)?; // sql语句以分号结尾
-        if let Some(token) = self.peek()? {
-            // 后面如果还有token，说明语句不合法
-            return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)));
+        // sql语句以分号结尾，最后一条语句的分号可以省略，方便以库的形式调用
+        if self.peek()?.is_none() {
+            return Ok(sentence);
+        }
+        self.expect_next_token_is(Token::Semicolon)?;
+        if self.peek()?.is_some() {
+            // 后面如果还有token，说明传入了多条语句，一次只能解析一条语句，避免拼接的输入中混入其他语句
+            return Err(Error::Parse(
+                "[Parser] Multiple statements are not allowed, use execute_batch to run them"
+                    .into(),
+            ));
         }
         Ok(sentence)
     }
 
+    // 解析以分号分隔的多条语句，最后一条语句的分号可以省略
+    pub fn parse_batch(&mut self) -> Result<Vec<Sentence>> {
+        debug!("[Parser] Parse sql batch: {}", self.sql);
+        let mut sentences = Vec::new();
+        while self.peek()?.is_some() {
+            sentences.push(self.parse_sentence()?);
+            if self.peek()?.is_none() {
+                break;
+            }
+            self.expect_next_token_is(Token::Semicolon)?;
+        }
+        Ok(sentences)
+    }
+
     // 解析语句
     fn parse_sentence(&mut self) -> Result<Sentence> {
         // 我们尝试查看第一个Token以进行分类
         match self.peek()? {
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Drop)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Purge)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Select)) => self.parse_select(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
             Some(Token::Keyword(Keyword::Update)) => self.parse_update(),
@@ -55,9 +81,16 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Begin)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Savepoint)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Release)) => self.parse_transaction(),
             Some(Token::Keyword(Keyword::Explain)) => self.parse_explain(),
+            Some(Token::Keyword(Keyword::Use)) => self.parse_use(),
+            Some(Token::Keyword(Keyword::Checksum)) => self.parse_checksum(),
+            // check、kv 不是保留字，仍然可以用作表名、列名
+            Some(Token::Ident(ident)) if ident == "check" => self.parse_check(),
+            Some(Token::Ident(ident)) if ident == "kv" => self.parse_kv(),
             Some(token) => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))), // 其他token
-            None => Err(Error::Parse("[Parser] Unexpected EOF".to_string())),
+            None => Err(Self::unexpected_eof("a statement")),
         }
     }
 
@@ -65,14 +98,23 @@ impl<'a> Parser<'a> {
     fn parse_ddl(&mut self) -> Result<ast::Sentence> {
         match self.next()? {
             // 这里要消耗token
-            Token::Keyword(Keyword::Create) => match self.next()? {
+            Token::Keyword(Keyword::Create) => match self.next_expecting("TABLE or DATABASE")? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(), // CREATE TABLE
+                Token::Keyword(Keyword::Database) => Ok(Sentence::CreateDatabase {
+                    name: self.expect_next_is_ident()?,
+                }), // CREATE DATABASE
                 token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))), // 语法错误
             },
-            Token::Keyword(Keyword::Drop) => match self.next()? {
+            Token::Keyword(Keyword::Drop) => match self.next_expecting("TABLE")? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_drop_table(), // DROP TABLE
                 token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
             },
+            Token::Keyword(Keyword::Purge) => match self.next_expecting("TABLE")? {
+                Token::Keyword(Keyword::Table) => Ok(Sentence::PurgeTable {
+                    name: self.expect_next_is_ident()?,
+                }), // PURGE TABLE
+                token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+            },
             token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
         }
     }
@@ -95,17 +137,29 @@ impl<'a> Parser<'a> {
         }
 
         self.expect_next_token_is(Token::CloseParen)?;
+
+        // 括号之后可选的表注释
+        let comment = match self.next_if_is_token(Token::Keyword(Keyword::Comment)) {
+            Some(_) => match self.next()? {
+                Token::String(comment) => Some(comment),
+                token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+            },
+            None => None,
+        };
         Ok(Sentence::CreateTable {
             name: table_name,
             columns,
+            comment,
         })
     }
 
     // 解析column
     fn parse_ddl_column(&mut self) -> Result<Column> {
+        let name = self.expect_next_is_ident()?;
+        let mut precision = None;
         let mut column: Column = Column {
-            name: self.expect_next_is_ident()?,
-            datatype: match self.next()? {
+            name,
+            datatype: match self.next_expecting("a data type")? {
                 Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => {
                     DataType::Integer
                 }
@@ -116,12 +170,20 @@ impl<'a> Parser<'a> {
                 Token::Keyword(Keyword::String)
                 | Token::Keyword(Keyword::Text)
                 | Token::Keyword(Keyword::Varchar) => DataType::String,
+                Token::Keyword(Keyword::Char) => DataType::Char(self.parse_ddl_char_length()?),
+                // decimal 以浮点数存储，可选的 (precision, scale) 在写入时校验
+                Token::Keyword(Keyword::Decimal) | Token::Keyword(Keyword::Numeric) => {
+                    precision = self.parse_ddl_precision()?;
+                    DataType::Float
+                }
                 token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
             },
             nullable: None,
             default: None,
             is_primary_key: false,
             is_index: false,
+            collation: Collation::Binary,
+            precision,
         };
 
         // 解析是否为空，是否有默认值，是否为主键，是否有索引
@@ -138,6 +200,19 @@ impl<'a> Parser<'a> {
                     column.is_primary_key = true;
                 }
                 Keyword::Index => column.is_index = true,
+                // collate nocase 不区分大小写，collate binary 为默认的区分大小写
+                Keyword::Collate => {
+                    column.collation = match self.expect_next_is_ident()?.as_str() {
+                        "nocase" => Collation::NoCase,
+                        "binary" => Collation::Binary,
+                        collation => {
+                            return Err(Error::Parse(format!(
+                                "[Parser] Unknown collation {}",
+                                collation
+                            )))
+                        }
+                    }
+                }
                 keyword => {
                     return Err(Error::Parse(format!(
                         "[Parser] Unexpected keyword {}",
@@ -149,6 +224,52 @@ impl<'a> Parser<'a> {
         Ok(column)
     }
 
+    // 解析 decimal 的 (precision, scale)，scale 省略时为0
+    // 浮点数只能精确表示15位有效数字，所以 precision 最大为15
+    fn parse_ddl_precision(&mut self) -> Result<Option<(u32, u32)>> {
+        if self.next_if_is_token(Token::OpenParen).is_none() {
+            return Ok(None);
+        }
+        let precision = self.expect_next_is_u32()?;
+        let scale = match self.next_if_is_token(Token::Comma) {
+            Some(_) => self.expect_next_is_u32()?,
+            None => 0,
+        };
+        self.expect_next_token_is(Token::CloseParen)?;
+        if precision == 0 || precision > 15 || scale > precision {
+            return Err(Error::Parse(format!(
+                "[Parser] Invalid decimal precision ({}, {}), precision must be between 1 and 15 and scale must not exceed precision",
+                precision, scale
+            )));
+        }
+        Ok(Some((precision, scale)))
+    }
+
+    // char 的长度，省略时为 1
+    fn parse_ddl_char_length(&mut self) -> Result<u32> {
+        if self.next_if_is_token(Token::OpenParen).is_none() {
+            return Ok(1);
+        }
+        let length = self.expect_next_is_u32()?;
+        self.expect_next_token_is(Token::CloseParen)?;
+        if length == 0 || length > 255 {
+            return Err(Error::Parse(format!(
+                "[Parser] Invalid char length {}, length must be between 1 and 255",
+                length
+            )));
+        }
+        Ok(length)
+    }
+
+    fn expect_next_is_u32(&mut self) -> Result<u32> {
+        match self.next_expecting("a number")? {
+            Token::Number(n) => n
+                .parse::<u32>()
+                .map_err(|_| Error::Parse(format!("[Parser] Invalid number {}", n))),
+            token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        }
+    }
+
     // 解析Drop Table 语句
     fn parse_ddl_drop_table(&mut self) -> Result<Sentence> {
         let table_name = self.expect_next_is_ident()?;
@@ -157,17 +278,52 @@ impl<'a> Parser<'a> {
 
     // 解析表达式
     fn parse_expression(&mut self) -> Result<Expression> {
-        let expr = match self.next()? {
+        let expr = match self.next_expecting("an expression")? {
             Token::Ident(ident) => {
                 // 解析select的列，或者聚集函数（count(col_name)）
-                if self.next_if_is_token(Token::OpenParen).is_some() {
+                if ident == "concat" && self.next_if_is_token(Token::OpenParen).is_some() {
+                    // concat(a, b, ...) 等价于 a || b || ...
+                    let mut expr = self.calculate_expression(1)?;
+                    while self.next_if_is_token(Token::Comma).is_some() {
+                        expr = Expression::Operation(Operation::Concat(
+                            Box::new(expr),
+                            Box::new(self.calculate_expression(1)?),
+                        ));
+                    }
+                    self.expect_next_token_is(Token::CloseParen)?;
+                    expr
+                } else if self.next_if_is_token(Token::OpenParen).is_some() {
                     // 情况1：ident后面跟了个括号，判断为聚集函数
-                    let col_name = self.expect_next_is_ident()?;
+                    // count(*) 统计所有行，* 只能用于 count
+                    let col_name = if self.next_if_is_token(Token::Asterisk).is_some() {
+                        if ident != "count" {
+                            return Err(Error::Parse(format!(
+                                "[Parser] Only COUNT supports * as argument, got {}(*)",
+                                ident
+                            )));
+                        }
+                        "*".to_string()
+                    } else {
+                        self.expect_next_is_column()?
+                    };
                     self.expect_next_token_is(Token::CloseParen)?;
-                    Expression::Function(ident.clone(), col_name)
+                    // 可选的 filter (where ...) 条件
+                    let filter = if self
+                        .next_if_is_token(Token::Keyword(Keyword::Filter))
+                        .is_some()
+                    {
+                        self.expect_next_token_is(Token::OpenParen)?;
+                        self.expect_next_token_is(Token::Keyword(Keyword::Where))?;
+                        let condition = self.parse_condition()?;
+                        self.expect_next_token_is(Token::CloseParen)?;
+                        Some(Box::new(condition))
+                    } else {
+                        None
+                    };
+                    Expression::Function(ident.clone(), col_name, filter)
                 } else {
-                    // 情况2：ident后面什么都没有，判断为列名，直接返回列名即可
-                    Expression::Field(ident)
+                    // 情况2：ident后面没有括号，判断为列名，可能是 表名.列名 的形式
+                    Expression::Field(self.parse_qualified_column(ident)?)
                 }
             }
             Token::Number(n) => {
@@ -199,10 +355,56 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    // 解析表达式当中的Operation类型
-    fn parse_operation(&mut self) -> Result<Expression> {
-        let left = self.parse_expression()?;
-        let token = self.next()?;
+    // 解析 where / having 的条件，二者共用这一个入口
+    // 既可以是比较运算，如 a > 1，也可以是单独的布尔表达式，如 having flag
+    fn parse_condition(&mut self) -> Result<Expression> {
+        // not 作用于其后的整个比较：not a = 1 即 not (a = 1)
+        if self
+            .next_if_is_token(Token::Keyword(Keyword::Not))
+            .is_some()
+        {
+            return Ok(Expression::Operation(Operation::Not(Box::new(
+                self.parse_condition()?,
+            ))));
+        }
+        let left = self.calculate_expression(1)?;
+        match self.peek()? {
+            Some(token) if token.is_comparison() => self.parse_operation(left),
+            Some(Token::Keyword(Keyword::In)) => self.parse_in_list(left),
+            // a not in (...) 即 not (a in (...))
+            Some(Token::Keyword(Keyword::Not)) => {
+                self.next()?;
+                Ok(Expression::Operation(Operation::Not(Box::new(
+                    self.parse_in_list(left)?,
+                ))))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    // 解析 in 及其后的值列表：a in (1, 2, 3)，列表可以为空
+    fn parse_in_list(&mut self, left: Expression) -> Result<Expression> {
+        self.expect_next_token_is(Token::Keyword(Keyword::In))?;
+        self.expect_next_token_is(Token::OpenParen)?;
+        let mut list = Vec::new();
+        if self.next_if_is_token(Token::CloseParen).is_none() {
+            loop {
+                list.push(self.calculate_expression(1)?);
+                match self.next_expecting("\")\" or \",\"")? {
+                    Token::CloseParen => break,
+                    Token::Comma => continue,
+                    token => {
+                        return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)))
+                    }
+                }
+            }
+        }
+        Ok(Expression::Operation(Operation::In(Box::new(left), list)))
+    }
+
+    // 解析表达式当中的Operation类型：比较运算符及其右侧的表达式
+    fn parse_operation(&mut self, left: Expression) -> Result<Expression> {
+        let token = self.next_expecting("a comparison operator")?;
         let res = match token {
             Token::Equal => Expression::Operation(Operation::Equal(
                 Box::new(left),
@@ -228,6 +430,10 @@ impl<'a> Parser<'a> {
                 Box::new(left),
                 Box::new(self.calculate_expression(1)?),
             )),
+            Token::NullSafeEqual => Expression::Operation(Operation::NullSafeEqual(
+                Box::new(left),
+                Box::new(self.calculate_expression(1)?),
+            )),
             _ => {
                 return Err(Error::Internal(format!(
                     "[Parser] Unexpected token {}",
@@ -240,13 +446,13 @@ impl<'a> Parser<'a> {
 
     // 计算数学表达式
     // 这里是不处理括号的，括号在parse_expression()里面处理
-    /** 例如计算 5+2+1：
+    /* 例如计算 5+2+1：
         初始 prev_priority=1， left = 5 ，token = + ，是运算符，可以继续处理
-        并且此时 (+.priority = 1) == (prev_priority = 1)，所以不会跳出循环
-        结束时置 next_priority = +.priority + 1 => 2
+        并且此时 (+.priority = 2) > (prev_priority = 1)，所以不会跳出循环
+        结束时置 next_priority = +.priority + 1 => 3
 
-        递归调用下 prev_priority=2，left=2, token = + ，是运算符，可以继续处理
-        但此时 (+.priority = 1) < (prev_priority = 2)，会跳出循环
+        递归调用下 prev_priority=3，left=2, token = + ，是运算符，可以继续处理
+        但此时 (+.priority = 2) < (prev_priority = 3)，会跳出循环
         所以right=2
 
         接着计算left与right的计算结果即可
@@ -281,31 +487,31 @@ impl<'a> Parser<'a> {
 
     // 分类二：Select语句
     fn parse_select(&mut self) -> Result<Sentence> {
+        let select_condition = self.parse_select_condition()?;
+        let from_item = self.parse_from_condition()?;
+        let where_condition = self.parse_where_condition()?;
+        let group_by = self.parse_group_by()?;
+        let having = self.parse_having()?;
+        let order_by = self.parse_order_by_condition()?;
+        let (limit, offset) = self.parse_limit_offset()?;
         Ok(Sentence::Select {
-            select_condition: self.parse_select_condition()?,
-            from_item: self.parse_from_condition()?,
-            where_condition: self.parse_where_condition()?,
-            group_by: self.parse_group_by()?,
-            having: self.parse_having()?,
-            order_by: self.parse_order_by_condition()?,
-            limit: {
+            select_condition,
+            from_item,
+            where_condition,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+            for_update: {
                 if self
-                    .next_if_is_token(Token::Keyword(Keyword::Limit))
+                    .next_if_is_token(Token::Keyword(Keyword::For))
                     .is_some()
                 {
-                    Some(self.parse_expression()?)
+                    self.expect_next_token_is(Token::Keyword(Keyword::Update))?;
+                    true
                 } else {
-                    None
-                }
-            },
-            offset: {
-                if self
-                    .next_if_is_token(Token::Keyword(Keyword::Offset))
-                    .is_some()
-                {
-                    Some(self.parse_expression()?)
-                } else {
-                    None
+                    false
                 }
             },
         })
@@ -319,10 +525,15 @@ impl<'a> Parser<'a> {
 
         // 接下来是可选项，我们需要做出判断：是否给出了指定列名
         let columns = if self.next_if_is_token(Token::OpenParen).is_some() {
+            if self.next_if_is_token(Token::CloseParen).is_some() {
+                return Err(Error::Parse(
+                    "[Parser] Empty column list in INSERT, omit the parentheses to insert all columns".into(),
+                ));
+            }
             let mut cols = Vec::new();
             loop {
                 cols.push(self.expect_next_is_ident()?.to_string());
-                match self.next()? {
+                match self.next_expecting("\")\" or \",\"")? {
                     Token::CloseParen => break,
                     Token::Comma => continue,
                     token => {
@@ -341,10 +552,15 @@ impl<'a> Parser<'a> {
         let mut values = Vec::new();
         loop {
             self.expect_next_token_is(Token::OpenParen)?;
+            if self.next_if_is_token(Token::CloseParen).is_some() {
+                return Err(Error::Parse(
+                    "[Parser] Empty value list in VALUES, each row needs at least one value".into(),
+                ));
+            }
             let mut expressions = Vec::new();
             loop {
                 expressions.push(self.parse_expression()?);
-                match self.next()? {
+                match self.next_expecting("\")\" or \",\"")? {
                     Token::CloseParen => break,
                     Token::Comma => continue,
                     token => {
@@ -377,7 +593,11 @@ impl<'a> Parser<'a> {
         loop {
             let col = self.expect_next_is_ident()?;
             self.expect_next_token_is(Token::Equal)?;
-            let value = self.parse_expression()?;
+            // set col = default，将列重置为建表时的默认值
+            let value = match self.next_if_is_token(Token::Keyword(Keyword::Default)) {
+                Some(_) => Expression::Default,
+                None => self.parse_expression()?,
+            };
             if columns.contains_key(&col) {
                 return Err(Error::Parse(format!(
                     "[Parser] Update column {} conflicted",
@@ -411,21 +631,86 @@ impl<'a> Parser<'a> {
     // 分类：show语句
     fn parse_show(&mut self) -> Result<Sentence> {
         self.expect_next_token_is(Token::Keyword(Keyword::Show))?;
-        match self.next()? {
+        match self.next_expecting("TABLES, TABLE, CREATE, INDEXES or DATABASES")? {
             Token::Keyword(Keyword::Tables) => Ok(TableNames {}),
+            Token::Keyword(Keyword::Databases) => Ok(Sentence::DatabaseNames {}),
             Token::Keyword(Keyword::Table) => Ok(TableSchema {
                 table_name: self.expect_next_is_ident()?,
             }),
+            Token::Keyword(Keyword::Create) => {
+                self.expect_next_token_is(Token::Keyword(Keyword::Table))?;
+                Ok(Sentence::ShowCreateTable {
+                    table_name: self.expect_next_is_ident()?,
+                })
+            }
+            // show indexes from t1，也可以写作 show index from t1
+            Token::Keyword(Keyword::Indexes) | Token::Keyword(Keyword::Index) => {
+                self.expect_next_token_is(Token::Keyword(Keyword::From))?;
+                Ok(Sentence::ShowIndexes {
+                    table_name: self.expect_next_is_ident()?,
+                })
+            }
+            // status 不是保留字，仍然可以用作表名、列名
+            Token::Ident(ident) if ident == "status" => Ok(Sentence::ShowStatus {}),
             _ => Err(Error::Internal("[Parser] Unexpected token".to_string())),
         }
     }
 
+    // checksum table t1;
+    fn parse_checksum(&mut self) -> Result<Sentence> {
+        self.expect_next_token_is(Token::Keyword(Keyword::Checksum))?;
+        self.expect_next_token_is(Token::Keyword(Keyword::Table))?;
+        Ok(Sentence::ChecksumTable {
+            table_name: self.expect_next_is_ident()?,
+        })
+    }
+
+    // check database;
+    fn parse_check(&mut self) -> Result<Sentence> {
+        self.next()?;
+        self.expect_next_token_is(Token::Keyword(Keyword::Database))?;
+        Ok(Sentence::CheckDatabase {})
+    }
+
+    // use db1; 或者 use database db1;
+    fn parse_use(&mut self) -> Result<Sentence> {
+        self.expect_next_token_is(Token::Keyword(Keyword::Use))?;
+        self.next_if_is_token(Token::Keyword(Keyword::Database));
+        Ok(Sentence::UseDatabase {
+            name: self.expect_next_is_ident()?,
+        })
+    }
+
     // 分类：事务命令
     fn parse_transaction(&mut self) -> Result<Sentence> {
         let sentence = match self.next()? {
-            Token::Keyword(Keyword::Begin) => Sentence::Begin {},
+            Token::Keyword(Keyword::Begin) => Sentence::Begin {
+                serializable: self
+                    .next_if_is_token(Token::Keyword(Keyword::Serializable))
+                    .is_some(),
+            },
             Token::Keyword(Keyword::Commit) => Sentence::Commit {},
-            Token::Keyword(Keyword::Rollback) => Sentence::Rollback {},
+            // rollback to [savepoint] s1 回滚到保存点，事务继续
+            Token::Keyword(Keyword::Rollback) => {
+                if self.next_if_is_token(Token::Keyword(Keyword::To)).is_some() {
+                    self.next_if_is_token(Token::Keyword(Keyword::Savepoint));
+                    Sentence::RollbackTo {
+                        name: self.expect_next_is_ident()?,
+                    }
+                } else {
+                    Sentence::Rollback {}
+                }
+            }
+            Token::Keyword(Keyword::Savepoint) => Sentence::Savepoint {
+                name: self.expect_next_is_ident()?,
+            },
+            // release [savepoint] s1
+            Token::Keyword(Keyword::Release) => {
+                self.next_if_is_token(Token::Keyword(Keyword::Savepoint));
+                Sentence::Release {
+                    name: self.expect_next_is_ident()?,
+                }
+            }
             _ => {
                 return Err(Error::Internal(
                     "[Parser] Unknown transaction command".to_string(),
@@ -437,9 +722,22 @@ impl<'a> Parser<'a> {
 
     fn parse_explain(&mut self) -> Result<Sentence> {
         self.expect_next_token_is(Token::Keyword(Keyword::Explain))?;
-        // 不支持对Explain语句进行Explain
-        if let Some(Token::Keyword(Keyword::Explain)) = self.peek()? {
-            return Err(Parse("[Parser] Cannot explain the explain sql".to_string()));
+        // 能生成执行计划的语句（DDL、DML、查询、SHOW 等）都可以 explain
+        // 事务命令、purge table 和 explain 本身不生成执行计划，在解析时直接拒绝
+        if let Some(Token::Keyword(
+            keyword @ (Keyword::Explain
+            | Keyword::Begin
+            | Keyword::Commit
+            | Keyword::Rollback
+            | Keyword::Savepoint
+            | Keyword::Release
+            | Keyword::Purge),
+        )) = self.peek()?
+        {
+            return Err(Parse(format!(
+                "[Parser] Cannot EXPLAIN {} statements",
+                keyword.to_str()
+            )));
         }
         // 拿到explain后面的sql语句
         Ok(Sentence::Explain {
@@ -447,18 +745,55 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // 分类：元数据命令 kv get/put/delete
+    // get、put 只在 kv 之后作为命令，其他位置是普通的标识符
+    fn parse_kv(&mut self) -> Result<Sentence> {
+        self.next()?;
+        let sentence = match self.next_expecting("GET, PUT or DELETE")? {
+            Token::Ident(ident) if ident == "get" => Sentence::KvGet {
+                key: self.expect_next_is_string()?,
+            },
+            Token::Ident(ident) if ident == "put" => Sentence::KvPut {
+                key: self.expect_next_is_string()?,
+                value: self.expect_next_is_string()?,
+            },
+            Token::Keyword(Keyword::Delete) => Sentence::KvDelete {
+                key: self.expect_next_is_string()?,
+            },
+            token => {
+                return Err(Error::Parse(format!(
+                    "[Parser] Unknown kv command {}, expected GET, PUT or DELETE",
+                    token
+                )))
+            }
+        };
+        Ok(sentence)
+    }
+
     fn parse_select_condition(&mut self) -> Result<Vec<(Expression, Option<String>)>> {
         self.expect_next_token_is(Token::Keyword(Keyword::Select))?;
 
         let mut selects = Vec::new();
-        // 如果是select *
+        // select * 表示全部列，不能和其他列或者聚集函数一起使用，如 select *, count(*)
+        let star_error = || {
+            Error::Parse(
+                "[Parser] SELECT * cannot be combined with other columns or aggregate functions"
+                    .into(),
+            )
+        };
         if self.next_if_is_token(Token::Asterisk).is_some() {
+            if self.next_if_is_token(Token::Comma).is_some() {
+                return Err(star_error());
+            }
             return Ok(selects);
         }
 
         // 处理多个select的列
         loop {
-            let col_name = self.parse_expression()?;
+            if self.next_if_is_token(Token::Asterisk).is_some() {
+                return Err(star_error());
+            }
+            let col_name = self.calculate_expression(1)?;
             // 查看是否有别名，比如 select user_name as a
             let nick_name = match self.next_if_is_token(Token::Keyword(Keyword::As)) {
                 Some(_) => Some(self.expect_next_is_ident()?),
@@ -490,19 +825,28 @@ impl<'a> Parser<'a> {
             let condition = match join_type {
                 Cross => None,
                 _ => {
-                    // select * from A join B on A.a = B.b
+                    // select * from A join B on A.a = B.b，也可以是其他比较，如 A.a > B.b
+                    // 多个比较用 and 连接：on A.a = B.b and A.c > B.d
                     self.expect_next_token_is(Token::Keyword(Keyword::On))?;
-                    let left_col = self.parse_expression()?;
-                    self.expect_next_token_is(Token::Equal)?;
-                    let right_col = self.parse_expression()?;
-
-                    let (l, r) = match join_type {
-                        Right => (right_col, left_col),
-                        _ => (left_col, right_col),
-                    };
-
-                    let condition = ast::Operation::Equal(Box::new(l), Box::new(r));
-                    Some(Expression::Operation(condition))
+                    let mut conjuncts = Vec::new();
+                    loop {
+                        let left_col = self.parse_expression()?;
+                        let condition = match self.parse_operation(left_col)? {
+                            Expression::Operation(operation) => match join_type {
+                                Right => operation.reverse(),
+                                _ => operation,
+                            },
+                            _ => unreachable!(),
+                        };
+                        conjuncts.push(Expression::Operation(condition));
+                        if self
+                            .next_if_is_token(Token::Keyword(Keyword::And))
+                            .is_none()
+                        {
+                            break;
+                        }
+                    }
+                    ast::join_conjuncts(conjuncts)
                 }
             };
 
@@ -561,7 +905,15 @@ impl<'a> Parser<'a> {
         }
 
         self.expect_next_token_is(Token::Keyword(Keyword::By))?;
-        Ok(Some(self.parse_expression()?))
+        // group by 1 表示按select中的第一列分组，由planner解析为对应的列
+        match self.parse_expression()? {
+            expr @ Expression::Consts(ast::Consts::Integer(_)) => Ok(Some(expr)),
+            Expression::Consts(c) => Err(Error::Parse(format!(
+                "[Parser] GROUP BY expects a column name or a select list position, got {}",
+                Expression::Consts(c)
+            ))),
+            expr => Ok(Some(expr)),
+        }
     }
 
     fn parse_where_condition(&mut self) -> Result<Option<Expression>> {
@@ -571,7 +923,15 @@ impl<'a> Parser<'a> {
         {
             return Ok(None); // 没有指定where条件
         }
-        Ok(Some(self.parse_operation()?))
+        // 多个条件用 and 连接：where a = 1 and b > 2
+        let mut conjuncts = vec![self.parse_condition()?];
+        while self
+            .next_if_is_token(Token::Keyword(Keyword::And))
+            .is_some()
+        {
+            conjuncts.push(self.parse_condition()?);
+        }
+        Ok(ast::join_conjuncts(conjuncts))
     }
 
     fn parse_having(&mut self) -> Result<Option<Expression>> {
@@ -581,10 +941,10 @@ impl<'a> Parser<'a> {
         {
             return Ok(None);
         }
-        Ok(Some(self.parse_operation()?))
+        Ok(Some(self.parse_condition()?))
     }
 
-    fn parse_order_by_condition(&mut self) -> Result<Vec<(String, OrderBy)>> {
+    fn parse_order_by_condition(&mut self) -> Result<Vec<(Expression, OrderBy)>> {
         let mut order_by_condition = Vec::new();
         if self
             .next_if_is_token(Token::Keyword(Keyword::Order))
@@ -595,8 +955,8 @@ impl<'a> Parser<'a> {
         self.expect_next_token_is(Token::Keyword(Keyword::By))?;
 
         loop {
-            // 可能有多个排序条件
-            let col = self.expect_next_is_ident()?;
+            // 可能有多个排序条件，排序条件可以是列名、聚集函数或者拼接表达式，由planner进行校验
+            let col = self.calculate_expression(1)?;
             // 可以不指定asc或者desc，默认asc
             // matches! 是 Rust 中的一个宏，用于检查一个值是否与给定的模式匹配
             let order = match self.next_if(|token| {
@@ -618,6 +978,41 @@ impl<'a> Parser<'a> {
         Ok(order_by_condition)
     }
 
+    // limit 和 offset 子句，顺序任意，但每个最多出现一次
+    // limit all 表示不限制行数，和省略 limit 相同
+    fn parse_limit_offset(&mut self) -> Result<(Option<Expression>, Option<Expression>)> {
+        let (mut limit, mut offset) = (None, None);
+        let (mut has_limit, mut has_offset) = (false, false);
+        loop {
+            if self
+                .next_if_is_token(Token::Keyword(Keyword::Limit))
+                .is_some()
+            {
+                if has_limit {
+                    return Err(Error::Parse("[Parser] Duplicate LIMIT clause".into()));
+                }
+                has_limit = true;
+                if self
+                    .next_if_is_token(Token::Keyword(Keyword::All))
+                    .is_none()
+                {
+                    limit = Some(self.parse_expression()?);
+                }
+            } else if self
+                .next_if_is_token(Token::Keyword(Keyword::Offset))
+                .is_some()
+            {
+                if has_offset {
+                    return Err(Error::Parse("[Parser] Duplicate OFFSET clause".into()));
+                }
+                has_offset = true;
+                offset = Some(self.parse_expression()?);
+            } else {
+                return Ok((limit, offset));
+            }
+        }
+    }
+
     // 一些小工具
     // 重写peek方法，因为原peek是迭代器，会返回Option，可能为None，但是我们不希望返回None
     fn peek(&mut self) -> Result<Option<Token>> {
@@ -628,13 +1023,53 @@ impl<'a> Parser<'a> {
     fn next(&mut self) -> Result<Token> {
         self.lexer
             .next()
-            .unwrap_or_else(|| Err(Error::Parse("[Parser] Unexpected EOF".to_string())))
+            .unwrap_or_else(|| Err(Error::Parse("[Parser] Unexpected end of input".to_string())))
         // unwrap_or_else：如果返回Some(Token)，返回Token；如果返回None，则执行闭包（报错）
     }
 
+    // 和next相同，但语句提前结束时在报错中说明期望的内容
+    fn next_expecting(&mut self, expected: &str) -> Result<Token> {
+        self.lexer
+            .next()
+            .unwrap_or_else(|| Err(Self::unexpected_eof(expected)))
+    }
+
+    // 语句不完整时的报错
+    fn unexpected_eof(expected: &str) -> Error {
+        Error::Parse(format!(
+            "[Parser] Unexpected end of input, expected {}",
+            expected
+        ))
+    }
+
     // 下一个token必须是ident
+    // 期望下一个是列名，可以带表名限定，如 t1.a
+    fn expect_next_is_column(&mut self) -> Result<String> {
+        let ident = self.expect_next_is_ident()?;
+        self.parse_qualified_column(ident)
+    }
+
+    // 已经读到了一个ident，如果后面跟着 . 则和之后的列名拼接为 表名.列名
+    fn parse_qualified_column(&mut self, ident: String) -> Result<String> {
+        if self.next_if_is_token(Token::Period).is_some() {
+            let col_name = self.expect_next_is_ident()?;
+            return Ok(format!("{}.{}", ident, col_name));
+        }
+        Ok(ident)
+    }
+
+    fn expect_next_is_string(&mut self) -> Result<String> {
+        match self.next_expecting("String")? {
+            Token::String(s) => Ok(s),
+            token => Err(Error::Parse(format!(
+                "[Parser] Expected String, got token: {}",
+                token
+            ))),
+        }
+    }
+
     fn expect_next_is_ident(&mut self) -> Result<String> {
-        match self.next()? {
+        match self.next_expecting("Ident")? {
             Token::Ident(ident) => Ok(ident),
             token => Err(Error::Parse(format!(
                 "[Parser] Expected Ident, got token: {}",
@@ -645,7 +1080,7 @@ impl<'a> Parser<'a> {
 
     // 下一个token必须是指定的token
     fn expect_next_token_is(&mut self, expected_token: Token) -> Result<()> {
-        let token = self.next()?;
+        let token = self.next_expecting(&format!("Token: {}", expected_token))?;
         if token != expected_token {
             return Err(Error::Parse(format!(
                 "[Parser] Expected Token: {}, got token: {}",
@@ -714,8 +1149,73 @@ mod tests {
         )
         ";
 
-        let sentence3 = Parser::new(sql3).parse();
-        assert!(sentence3.is_err());
+        // 最后的分号可以省略
+        let sentence3 = Parser::new(sql3).parse()?;
+        assert_eq!(sentence1, sentence3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_optional_semicolon() -> Result<()> {
+        let sqls = [
+            "select * from tbl1 where a = 1",
+            "select * from tbl1 where a = 1;",
+            "select * from tbl1 where a = 1;   \n\t",
+            "select * from tbl1 where a = 1; -- comment",
+            "-- comment\nselect * from tbl1 -- comment\nwhere a = 1 -- comment",
+        ];
+        let expected = Parser::new(sqls[0]).parse()?;
+        for sql in sqls {
+            assert_eq!(Parser::new(sql).parse()?, expected);
+        }
+        assert_eq!(
+            Parser::new("show tables").parse()?,
+            Parser::new("show tables;").parse()?
+        );
+
+        // 多条语句之间仍然需要分号分隔，分号之后也不能有多余的token
+        assert_eq!(
+            Parser::new("select * from tbl1 select * from tbl2").parse(),
+            Err(Error::Parse(
+                "[Parser] Expected Token: ;, got token: SELECT".into()
+            ))
+        );
+        assert!(Parser::new("select * from tbl1; select * from tbl2;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from tbl1;;").parse().is_err());
+
+        // 不完整的语句在报错中说明期望的内容
+        assert_eq!(
+            Parser::new("").parse(),
+            Err(Error::Parse(
+                "[Parser] Unexpected end of input, expected a statement".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("select * from").parse(),
+            Err(Error::Parse(
+                "[Parser] Unexpected end of input, expected Ident".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("create table t1 (a int").parse(),
+            Err(Error::Parse(
+                "[Parser] Unexpected end of input, expected Token: )".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("insert into t1 values (1").parse(),
+            Err(Error::Parse(
+                "[Parser] Unexpected end of input, expected \")\" or \",\"".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("select * from t1 where a =").parse(),
+            Err(Error::Parse(
+                "[Parser] Unexpected end of input, expected an expression".into()
+            ))
+        );
         Ok(())
     }
 
@@ -783,6 +1283,7 @@ mod tests {
                 order_by: vec![],
                 limit: Some(Expression::Consts(Integer(10))),
                 offset: Some(Expression::Consts(Integer(20))),
+                for_update: false,
             }
         );
 
@@ -799,12 +1300,13 @@ mod tests {
                 group_by: None,
                 having: None,
                 order_by: vec![
-                    ("a".to_string(), Asc),
-                    ("b".to_string(), Asc),
-                    ("c".to_string(), Desc),
+                    (Expression::Field("a".into()), Asc),
+                    (Expression::Field("b".into()), Asc),
+                    (Expression::Field("c".into()), Desc),
                 ],
                 limit: None,
                 offset: None,
+                for_update: false,
             }
         );
 
@@ -825,12 +1327,13 @@ mod tests {
                 group_by: None,
                 having: None,
                 order_by: vec![
-                    ("a".to_string(), Asc),
-                    ("b".to_string(), Asc),
-                    ("c".to_string(), Desc),
+                    (Expression::Field("a".into()), Asc),
+                    (Expression::Field("b".into()), Asc),
+                    (Expression::Field("c".into()), Desc),
                 ],
                 limit: None,
                 offset: None,
+                for_update: false,
             }
         );
 
@@ -863,6 +1366,7 @@ mod tests {
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                for_update: false,
             }
         );
 
@@ -872,9 +1376,18 @@ mod tests {
             sentence,
             ast::Sentence::Select {
                 select_condition: vec![
-                    (ast::Expression::Function("count".into(), "a".into()), None),
-                    (ast::Expression::Function("min".into(), "b".into()), None),
-                    (ast::Expression::Function("max".into(), "c".into()), None),
+                    (
+                        ast::Expression::Function("count".into(), "a".into(), None),
+                        None
+                    ),
+                    (
+                        ast::Expression::Function("min".into(), "b".into(), None),
+                        None
+                    ),
+                    (
+                        ast::Expression::Function("max".into(), "c".into(), None),
+                        None
+                    ),
                 ],
                 from_item: ast::FromItem::Table {
                     name: "tbl1".into()
@@ -888,9 +1401,19 @@ mod tests {
                 order_by: vec![],
                 limit: None,
                 offset: None,
+                for_update: false,
             }
         );
 
+        let sql = "select a, max(b) as flag from tbl1 group by a having flag;";
+        let sentence = Parser::new(sql).parse()?;
+        match sentence {
+            ast::Sentence::Select { having, .. } => {
+                assert_eq!(having, Some(Expression::Field("flag".into())))
+            }
+            _ => unreachable!(),
+        }
+
         Ok(())
     }
 
@@ -916,6 +1439,288 @@ mod tests {
             }
         );
 
+        let sql = "update tbl set a = default where c = 'a';";
+        let sentence = Parser::new(sql).parse()?;
+        assert_eq!(
+            sentence,
+            Sentence::Update {
+                table_name: "tbl".into(),
+                columns: vec![("a".into(), Expression::Default)]
+                    .into_iter()
+                    .collect(),
+                condition: Some(ast::Expression::Operation(ast::Operation::Equal(
+                    Box::new(ast::Expression::Field("c".into())),
+                    Box::new(ast::Expression::Consts(Consts::String("a".into())))
+                ))),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_asterisk() -> Result<()> {
+        // select * 和 count(*) 中的 * 含义不同
+        match Parser::new("select * from tbl1;").parse()? {
+            Sentence::Select {
+                select_condition, ..
+            } => assert!(select_condition.is_empty()),
+            _ => unreachable!(),
+        }
+        match Parser::new("select count(*), count(a) as n from tbl1;").parse()? {
+            Sentence::Select {
+                select_condition, ..
+            } => assert_eq!(
+                select_condition,
+                vec![
+                    (Expression::Function("count".into(), "*".into(), None), None),
+                    (
+                        Expression::Function("count".into(), "a".into(), None),
+                        Some("n".into())
+                    ),
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        assert!(Parser::new("select COUNT(*) from tbl1 group by a;")
+            .parse()
+            .is_ok());
+
+        // select * 不能和其他列、聚集函数混用
+        let star_error = Err(Error::Parse(
+            "[Parser] SELECT * cannot be combined with other columns or aggregate functions".into(),
+        ));
+        assert_eq!(
+            Parser::new("select *, count(*) from tbl1;").parse(),
+            star_error
+        );
+        assert_eq!(Parser::new("select *, a from tbl1;").parse(), star_error);
+        assert_eq!(
+            Parser::new("select count(*), * from tbl1;").parse(),
+            star_error
+        );
+
+        // 只有 count 可以使用 *
+        assert_eq!(
+            Parser::new("select sum(*) from tbl1;").parse(),
+            Err(Error::Parse(
+                "[Parser] Only COUNT supports * as argument, got sum(*)".into()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_batch() -> Result<()> {
+        let injected = "select * from users where name = 'a'; drop table users; -- '";
+        assert_eq!(
+            Parser::new(injected).parse(),
+            Err(Error::Parse(
+                "[Parser] Multiple statements are not allowed, use execute_batch to run them"
+                    .into()
+            ))
+        );
+        let sentences = Parser::new(injected).parse_batch()?;
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(
+            sentences[1],
+            Sentence::DropTable {
+                name: "users".into()
+            }
+        );
+
+        // 最后一条语句的分号可以省略，空输入没有语句
+        assert_eq!(
+            Parser::new("show tables; show tables").parse_batch()?.len(),
+            2
+        );
+        assert_eq!(Parser::new(" -- comment").parse_batch()?, vec![]);
+        assert!(Parser::new("show tables show tables")
+            .parse_batch()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_explain_policy() -> Result<()> {
+        // 不生成执行计划的语句不能 explain
+        for (sql, keyword) in [
+            ("explain begin;", "BEGIN"),
+            ("explain begin serializable;", "BEGIN"),
+            ("explain commit;", "COMMIT"),
+            ("explain rollback;", "ROLLBACK"),
+            ("explain rollback to s1;", "ROLLBACK"),
+            ("explain savepoint s1;", "SAVEPOINT"),
+            ("explain release s1;", "RELEASE"),
+            ("explain purge table t;", "PURGE"),
+            ("explain explain select * from t;", "EXPLAIN"),
+        ] {
+            assert_eq!(
+                Parser::new(sql).parse(),
+                Err(Error::Parse(format!(
+                    "[Parser] Cannot EXPLAIN {} statements",
+                    keyword
+                ))),
+                "{}",
+                sql
+            );
+        }
+
+        // 其他语句都可以 explain
+        for sql in [
+            "explain create table t (a int primary key);",
+            "explain drop table t;",
+            "explain insert into t values (1);",
+            "explain update t set a = 1;",
+            "explain delete from t;",
+            "explain select * from t;",
+            "explain show tables;",
+            "explain show table t;",
+            "explain show create table t;",
+            "explain show indexes from t;",
+            "explain kv get 'k';",
+            "explain kv put 'k' 'v';",
+            "explain kv delete 'k';",
+        ] {
+            assert!(
+                matches!(Parser::new(sql).parse()?, Sentence::Explain { .. }),
+                "{}",
+                sql
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_in_list_and_empty_values() -> Result<()> {
+        let where_of = |sql: &str| -> Result<Option<Expression>> {
+            match Parser::new(sql).parse()? {
+                Sentence::Select {
+                    where_condition, ..
+                } => Ok(where_condition),
+                _ => unreachable!(),
+            }
+        };
+        let field = |name: &str| Box::new(Expression::Field(name.into()));
+        let in_list = |name: &str, list: Vec<Expression>| {
+            Expression::Operation(ast::Operation::In(field(name), list))
+        };
+
+        assert_eq!(
+            where_of("select * from t where a in ();")?,
+            Some(in_list("a", vec![]))
+        );
+        assert_eq!(
+            where_of("select * from t where a in (1, 'x', null, 2 + 1);")?,
+            Some(in_list(
+                "a",
+                vec![
+                    Consts::Integer(1).into(),
+                    Consts::String("x".into()).into(),
+                    Consts::Null.into(),
+                    Consts::Float(3.0).into(),
+                ]
+            ))
+        );
+        assert_eq!(
+            where_of("select * from t where a not in (1) and b in ();")?,
+            Some(Expression::Operation(ast::Operation::And(
+                Box::new(Expression::Operation(ast::Operation::Not(Box::new(
+                    in_list("a", vec![Consts::Integer(1).into()])
+                )))),
+                Box::new(in_list("b", vec![])),
+            )))
+        );
+        assert_eq!(
+            in_list(
+                "a",
+                vec![Consts::Integer(1).into(), Consts::Integer(2).into()]
+            )
+            .to_string(),
+            "a IN (1, 2)"
+        );
+        assert_eq!(
+            Parser::new("select * from t where a in (1, 2;").parse(),
+            Err(Error::Parse("[Parser] Unexpected token ;".into()))
+        );
+        assert!(Parser::new("select * from t where a in 1;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from t where a not 1;")
+            .parse()
+            .is_err());
+
+        // insert 中空的值列表、空的列名列表直接报错
+        assert_eq!(
+            Parser::new("insert into t values ();").parse(),
+            Err(Error::Parse(
+                "[Parser] Empty value list in VALUES, each row needs at least one value".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("insert into t values (1), ();").parse(),
+            Err(Error::Parse(
+                "[Parser] Empty value list in VALUES, each row needs at least one value".into()
+            ))
+        );
+        assert_eq!(
+            Parser::new("insert into t () values (1);").parse(),
+            Err(Error::Parse(
+                "[Parser] Empty column list in INSERT, omit the parentheses to insert all columns"
+                    .into()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_limit_offset() -> Result<()> {
+        let limit_offset = |sql: &str| -> Result<(Option<Expression>, Option<Expression>)> {
+            match Parser::new(sql).parse()? {
+                Sentence::Select { limit, offset, .. } => Ok((limit, offset)),
+                _ => unreachable!(),
+            }
+        };
+        let int = |i: i64| Some(Expression::from(Consts::Integer(i)));
+
+        // 两个子句的顺序任意
+        assert_eq!(
+            limit_offset("select * from t limit 5 offset 10;")?,
+            (int(5), int(10))
+        );
+        assert_eq!(
+            limit_offset("select * from t offset 10 limit 5;")?,
+            (int(5), int(10))
+        );
+        assert_eq!(
+            limit_offset("select * from t order by a offset 3;")?,
+            (None, int(3))
+        );
+        assert_eq!(
+            limit_offset("select * from t offset 3 for update;")?,
+            (None, int(3))
+        );
+
+        // limit all 等同于没有 limit
+        assert_eq!(limit_offset("select * from t limit all;")?, (None, None));
+        assert_eq!(
+            limit_offset("select * from t offset 2 limit all;")?,
+            (None, int(2))
+        );
+
+        // 每个子句最多出现一次
+        assert_eq!(
+            Parser::new("select * from t limit 1 limit 2;").parse(),
+            Err(Error::Parse("[Parser] Duplicate LIMIT clause".into()))
+        );
+        assert_eq!(
+            Parser::new("select * from t offset 1 limit 2 offset 3;").parse(),
+            Err(Error::Parse("[Parser] Duplicate OFFSET clause".into()))
+        );
+        assert_eq!(
+            Parser::new("select * from t limit all limit 1;").parse(),
+            Err(Error::Parse("[Parser] Duplicate LIMIT clause".into()))
+        );
         Ok(())
     }
 }
@@ -5,13 +5,19 @@ use crate::sql::parser::ast::OrderBy::Asc;
 use crate::sql::parser::ast::{Expression, OrderBy, Sentence};
 use crate::sql::planner::planner::Planner;
 use crate::sql::schema::Table;
-use crate::sql::types::Value;
+use crate::sql::types::{Collation, Value};
+use log::debug;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 
 mod planner;
 
 // 定义执行节点
+// 节点组成一棵树，数据从叶子节点（Scan、ScanIndex、PkIndex等）流向根节点
+// 约定：
+// 1. 每个节点只持有自己的子节点（Box<Node>），不存在共享或成环
+// 2. Join 节点的 left / right 顺序即输出列的顺序，right join 在构建时已经被改写为 left join，并在外层用 Projection 恢复原来的列顺序
+// 3. 改写节点时需保证子节点输出的列能被父节点使用，例如 Projection、Aggregate 中引用的列必须存在于 source 中
 #[derive(Debug, PartialEq)]
 pub enum Node {
     CreateTable {
@@ -30,16 +36,25 @@ pub enum Node {
         table_name: String,
         // 过滤条件
         filter: Option<Expression>,
+        // 列裁剪之后需要输出的列，None表示输出全部列
+        columns: Option<Vec<String>>,
+        // 按主键降序反向扫描，代替 order by 主键 desc 的排序
+        reverse: bool,
     },
     ScanIndex {
         table_name: String,
         col_name: String,
         value: Value,
+        columns: Option<Vec<String>>,
     },
     PkIndex {
         table_name: String,
         value: Value,
     },
+    // where 条件恒为假（如 where 1 = 2）时代替扫描，不读取数据，只输出列名
+    Nothing {
+        columns: Vec<String>,
+    },
     Update {
         table_name: String,
         scan: Box<Node>,
@@ -51,7 +66,8 @@ pub enum Node {
     },
     OrderBy {
         scan: Box<Node>,
-        order_by: Vec<(String, OrderBy)>,
+        order_by: Vec<(Expression, OrderBy)>,
+        collations: Vec<Collation>, // 每个排序列的比较规则
     },
     Limit {
         source: Box<Node>,
@@ -77,6 +93,7 @@ pub enum Node {
         left: Box<Node>,
         right: Box<Node>,
         condition: Option<Expression>,
+        residual: Option<Expression>, // 等值条件之外的其他连接条件，匹配时逐对求值
         outer: bool,
     },
     Aggregate {
@@ -84,15 +101,46 @@ pub enum Node {
         source: Box<Node>,
         expression: Vec<(Expression, Option<String>)>, // Function, 别名
         group_by: Option<Expression>,
+        group_collation: Collation, // group by 列的比较规则
     },
     Having {
         source: Box<Node>,
         condition: Expression,
     },
+    Filter {
+        // 连接之后的过滤，用于无法下推到单表扫描的where条件
+        source: Box<Node>,
+        condition: Expression,
+    },
     TableSchema {
         name: String,
     },
+    ShowCreateTable {
+        name: String,
+    },
+    ShowIndexes {
+        name: String,
+    },
+    ChecksumTable {
+        name: String,
+    },
+    CheckDatabase {},
     TableNames {},
+    LockRows {
+        // select ... for update，锁定扫描到的行
+        source: Box<Node>,
+        table_name: String,
+    },
+    KvGet {
+        key: String,
+    },
+    KvPut {
+        key: String,
+        value: String,
+    },
+    KvDelete {
+        key: String,
+    },
 }
 
 // Plan Node 的格式化输出方法
@@ -137,23 +185,40 @@ impl Node {
             } => {
                 write!(f, "Insert Into Table {}", table_name)
             }
-            Node::Scan { table_name, filter } => {
-                write!(f, "Sequence Scan On Table {}", table_name)?;
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                reverse,
+            } => {
+                match reverse {
+                    true => write!(f, "Reverse Sequence Scan On Table {}", table_name)?,
+                    false => write!(f, "Sequence Scan On Table {}", table_name)?,
+                }
                 if let Some(filter) = filter {
                     write!(f, " ( Filter: {} )", filter)?;
                 }
+                if let Some(columns) = columns {
+                    write!(f, " ( Columns: {} )", columns.join(", "))?;
+                }
                 Ok(())
             }
             Node::ScanIndex {
                 table_name,
                 col_name,
                 value: _,
+                columns,
             } => {
-                write!(f, "Index Scan On Table {}.{}", table_name, col_name)
+                write!(f, "Index Scan On Table {}.{}", table_name, col_name)?;
+                if let Some(columns) = columns {
+                    write!(f, " ( Columns: {} )", columns.join(", "))?;
+                }
+                Ok(())
             }
             Node::PkIndex { table_name, value } => {
                 write!(f, "Primary Key Scan On Table {}({})", table_name, value)
             }
+            Node::Nothing { .. } => write!(f, "Nothing"),
             Node::Update {
                 table_name,
                 scan,
@@ -166,7 +231,7 @@ impl Node {
                 write!(f, "Delete On Table {}", table_name)?;
                 (*scan).format(f, &prefix, false)
             }
-            Node::OrderBy { scan, order_by } => {
+            Node::OrderBy { scan, order_by, .. } => {
                 let condition = order_by
                     .iter()
                     .map(|c| format!("{} {}", c.0, if c.1 == Asc { "Asc" } else { "Desc" }))
@@ -222,12 +287,16 @@ impl Node {
                 left,
                 right,
                 condition,
+                residual,
                 outer: _,
             } => {
                 write!(f, "Hash Join")?;
                 if let Some(expr) = condition {
                     write!(f, "( {} )", expr)?;
                 }
+                if let Some(expr) = residual {
+                    write!(f, " Residual( {} )", expr)?;
+                }
                 (*left).format(f, &prefix, false)?;
                 (*right).format(f, &prefix, false)
             }
@@ -235,6 +304,7 @@ impl Node {
                 source,
                 expression,
                 group_by,
+                ..
             } => {
                 let agg = expression
                     .iter()
@@ -258,17 +328,320 @@ impl Node {
                 (*source).format(f, &prefix, false)
             }
             Node::Having { source, condition } => {
+                write!(f, "Having: {}", condition)?;
+                (*source).format(f, &prefix, false)
+            }
+            Node::Filter { source, condition } => {
                 write!(f, "Filter: {}", condition)?;
                 (*source).format(f, &prefix, false)
             }
             Node::TableSchema { name } => {
                 write!(f, "Show Table Schema: {}", name)
             }
+            Node::ShowCreateTable { name } => {
+                write!(f, "Show Create Table: {}", name)
+            }
+            Node::ShowIndexes { name } => {
+                write!(f, "Show Indexes: {}", name)
+            }
+            Node::ChecksumTable { name } => {
+                write!(f, "Checksum Table: {}", name)
+            }
+            Node::CheckDatabase {} => {
+                write!(f, "Check Database")
+            }
             Node::TableNames {} => {
                 write!(f, "Show Table Names")
             }
+            Node::LockRows { source, table_name } => {
+                write!(f, "Lock Rows On Table {}", table_name)?;
+                (*source).format(f, &prefix, false)
+            }
+            Node::KvGet { key } => write!(f, "KV Get: {}", key),
+            Node::KvPut { key, .. } => write!(f, "KV Put: {}", key),
+            Node::KvDelete { key } => write!(f, "KV Delete: {}", key),
+        }
+    }
+}
+
+impl Node {
+    // 扫描节点所扫描的表名，其余节点返回None
+    pub fn scan_table(&self) -> Option<&String> {
+        match self {
+            Node::Scan { table_name, .. }
+            | Node::ScanIndex { table_name, .. }
+            | Node::PkIndex { table_name, .. } => Some(table_name),
+            Node::LockRows { source, .. } => source.scan_table(),
+            _ => None,
+        }
+    }
+
+    // 节点名称
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::CreateTable { .. } => "CreateTable",
+            Node::DropTable { .. } => "DropTable",
+            Node::Insert { .. } => "Insert",
+            Node::Scan { .. } => "Scan",
+            Node::ScanIndex { .. } => "ScanIndex",
+            Node::PkIndex { .. } => "PkIndex",
+            Node::Nothing { .. } => "Nothing",
+            Node::Update { .. } => "Update",
+            Node::Delete { .. } => "Delete",
+            Node::OrderBy { .. } => "OrderBy",
+            Node::Limit { .. } => "Limit",
+            Node::Offset { .. } => "Offset",
+            Node::Projection { .. } => "Projection",
+            Node::NestedLoopJoin { .. } => "NestedLoopJoin",
+            Node::HashJoin { .. } => "HashJoin",
+            Node::Aggregate { .. } => "Aggregate",
+            Node::Having { .. } => "Having",
+            Node::Filter { .. } => "Filter",
+            Node::TableSchema { .. } => "TableSchema",
+            Node::ShowCreateTable { .. } => "ShowCreateTable",
+            Node::ShowIndexes { .. } => "ShowIndexes",
+            Node::ChecksumTable { .. } => "ChecksumTable",
+            Node::CheckDatabase { .. } => "CheckDatabase",
+            Node::TableNames { .. } => "TableNames",
+            Node::LockRows { .. } => "LockRows",
+            Node::KvGet { .. } => "KvGet",
+            Node::KvPut { .. } => "KvPut",
+            Node::KvDelete { .. } => "KvDelete",
+        }
+    }
+
+    // 子节点，join 节点按照 left、right 的顺序返回
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Update { scan, .. } | Node::Delete { scan, .. } | Node::OrderBy { scan, .. } => {
+                vec![scan]
+            }
+            Node::Limit { source, .. }
+            | Node::Offset { source, .. }
+            | Node::Projection { source, .. }
+            | Node::Aggregate { source, .. }
+            | Node::Having { source, .. }
+            | Node::Filter { source, .. }
+            | Node::LockRows { source, .. } => vec![source],
+            Node::NestedLoopJoin { left, right, .. } | Node::HashJoin { left, right, .. } => {
+                vec![left, right]
+            }
+            _ => vec![],
+        }
+    }
+
+    // 先序遍历整棵树
+    pub fn walk(&self, f: &mut impl FnMut(&Node)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    // 自底向上重建整棵树：先改写子节点，再把改写后的当前节点交给 f
+    pub fn map(self, f: &mut impl FnMut(Node) -> Node) -> Node {
+        let mut map_box = |node: Box<Node>| Box::new(node.map(f));
+        let node = match self {
+            Node::Update {
+                table_name,
+                scan,
+                columns,
+            } => Node::Update {
+                table_name,
+                scan: map_box(scan),
+                columns,
+            },
+            Node::Delete { table_name, scan } => Node::Delete {
+                table_name,
+                scan: map_box(scan),
+            },
+            Node::OrderBy {
+                scan,
+                order_by,
+                collations,
+            } => Node::OrderBy {
+                scan: map_box(scan),
+                order_by,
+                collations,
+            },
+            Node::Limit { source, limit } => Node::Limit {
+                source: map_box(source),
+                limit,
+            },
+            Node::Offset { source, offset } => Node::Offset {
+                source: map_box(source),
+                offset,
+            },
+            Node::Projection {
+                source,
+                expressions,
+            } => Node::Projection {
+                source: map_box(source),
+                expressions,
+            },
+            Node::NestedLoopJoin {
+                left,
+                right,
+                condition,
+                outer,
+            } => Node::NestedLoopJoin {
+                left: map_box(left),
+                right: map_box(right),
+                condition,
+                outer,
+            },
+            Node::HashJoin {
+                left,
+                right,
+                condition,
+                residual,
+                outer,
+            } => Node::HashJoin {
+                left: map_box(left),
+                right: map_box(right),
+                condition,
+                residual,
+                outer,
+            },
+            Node::Aggregate {
+                source,
+                expression,
+                group_by,
+                group_collation,
+            } => Node::Aggregate {
+                source: map_box(source),
+                expression,
+                group_by,
+                group_collation,
+            },
+            Node::Having { source, condition } => Node::Having {
+                source: map_box(source),
+                condition,
+            },
+            Node::Filter { source, condition } => Node::Filter {
+                source: map_box(source),
+                condition,
+            },
+            Node::LockRows { source, table_name } => Node::LockRows {
+                source: map_box(source),
+                table_name,
+            },
+            node => node,
+        };
+        f(node)
+    }
+
+    // 节点参数，表达式等统一转换为字符串
+    #[cfg(feature = "json")]
+    fn params(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        let exprs = |exprs: &Vec<(Expression, Option<String>)>| {
+            exprs
+                .iter()
+                .map(|(expr, nick_name)| json!({ "expression": expr.to_string(), "alias": nick_name }))
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Node::CreateTable { schema } => json!({ "table_name": schema.name }),
+            Node::DropTable { name } => json!({ "table_name": name }),
+            Node::Insert {
+                table_name,
+                columns,
+                values,
+            } => json!({ "table_name": table_name, "columns": columns, "rows": values.len() }),
+            Node::Scan {
+                table_name,
+                filter,
+                columns,
+                reverse,
+            } => json!({
+                "table_name": table_name,
+                "filter": filter.as_ref().map(|f| f.to_string()),
+                "columns": columns,
+                "reverse": reverse,
+            }),
+            Node::ScanIndex {
+                table_name,
+                col_name,
+                value,
+                columns,
+            } => json!({
+                "table_name": table_name,
+                "column": col_name,
+                "value": value.to_string(),
+                "columns": columns,
+            }),
+            Node::PkIndex { table_name, value } => {
+                json!({ "table_name": table_name, "value": value.to_string() })
+            }
+            Node::Nothing { columns } => json!({ "columns": columns }),
+            Node::Update {
+                table_name,
+                columns,
+                ..
+            } => json!({
+                "table_name": table_name,
+                "columns": columns.iter().map(|(c, e)| (c.clone(), e.to_string())).collect::<BTreeMap<_, _>>(),
+            }),
+            Node::Delete { table_name, .. } => json!({ "table_name": table_name }),
+            Node::OrderBy { order_by, .. } => json!({
+                "order_by": order_by
+                    .iter()
+                    .map(|(c, o)| json!({ "column": c.to_string(), "order": if *o == Asc { "Asc" } else { "Desc" } }))
+                    .collect::<Vec<_>>(),
+            }),
+            Node::Limit { limit, .. } => json!({ "limit": limit }),
+            Node::Offset { offset, .. } => json!({ "offset": offset }),
+            Node::Projection { expressions, .. } => json!({ "expressions": exprs(expressions) }),
+            Node::NestedLoopJoin {
+                condition, outer, ..
+            } => json!({
+                "condition": condition.as_ref().map(|c| c.to_string()),
+                "outer": outer,
+            }),
+            Node::HashJoin {
+                condition,
+                residual,
+                outer,
+                ..
+            } => json!({
+                "condition": condition.as_ref().map(|c| c.to_string()),
+                "residual": residual.as_ref().map(|c| c.to_string()),
+                "outer": outer,
+            }),
+            Node::Aggregate {
+                expression,
+                group_by,
+                ..
+            } => json!({
+                "expressions": exprs(expression),
+                "group_by": group_by.as_ref().map(|g| g.to_string()),
+            }),
+            Node::Having { condition, .. } | Node::Filter { condition, .. } => {
+                json!({ "condition": condition.to_string() })
+            }
+            Node::TableSchema { name }
+            | Node::ShowCreateTable { name }
+            | Node::ShowIndexes { name }
+            | Node::ChecksumTable { name } => {
+                json!({ "table_name": name })
+            }
+            Node::TableNames {} | Node::CheckDatabase {} => json!({}),
+            Node::LockRows { table_name, .. } => json!({ "table_name": table_name }),
+            Node::KvGet { key } | Node::KvDelete { key } => json!({ "key": key }),
+            Node::KvPut { key, value } => json!({ "key": key, "value": value }),
         }
     }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kind": self.kind(),
+            "params": self.params(),
+            "children": self.children().iter().map(|c| c.to_json()).collect::<Vec<_>>(),
+        })
+    }
 }
 
 // 定义执行计划，执行计划的底层是不同执行节点
@@ -279,22 +652,43 @@ pub struct Plan(pub Node); // 元素结构体，可以通过 let plan = Plan(nod
 // 实现构建Plan的方法
 impl Plan {
     pub fn build<T: Transaction>(sentence: Sentence, transaction: &mut T) -> Result<Self> {
-        Ok(Planner::new(transaction).build(sentence)?)
+        let plan = Planner::new(transaction).build(sentence)?;
+        debug!("[Planner] Plan chosen:\n{}", plan.0);
+        Ok(plan)
     }
 
     // planner与executor交互，plan节点 -> 执行器结构体
     pub fn execute<T: Transaction + 'static>(self, transaction: &mut T) -> Result<ResultSet> {
         <dyn Executor<T>>::build(self.0).execute(transaction) // self.0 == node 只有这一个元素
     }
+
+    // 遍历计划树的每个节点，供外部工具检查执行计划
+    pub fn walk(&self, mut f: impl FnMut(&Node)) {
+        self.0.walk(&mut f)
+    }
+
+    // 改写计划树，可以在 planner 之外编写优化规则
+    pub fn map(self, mut f: impl FnMut(Node) -> Node) -> Plan {
+        Plan(self.0.map(&mut f))
+    }
+
+    // 结构化输出计划树：{ kind, params, children }
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        self.0.to_json()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::sql::engine::kv::KVEngine;
-    use crate::sql::engine::Engine;
+    use crate::sql::engine::{Engine, Transaction};
+    use crate::sql::executor::ResultSet;
+    use crate::sql::types::Value;
     use crate::storage::disk::DiskEngine;
+    use crate::test_util::{explain_plan, query_rows};
     use crate::{
-        error::Result,
+        error::{Error, Result},
         sql::{
             parser::{
                 ast::{self, Expression},
@@ -307,7 +701,7 @@ mod tests {
     #[test]
     fn test_plan_create_table() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut transaction = kvengine.begin()?;
 
         let sql1 = "
@@ -340,7 +734,7 @@ mod tests {
     #[test]
     fn test_plan_insert() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut transaction = kvengine.begin()?;
 
         let sql1 = "insert into tbl1 values (1, 2, 3, 'a', true);";
@@ -390,7 +784,7 @@ mod tests {
     #[test]
     fn test_plan_select() -> Result<()> {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
-        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?);
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
         let mut transaction = kvengine.begin()?;
 
         let sql = "select * from tbl1;";
@@ -401,8 +795,555 @@ mod tests {
             Plan(Node::Scan {
                 table_name: "tbl1".to_string(),
                 filter: None,
+                columns: None,
+                reverse: false,
+            })
+        );
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_walk() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("create table t2 (c int primary key, d int);")?;
+
+        let mut transaction = kvengine.begin()?;
+        let sql = "select a, d from t1 join t2 on a = c order by a limit 10;";
+        let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+
+        let mut kinds = Vec::new();
+        plan.walk(|node| kinds.push(node.kind()));
+        assert_eq!(
+            kinds,
+            vec!["Projection", "Limit", "OrderBy", "HashJoin", "Scan", "Scan"]
+        );
+
+        #[cfg(feature = "json")]
+        {
+            let json = plan.to_json();
+            assert_eq!(json["kind"], "Projection");
+            assert_eq!(json["children"][0]["params"]["limit"], 10);
+            assert_eq!(
+                json["children"][0]["children"][0]["children"][0]["children"][1]["params"]
+                    ["table_name"],
+                "t2"
+            );
+        }
+        transaction.commit()?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_filter() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("create table t2 (c int primary key, d int);")?;
+        s.execute("insert into t1 values (1, 10), (2, 20), (3, 30);")?;
+        s.execute("insert into t2 values (1, 15), (2, 15), (3, 15);")?;
+
+        // 跨表的条件作为连接之后的 Filter 节点
+        let mut transaction = kvengine.begin()?;
+        let sql = "select a from t1 join t2 on a = c where b > d;";
+        let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+        let mut kinds = Vec::new();
+        plan.walk(|node| kinds.push(node.kind()));
+        assert_eq!(
+            kinds,
+            vec!["Projection", "Filter", "HashJoin", "Scan", "Scan"]
+        );
+        match plan.execute(&mut transaction)? {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(2)], vec![Value::Integer(3)]]);
+            }
+            _ => unreachable!(),
+        }
+
+        // having 仍然是单独的节点，但和 Filter 共用执行器
+        let sql = "select b, count(a) from t1 group by b having b > 15;";
+        let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+        let mut kinds = Vec::new();
+        plan.walk(|node| kinds.push(node.kind()));
+        assert_eq!(kinds, vec!["Having", "Aggregate", "Scan"]);
+        assert!(plan.0.to_string().contains("Having: b > 15"));
+        match plan.execute(&mut transaction)? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        transaction.commit()?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_map() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("insert into t1 values (1, 1), (2, 2), (3, 3), (4, 4);")?;
+
+        let mut transaction = kvengine.begin()?;
+        let sql = "select * from t1 limit 3;";
+        let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+
+        // 将 limit 改写为 1
+        let plan = plan.map(|node| match node {
+            Node::Limit { source, .. } => Node::Limit { source, limit: 1 },
+            node => node,
+        });
+        match plan.execute(&mut transaction)? {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["a", "b"]);
+                assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(1)]]);
+            }
+            _ => unreachable!(),
+        }
+        transaction.commit()?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_order_by_validation() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int, c text);")?;
+        s.execute("create table t2 (a int primary key, d int);")?;
+        let mut transaction = kvengine.begin()?;
+
+        // 返回解析后的排序列，或者报错信息
+        let mut order_by = |sql: &str| -> std::result::Result<Vec<String>, String> {
+            let mut cols = Vec::new();
+            match Plan::build(Parser::new(sql).parse().unwrap(), &mut transaction) {
+                Ok(plan) => plan.walk(|node| {
+                    if let Node::OrderBy { order_by, .. } = node {
+                        cols = order_by.iter().map(|(c, _)| c.to_string()).collect();
+                    }
+                }),
+                Err(Error::Internal(msg) | Error::NotFound(msg)) => return Err(msg),
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+            Ok(cols)
+        };
+
+        // 非聚集查询：原表的列，即使没有出现在select中
+        assert_eq!(
+            order_by("select a from t1 order by b, c desc;"),
+            Ok(vec!["b".into(), "c".into()])
+        );
+        // 非聚集查询：select 的别名被还原为原列名
+        assert_eq!(
+            order_by("select b as x, a from t1 order by x;"),
+            Ok(vec!["b".into()])
+        );
+        // 非聚集查询：不能使用聚集函数
+        assert!(order_by("select a from t1 order by count(b);")
+            .unwrap_err()
+            .contains("is not allowed in a query without aggregate functions"));
+        // 非聚集查询：列不存在
+        assert!(order_by("select a from t1 order by d;")
+            .unwrap_err()
+            .contains("does not exist in the source tables or select aliases"));
+        assert!(order_by("select * from t1 order by b, x;")
+            .unwrap_err()
+            .contains("\" x \" does not exist in the source tables"));
+        // 连接查询：带表名的列必须属于对应的表，同名的列不能有歧义
+        assert_eq!(
+            order_by("select * from t1 join t2 on t1.a = t2.a order by t2.d, c;"),
+            Ok(vec!["t2.d".into(), "c".into()])
+        );
+        assert!(
+            order_by("select * from t1 join t2 on t1.a = t2.a order by t1.d;")
+                .unwrap_err()
+                .contains("\" t1.d \" does not exist in the source tables")
+        );
+        assert!(
+            order_by("select * from t1 join t2 on t1.a = t2.a order by a;")
+                .unwrap_err()
+                .contains("is ambiguous")
+        );
+        // 单表查询：表名必须是查询的表
+        assert_eq!(
+            order_by("select t1.a from t1 order by t1.b;"),
+            Ok(vec!["b".into()])
+        );
+        assert!(order_by("select a from t1 order by t2.b;")
+            .unwrap_err()
+            .contains("\" t2.b \" does not exist in table \" t1 \""));
+
+        // 聚集查询：聚集函数的别名、函数名以及重复书写的聚集函数
+        assert_eq!(
+            order_by("select c, count(b) as cnt from t1 group by c order by cnt;"),
+            Ok(vec!["cnt".into()])
+        );
+        assert_eq!(
+            order_by("select c, count(b) from t1 group by c order by count, count(b);"),
+            Ok(vec!["count".into(), "count".into()])
+        );
+        assert_eq!(
+            order_by("select c, sum(b) as total from t1 group by c order by sum(b) desc;"),
+            Ok(vec!["total".into()])
+        );
+        // 聚集查询：group by 列，包括被起了别名的情况
+        assert_eq!(
+            order_by("select c as name, max(b) from t1 group by c order by c;"),
+            Ok(vec!["name".into()])
+        );
+        // 聚集查询：group by 列没有出现在select中
+        assert!(order_by("select count(b) from t1 group by c order by c;")
+            .unwrap_err()
+            .contains("must appear in the select list"));
+        // 聚集查询：未分组的原始列
+        assert!(
+            order_by("select c, count(b) from t1 group by c order by a;")
+                .unwrap_err()
+                .contains("must appear in GROUP BY or be used in an aggregate function")
+        );
+        // 聚集查询：select中没有的聚集函数
+        assert!(
+            order_by("select c, count(b) from t1 group by c order by max(b);")
+                .unwrap_err()
+                .contains("aggregate \" max(b) \" must appear in the select list")
+        );
+        // 聚集查询：不存在的列
+        assert!(
+            order_by("select c, count(b) from t1 group by c order by d;")
+                .unwrap_err()
+                .contains("does not exist in the aggregate output")
+        );
+
+        transaction.commit()?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_column_pruning() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        // 20 列的宽表
+        let cols = (1..20)
+            .map(|i| format!("c{} int{}", i, if i == 6 { " index" } else { "" }))
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("create table t20 (c0 int primary key, {});", cols))?;
+        s.execute("create table u (id int primary key, x int, y int);")?;
+        let rows = (0..30)
+            .map(|i| {
+                let values = (0..20).map(|j| ((i * 7 + j) % 11).to_string());
+                format!("({}, {})", i, values.skip(1).collect::<Vec<_>>().join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t20 values {};", rows))?;
+        s.execute("insert into u values (1, 5, 1), (3, 8, 0), (5, 2, 1), (40, 1, 1);")?;
+
+        // 扫描节点只输出需要的列
+        let plan = explain_plan(&mut s, "select c1 from t20 join u on t20.c0 = u.id;")?;
+        assert!(plan.contains("Sequence Scan On Table t20 ( Columns: c0, c1 )"));
+        assert!(plan.contains("Sequence Scan On Table u ( Columns: id )"));
+        let plan = explain_plan(&mut s, "select c3 from t20 where c6 = 2 order by c4;")?;
+        assert!(plan.contains("Index Scan On Table t20.c6 ( Columns: c3, c4 )"));
+        // select *、锁定行不裁剪
+        let plan = explain_plan(&mut s, "select * from t20 join u on c0 = id;")?;
+        assert!(!plan.contains("Columns:"));
+        let plan = explain_plan(&mut s, "select c1 from t20 for update;")?;
+        assert!(!plan.contains("Columns:"));
+
+        // 裁剪前后的结果完全一致
+        let run = |sql: &str, prune: bool| -> Result<ResultSet> {
+            let mut transaction = kvengine.begin()?;
+            let plan = Plan::build(Parser::new(sql).parse()?, &mut transaction)?;
+            let plan = match prune {
+                true => plan,
+                false => plan.map(|node| match node {
+                    Node::Scan {
+                        table_name,
+                        filter,
+                        reverse,
+                        ..
+                    } => Node::Scan {
+                        table_name,
+                        filter,
+                        columns: None,
+                        reverse,
+                    },
+                    Node::ScanIndex {
+                        table_name,
+                        col_name,
+                        value,
+                        ..
+                    } => Node::ScanIndex {
+                        table_name,
+                        col_name,
+                        value,
+                        columns: None,
+                    },
+                    node => node,
+                }),
+            };
+            let res = plan.execute(&mut transaction)?;
+            transaction.commit()?;
+            Ok(res)
+        };
+        for sql in [
+            "select c1, x from t20 join u on t20.c0 = u.id;",
+            "select c1, y from t20 join u on t20.c2 > u.x order by c1, y;",
+            "select c1, t20.c0 from t20 join u on c0 = id and c5 > x;",
+            "select c5, y from t20 left join u on c0 = id where y = 1;",
+            "select c1, x from t20 right join u on c0 = id;",
+            "select c3, count(c4) as n, max(x) from t20 join u on c2 > x group by c3 order by c3;",
+            "select c8, sum(c9) from t20 group by c8 order by c8;",
+            "select c7 from t20 where c6 = 3 order by c7 desc;",
+            "select c1 as a, c2 from t20 order by c2, a limit 5 offset 2;",
+        ] {
+            let res = run(sql, true)?;
+            match &res {
+                ResultSet::Scan { rows, .. } => assert!(!rows.is_empty(), "{}", sql),
+                _ => unreachable!(),
+            }
+            assert_eq!(res, run(sql, false)?, "{}", sql);
+        }
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_index_with_filter() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+
+        // 同样的数据，一张表有索引，一张表没有
+        s.execute("create table t (id int primary key, a int index, b int, c varchar collate nocase index);")?;
+        s.execute("create table n (id int primary key, a int, b int, c varchar collate nocase);")?;
+        let rows = (0..40)
+            .map(|i| {
+                format!(
+                    "({}, {}, {}, '{}')",
+                    i,
+                    i % 4,
+                    i % 7,
+                    ["x", "X", "y"][i % 3]
+                )
             })
+            .collect::<Vec<_>>()
+            .join(", ");
+        s.execute(&format!("insert into t values {};", rows))?;
+        s.execute(&format!("insert into n values {};", rows))?;
+
+        // 索引列的等值条件走索引，其余条件在索引扫描之后过滤
+        let plan = explain_plan(&mut s, "select * from t where b > 3 and a = 1;")?;
+        assert!(plan.contains("Filter: b > 3"));
+        assert!(plan.contains("Index Scan On Table t.a"));
+        let plan = explain_plan(&mut s, "delete from t where a = 1 and b > 3 and b < 6;")?;
+        assert!(plan.contains("Filter: b > 3 AND b < 6"));
+        assert!(plan.contains("Index Scan On Table t.a"));
+        let plan = explain_plan(&mut s, "update t set b = 0 where a = 2 and b = 1;")?;
+        assert!(plan.contains("Filter: b = 1"));
+        assert!(plan.contains("Index Scan On Table t.a"));
+        // 主键优先于普通索引
+        let plan = explain_plan(&mut s, "select * from t where a = 1 and id = 5;")?;
+        assert!(plan.contains("Filter: a = 1"));
+        assert!(plan.contains("Primary Key Scan On Table t(5)"));
+        // 只有一个条件时不需要过滤
+        let plan = explain_plan(&mut s, "select * from t where a = 1;")?;
+        assert!(!plan.contains("Filter"));
+        // 没有可以走索引的条件，或者剩余条件涉及 nocase 列时全表扫描
+        let plan = explain_plan(&mut s, "select * from t where b = 1 and a > 2;")?;
+        assert!(plan.contains("Sequence Scan On Table t ( Filter: b = 1 AND a > 2 )"));
+        let plan = explain_plan(&mut s, "select * from t where a = 1 and c > 'x';")?;
+        assert!(plan.contains("Sequence Scan On Table t ( Filter: a = 1 AND c > x )"));
+        let plan = explain_plan(&mut s, "select * from t where a = 1 and c = 'x';")?;
+        assert!(plan.contains("Filter: a = 1"));
+        assert!(plan.contains("Index Scan On Table t.c"));
+
+        // 结果和全表扫描一致
+        for condition in [
+            "a = 1 and b > 3",
+            "b < 5 and a = 2 and b > 1",
+            "a = 3 and id = 7",
+            "a = 3 and id = 8",
+            "c = 'x' and b = 2",
+            "c = 'x' and a = 2",
+            "a = 0 and b = 0 and id > 10",
+        ] {
+            let sql =
+                |table: &str| format!("select * from {} where {} order by id;", table, condition);
+            let expected = query_rows(&mut s, &sql("n"))?;
+            assert_eq!(query_rows(&mut s, &sql("t"))?, expected, "{}", condition);
+        }
+        for (update, delete) in [
+            ("set b = 10 where a = 1 and b > 3", "where a = 2 and b < 4"),
+            (
+                "set a = 3 where a = 0 and id > 20",
+                "where id = 30 and a = 2",
+            ),
+        ] {
+            let count = |rs: ResultSet| match rs {
+                ResultSet::Update { count } | ResultSet::Delete { count } => count,
+                _ => unreachable!(),
+            };
+            assert_eq!(
+                count(s.execute(&format!("update t {};", update))?),
+                count(s.execute(&format!("update n {};", update))?)
+            );
+            assert_eq!(
+                count(s.execute(&format!("delete from t {};", delete))?),
+                count(s.execute(&format!("delete from n {};", delete))?)
+            );
+            assert_eq!(
+                query_rows(&mut s, "select * from t order by id;")?,
+                query_rows(&mut s, "select * from n order by id;")?
+            );
+        }
+
+        // 连接之后的条件同样使用过滤节点
+        let plan = explain_plan(
+            &mut s,
+            "select * from t join n on t.id = n.id where t.b > n.b;",
+        )?;
+        assert!(plan.contains("Filter: t.b > n.b"));
+        assert!(query_rows(
+            &mut s,
+            "select t.id from t join n on t.id = n.id where t.b > n.b;"
+        )?
+        .is_empty());
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_constant_folding() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let kvengine = KVEngine::new(DiskEngine::new(p.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, b int, c text);")?;
+        s.execute("create table u (id int primary key, d int);")?;
+        s.execute("insert into t values (1, 10, 'x'), (5, 50, 'y'), (6, 60, NULL);")?;
+        s.execute("insert into u values (5, 500), (6, 600);")?;
+
+        // 常量折叠之后是 列 = 常量 的形式，可以走主键索引
+        for sql in [
+            "select * from t where id = 2 + 3;",
+            "select * from t where 2 + 3 = id;",
+            "select * from t where 1 = 1 and id = 5;",
+            "select * from t where id = 5 and 'a' || 'b' = 'ab';",
+        ] {
+            assert!(
+                explain_plan(&mut s, sql)?.contains("Primary Key Scan On Table t"),
+                "{}",
+                sql
+            );
+            assert!(!explain_plan(&mut s, sql)?.contains("Filter"), "{}", sql);
+            assert_eq!(
+                query_rows(&mut s, sql)?,
+                vec![vec![
+                    Value::Integer(5),
+                    Value::Integer(50),
+                    Value::String("y".into())
+                ]],
+                "{}",
+                sql
+            );
+        }
+        // 常量在左侧的范围比较交换两侧
+        let plan = explain_plan(&mut s, "select * from t where 5 < b;")?;
+        assert!(plan.ends_with("Sequence Scan On Table t ( Filter: b > 5 )"));
+        assert_eq!(
+            query_rows(&mut s, "select id from t where 10 < b;")?.len(),
+            2
         );
+
+        // 恒为真的条件直接去掉
+        let plan = explain_plan(&mut s, "select * from t where 1 = 1;")?;
+        assert!(plan.ends_with("Sequence Scan On Table t"));
+        assert_eq!(query_rows(&mut s, "select * from t where true;")?.len(), 3);
+
+        // 恒为假或者恒为 NULL 时不扫描
+        for sql in [
+            "select * from t where 1 = 2;",
+            "select * from t where false;",
+            "select * from t where null = 1;",
+            "select * from t where b > 1 and 1 > 2;",
+            "select * from t where not 'a' = 'a';",
+        ] {
+            assert!(explain_plan(&mut s, sql)?.ends_with("\nNothing"), "{}", sql);
+            assert!(query_rows(&mut s, sql)?.is_empty(), "{}", sql);
+        }
+        // 上层节点仍然可以使用扫描的列
+        assert_eq!(
+            s.execute("select c, b from t where 1 = 2 order by b;")?,
+            ResultSet::Scan {
+                columns: vec!["c".into(), "b".into()],
+                rows: vec![],
+            }
+        );
+        assert_eq!(
+            query_rows(&mut s, "select count(*), max(b) from t where 1 = 2;")?,
+            vec![vec![Value::Integer(0), Value::Null]]
+        );
+        assert_eq!(
+            s.execute("select * from t join u on t.id = u.id where 1 = 2;")?,
+            ResultSet::Scan {
+                columns: vec![
+                    "t.id".into(),
+                    "t.b".into(),
+                    "t.c".into(),
+                    "u.id".into(),
+                    "u.d".into()
+                ],
+                rows: vec![],
+            }
+        );
+        // 恒为假时不再绑定条件中的列
+        assert!(query_rows(&mut s, "select * from t where 1 = 2 and x = 1;")?.is_empty());
+        assert!(explain_plan(&mut s, "delete from t where 1 = 2;")?
+            .ends_with("Delete On Table t\n -> Nothing"));
+        assert_eq!(
+            s.execute("delete from t where 1 = 2;")?,
+            ResultSet::Delete { count: 0 }
+        );
+        assert_eq!(
+            s.execute("update t set b = 0 where null;")?,
+            ResultSet::Update { count: 0 }
+        );
+        assert_eq!(
+            s.execute("update u set d = 1 where 1 = 1;")?,
+            ResultSet::Update { count: 2 }
+        );
+
+        // 不是常量的条件结果不变，常量之间求值出错时留到执行时报错
+        assert_eq!(
+            query_rows(&mut s, "select id from t where c = 'x' || '';")?,
+            vec![vec![Value::Integer(1)]]
+        );
+        assert_eq!(
+            query_rows(&mut s, "select id from t where b >= 50 and 'a' = 'a';")?,
+            vec![vec![Value::Integer(5)], vec![Value::Integer(6)]]
+        );
+        assert_eq!(
+            s.execute("select * from t where 1 = 'a';"),
+            Err(Error::Type(
+                "[Executor] Can not compare expression 1 and a".into()
+            ))
+        );
+
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
@@ -1,12 +1,15 @@
 use crate::error::{Error, Result};
-use crate::sql::engine::Transaction;
+use crate::sql::engine::{catalog, Transaction};
 use crate::sql::parser::ast;
-use crate::sql::parser::ast::JoinType::Cross;
-use crate::sql::parser::ast::{Expression, FromItem, JoinType, Operation, Sentence};
+use crate::sql::parser::ast::{
+    find_column, fold_constants, join_conjuncts, replace_fields, split_conjuncts, Consts,
+    Expression, FromItem, JoinType, Operation, OrderBy, Sentence,
+};
 use crate::sql::planner::{Node, Plan};
 use crate::sql::schema;
 use crate::sql::schema::Table;
-use crate::sql::types::Value;
+use crate::sql::types::{Collation, DataType, Value};
+use log::debug;
 
 pub struct Planner<'a, T: Transaction> {
     // 辅助Plan的结构体
@@ -19,37 +22,37 @@ impl<'a, T: Transaction> Planner<'a, T> {
     }
 
     pub fn build(&mut self, sentence: Sentence) -> Result<Plan> {
-        Ok(Plan(self.build_sentence(sentence)?))
+        let node = self.build_sentence(sentence)?;
+        // 根节点需要全部的列，select * 之类的查询不会被裁剪
+        Ok(Plan(self.prune_columns(node, None)?))
     }
 
     // 将parser得到的sql-sentence转换为node节点
     fn build_sentence(&mut self, sentence: Sentence) -> Result<Node> {
+        // 系统目录表只读，写操作直接拒绝
+        match &sentence {
+            Sentence::CreateTable { name, .. } | Sentence::DropTable { name } => {
+                catalog::check_writable(name)?
+            }
+            Sentence::Insert { table_name, .. }
+            | Sentence::Update { table_name, .. }
+            | Sentence::Delete { table_name, .. } => catalog::check_writable(table_name)?,
+            _ => {}
+        }
+
         Ok(match sentence {
-            Sentence::CreateTable { name, columns } => Node::CreateTable {
+            Sentence::CreateTable {
+                name,
+                columns,
+                comment,
+            } => Node::CreateTable {
                 schema: Table {
                     name,
+                    comment,
                     columns: columns
                         .into_iter()
-                        .map(|c| {
-                            let nullable = c.nullable.unwrap_or(!c.is_primary_key); // 如果是主键，则!c.is_primary_key == false，不能为空
-                            let default = match c.default {
-                                Some(expression) => {
-                                    Some(Value::from_expression_to_value(expression))
-                                }
-                                None if nullable => Some(Value::Null), // 如果没写default且可为null，则默认null
-                                None => None,
-                            };
-
-                            schema::Column {
-                                name: c.name,
-                                datatype: c.datatype,
-                                nullable,
-                                default,
-                                is_primary_key: c.is_primary_key,
-                                is_index: c.is_index && !c.is_primary_key, // 主键不能建索引
-                            }
-                        })
-                        .collect(),
+                        .map(schema::Column::from_definition)
+                        .collect::<Result<_>>()?,
                 },
             },
 
@@ -74,16 +77,99 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 order_by,
                 limit,
                 offset,
+                for_update,
             } => {
+                // 单表查询扫描出的列不带表名，先去掉列名中的表名，表名必须是查询的表
+                let (select_condition, where_condition, group_by, having, order_by) =
+                    match &from_item {
+                        FromItem::Table { name } => (
+                            select_condition
+                                .into_iter()
+                                .map(|(expr, alias)| Ok((Self::unqualify(expr, name)?, alias)))
+                                .collect::<Result<Vec<_>>>()?,
+                            Self::unqualify_opt(where_condition, name)?,
+                            Self::unqualify_opt(group_by, name)?,
+                            Self::unqualify_opt(having, name)?,
+                            order_by
+                                .into_iter()
+                                .map(|(expr, order)| Ok((Self::unqualify(expr, name)?, order)))
+                                .collect::<Result<Vec<_>>>()?,
+                        ),
+                        FromItem::Join { .. } => (
+                            select_condition,
+                            where_condition,
+                            group_by,
+                            having,
+                            order_by,
+                        ),
+                    };
+                // order by 和聚集函数需要根据原表的列进行校验
+                let need_check = !order_by.is_empty()
+                    || group_by.is_some()
+                    || select_condition
+                        .iter()
+                        .any(|(expr, _)| matches!(expr, Expression::Function(_, _, _)));
+                let (source_cols, nocase_cols) = if need_check {
+                    (
+                        self.source_columns(&from_item)?,
+                        self.nocase_columns(&from_item)?,
+                    )
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                let group_by = Self::resolve_group_by(group_by, &select_condition)?;
+                let group_col = match &group_by {
+                    Some(Expression::Field(col)) => Some(col.clone()),
+                    _ => None,
+                };
+
+                // where 中可以使用 select 中的别名，在下推和选择索引之前还原为原表达式
+                let where_condition = match where_condition {
+                    Some(condition)
+                        if select_condition.iter().any(|(_, alias)| alias.is_some()) =>
+                    {
+                        Some(Self::resolve_where_aliases(
+                            condition,
+                            &select_condition,
+                            &self.source_columns(&from_item)?,
+                        )?)
+                    }
+                    condition => condition,
+                };
+
                 // from
-                let mut node = self.build_from_item(from_item, &where_condition)?;
+                // where条件恒为假时不需要扫描，select ... for update 仍然正常构建，以便检查每张表能否加锁
+                let where_condition = Self::fold_filter(where_condition);
+                let mut node = if Self::is_never(&where_condition) && !for_update {
+                    debug!("[Planner] Filter is always false, skipping the scan");
+                    Node::Nothing {
+                        columns: self.source_columns(&from_item)?,
+                    }
+                } else {
+                    // where条件只涉及一张表时下推到该表的扫描节点，否则在连接之后再过滤
+                    let (pushdown, post_filter) = match where_condition {
+                        Some(expr) => match self.pushdown_table(&from_item, &expr)? {
+                            Some(table_name) => (Some((table_name, expr)), None),
+                            None => (None, Some(expr)),
+                        },
+                        None => (None, None),
+                    };
+                    let node = self.build_from_item(from_item, &pushdown, for_update)?;
+                    match post_filter {
+                        Some(condition) => Node::Filter {
+                            source: Box::new(node),
+                            condition,
+                        },
+                        None => node,
+                    }
+                };
 
                 // agg or group by
                 let mut has_agg = false;
                 if !select_condition.is_empty() {
                     for (expr, _) in select_condition.iter() {
                         // 判断expr是否是聚集函数
-                        if let ast::Expression::Function(_, _) = expr {
+                        if let ast::Expression::Function(_, _, _) = expr {
                             has_agg = true;
                             break;
                         }
@@ -94,10 +180,16 @@ impl<'a, T: Transaction> Planner<'a, T> {
                     }
 
                     if has_agg {
+                        Self::check_agg_columns(&select_condition, &group_by, &source_cols)?;
+                        let group_collation = match &group_col {
+                            Some(col) => Self::collation_of(col, &nocase_cols)?,
+                            None => Collation::Binary,
+                        };
                         node = Node::Aggregate {
                             source: Box::new(node),
                             expression: select_condition.clone(),
                             group_by,
+                            group_collation,
                         }
                     }
                 }
@@ -112,31 +204,57 @@ impl<'a, T: Transaction> Planner<'a, T> {
 
                 // 如果有order by，那么这里就返回OrderBy节点而不是Scan节点
                 if !order_by.is_empty() {
-                    node = Node::OrderBy {
-                        scan: Box::new(node),
+                    let order_by = Self::resolve_order_by(
                         order_by,
-                    }; // 更新 scan_node 为 order_by_node
+                        &select_condition,
+                        &source_cols,
+                        group_col.as_ref(),
+                        has_agg,
+                    )?;
+                    let collations = order_by
+                        .iter()
+                        .map(|(expr, _)| match expr {
+                            Expression::Field(col) => Self::order_by_collation(
+                                col,
+                                &select_condition,
+                                &nocase_cols,
+                                has_agg,
+                            ),
+                            _ => Ok(Collation::Binary), // 拼接的结果按原值排序
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    // 按主键排序时直接按主键的顺序扫描表，不需要排序
+                    match (self.pk_scan_order(&node, &order_by)?, &mut node) {
+                        (Some(order), Node::Scan { reverse, .. }) => {
+                            debug!("[Planner] ORDER BY primary key {:?}, sort skipped", order);
+                            *reverse = order == OrderBy::Desc;
+                        }
+                        _ => {
+                            node = Node::OrderBy {
+                                scan: Box::new(node),
+                                order_by,
+                                collations,
+                            }; // 更新 scan_node 为 order_by_node
+                        }
+                    }
                 }
 
-                // offset
-                if let Some(expr) = offset {
-                    node = Node::Offset {
-                        source: Box::new(node),
-                        offset: match Value::from_expression_to_value(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => return Err(Error::Internal("invalid offset".into())),
-                        },
+                // offset，offset 0 不跳过任何行，不需要生成节点
+                if let Some(offset) = offset {
+                    let offset = Self::row_count(offset, "OFFSET")?;
+                    if offset > 0 {
+                        node = Node::Offset {
+                            source: Box::new(node),
+                            offset,
+                        }
                     }
                 }
 
-                // limit
-                if let Some(expr) = limit {
+                // limit，limit 0 不返回任何行
+                if let Some(limit) = limit {
                     node = Node::Limit {
                         source: Box::new(node),
-                        limit: match Value::from_expression_to_value(expr) {
-                            Value::Integer(i) => i as usize,
-                            _ => return Err(Error::Internal("invalid offset".into())),
-                        },
+                        limit: Self::row_count(limit, "LIMIT")?,
                     }
                 }
 
@@ -156,26 +274,64 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 columns,
                 condition,
             } => Node::Update {
-                table_name: table_name.clone(),
-                scan: Box::new(self.build_scan_or_index(table_name, condition)?),
-                columns,
+                columns: columns
+                    .into_iter()
+                    .map(|(col, expr)| Ok((col, Self::unqualify(expr, &table_name)?)))
+                    .collect::<Result<_>>()?,
+                scan: Box::new(self.build_scan_or_index(
+                    table_name.clone(),
+                    Self::unqualify_opt(condition, &table_name)?,
+                )?),
+                table_name,
             },
 
             Sentence::Delete {
                 table_name,
                 condition,
             } => Node::Delete {
-                table_name: table_name.clone(),
-                scan: Box::new(self.build_scan_or_index(table_name, condition)?),
+                scan: Box::new(self.build_scan_or_index(
+                    table_name.clone(),
+                    Self::unqualify_opt(condition, &table_name)?,
+                )?),
+                table_name,
             },
 
             Sentence::TableSchema { table_name } => Node::TableSchema { name: table_name },
+            Sentence::ShowCreateTable { table_name } => Node::ShowCreateTable { name: table_name },
+            Sentence::ShowIndexes { table_name } => Node::ShowIndexes { name: table_name },
+            Sentence::ChecksumTable { table_name } => Node::ChecksumTable { name: table_name },
+            Sentence::CheckDatabase {} => Node::CheckDatabase {},
             Sentence::TableNames {} => Node::TableNames {},
-            Sentence::Begin {} | Sentence::Commit {} | Sentence::Rollback {} => {
+            Sentence::KvGet { key } => Node::KvGet { key },
+            Sentence::KvPut { key, value } => Node::KvPut { key, value },
+            Sentence::KvDelete { key } => Node::KvDelete { key },
+            Sentence::Begin { .. }
+            | Sentence::Commit {}
+            | Sentence::Rollback {}
+            | Sentence::Savepoint { .. }
+            | Sentence::RollbackTo { .. }
+            | Sentence::Release { .. } => {
                 return Err(Error::Internal(
                     "[Planner] Unexpected transaction command".into(),
                 ));
             }
+            Sentence::PurgeTable { .. } => {
+                return Err(Error::Internal(
+                    "[Planner] Unexpected purge table command".into(),
+                ));
+            }
+            Sentence::ShowStatus {} => {
+                return Err(Error::Internal(
+                    "[Planner] Unexpected show status command".into(),
+                ));
+            }
+            Sentence::CreateDatabase { .. }
+            | Sentence::UseDatabase { .. }
+            | Sentence::DatabaseNames {} => {
+                return Err(Error::Internal(
+                    "[Planner] Unexpected database command".into(),
+                ));
+            }
             Sentence::Explain { sentence: _ } => {
                 // 不使用字段sentence
                 return Err(Error::Internal(
@@ -185,10 +341,35 @@ impl<'a, T: Transaction> Planner<'a, T> {
         })
     }
 
-    // 将from_item变成plan_node
-    fn build_from_item(&mut self, item: FromItem, filter: &Option<Expression>) -> Result<Node> {
+    // 将from_item变成plan_node，filter为下推的where条件及其所属的表
+    // for_update 时在每张表的扫描节点之上加锁
+    fn build_from_item(
+        &mut self,
+        item: FromItem,
+        filter: &Option<(String, Expression)>,
+        for_update: bool,
+    ) -> Result<Node> {
         let node = match item {
-            FromItem::Table { name } => self.build_scan_or_index(name, filter.clone())?,
+            FromItem::Table { name } => {
+                // 下推到扫描节点的条件按扫描出的列名（不带表名）求值
+                let filter = match filter {
+                    Some((table_name, expr)) if *table_name == name => {
+                        Some(Self::unqualify(expr.clone(), &name)?)
+                    }
+                    _ => None,
+                };
+                let scan = self.build_scan_or_index(name.clone(), filter)?;
+                if for_update {
+                    // 系统目录是虚拟表，没有可以锁定的行
+                    catalog::check_writable(&name)?;
+                    Node::LockRows {
+                        source: Box::new(scan),
+                        table_name: name,
+                    }
+                } else {
+                    scan
+                }
+            }
             FromItem::Join {
                 left,
                 right,
@@ -196,9 +377,14 @@ impl<'a, T: Transaction> Planner<'a, T> {
                 condition,
             } => {
                 // 优化： a right join b == b left join a， 这样一套逻辑就可以复用
-                let (left, right) = match join_type {
-                    JoinType::Right => (right, left),
-                    _ => (left, right),
+                // 交换之后输出的列是 b 在前，需要记下原来的列顺序，最后再投影回 a 在前
+                let (left, right, swapped_cols) = match join_type {
+                    JoinType::Right => {
+                        let mut cols = self.join_input_columns(&left)?;
+                        cols.extend(self.join_input_columns(&right)?);
+                        (right, left, Some(cols))
+                    }
+                    _ => (left, right, None),
                 };
 
                 let outer = match join_type {
@@ -206,65 +392,874 @@ impl<'a, T: Transaction> Planner<'a, T> {
                     _ => true,
                 };
 
-                if join_type == Cross {
-                    Node::NestedLoopJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
-                        condition,
-                        outer,
+                // 连接条件按 and 拆开：左右两表的列之间的等值比较（= 或者 <=>）全部作为 HashJoin 的连接键，
+                // 其余的作为剩余条件，在等值匹配上的每一对行上求值
+                // 没有等值比较时（如 a > b）由 NestedLoopJoin 逐对求值
+                let (left_cols, right_cols) = (
+                    self.join_input_columns(&left)?,
+                    self.join_input_columns(&right)?,
+                );
+                let conjuncts = match condition {
+                    Some(condition) => split_conjuncts(condition)
+                        .into_iter()
+                        .map(|c| Self::orient_join_condition(c, &left_cols))
+                        .collect::<Result<Vec<_>>>()?,
+                    None => Vec::new(),
+                };
+                let (mut equi, mut rest) = (Vec::new(), Vec::new());
+                for conjunct in conjuncts {
+                    match Self::is_join_key(&conjunct, &left_cols, &right_cols)? {
+                        true => equi.push(conjunct),
+                        false => rest.push(conjunct),
                     }
-                } else {
-                    Node::HashJoin {
-                        left: Box::new(self.build_from_item(*left, filter)?),
-                        right: Box::new(self.build_from_item(*right, filter)?),
-                        condition,
+                }
+                let node = match equi.is_empty() {
+                    false => Node::HashJoin {
+                        left: Box::new(self.build_from_item(*left, filter, for_update)?),
+                        right: Box::new(self.build_from_item(*right, filter, for_update)?),
+                        condition: join_conjuncts(equi),
+                        residual: join_conjuncts(rest),
                         outer,
-                    }
+                    },
+                    true => Node::NestedLoopJoin {
+                        left: Box::new(self.build_from_item(*left, filter, for_update)?),
+                        right: Box::new(self.build_from_item(*right, filter, for_update)?),
+                        condition: join_conjuncts(rest),
+                        outer,
+                    },
+                };
+
+                match swapped_cols {
+                    Some(cols) => Node::Projection {
+                        source: Box::new(node),
+                        expressions: cols
+                            .into_iter()
+                            .map(|col| (Expression::Field(col), None))
+                            .collect(),
+                    },
+                    None => node,
                 }
             }
         };
         Ok(node)
     }
 
-    // 根据filter条件判断是否可以走索引
-    fn build_scan_or_index(&self, table_name: String, filter: Option<Expression>) -> Result<Node> {
-        let node = match Self::parse_filter(filter.clone()) {
-            Some((col, val)) => {
-                // 即使条件是 b=2，但是若不是索引列，也不能走索引
-                let table = self.transaction.must_get_table(table_name.clone())?;
+    // 判断where条件可以下推到哪张表的扫描节点
+    // 单表查询直接下推；连接查询中，条件里的列都属于同一张表时才能下推
+    // 外连接中可能被补NULL的一侧不能下推，否则补出来的NULL行不会被where过滤掉
+    fn pushdown_table(&self, item: &FromItem, condition: &Expression) -> Result<Option<String>> {
+        if let FromItem::Table { name } = item {
+            return Ok(Some(name.clone()));
+        }
+
+        let mut fields = Vec::new();
+        Self::collect_fields(condition, &mut fields);
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        // 每张表的列名（带表名）以及是否可能被补NULL
+        let mut tables = Vec::new();
+        self.join_tables(item, false, &mut tables)?;
+        let columns = tables
+            .iter()
+            .flat_map(|(_, cols, _)| cols.clone())
+            .collect::<Vec<_>>();
+
+        let mut target: Option<usize> = None;
+        for field in fields {
+            // 列不存在时留到连接之后再过滤，执行时会报错
+            let pos = match find_column(&columns, field)? {
+                Some(pos) => pos,
+                None => return Ok(None),
+            };
+            // 根据列的下标找到所属的表
+            let mut table_index = 0;
+            let mut offset = tables[0].1.len();
+            while pos >= offset {
+                table_index += 1;
+                offset += tables[table_index].1.len();
+            }
+            match target {
+                Some(i) if i != table_index => return Ok(None),
+                _ => target = Some(table_index),
+            }
+        }
+
+        Ok(target.and_then(|i| {
+            let (name, _, nullable) = &tables[i];
+            if *nullable {
+                None
+            } else {
+                Some(name.clone())
+            }
+        }))
+    }
 
-                // 如果是主键，那走主键索引
-                if table
+    // 收集连接中的所有表：表名、带表名的列名、是否在外连接中可能被补NULL
+    fn join_tables(
+        &self,
+        item: &FromItem,
+        nullable: bool,
+        tables: &mut Vec<(String, Vec<String>, bool)>,
+    ) -> Result<()> {
+        match item {
+            FromItem::Table { name } => {
+                let cols = self
+                    .transaction
+                    .must_get_table(name.clone())?
                     .columns
-                    .iter()
-                    .position(|c| c.name == col && c.is_primary_key)
-                    .is_some()
-                {
-                    return Ok(Node::PkIndex {
-                        table_name,
-                        value: val,
-                    });
+                    .into_iter()
+                    .map(|c| format!("{}.{}", name, c.name))
+                    .collect();
+                tables.push((name.clone(), cols, nullable));
+            }
+            FromItem::Join {
+                left,
+                right,
+                join_type,
+                ..
+            } => {
+                let (left_nullable, right_nullable) = match join_type {
+                    JoinType::Left => (nullable, true),
+                    JoinType::Right => (true, nullable),
+                    JoinType::Cross | JoinType::Inner => (nullable, nullable),
+                };
+                self.join_tables(left, left_nullable, tables)?;
+                self.join_tables(right, right_nullable, tables)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 收集表达式中用到的列名，包括聚集函数的参数及其 filter 条件
+    fn collect_fields<'b>(expr: &'b Expression, fields: &mut Vec<&'b String>) {
+        match expr {
+            Expression::Field(col) => fields.push(col),
+            Expression::Operation(
+                Operation::Equal(l, r)
+                | Operation::Greater(l, r)
+                | Operation::GreaterEqual(l, r)
+                | Operation::Less(l, r)
+                | Operation::LessEqual(l, r)
+                | Operation::NotEqual(l, r)
+                | Operation::NullSafeEqual(l, r)
+                | Operation::And(l, r)
+                | Operation::Concat(l, r),
+            ) => {
+                Self::collect_fields(l, fields);
+                Self::collect_fields(r, fields);
+            }
+            Expression::Operation(Operation::Not(e)) => Self::collect_fields(e, fields),
+            Expression::Operation(Operation::In(e, list)) => {
+                Self::collect_fields(e, fields);
+                for item in list {
+                    Self::collect_fields(item, fields);
+                }
+            }
+            Expression::Function(_, col, filter) => {
+                fields.push(col);
+                if let Some(filter) = filter {
+                    Self::collect_fields(filter, fields);
                 }
+            }
+            Expression::Consts(_) | Expression::Default => {}
+        }
+    }
 
-                match table
-                    .columns
+    // 列裁剪：自顶向下计算每个节点需要其子节点输出的列，扫描节点只输出需要的列
+    // 宽表参与连接、排序时，可以尽早丢掉用不到的值，减少内存占用
+    // required 为 None 表示需要全部的列；锁定行、更新、删除需要完整的行，不做裁剪
+    fn prune_columns(&self, node: Node, required: Option<Vec<String>>) -> Result<Node> {
+        // 表达式中用到的列
+        let fields = |exprs: &[&Expression]| {
+            let mut fields = Vec::new();
+            for expr in exprs {
+                Self::collect_fields(expr, &mut fields);
+            }
+            fields.into_iter().cloned().collect::<Vec<_>>()
+        };
+        // 在上层需要的列的基础上，加上本节点用到的列
+        let with_fields = |required: &Option<Vec<String>>, exprs: &[&Expression]| {
+            required.clone().map(|mut cols| {
+                cols.extend(fields(exprs));
+                cols
+            })
+        };
+
+        Ok(match node {
+            // 投影和聚集之后的列都由本节点重新生成，子节点只需要输出本节点用到的列
+            Node::Projection {
+                source,
+                expressions,
+            } => {
+                let exprs = expressions.iter().map(|(e, _)| e).collect::<Vec<_>>();
+                Node::Projection {
+                    source: Box::new(self.prune_columns(*source, Some(fields(&exprs)))?),
+                    expressions,
+                }
+            }
+            Node::Aggregate {
+                source,
+                expression,
+                group_by,
+                group_collation,
+            } => {
+                let exprs = expression
                     .iter()
-                    .position(|c| *c.name == col && c.is_index)
-                {
-                    Some(_) => {
-                        // 本列有索引
-                        Node::ScanIndex {
-                            table_name,
-                            col_name: col,
-                            value: val,
+                    .map(|(e, _)| e)
+                    .chain(group_by.iter())
+                    .collect::<Vec<_>>();
+                Node::Aggregate {
+                    source: Box::new(self.prune_columns(*source, Some(fields(&exprs)))?),
+                    expression,
+                    group_by,
+                    group_collation,
+                }
+            }
+            Node::Having { source, condition } => Node::Having {
+                source: Box::new(
+                    self.prune_columns(*source, with_fields(&required, &[&condition]))?,
+                ),
+                condition,
+            },
+            Node::Filter { source, condition } => Node::Filter {
+                source: Box::new(
+                    self.prune_columns(*source, with_fields(&required, &[&condition]))?,
+                ),
+                condition,
+            },
+            Node::OrderBy {
+                scan,
+                order_by,
+                collations,
+            } => {
+                let exprs = order_by.iter().map(|(e, _)| e).collect::<Vec<_>>();
+                let required = with_fields(&required, &exprs);
+                Node::OrderBy {
+                    scan: Box::new(self.prune_columns(*scan, required)?),
+                    order_by,
+                    collations,
+                }
+            }
+            Node::Limit { source, limit } => Node::Limit {
+                source: Box::new(self.prune_columns(*source, required)?),
+                limit,
+            },
+            Node::Offset { source, offset } => Node::Offset {
+                source: Box::new(self.prune_columns(*source, required)?),
+                offset,
+            },
+            // 连接的两侧都按同一组列名裁剪，扫描节点只保留属于自己的列
+            Node::NestedLoopJoin {
+                left,
+                right,
+                condition,
+                outer,
+            } => {
+                let required = with_fields(&required, &condition.iter().collect::<Vec<_>>());
+                Node::NestedLoopJoin {
+                    left: Box::new(self.prune_columns(*left, required.clone())?),
+                    right: Box::new(self.prune_columns(*right, required)?),
+                    condition,
+                    outer,
+                }
+            }
+            Node::HashJoin {
+                left,
+                right,
+                condition,
+                residual,
+                outer,
+            } => {
+                let exprs = condition.iter().chain(residual.iter()).collect::<Vec<_>>();
+                let required = with_fields(&required, &exprs);
+                Node::HashJoin {
+                    left: Box::new(self.prune_columns(*left, required.clone())?),
+                    right: Box::new(self.prune_columns(*right, required)?),
+                    condition,
+                    residual,
+                    outer,
+                }
+            }
+            // 扫描时的过滤条件在读出完整的行之后求值，不需要额外保留过滤用到的列
+            Node::Scan {
+                table_name,
+                filter,
+                columns: _,
+                reverse,
+            } => Node::Scan {
+                columns: self.scan_columns(&table_name, &required)?,
+                table_name,
+                filter,
+                reverse,
+            },
+            Node::ScanIndex {
+                table_name,
+                col_name,
+                value,
+                columns: _,
+            } => Node::ScanIndex {
+                columns: self.scan_columns(&table_name, &required)?,
+                table_name,
+                col_name,
+                value,
+            },
+            node => node,
+        })
+    }
+
+    // 扫描节点需要输出的列，按表中的列顺序排列，全部列都需要时返回None
+    // 只比较列名部分：t1.a 和 a 都会保留表中的 a 列，宁可多留也不能漏掉
+    fn scan_columns(
+        &self,
+        table_name: &str,
+        required: &Option<Vec<String>>,
+    ) -> Result<Option<Vec<String>>> {
+        let required = match required {
+            Some(required) => required,
+            None => return Ok(None),
+        };
+        let table = self.transaction.must_get_table(table_name.to_string())?;
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| &c.name)
+            .filter(|name| {
+                required.iter().any(|col| {
+                    col.split_once('.').map(|(_, col)| col).unwrap_or(col) == name.as_str()
+                })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        Ok(if columns.len() == table.columns.len() {
+            None
+        } else {
+            Some(columns)
+        })
+    }
+
+    // 获取from中所有表的列名
+    // 和执行时一致，连接查询中表的列名会带上表名，如 t1.a
+    fn source_columns(&self, item: &FromItem) -> Result<Vec<String>> {
+        Ok(match item {
+            FromItem::Table { name } => self
+                .transaction
+                .must_get_table(name.clone())?
+                .columns
+                .into_iter()
+                .map(|c| c.name)
+                .collect(),
+            FromItem::Join { left, right, .. } => {
+                let mut cols = self.join_input_columns(left)?;
+                cols.extend(self.join_input_columns(right)?);
+                cols
+            }
+        })
+    }
+
+    // 连接条件求值时比较的左侧取左表的列，右侧取右表的列
+    // 条件写成 右表列 > 左表列 时，交换两侧并翻转比较方向
+    fn orient_join_condition(condition: Expression, left_cols: &[String]) -> Result<Expression> {
+        let left_operand = match &condition {
+            Expression::Operation(
+                Operation::Equal(l, _)
+                | Operation::Greater(l, _)
+                | Operation::GreaterEqual(l, _)
+                | Operation::Less(l, _)
+                | Operation::LessEqual(l, _)
+                | Operation::NotEqual(l, _)
+                | Operation::NullSafeEqual(l, _),
+            ) => l.as_ref(),
+            _ => return Ok(condition),
+        };
+        Ok(match (left_operand, condition.clone()) {
+            (Expression::Field(col), Expression::Operation(operation))
+                if find_column(left_cols, col)?.is_none() =>
+            {
+                Expression::Operation(operation.reverse())
+            }
+            _ => condition,
+        })
+    }
+
+    // 是否可以作为 HashJoin 的连接键：左表的一列和右表的一列的等值比较，条件已经调整为左表的列在左侧
+    fn is_join_key(
+        condition: &Expression,
+        left_cols: &[String],
+        right_cols: &[String],
+    ) -> Result<bool> {
+        match condition {
+            Expression::Operation(Operation::Equal(l, r) | Operation::NullSafeEqual(l, r)) => {
+                match (l.as_ref(), r.as_ref()) {
+                    (Expression::Field(l), Expression::Field(r)) => Ok(find_column(left_cols, l)?
+                        .is_some()
+                        && find_column(right_cols, r)?.is_some()),
+                    _ => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn join_input_columns(&self, item: &FromItem) -> Result<Vec<String>> {
+        let cols = self.source_columns(item)?;
+        Ok(match item {
+            FromItem::Table { name } => cols
+                .into_iter()
+                .map(|c| format!("{}.{}", name, c))
+                .collect(),
+            FromItem::Join { .. } => cols,
+        })
+    }
+
+    // 不区分大小写的列，连接查询中和 source_columns 一样带上表名
+    fn nocase_columns(&self, item: &FromItem) -> Result<Vec<String>> {
+        Ok(match item {
+            FromItem::Table { name } => self
+                .transaction
+                .must_get_table(name.clone())?
+                .nocase_columns(),
+            FromItem::Join { left, right, .. } => {
+                let mut cols = Vec::new();
+                for item in [left, right] {
+                    let nocase_cols = self.nocase_columns(item)?;
+                    match item.as_ref() {
+                        FromItem::Table { name } => {
+                            cols.extend(nocase_cols.into_iter().map(|c| format!("{}.{}", name, c)))
                         }
+                        FromItem::Join { .. } => cols.extend(nocase_cols),
                     }
-                    None => Node::Scan { table_name, filter },
                 }
+                cols
+            }
+        })
+    }
+
+    // 原表中某列的比较规则
+    fn collation_of(col: &str, nocase_cols: &[String]) -> Result<Collation> {
+        Ok(match find_column(nocase_cols, col)? {
+            Some(_) => Collation::NoCase,
+            None => Collation::Binary,
+        })
+    }
+
+    // 排序列的比较规则：非聚集查询中排序列就是原表的列；聚集查询中只有来自 group by 列的输出才可能不区分大小写
+    fn order_by_collation(
+        col: &str,
+        select_condition: &[(Expression, Option<String>)],
+        nocase_cols: &[String],
+        has_agg: bool,
+    ) -> Result<Collation> {
+        if !has_agg {
+            return Self::collation_of(col, nocase_cols);
+        }
+        for (expr, nick_name) in select_condition {
+            match (expr, nick_name) {
+                (Expression::Field(name), Some(nick_name)) if nick_name == col => {
+                    return Self::collation_of(name, nocase_cols)
+                }
+                (Expression::Field(name), None) if name == col => {
+                    return Self::collation_of(name, nocase_cols)
+                }
+                _ => {}
+            }
+        }
+        Ok(Collation::Binary)
+    }
+
+    // 校验聚集查询中用到的列：聚集函数的参数、select的列和group by的列
+    // 必须存在于原表中，并且在连接查询中不能有歧义
+    fn check_agg_columns(
+        select_condition: &[(Expression, Option<String>)],
+        group_by: &Option<Expression>,
+        source_cols: &[String],
+    ) -> Result<()> {
+        let cols = select_condition
+            .iter()
+            .map(|(expr, _)| expr)
+            .chain(group_by.iter())
+            .filter_map(|expr| match expr {
+                Expression::Function(_, col, _) if col == "*" => None, // count(*) 不引用具体的列
+                Expression::Field(col) | Expression::Function(_, col, _) => Some(col),
+                _ => None,
+            });
+        for col in cols {
+            if find_column(source_cols, col)?.is_none() {
+                return Err(Error::NotFound(format!(
+                    "[Planner] Column \" {} \" does not exist in the source tables",
+                    col
+                )));
             }
-            None => Node::Scan { table_name, filter },
+        }
+        Ok(())
+    }
+
+    // 第一个排序列是直接扫描的表的主键时，返回排序方向：主键唯一，后面的排序列不起作用
+    // 只有主键的编码顺序和值的顺序一致时才能用扫描顺序代替排序：
+    // 浮点数目前按大端字节编码，负数排在正数之后，还不满足
+    fn pk_scan_order(
+        &self,
+        node: &Node,
+        order_by: &[(Expression, OrderBy)],
+    ) -> Result<Option<OrderBy>> {
+        let (Node::Scan { table_name, .. }, Some((Expression::Field(col), order))) =
+            (node, order_by.first())
+        else {
+            return Ok(None);
         };
-        Ok(node)
+        if catalog::is_catalog_table(table_name) {
+            return Ok(None);
+        }
+        let table = self.transaction.must_get_table(table_name.clone())?;
+        let cols = table
+            .columns
+            .iter()
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+        let Some(pos) = find_column(&cols, col)? else {
+            return Ok(None);
+        };
+        let column = &table.columns[pos];
+        let ordered = matches!(
+            column.datatype,
+            DataType::Integer | DataType::String | DataType::Char(_) | DataType::Boolean
+        );
+        Ok((column.is_primary_key && ordered).then(|| order.clone()))
+    }
+
+    // 去掉表达式中列名的表名，如 t1.a -> a，表名不是 table_name 时列不存在
+    fn unqualify(expr: Expression, table_name: &str) -> Result<Expression> {
+        let unqualify_col = |col: String| match col.split_once('.') {
+            Some((table, name)) if table == table_name => Ok(name.to_string()),
+            Some(_) => Err(Error::Internal(format!(
+                "[Planner] Column \" {} \" does not exist in table \" {} \"",
+                col, table_name
+            ))),
+            None => Ok(col),
+        };
+        match expr {
+            Expression::Function(func_name, col, filter) => Ok(Expression::Function(
+                func_name,
+                unqualify_col(col)?,
+                Self::unqualify_opt(filter.map(|f| *f), table_name)?.map(Box::new),
+            )),
+            expr => replace_fields(expr, &mut |col| Ok(Expression::Field(unqualify_col(col)?))),
+        }
+    }
+
+    fn unqualify_opt(expr: Option<Expression>, table_name: &str) -> Result<Option<Expression>> {
+        expr.map(|e| Self::unqualify(e, table_name)).transpose()
+    }
+
+    // group by 中的整数表示select列表中的位置，从 1 开始，解析为该位置上的列
+    fn resolve_group_by(
+        group_by: Option<Expression>,
+        select_condition: &[(Expression, Option<String>)],
+    ) -> Result<Option<Expression>> {
+        let Some(Expression::Consts(Consts::Integer(pos))) = group_by else {
+            return Ok(group_by);
+        };
+        let item = usize::try_from(pos)
+            .ok()
+            .and_then(|pos| pos.checked_sub(1))
+            .and_then(|i| select_condition.get(i));
+        match item {
+            Some((expr @ Expression::Field(_), _)) => Ok(Some(expr.clone())),
+            Some((expr, _)) => Err(Error::Internal(format!(
+                "[Planner] GROUP BY position {} refers to \" {} \", only columns can be grouped by",
+                pos, expr
+            ))),
+            None => Err(Error::NotFound(format!(
+                "[Planner] GROUP BY position {} is not in the select list, which has {} columns",
+                pos,
+                select_condition.len()
+            ))),
+        }
+    }
+
+    // 把 where 条件中 select 的别名还原为原表达式，如 select a as x from t where x > 5 即 where a > 5
+    // 原表中有同名的列时用原表的列；聚集在过滤之后才计算，聚集函数的别名不能用在 where 中
+    fn resolve_where_aliases(
+        condition: Expression,
+        select_condition: &[(Expression, Option<String>)],
+        source_cols: &[String],
+    ) -> Result<Expression> {
+        replace_fields(condition, &mut |col| {
+            if find_column(source_cols, &col)?.is_some() {
+                return Ok(Expression::Field(col));
+            }
+            match select_condition
+                .iter()
+                .find(|(_, alias)| alias.as_ref() == Some(&col))
+            {
+                Some((Expression::Function(func_name, _, _), _)) => Err(Error::Internal(format!(
+                    "[Planner] Alias \" {} \" of aggregate function {} cannot be used in WHERE, use HAVING instead",
+                    col, func_name
+                ))),
+                Some((expr, _)) => Ok(expr.clone()),
+                // 既不是原表的列也不是别名，执行时报列不存在
+                None => Ok(Expression::Field(col)),
+            }
+        })
+    }
+
+    // 校验 order by 条件，并解析为排序节点处实际的列名
+    // 非聚集查询：排序在投影之前执行，只能使用原表的列、拼接表达式或者select中的别名，别名会被还原为原表达式
+    // 聚集查询：排序在聚集之后执行，只能使用group by的列或者聚集函数的输出（别名、函数名或者重复写一遍聚集函数）
+    fn resolve_order_by(
+        order_by: Vec<(Expression, OrderBy)>,
+        select_condition: &[(Expression, Option<String>)],
+        source_cols: &[String],
+        group_col: Option<&String>,
+        has_agg: bool,
+    ) -> Result<Vec<(Expression, OrderBy)>> {
+        let mut res = Vec::new();
+        for (expr, order) in order_by {
+            let col = match expr {
+                Expression::Field(col) if has_agg => Expression::Field(Self::resolve_agg_order_by(
+                    col,
+                    select_condition,
+                    source_cols,
+                    group_col,
+                )?),
+                Expression::Field(col) => {
+                    // select中的别名优先
+                    match select_condition
+                        .iter()
+                        .find(|(_, nick_name)| nick_name.as_ref() == Some(&col))
+                    {
+                        Some((Expression::Field(name), _)) => Expression::Field(name.clone()),
+                        Some((expr @ Expression::Operation(Operation::Concat(..)), _)) => {
+                            expr.clone()
+                        }
+                        Some((expr, _)) => {
+                            return Err(Error::Internal(format!(
+                                "[Planner] ORDER BY alias \" {} \" refers to an unsupported expression {}",
+                                col, expr
+                            )))
+                        }
+                        None if find_column(source_cols, &col)?.is_some() => Expression::Field(col),
+                        None => {
+                            return Err(Error::NotFound(format!(
+                                "[Planner] ORDER BY column \" {} \" does not exist in the source tables or select aliases",
+                                col
+                            )))
+                        }
+                    }
+                }
+                Expression::Function(_, _, _) if has_agg => {
+                    match select_condition.iter().find(|(e, _)| *e == expr) {
+                        Some((Expression::Function(func_name, _, _), nick_name)) => {
+                            Expression::Field(nick_name.clone().unwrap_or(func_name.clone()))
+                        }
+                        _ => {
+                            return Err(Error::Internal(format!(
+                                "[Planner] ORDER BY aggregate \" {} \" must appear in the select list",
+                                expr
+                            )))
+                        }
+                    }
+                }
+                Expression::Function(_, _, _) => {
+                    return Err(Error::Internal(format!(
+                        "[Planner] ORDER BY aggregate \" {} \" is not allowed in a query without aggregate functions or GROUP BY",
+                        expr
+                    )))
+                }
+                // 拼接表达式用到的列在排序时绑定，不存在的列在那里报错
+                expr @ Expression::Operation(Operation::Concat(..)) if !has_agg => expr,
+                expr => {
+                    return Err(Error::Internal(format!(
+                        "[Planner] ORDER BY only supports columns or aggregate functions, got \" {} \"",
+                        expr
+                    )))
+                }
+            };
+            res.push((col, order));
+        }
+        Ok(res)
+    }
+
+    // 聚集查询中 order by 的列名解析
+    fn resolve_agg_order_by(
+        col: String,
+        select_condition: &[(Expression, Option<String>)],
+        source_cols: &[String],
+        group_col: Option<&String>,
+    ) -> Result<String> {
+        // 先看是否是聚集之后的输出列
+        for (select_expr, nick_name) in select_condition {
+            let output = match (select_expr, nick_name) {
+                (_, Some(nick_name)) => nick_name,
+                (Expression::Field(name), None) => name,
+                (Expression::Function(func_name, _, _), None) => func_name,
+                _ => continue,
+            };
+            if *output == col {
+                return Ok(col);
+            }
+        }
+        // 再看是否是被起了别名的group by列
+        if let Some((_, Some(nick_name))) = select_condition
+            .iter()
+            .find(|(e, _)| *e == Expression::Field(col.clone()))
+        {
+            return Ok(nick_name.clone());
+        }
+
+        Err(Error::Internal(if group_col == Some(&col) {
+            format!(
+                "[Planner] ORDER BY group by column \" {} \" must appear in the select list",
+                col
+            )
+        } else if find_column(source_cols, &col)?.is_some() {
+            format!("[Planner] ORDER BY column \" {} \" must appear in GROUP BY or be used in an aggregate function", col)
+        } else {
+            format!(
+                "[Planner] ORDER BY column \" {} \" does not exist in the aggregate output",
+                col
+            )
+        }))
+    }
+
+    // 根据filter条件判断是否可以走索引
+    fn build_scan_or_index(&self, table_name: String, filter: Option<Expression>) -> Result<Node> {
+        let filter = Self::fold_filter(filter);
+        if Self::is_never(&filter) {
+            debug!(
+                "[Planner] Filter is always false, skipping the scan on {}",
+                table_name
+            );
+            let table = self.transaction.must_get_table(table_name)?;
+            return Ok(Node::Nothing {
+                columns: table.columns.into_iter().map(|c| c.name).collect(),
+            });
+        }
+        let Some(filter) = filter else {
+            debug!("[Planner] Full table scan chosen on {}", table_name);
+            return Ok(Node::Scan {
+                table_name,
+                filter: None,
+                columns: None,
+                reverse: false,
+            });
+        };
+        let table = self.transaction.must_get_table(table_name.clone())?;
+
+        // 把 and 连接的条件拆开，找一个可以走索引的等值条件，优先主键
+        // 只有 列 = 常量 的条件才有可能走索引，单独的布尔列（如 where flag）需要全表扫描
+        let mut conjuncts = split_conjuncts(filter);
+        let mut candidates = Vec::new();
+        for (i, conjunct) in conjuncts.iter().enumerate() {
+            if !matches!(conjunct, Expression::Operation(Operation::Equal(l, r))
+                if matches!(**l, Expression::Field(_)) && matches!(**r, Expression::Consts(_)))
+            {
+                continue;
+            }
+            let Some((col, val)) = Self::parse_filter(Some(conjunct.clone())) else {
+                continue;
+            };
+            // 带表名的列，如 t1.a = 2，只有表名一致才可能走索引
+            let col = match col.split_once('.') {
+                Some((table, name)) if table == table_name => name.to_string(),
+                _ => col,
+            };
+            // 即使条件是 b=2，但是若不是索引列，也不能走索引
+            match table.columns.iter().find(|c| c.name == col) {
+                Some(c) if c.is_primary_key => candidates.insert(0, (i, col, val)),
+                Some(c) if c.is_index => candidates.push((i, col, val)),
+                _ => {}
+            }
+        }
+
+        // 其余的条件在索引扫描之后过滤，nocase 列的比较需要在扫描时按比较规则求值，这种情况只能全表扫描
+        let nocase_cols = table.nocase_columns();
+        let references_nocase = |exprs: &[Expression]| {
+            let mut fields = Vec::new();
+            for expr in exprs {
+                Self::collect_fields(expr, &mut fields);
+            }
+            fields.iter().any(|f| {
+                let name = f.split_once('.').map_or(f.as_str(), |(_, name)| name);
+                nocase_cols.iter().any(|c| c == name)
+            })
+        };
+        let index = candidates.into_iter().find(|(i, _, _)| {
+            let rest = conjuncts
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| j != i)
+                .map(|(_, c)| c.clone())
+                .collect::<Vec<_>>();
+            !references_nocase(&rest)
+        });
+        let Some((i, col, value)) = index else {
+            debug!("[Planner] Full table scan chosen on {}", table_name);
+            return Ok(Node::Scan {
+                table_name,
+                filter: join_conjuncts(conjuncts),
+                columns: None,
+                reverse: false,
+            });
+        };
+
+        conjuncts.remove(i);
+        let is_primary_key = table
+            .columns
+            .iter()
+            .any(|c| c.name == col && c.is_primary_key);
+        let node = if is_primary_key {
+            // 如果是主键，那走主键索引
+            debug!(
+                "[Planner] Primary key lookup chosen on {}.{}",
+                table_name, col
+            );
+            Node::PkIndex { table_name, value }
+        } else {
+            // 本列有索引
+            debug!("[Planner] Index scan chosen on {}.{}", table_name, col);
+            Node::ScanIndex {
+                table_name,
+                col_name: col,
+                value,
+                columns: None,
+            }
+        };
+        Ok(match join_conjuncts(conjuncts) {
+            Some(condition) => Node::Filter {
+                source: Box::new(node),
+                condition,
+            },
+            None => node,
+        })
+    }
+
+    // 对过滤条件做常量折叠：恒为真的条件直接去掉，恒为假或者恒为 NULL 时统一为 false
+    fn fold_filter(filter: Option<Expression>) -> Option<Expression> {
+        match fold_constants(filter?) {
+            Expression::Consts(Consts::Boolean(true)) => None,
+            Expression::Consts(Consts::Boolean(false) | Consts::Null) => {
+                Some(Expression::Consts(Consts::Boolean(false)))
+            }
+            expr => Some(expr),
+        }
+    }
+
+    // limit、offset 的行数，只能是非负整数
+    fn row_count(expr: Expression, clause: &str) -> Result<usize> {
+        match expr {
+            Expression::Consts(Consts::Integer(i)) if i >= 0 => Ok(i as usize),
+            expr => Err(Error::Type(format!(
+                "[Planner] {} must be a non-negative integer, got {}",
+                clause, expr
+            ))),
+        }
+    }
+
+    // 折叠之后的过滤条件是否恒为假，此时不会有任何行满足条件
+    fn is_never(filter: &Option<Expression>) -> bool {
+        matches!(filter, Some(Expression::Consts(Consts::Boolean(false))))
     }
 
     // 解析上个函数的filter表达式
@@ -274,10 +1269,7 @@ impl<'a, T: Transaction> Planner<'a, T> {
             Some(expr) => {
                 match expr {
                     // 解析右边的常数
-                    Expression::Consts(val) => Some((
-                        "".into(),
-                        Value::from_expression_to_value(Expression::Consts(val)),
-                    )),
+                    Expression::Consts(val) => Some(("".into(), Value::from_consts(val))),
                     // 解析左边的列名
                     Expression::Field(col) => Some((col, Value::Null)),
                     Expression::Operation(operation) => {
@@ -1,12 +1,17 @@
 use crate::error::*;
-use crate::sql::types::{DataType, Row, Value};
+use crate::sql::parser::ast;
+use crate::sql::parser::lexer::{quote_identifier, quote_string};
+use crate::sql::types::{Collation, DataType, Row, Value};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+// 表结构用 bincode 编码之后持久化，bincode 按字段顺序编码，不能识别缺少的字段
+// 增删或调整 Table、Column 的字段都要递增 KVEngine 的存储格式版本号
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    pub comment: Option<String>, // 表注释
 }
 
 impl Table {
@@ -14,9 +19,9 @@ impl Table {
     pub fn is_valid(&self) -> Result<()> {
         // 判断列是否为空
         if self.columns.is_empty() {
-            return Err(Error::Internal(format!(
+            return Err(Error::Schema(format!(
                 "[CreateTable] Failed, Table \" {} \" has no columns",
-                self.name
+                quote_identifier(&self.name)
             )));
         }
 
@@ -24,15 +29,15 @@ impl Table {
         match self.columns.iter().filter(|c| c.is_primary_key).count() {
             1 => {}
             0 => {
-                return Err(Error::Internal(format!(
+                return Err(Error::Schema(format!(
                     "[CreateTable] Failed, Table \" {} \" has no primary key",
-                    self.name
+                    quote_identifier(&self.name)
                 )))
             }
             _ => {
-                return Err(Error::Internal(format!(
+                return Err(Error::Schema(format!(
                     "[CreateTable] Failed, Table \" {} \" has multiple primary keys",
-                    self.name
+                    quote_identifier(&self.name)
                 )))
             }
         }
@@ -41,15 +46,30 @@ impl Table {
         for column in &self.columns {
             // 主键不能空
             if column.is_primary_key && column.nullable {
-                return Err(Error::Internal(format!("[CreateTable] Failed, primary key \" {} \" cannot be nullable in table \" {} \"", column.name, self.name)));
+                return Err(Error::Schema(format!("[CreateTable] Failed, primary key \" {} \" cannot be nullable in table \" {} \"", quote_identifier(&column.name), quote_identifier(&self.name))));
+            }
+
+            // 只有字符串列可以不区分大小写，主键按原值存储和查找，不支持
+            if column.collation == Collation::NoCase
+                && (column.datatype.value_type() != DataType::String || column.is_primary_key)
+            {
+                return Err(Error::Schema(format!("[CreateTable] Failed, collate nocase is only supported on non primary key string columns, column \" {} \" in table \" {} \"", quote_identifier(&column.name), quote_identifier(&self.name))));
+            }
+
+            // 不可为空的列不能以 NULL 作为默认值
+            if column.default == Some(Value::Null) && !column.nullable {
+                return Err(Error::Schema(format!("[CreateTable] Failed, column \" {} \" is NOT NULL but has DEFAULT NULL in table \" {} \"", quote_identifier(&column.name), quote_identifier(&self.name))));
             }
 
             // 列默认值需要和列数据类型匹配
             if let Some(default_value) = &column.default {
                 match default_value.get_datatype() {
                     Some(datatype) => {
-                        if datatype != column.datatype {
-                            return Err(Error::Internal(format!("[CreateTable] Failed, default value type for column \" {} \" mismatch in table \" {} \"", column.name, self.name)));
+                        if datatype != column.datatype.value_type() {
+                            return Err(Error::Schema(format!("[CreateTable] Failed, default value type for column \" {} \" mismatch in table \" {} \"", quote_identifier(&column.name), quote_identifier(&self.name))));
+                        }
+                        if column.exceeds_length(default_value) {
+                            return Err(Error::Schema(format!("[CreateTable] Failed, default value for column \" {} \" is longer than {:?} in table \" {} \"", quote_identifier(&column.name), column.datatype, quote_identifier(&self.name))));
                         }
                     }
                     None => {}
@@ -60,10 +80,154 @@ impl Table {
         Ok(())
     }
 
-    // 获取主键
+    // 主键列的下标，建表时已经校验过主键信息，但从存储中读出的表结构可能已经损坏
+    pub fn primary_key_index(&self) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.is_primary_key)
+            .ok_or(Error::Schema(format!(
+                "[Table] Table \" {} \" has no primary key",
+                quote_identifier(&self.name)
+            )))
+    }
+
+    // 获取主键，损坏的数据行可能比主键列的位置还短
     pub fn get_primary_key(&self, row: &Row) -> Result<Value> {
-        let index = self.columns.iter().position(|c| c.is_primary_key).unwrap(); // 由于建表时已经判断了主键信息，所以这里直接解包即可
-        Ok(row[index].clone())
+        let index = self.primary_key_index()?;
+        row.get(index).cloned().ok_or(Error::Storage(format!(
+            "[Table] Row of table \" {} \" has {} values, missing primary key column \" {} \"",
+            quote_identifier(&self.name),
+            row.len(),
+            quote_identifier(&self.columns[index].name)
+        )))
+    }
+
+    // 不区分大小写的列
+    pub fn nocase_columns(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|c| c.collation == Collation::NoCase)
+            .map(|c| c.name.clone())
+            .collect()
+    }
+
+    // 定长字符串 char(n) 列
+    pub fn char_columns(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|c| matches!(c.datatype, DataType::Char(_)))
+            .map(|c| c.name.clone())
+            .collect()
+    }
+
+    // 旧的数据行可能比当前表结构短（写入后表又增加了列），缺少的列补上默认值，没有默认值的补 NULL
+    // 这里不检查 NOT NULL，已经写入的数据不应该因为表结构变化而读不出来
+    // 保证读出的每一行都和表结构等宽，执行器按下标取值时不会越界
+    // 主键列总是存在于写入时的行中，连主键都缺少的行已经损坏，报错而不是补上 NULL 主键
+    // char 列存储时补齐的空格在这里去掉
+    pub fn pad_row(&self, mut row: Row) -> Result<Row> {
+        self.get_primary_key(&row)?;
+        row.reserve_exact(self.columns.len().saturating_sub(row.len()));
+        for column in self.columns.iter().skip(row.len()) {
+            row.push(column.default.clone().unwrap_or(Value::Null));
+        }
+        for (column, value) in self.columns.iter().zip(row.iter_mut()) {
+            if let (DataType::Char(_), Value::String(s)) = (&column.datatype, &mut *value) {
+                s.truncate(s.trim_end_matches(' ').len());
+            }
+        }
+        Ok(row)
+    }
+
+    // 一行数据写入存储时的编码，char 列的值在末尾补齐空格到定义的长度
+    pub fn encode_row(&self, row: &Row) -> Result<Vec<u8>> {
+        if !self
+            .columns
+            .iter()
+            .any(|c| matches!(c.datatype, DataType::Char(_)))
+        {
+            return Ok(bincode::serialize(row)?);
+        }
+        let row = self
+            .columns
+            .iter()
+            .zip(row.iter())
+            .map(|(column, value)| match (&column.datatype, value) {
+                (DataType::Char(n), Value::String(s)) => {
+                    let padding = (*n as usize).saturating_sub(s.chars().count());
+                    Value::String(format!("{}{}", s, " ".repeat(padding)))
+                }
+                _ => value.clone(),
+            })
+            .collect::<Row>();
+        Ok(bincode::serialize(&row)?)
+    }
+
+    // 按表中列的顺序构造一行要写入的数据，None 表示没有给出值的列，使用列的默认值
+    // 数值转为列类型的规范形式，decimal 列按精度规整，再检查是否为空和类型
+    // 一行中所有列的问题都检查完之后一起报告，而不是遇到第一个就返回
+    pub fn build_row(&self, values: Vec<Option<Value>>) -> Result<Row> {
+        let mut row = Vec::with_capacity(self.columns.len());
+        let mut errors = self
+            .check_width(values.len())
+            .err()
+            .into_iter()
+            .collect::<Vec<_>>();
+        for (column, value) in self.columns.iter().zip(values) {
+            let value = match value {
+                Some(value) => Ok(value),
+                None => column.default_value(),
+            }
+            .and_then(|value| column.apply_precision(value.canonical_for(&column.datatype)))
+            .and_then(|value| column.check_value(&self.name, &value).map(|_| value));
+            match value {
+                Ok(value) => row.push(value),
+                Err(e) => {
+                    errors.push(e);
+                    row.push(Value::Null);
+                }
+            }
+        }
+        check_row_errors(errors)?;
+        Ok(row)
+    }
+
+    // 检查已经构造好的一行数据：不可为空的列不能为 NULL，值的类型和列的类型一致
+    pub fn check_row(&self, row: &Row) -> Result<()> {
+        check_row_errors(
+            self.check_width(row.len())
+                .err()
+                .into_iter()
+                .chain(
+                    self.columns
+                        .iter()
+                        .zip(row.iter())
+                        .filter_map(|(column, value)| column.check_value(&self.name, value).err()),
+                )
+                .collect(),
+        )
+    }
+
+    // 一行的值的个数必须和表的列数一致，多出的值不能被悄悄丢弃
+    pub fn check_width(&self, values: usize) -> Result<()> {
+        if values != self.columns.len() {
+            return Err(Error::Schema(format!(
+                "[Insert Table] Row has {} values but table \" {} \" has {} columns",
+                values,
+                quote_identifier(&self.name),
+                self.columns.len()
+            )));
+        }
+        Ok(())
+    }
+
+    // 按各列的比较规则归一化一行数据
+    pub fn collate_row(&self, row: &Row) -> Row {
+        self.columns
+            .iter()
+            .zip(row.iter())
+            .map(|(c, v)| c.collation.normalize(v))
+            .collect()
     }
 
     // 获取列索引
@@ -71,13 +235,38 @@ impl Table {
         self.columns
             .iter()
             .position(|c| c.name == col_name)
-            .ok_or(Error::Internal(format!(
+            .ok_or(Error::NotFound(format!(
                 "[Get Column Index Failed] Column {} not found",
-                col_name
+                quote_identifier(col_name)
             )))
     }
 }
 
+impl Table {
+    // 生成可以重新执行的建表语句，用于 show create table 导出表结构
+    pub fn to_create_sql(&self) -> String {
+        let column_description = self
+            .columns
+            .iter()
+            .map(|c| format!("  {}", c.to_create_sql()))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        match &self.comment {
+            Some(comment) => format!(
+                "CREATE TABLE {} (\n{}\n) COMMENT {};",
+                quote_identifier(&self.name),
+                column_description,
+                quote_string(comment)
+            ),
+            None => format!(
+                "CREATE TABLE {} (\n{}\n);",
+                quote_identifier(&self.name),
+                column_description
+            ),
+        }
+    }
+}
+
 impl Display for Table {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let column_description = self
@@ -86,11 +275,20 @@ impl Display for Table {
             .map(|c| format!("{}", c))
             .collect::<Vec<_>>()
             .join(",\n");
-        write!(f, "TABLE NAME: {} (\n{}\n)", self.name, column_description)
+        write!(
+            f,
+            "TABLE NAME: {} (\n{}\n)",
+            quote_identifier(&self.name),
+            column_description
+        )?;
+        if let Some(comment) = &self.comment {
+            write!(f, "\nCOMMENT: {}", comment)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
@@ -98,20 +296,507 @@ pub struct Column {
     pub default: Option<Value>,
     pub is_primary_key: bool,
     pub is_index: bool,
+    pub collation: Collation,
+    pub precision: Option<(u32, u32)>, // decimal(precision, scale)，以浮点数存储
+}
+
+impl Column {
+    // 由建表语句中的列定义推导出列的完整定义，create table 和 TableBuilder 共用
+    pub fn from_definition(c: ast::Column) -> Result<Self> {
+        let nullable = c.nullable.unwrap_or(!c.is_primary_key); // 如果是主键，则!c.is_primary_key == false，不能为空
+                                                                // 没写 default 时为 None，和显式的 default null 区分开
+                                                                // 可为空的列没有默认值时插入 NULL，不可为空的列没有默认值时必须给出值
+        let default = c
+            .default
+            .map(Value::from_expression_to_value)
+            .transpose()
+            .map_err(|e| {
+                e.map_message(|msg| {
+                    format!(
+                        "[CreateTable] Failed, default of column \" {} \": {}",
+                        quote_identifier(&c.name),
+                        msg
+                    )
+                })
+            })?;
+
+        let mut column = Column {
+            name: c.name,
+            datatype: c.datatype,
+            nullable,
+            default,
+            is_primary_key: c.is_primary_key,
+            is_index: c.is_index && !c.is_primary_key, // 主键不能建索引
+            collation: c.collation,
+            precision: c.precision,
+        };
+        // decimal 列的默认值同样按精度规整
+        if let Some(default) = column.default.take() {
+            column.default = Some(column.apply_precision(default)?);
+        }
+        Ok(column)
+    }
+
+    // 本列的值在索引中的key：数值统一为列类型的规范形式，nocase 列按小写存储
+    pub fn index_key(&self, value: &Value) -> Value {
+        self.collation
+            .normalize(&value.canonical_for(&self.datatype))
+    }
+
+    // 按值查找索引时需要查找的key，规范形式在前
+    // 历史数据中的数值可能以整数或浮点数的另一种形式存储在索引中，这里一并列出
+    pub fn index_lookup_keys(&self, value: &Value) -> Vec<Value> {
+        let mut keys = vec![self.index_key(value)];
+        for datatype in [DataType::Integer, DataType::Float] {
+            let key = self.collation.normalize(&value.canonical_for(&datatype));
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    // 没有给出值的列使用的值：有默认值时使用默认值，没有默认值的可为空列使用 NULL
+    // 不可为空且没有默认值的列必须给出值，返回None
+    // 建表时不会把可为空列的默认值记为 NULL，这里是省略的列取 NULL 的唯一来源
+    pub fn omitted_value(&self) -> Option<Value> {
+        match &self.default {
+            Some(default) => Some(default.clone()),
+            None if self.nullable => Some(Value::Null),
+            None => None,
+        }
+    }
+
+    // insert 时没有给出值的列使用的值，不可为空且没有默认值的列报错
+    pub fn default_value(&self) -> Result<Value> {
+        self.omitted_value().ok_or_else(|| {
+            Error::Schema(format!(
+                "[Insert Table] Column \" {} \" has no default value",
+                quote_identifier(&self.name)
+            ))
+        })
+    }
+
+    // 检查写入的值是否满足列的定义：不可为空的列不能为 NULL，值的类型和列的类型一致
+    // 写入 NULL 的错误中给出表名和列的定义，多列同时出错时能分清是哪一列
+    pub fn check_value(&self, table: &str, value: &Value) -> Result<()> {
+        match value.get_datatype() {
+            None if !self.nullable => Err(Error::Schema(format!(
+                "[Insert Table] Column \" {} \" {} {} in table \" {} \" cannot be null",
+                quote_identifier(&self.name),
+                self.sql_type(),
+                if self.is_primary_key {
+                    "PRIMARY KEY"
+                } else {
+                    "NOT NULL"
+                },
+                quote_identifier(table)
+            ))),
+            Some(datatype) if datatype != self.datatype.value_type() => Err(Error::Type(format!(
+                "[Insert Table] Column \" {} \" mismatched data type",
+                quote_identifier(&self.name)
+            ))),
+            _ if self.exceeds_length(value) => Err(Error::Type(format!(
+                "[Insert Table] Value {} is too long for column \" {} \" {:?}",
+                value,
+                quote_identifier(&self.name),
+                self.datatype
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // char(n) 列的值超过 n 个字符，末尾的空格不计入长度
+    fn exceeds_length(&self, value: &Value) -> bool {
+        match (&self.datatype, value) {
+            (DataType::Char(n), Value::String(s)) => {
+                s.trim_end_matches(' ').chars().count() > *n as usize
+            }
+            _ => false,
+        }
+    }
+
+    // 按 decimal(precision, scale) 规整写入的值：小数部分四舍五入到 scale 位，整数部分超出 precision - scale 位则报错
+    // 整数写入 decimal 列时转为浮点数
+    pub fn apply_precision(&self, value: Value) -> Result<Value> {
+        let (precision, scale) = match self.precision {
+            Some(p) => p,
+            None => return Ok(value),
+        };
+        let v = match value {
+            Value::Integer(v) => v as f64,
+            Value::Float(v) => v,
+            value => return Ok(value), // null和类型不匹配的值交给类型检查处理
+        };
+        let rounded = round_half_up(v, scale as usize);
+        if rounded.abs() >= 10f64.powi((precision - scale) as i32) {
+            return Err(Error::Type(format!(
+                "[Decimal] Value {} is out of range for column \" {} \" DECIMAL({}, {})",
+                Value::Float(v),
+                self.name,
+                precision,
+                scale
+            )));
+        }
+        Ok(Value::Float(rounded))
+    }
+
+    // 列类型在 SQL 中的写法
+    fn sql_type(&self) -> String {
+        match (&self.datatype, self.precision) {
+            (_, Some((precision, scale))) => format!("DECIMAL({}, {})", precision, scale),
+            (DataType::Boolean, _) => "BOOLEAN".into(),
+            (DataType::Integer, _) => "INTEGER".into(),
+            (DataType::Float, _) => "FLOAT".into(),
+            (DataType::String, _) => "STRING".into(),
+            (DataType::Char(n), _) => format!("CHAR({})", n),
+        }
+    }
+
+    // 建表语句中的列定义
+    fn to_create_sql(&self) -> String {
+        let mut column_description =
+            format!("{} {}", quote_identifier(&self.name), self.sql_type());
+        if self.is_primary_key {
+            column_description += " PRIMARY KEY";
+        } else if self.nullable {
+            column_description += " NULL";
+        } else {
+            column_description += " NOT NULL";
+        }
+        if self.collation == Collation::NoCase {
+            column_description += " COLLATE NOCASE";
+        }
+        // 显式的 default null 原样输出，没写 default 时不输出
+        match &self.default {
+            Some(Value::String(s)) => {
+                column_description += &format!(" DEFAULT {}", quote_string(s))
+            }
+            // 浮点数需要带小数点，否则会被解析为整数
+            Some(Value::Float(f)) if f.fract() == 0.0 => {
+                column_description += &format!(" DEFAULT {:.1}", f)
+            }
+            // 按能还原出原值的最短形式输出，不经过查询结果中按固定小数位数舍入的格式
+            Some(Value::Float(f)) => column_description += &format!(" DEFAULT {}", f),
+            Some(v) => column_description += &format!(" DEFAULT {}", v),
+            None => {}
+        }
+        if self.is_index {
+            column_description += " INDEX";
+        }
+        column_description
+    }
+}
+
+// 按十进制的写法四舍五入到 scale 位小数，绝对值相同的正负数舍入结果对称
+// 直接计算 (v * 10^scale).round() 会受二进制误差影响，如 1.005 实际存储为 1.00499999...，会被舍入为 1.0
+// 这里先把浮点数格式化为能精确还原它的最短十进制字符串，再对十进制的各位数字进位
+fn round_half_up(v: f64, scale: usize) -> f64 {
+    let text = v.abs().to_string();
+    let Some((int_part, frac_part)) = text.split_once('.') else {
+        return v; // 整数、inf、NaN
+    };
+    if frac_part.len() <= scale {
+        return v;
+    }
+    let mut digits = format!("{}{}", int_part, &frac_part[..scale]).into_bytes();
+    if frac_part.as_bytes()[scale] >= b'5' {
+        // 从最低位开始进位，最高位进位时在前面补 1
+        let mut i = digits.len();
+        loop {
+            if i == 0 {
+                digits.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if digits[i] == b'9' {
+                digits[i] = b'0';
+            } else {
+                digits[i] += 1;
+                break;
+            }
+        }
+    }
+    let digits = String::from_utf8(digits).unwrap();
+    let (int_digits, frac_digits) = digits.split_at(digits.len() - scale);
+    let rounded: f64 = format!("{}.{}", int_digits, frac_digits)
+        .trim_end_matches('.')
+        .parse()
+        .unwrap();
+    rounded.copysign(v)
 }
 
 impl Display for Column {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut column_description = format!("  {} {:?} ", self.name, self.datatype);
+        let mut column_description = match self.precision {
+            Some((precision, scale)) => {
+                format!(
+                    "  {} Decimal({}, {}) ",
+                    quote_identifier(&self.name),
+                    precision,
+                    scale
+                )
+            }
+            None => format!("  {} {:?} ", quote_identifier(&self.name), self.datatype),
+        };
         if self.is_primary_key {
             column_description += "PRIMARY KEY ";
         }
         if !self.nullable && !self.is_primary_key {
             column_description += "NOT NULL ";
         }
+        if self.collation == Collation::NoCase {
+            column_description += "COLLATE NOCASE ";
+        }
         if let Some(v) = &self.default {
-            column_description += &format!("DEFAULT {}", v.to_string());
+            column_description += &format!("DEFAULT {} ", v);
+        }
+        if self.is_index {
+            column_description += "INDEX ";
         }
         write!(f, "{}", column_description)
     }
 }
+
+/// 以代码的方式定义表结构，嵌入使用时不必手动填写 [`Table`] 和 [`Column`] 的每个字段
+///
+/// 列的推导规则和 create table 语句完全一致：主键不可为空，其余列默认可为空，
+/// 主键不建索引，`build` 时按建表的规则校验表结构
+///
+/// ```
+/// use my_sql_db::sql::schema::{ColumnBuilder, TableBuilder};
+/// use my_sql_db::sql::types::Value;
+///
+/// let table = TableBuilder::new("users")
+///     .column(ColumnBuilder::int("id").primary_key())
+///     .column(ColumnBuilder::text("name").not_null().default("anon"))
+///     .column(ColumnBuilder::float("score").index())
+///     .build()?;
+///
+/// assert!(!table.columns[0].nullable);
+/// assert_eq!(table.columns[1].default, Some(Value::String("anon".into())));
+/// assert!(table.columns[2].nullable && table.columns[2].is_index);
+/// # Ok::<(), my_sql_db::error::Error>(())
+/// ```
+pub struct TableBuilder {
+    name: String,
+    columns: Vec<ColumnBuilder>,
+    comment: Option<String>,
+}
+
+impl TableBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            columns: Vec::new(),
+            comment: None,
+        }
+    }
+
+    pub fn column(mut self, column: ColumnBuilder) -> Self {
+        self.columns.push(column);
+        self
+    }
+
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Table> {
+        let table = Table {
+            name: self.name,
+            columns: self
+                .columns
+                .into_iter()
+                .map(|c| Column::from_definition(c.0))
+                .collect::<Result<_>>()?,
+            comment: self.comment,
+        };
+        table.is_valid()?;
+        Ok(table)
+    }
+}
+
+/// 列定义，和 create table 语句中的一列对应，配合 [`TableBuilder`] 使用
+pub struct ColumnBuilder(ast::Column);
+
+impl ColumnBuilder {
+    pub fn new(name: impl Into<String>, datatype: DataType) -> Self {
+        Self(ast::Column {
+            name: name.into(),
+            datatype,
+            nullable: None,
+            default: None,
+            is_primary_key: false,
+            is_index: false,
+            collation: Collation::Binary,
+            precision: None,
+        })
+    }
+
+    pub fn int(name: impl Into<String>) -> Self {
+        Self::new(name, DataType::Integer)
+    }
+
+    pub fn float(name: impl Into<String>) -> Self {
+        Self::new(name, DataType::Float)
+    }
+
+    pub fn boolean(name: impl Into<String>) -> Self {
+        Self::new(name, DataType::Boolean)
+    }
+
+    pub fn text(name: impl Into<String>) -> Self {
+        Self::new(name, DataType::String)
+    }
+
+    /// char(length)，定长字符串，存储时在末尾补齐空格，读出时去掉末尾的空格，length 在 1 到 255 之间
+    pub fn char(name: impl Into<String>, length: u32) -> Self {
+        Self::new(name, DataType::Char(length))
+    }
+
+    /// decimal(precision, scale)，以浮点数存储，precision 在 1 到 15 之间，scale 不超过 precision
+    pub fn decimal(name: impl Into<String>, precision: u32, scale: u32) -> Self {
+        let mut column = Self::new(name, DataType::Float);
+        column.0.precision = Some((precision, scale));
+        column
+    }
+
+    pub fn primary_key(mut self) -> Self {
+        self.0.is_primary_key = true;
+        self
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.0.nullable = Some(false);
+        self
+    }
+
+    pub fn nullable(mut self) -> Self {
+        self.0.nullable = Some(true);
+        self
+    }
+
+    /// 默认值，`None::<T>` 即 default null
+    pub fn default(mut self, value: impl Into<Value>) -> Self {
+        self.0.default = Some(value.into().into());
+        self
+    }
+
+    pub fn index(mut self) -> Self {
+        self.0.is_index = true;
+        self
+    }
+
+    pub fn collate(mut self, collation: Collation) -> Self {
+        self.0.collation = collation;
+        self
+    }
+}
+
+/// 按列名构造一行数据，没有给出的列使用列的默认值，值的类型和是否为空按列的定义检查
+///
+/// ```
+/// use my_sql_db::sql::schema::{ColumnBuilder, RowBuilder, TableBuilder};
+/// use my_sql_db::row;
+///
+/// let table = TableBuilder::new("users")
+///     .column(ColumnBuilder::int("id").primary_key())
+///     .column(ColumnBuilder::text("name").not_null().default("anon"))
+///     .column(ColumnBuilder::float("score"))
+///     .build()?;
+///
+/// let row = RowBuilder::new(&table).set("id", 1).set("score", 2).build()?;
+/// assert_eq!(row, row![1, "anon", 2.0]);
+///
+/// assert!(RowBuilder::new(&table).set("id", "x").build().is_err());
+/// # Ok::<(), my_sql_db::error::Error>(())
+/// ```
+pub struct RowBuilder<'a> {
+    table: &'a Table,
+    values: Vec<(String, Value)>,
+}
+
+impl<'a> RowBuilder<'a> {
+    pub fn new(table: &'a Table) -> Self {
+        Self {
+            table,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn set(mut self, column: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.values.push((column.into(), value.into()));
+        self
+    }
+
+    pub fn build(mut self) -> Result<Row> {
+        if let Some((name, _)) = self
+            .values
+            .iter()
+            .find(|(name, _)| !self.table.columns.iter().any(|c| &c.name == name))
+        {
+            return Err(Error::NotFound(format!(
+                "[Insert Table] Column \" {} \" does not exist in table \" {} \"",
+                quote_identifier(name),
+                quote_identifier(&self.table.name)
+            )));
+        }
+        // 同一列设置了多次时以最后一次为准
+        let values = self
+            .table
+            .columns
+            .iter()
+            .map(|column| {
+                self.values
+                    .iter()
+                    .rposition(|(name, _)| name == &column.name)
+                    .map(|pos| self.values.remove(pos).1)
+            })
+            .collect();
+        self.table.build_row(values)
+    }
+}
+
+// 一行数据的错误中最多列出的问题个数
+const MAX_ROW_ERRORS: usize = 10;
+
+// 把一行数据检查出的所有问题合并成一个错误，没有问题时返回 Ok
+// 只有一个问题时原样返回；有多个问题时错误类别取第一个问题的，信息中依次列出前 MAX_ROW_ERRORS 个问题
+fn check_row_errors(errors: Vec<Error>) -> Result<()> {
+    if errors.len() <= 1 {
+        return errors.into_iter().next().map_or(Ok(()), Err);
+    }
+    let mut problems = errors
+        .iter()
+        .take(MAX_ROW_ERRORS)
+        .map(error_detail)
+        .collect::<Vec<_>>();
+    if errors.len() > MAX_ROW_ERRORS {
+        problems.push(format!("and {} more", errors.len() - MAX_ROW_ERRORS));
+    }
+    let message = format!(
+        "[Insert Table] {} problems: {}",
+        errors.len(),
+        problems.join("; ")
+    );
+    Err(errors.into_iter().next().unwrap().map_message(|_| message))
+}
+
+/// 多行写入时在错误信息中标明出错的是第几行（从 1 开始），如 `Row 3 of 7: ...`
+pub fn error_at_row(err: Error, row: usize, rows: usize) -> Error {
+    let detail = error_detail(&err);
+    err.map_message(|_| format!("[Insert Table] Row {} of {}: {}", row, rows, detail))
+}
+
+// 去掉错误信息开头 [Insert Table] 这样的模块标记，便于拼接到另一条信息中
+fn error_detail(err: &Error) -> String {
+    match err.message() {
+        Some(msg) => match msg.strip_prefix('[').and_then(|m| m.split_once("] ")) {
+            Some((_, detail)) => detail.to_string(),
+            None => msg.to_string(),
+        },
+        None => err.to_string(),
+    }
+}
@@ -1,15 +1,56 @@
+use crate::error::{Error, Result};
 use crate::sql::parser::ast::{Consts, Expression};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     Boolean,
     Integer,
     Float,
     String,
+    Char(u32), // 定长字符串 char(n)，存储时在末尾补齐空格到 n 个字符，读出时去掉末尾的空格
+}
+
+impl DataType {
+    // 这种类型的列中值的类型，char(n) 列中的值是字符串
+    pub fn value_type(&self) -> DataType {
+        match self {
+            DataType::Char(_) => DataType::String,
+            datatype => datatype.clone(),
+        }
+    }
+}
+
+// 字符串的比较规则，create table 时用 collate nocase 指定
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Collation {
+    #[default]
+    Binary, // 区分大小写
+    NoCase, // 不区分大小写
+}
+
+impl Collation {
+    // 按比较规则归一化：nocase 的字符串统一转为小写，其余值不变
+    pub fn normalize(&self, value: &Value) -> Value {
+        match (self, value) {
+            (Collation::NoCase, Value::String(s)) => Value::String(s.to_lowercase()),
+            _ => value.clone(),
+        }
+    }
+
+    // 按比较规则比较两个值，nocase 的字符串忽略大小写比较之后再按原值比较，保证排序结果确定
+    pub fn compare(&self, l: &Value, r: &Value) -> Option<Ordering> {
+        match self {
+            Collation::NoCase => match self.normalize(l).partial_cmp(&self.normalize(r)) {
+                Some(Ordering::Equal) => l.partial_cmp(r),
+                o => o,
+            },
+            Collation::Binary => l.partial_cmp(r),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -22,15 +63,60 @@ pub enum Value {
 }
 
 impl Value {
-    pub fn from_expression_to_value(expression: Expression) -> Self {
+    // insert 的 values、update 的 set 和列的默认值只支持常量，其他表达式（列名、函数等）报错
+    // 错误信息不带模块标记，由调用方按所在的语句加上
+    pub fn from_expression_to_value(expression: Expression) -> Result<Self> {
         match expression {
-            Expression::Consts(Consts::Null) => Self::Null,
-            Expression::Consts(Consts::Boolean(bool)) => Self::Boolean(bool),
-            Expression::Consts(Consts::Integer(int)) => Self::Integer(int),
-            Expression::Consts(Consts::Float(float)) => Self::Float(float),
-            Expression::Consts(Consts::String(string)) => Self::String(string),
-            _ => unreachable!(),
+            Expression::Consts(consts) => Ok(Self::from_consts(consts)),
+            expression => Err(Error::Parse(format!(
+                "Only constant values are allowed, got {}",
+                expression
+            ))),
+        }
+    }
+
+    pub fn from_consts(consts: Consts) -> Self {
+        match consts {
+            Consts::Null => Self::Null,
+            Consts::Boolean(bool) => Self::Boolean(bool),
+            Consts::Integer(int) => Self::Integer(int),
+            Consts::Float(float) => Self::Float(float),
+            Consts::String(string) => Self::String(string),
+        }
+    }
+
+    // 数值在某种类型的列中的规范形式：浮点数列中的整数转为浮点数，整数列中没有小数部分的浮点数转为整数
+    // 2 和 2.0 比较时相等，分组、索引等按值查找的地方需要统一成同一种形式
+    // char 列中的字符串去掉末尾的空格，和读出时的值一致
+    pub fn canonical_for(&self, datatype: &DataType) -> Value {
+        match (self, datatype) {
+            (Value::String(s), DataType::Char(_)) => Value::String(s.trim_end_matches(' ').into()),
+            (Value::Integer(v), DataType::Float) => Value::Float(*v as f64),
+            (Value::Float(v), DataType::Integer)
+                if v.fract() == 0.0 && *v >= i64::MIN as f64 && *v < i64::MAX as f64 =>
+            {
+                Value::Integer(*v as i64)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // 排序用的全序比较：可以比较的值按 partial_cmp 的结果，
+    // 不可比较的值按类型排序（NULL、布尔、数字、字符串），同为数字时 NaN 排在其他数字之后
+    pub fn sort_cmp(&self, other: &Value) -> Ordering {
+        if let Some(o) = self.partial_cmp(other) {
+            return o;
         }
+        let rank = |v: &Value| match v {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) => 2,
+            Value::String(_) => 3,
+        };
+        let is_nan = |v: &Value| matches!(v, Value::Float(f) if f.is_nan());
+        rank(self)
+            .cmp(&rank(other))
+            .then(is_nan(self).cmp(&is_nan(other)))
     }
 
     pub fn get_datatype(&self) -> Option<DataType> {
@@ -51,12 +137,48 @@ impl Display for Value {
             Value::Boolean(b) if *b => write!(f, "{}", "TRUE"),
             Value::Boolean(_) => write!(f, "{}", "FALSE"),
             Value::Integer(v) => write!(f, "{}", v),
-            Value::Float(v) => write!(f, "{}", v),
+            Value::Float(v) => write!(f, "{}", format_float(*v)),
             Value::String(v) => write!(f, "{}", v),
         }
     }
 }
 
+// 浮点数输出时保留的最大小数位数
+const FLOAT_PRECISION: usize = 6;
+
+// 小于这个绝对值的非零浮点数改用科学计数法输出，避免按固定小数位数舍入后丢掉全部有效数字
+const FLOAT_SCIENTIFIC_BELOW: f64 = 1e-4;
+
+// 浮点数统一格式：最多保留 FLOAT_PRECISION 位小数，去掉末尾多余的0，但至少保留一位小数
+// 如 13.0 -> 13.0, 13.0/3.0 -> 4.333333, 0.1+0.2 -> 0.3
+// 绝对值很小的非零值用科学计数法，保留 FLOAT_PRECISION 位有效数字，如 1e-7 -> 1.0e-7
+fn format_float(v: f64) -> String {
+    if !v.is_finite() {
+        return v.to_string(); // NaN, inf, -inf
+    }
+    if v != 0.0 && v.abs() < FLOAT_SCIENTIFIC_BELOW {
+        let s = format!("{:.*e}", FLOAT_PRECISION - 1, v);
+        let (mantissa, exponent) = s.split_once('e').unwrap_or((&s, "0"));
+        return format!("{}e{}", trim_fraction(mantissa), exponent);
+    }
+    let s = trim_fraction(&format!("{:.*}", FLOAT_PRECISION, v));
+    if s == "-0.0" {
+        "0.0".to_string() // 避免舍入后出现 -0.0
+    } else {
+        s
+    }
+}
+
+// 去掉小数部分末尾多余的0，但至少保留一位小数
+fn trim_fraction(s: &str) -> String {
+    let s = s.trim_end_matches('0');
+    if s.ends_with('.') {
+        format!("{}0", s)
+    } else {
+        s.to_string()
+    }
+}
+
 impl PartialOrd for Value {
     // 参数：self-当前值；other-需要比较的值
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -106,4 +228,175 @@ impl Hash for Value {
 
 impl Eq for Value {}
 
+// 以代码的方式构造数据时，rust 的基础类型可以直接转为 Value
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Boolean(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Integer(v as i64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Integer(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+// None 即为 NULL
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+// Value -> Expression，和 from_consts 相反
+impl From<Value> for Expression {
+    fn from(v: Value) -> Self {
+        Expression::Consts(match v {
+            Value::Null => Consts::Null,
+            Value::Boolean(b) => Consts::Boolean(b),
+            Value::Integer(i) => Consts::Integer(i),
+            Value::Float(f) => Consts::Float(f),
+            Value::String(s) => Consts::String(s),
+        })
+    }
+}
+
 pub type Row = Vec<Value>;
+
+// 计算行的哈希值，结果只取决于行中的值，不随进程、平台变化，可以用来比较不同机器上的数据
+// 标准库的 DefaultHasher 不保证不同版本之间的结果一致，这里使用固定的 FNV-1a 算法
+pub fn hash_row(row: &Row) -> u64 {
+    let mut hasher = StableHasher::new();
+    row.hash(&mut hasher);
+    hasher.finish()
+}
+
+// 按顺序合并多个哈希值，例如把表中每一行的哈希合并为整张表的校验和
+pub fn combine_hashes(hashes: impl IntoIterator<Item = u64>) -> u64 {
+    let mut hasher = StableHasher::new();
+    for hash in hashes {
+        hasher.write_u64(hash);
+    }
+    hasher.finish()
+}
+
+// FNV-1a 哈希，整数统一按小端序写入，避免结果和平台的字节序、usize 的宽度有关
+struct StableHasher(u64);
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// 用 rust 的基础类型构造一行数据，`None` 为 NULL
+///
+/// ```
+/// use my_sql_db::row;
+/// use my_sql_db::sql::types::Value;
+///
+/// let row = row![1, "alice", 3.5, true, None::<i64>];
+/// assert_eq!(
+///     row,
+///     vec![
+///         Value::Integer(1),
+///         Value::String("alice".into()),
+///         Value::Float(3.5),
+///         Value::Boolean(true),
+///         Value::Null,
+///     ]
+/// );
+/// ```
+#[macro_export]
+macro_rules! row {
+    ($($value:expr),* $(,)?) => {
+        vec![$($crate::sql::types::Value::from($value)),*] as $crate::sql::types::Row
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Value;
+
+    #[test]
+    fn test_float_display() {
+        assert_eq!(Value::Float(13.0).to_string(), "13.0");
+        assert_eq!(Value::Float(-2.0).to_string(), "-2.0");
+        assert_eq!(Value::Float(0.0).to_string(), "0.0");
+        assert_eq!(Value::Float(1.1).to_string(), "1.1");
+        assert_eq!(Value::Float(13.0 / 3.0).to_string(), "4.333333");
+        assert_eq!(Value::Float(2.0 / 3.0).to_string(), "0.666667");
+        assert_eq!(Value::Float(0.1 + 0.2).to_string(), "0.3");
+        assert_eq!(Value::Float(-0.0).to_string(), "0.0");
+        // 很小的非零值用科学计数法，不会显示为 0
+        assert_eq!(Value::Float(1e-7).to_string(), "1.0e-7");
+        assert_eq!(Value::Float(-0.0000001).to_string(), "-1.0e-7");
+        assert_eq!(Value::Float(1.5e-5).to_string(), "1.5e-5");
+        assert_eq!(Value::Float(1.0 / 3e6).to_string(), "3.33333e-7");
+        assert_eq!(Value::Float(0.0001).to_string(), "0.0001");
+        assert_eq!(Value::Float(1e10).to_string(), "10000000000.0");
+        assert_eq!(Value::Float(f64::NAN).to_string(), "NaN");
+        assert_eq!(Value::Float(f64::INFINITY).to_string(), "inf");
+        assert_eq!(Value::Integer(13).to_string(), "13");
+    }
+}
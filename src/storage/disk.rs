@@ -1,6 +1,7 @@
 use crate::error::Result;
 use crate::storage::engine::{Engine, EngineIter};
 use fs4::FileExt;
+use log::{debug, error};
 use std::collections::{btree_map, BTreeMap};
 use std::fs::{rename, File, OpenOptions};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
@@ -48,14 +49,61 @@ impl Log {
         Ok((start, total_len))
     }
 
-    fn read_value(&mut self, offset: u64, value_len: u32) -> Result<Vec<u8>> {
-        // 读取value的数据
-        self.file.seek(SeekFrom::Start(offset))?;
+    // 批量写入删除记录：只定位一次文件末尾，所有记录放在同一个缓冲区中一次写入
+    fn write_deletes(&mut self, keys: &[Vec<u8>]) -> Result<()> {
+        let total_len = keys
+            .iter()
+            .map(|key| LOG_HEADER_SIZE as usize + key.len())
+            .sum::<usize>();
+        self.file.seek(SeekFrom::End(0))?;
+        let mut writer = BufWriter::with_capacity(total_len, &self.file);
+        for key in keys {
+            writer.write_all(&(key.len() as u32).to_be_bytes())?;
+            writer.write_all(&(-1i32).to_be_bytes())?;
+            writer.write_all(key)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // 按位置读取value，不移动文件的读写位置，多个线程可以同时通过共享引用读取
+    fn read_value(&self, offset: u64, value_len: u32) -> Result<Vec<u8>> {
         let mut buffer = vec![0; value_len as usize]; // 大小为 value_len，其中每个元素初始化为 0
-        self.file.read_exact(&mut buffer)?; // 和write_all() 一样，read_exact()保证必须将内容全部读完，否则会报错
+        self.read_exact_at(&mut buffer, offset)?; // 和write_all() 一样，保证必须将内容全部读完，否则会报错
         Ok(buffer) // buffer是大小为value长度的01字符流
     }
 
+    #[cfg(unix)]
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(&self.file, buffer, offset)?;
+        Ok(())
+    }
+
+    // windows 上的 seek_read 会移动文件的读写位置，但写日志之前总会先定位到文件末尾，不受影响
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buffer: &mut [u8], mut offset: u64) -> Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buffer.is_empty() {
+            match self.file.seek_read(buffer, offset)? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                n => {
+                    buffer = &mut buffer[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 其他平台没有按位置读取的接口，每次读取单独打开一个只读的文件句柄，不和写日志共用读写位置
+    #[cfg(not(any(unix, windows)))]
+    fn read_exact_at(&self, buffer: &mut [u8], offset: u64) -> Result<()> {
+        let mut file = File::open(&self.file_path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buffer)?;
+        Ok(())
+    }
+
     // 实现启动方法
     fn new(file_path: PathBuf) -> Result<Self> {
         // 如果传入的路径不存在，则需要自动创建
@@ -90,7 +138,13 @@ impl Log {
                 break; // 读完跳出循环
             }
 
-            let (key, val_len) = Self::read_log(&mut reader, offset)?;
+            let (key, val_len) = Self::read_log(&mut reader, offset).map_err(|e| {
+                error!(
+                    "[DiskEngine] Failed to recover log {:?} at offset {}: {}",
+                    self.file_path, offset, e
+                );
+                e
+            })?;
             let key_len = key.len() as u32;
             if val_len == -1 {
                 key_dir.remove(&key);
@@ -141,7 +195,7 @@ impl Engine for DiskEngine {
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         match self.key_dir.get(&key) {
             Some((offset, size)) => {
                 let value = self.log.read_value(*offset, *size)?;
@@ -157,18 +211,69 @@ impl Engine for DiskEngine {
         Ok(())
     }
 
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_> {
+    fn delete_batch(&mut self, keys: Vec<Vec<u8>>) -> Result<()> {
+        self.log.write_deletes(&keys)?;
+        for key in keys.iter() {
+            self.key_dir.remove(key);
+        }
+        Ok(())
+    }
+
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_> {
         DiskEngineIter {
             index: self.key_dir.range(range),
-            log: &mut self.log,
+            log: &self.log,
         }
     }
+
+    // 写日志时只刷新了缓冲区，数据还可能在操作系统的缓存中，这里要求操作系统写到磁盘上
+    fn sync(&mut self) -> Result<()> {
+        self.log.file.sync_all()?;
+        Ok(())
+    }
+
+    // 重写重复文件
+    fn compact(&mut self) -> Result<()> {
+        let size_before = self.log.file.metadata()?.len();
+        // 1. 在log相同目录打开一个新的临时文件
+        let mut compact_path = self.log.file_path.clone();
+        compact_path.set_extension("compact"); // 后缀名
+        let mut compact_log = Log::new(compact_path)?;
+
+        // 2. 在临时文件中重写
+        let mut compact_key_dir = KeyDir::new();
+        for (key, (offset, value_len)) in self.key_dir.iter() {
+            let value = self.log.read_value(*offset, *value_len)?;
+            let (compact_offset, compact_size) = compact_log.write_log(&key, Some(&value))?;
+            compact_key_dir.insert(
+                key.clone(),
+                (
+                    compact_offset + compact_size as u64 - *value_len as u64,
+                    *value_len,
+                ),
+            );
+        }
+
+        // 3. 将临时文件变为正式文件，删除原正式文件
+        rename(&compact_log.file_path, &self.log.file_path)?; // compact_log.file_path 变成 self.log.file_path
+        compact_log.file_path = self.log.file_path.clone();
+        self.key_dir = compact_key_dir;
+        self.log = compact_log;
+
+        debug!(
+            "[DiskEngine] Compacted log {:?}: {} bytes -> {} bytes",
+            self.log.file_path,
+            size_before,
+            self.log.file.metadata()?.len()
+        );
+        Ok(())
+    }
 }
 
 // 磁盘存储引擎的迭代器
 pub struct DiskEngineIter<'a> {
     index: btree_map::Range<'a, Vec<u8>, (u64, u32)>, // 范围迭代器, key | (offset, value-len)
-    log: &'a mut Log,                                 // 需要从文件读取数据
+    log: &'a Log,                                     // 需要从文件读取数据
 }
 
 impl<'a> DiskEngineIter<'a> {
@@ -216,36 +321,6 @@ impl DiskEngine {
         engine.compact()?;
         Ok(engine)
     }
-
-    // 重写重复文件
-    pub fn compact(&mut self) -> Result<()> {
-        // 1. 在log相同目录打开一个新的临时文件
-        let mut compact_path = self.log.file_path.clone();
-        compact_path.set_extension("compact"); // 后缀名
-        let mut compact_log = Log::new(compact_path)?;
-
-        // 2. 在临时文件中重写
-        let mut compact_key_dir = KeyDir::new();
-        for (key, (offset, value_len)) in self.key_dir.iter() {
-            let value = self.log.read_value(*offset, *value_len)?;
-            let (compact_offset, compact_size) = compact_log.write_log(&key, Some(&value))?;
-            compact_key_dir.insert(
-                key.clone(),
-                (
-                    compact_offset + compact_size as u64 - *value_len as u64,
-                    *value_len as u32,
-                ),
-            );
-        }
-
-        // 3. 将临时文件变为正式文件，删除原正式文件
-        rename(&compact_log.file_path, &self.log.file_path)?; // compact_log.file_path 变成 self.log.file_path
-        compact_log.file_path = self.log.file_path.clone();
-        self.key_dir = compact_key_dir;
-        self.log = compact_log;
-
-        Ok(())
-    }
 }
 
 #[cfg(test)]
@@ -297,7 +372,7 @@ mod tests {
         );
         drop(eng); // 结束eng的生命周期，释放排他锁
 
-        let mut eng2 = DiskEngine::new_compact(PathBuf::from("./tmp/sqldb/sqldb-log"))?;
+        let eng2 = DiskEngine::new_compact(PathBuf::from("./tmp/sqldb/sqldb-log"))?;
         let iter2 = eng2.scan(..);
         let v2 = iter2.collect::<Result<Vec<_>>>()?;
         assert_eq!(
@@ -314,4 +389,104 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_disk_engine_sync() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.delete(b"key1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        // 刷盘不改变数据，之后的文件长度就是写入的全部日志
+        eng.sync()?;
+        assert_eq!(std::fs::metadata(&p)?.len(), 8 * 3 + 4 * 3 + 6 * 2);
+        assert_eq!(eng.get(b"key2".to_vec())?, Some(b"value2".to_vec()));
+        drop(eng);
+
+        let eng = DiskEngine::new(p.clone())?;
+        assert_eq!(
+            eng.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"key2".to_vec(), b"value2".to_vec())]
+        );
+        drop(eng);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_delete_batch() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        for i in 0..100u32 {
+            eng.set(i.to_be_bytes().to_vec(), b"value".to_vec())?;
+        }
+        let size = std::fs::metadata(&p)?.len();
+
+        // 删除偶数的key，以及一个不存在的key
+        let mut keys = (0..100u32)
+            .filter(|i| i % 2 == 0)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        keys.push(b"missing".to_vec());
+        eng.delete_batch(keys)?;
+        eng.delete_batch(Vec::new())?;
+        // 每条删除记录只有头部和key
+        assert_eq!(std::fs::metadata(&p)?.len(), size + 50 * (8 + 4) + 8 + 7);
+        let remaining = |eng: &DiskEngine| -> Result<Vec<Vec<u8>>> {
+            eng.scan(..).map(|r| r.map(|(key, _)| key)).collect()
+        };
+        let expected = (0..100u32)
+            .filter(|i| i % 2 == 1)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(remaining(&eng)?, expected);
+        // 之后的写入追加在删除记录之后
+        eng.set(b"after".to_vec(), b"value".to_vec())?;
+        drop(eng);
+
+        // 重新打开之后删除仍然生效
+        let eng = DiskEngine::new(p.clone())?;
+        let mut expected = expected;
+        expected.push(b"after".to_vec());
+        assert_eq!(remaining(&eng)?, expected);
+        drop(eng);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_concurrent_reads() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        for i in 0..100u32 {
+            eng.set(i.to_be_bytes().to_vec(), format!("value{}", i).into_bytes())?;
+        }
+        // 读取只需要共享引用，多个线程可以同时读同一个引擎
+        let eng = &eng;
+        std::thread::scope(|scope| {
+            let handles = (0..4u32)
+                .map(|t| {
+                    scope.spawn(move || -> Result<()> {
+                        for round in 0..10u32 {
+                            let i = (t * 31 + round * 7) % 100;
+                            assert_eq!(
+                                eng.get(i.to_be_bytes().to_vec())?,
+                                Some(format!("value{}", i).into_bytes())
+                            );
+                            let all = eng.scan(..).collect::<Result<Vec<_>>>()?;
+                            assert_eq!(all.len(), 100);
+                            assert_eq!(all[i as usize].1, format!("value{}", i).into_bytes());
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().unwrap())
+        })?;
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
@@ -11,23 +11,31 @@ pub trait Engine {
     // 增
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()>;
 
-    // 查
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
+    // 查，读操作只需要共享引用，多个线程可以同时读取
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
 
     // 删
     fn delete(&mut self, key: Vec<u8>) -> Result<()>;
 
+    // 批量删，事务回滚等一次删除大量key时使用，磁盘存储引擎可以一次写入全部删除记录
+    fn delete_batch(&mut self, keys: Vec<Vec<u8>>) -> Result<()> {
+        for key in keys {
+            self.delete(key)?;
+        }
+        Ok(())
+    }
+
     // 扫描
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_>; // 自动推断生命周期
-                                                                                  // RangeBounds用法：
-                                                                                  // .. 无界范围
-                                                                                  // a..b [a,b)
-                                                                                  // a..=b：[a,b]
-                                                                                  // ..b： (,b)
-                                                                                  // a..   [a,]
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_>; // 自动推断生命周期
+                                                                              // RangeBounds用法：
+                                                                              // .. 无界范围
+                                                                              // a..b [a,b)
+                                                                              // a..=b：[a,b]
+                                                                              // ..b： (,b)
+                                                                              // a..   [a,]
 
     // 前缀扫描
-    fn prefix_scan(&mut self, prefix: Vec<u8>) -> Self::EngineIter<'_> {
+    fn prefix_scan(&self, prefix: Vec<u8>) -> Self::EngineIter<'_> {
         // abc,abd,abe, 均在 < abf的范围内，即[abc, ab (e+1) )
         let start = Bound::Included(prefix.clone());
         let mut bound_prefix = prefix.clone();
@@ -42,6 +50,16 @@ pub trait Engine {
         };
         self.scan((start, end))
     }
+
+    // 重写存储，回收被删除或覆盖的数据占用的空间，内存存储引擎无需处理
+    fn compact(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // 把已经写入的数据刷到磁盘上，机器宕机也不会丢失，内存存储引擎无需处理
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait EngineIter: DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>> {}
@@ -4,16 +4,35 @@ use serde::{de, ser, Serialize};
 use String;
 
 pub struct Serializer {
-    output: Vec<u8>, // 最终序列化的目标
+    output: Vec<u8>,  // 最终序列化的目标
+    bytes_end: usize, // 最近一个字节数组字段（包括 0 0 结尾）编码结束的位置
 }
 
 // 传入的实际上是 #[derive(Serialize)] pub enum MvccKey
 pub fn serialize_key<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
-    let mut ser = Serializer { output: Vec::new() };
+    let mut ser = Serializer {
+        output: Vec::new(),
+        bytes_end: 0,
+    };
     key.serialize(&mut ser)?;
     Ok(ser.output)
 }
 
+// 编码为前缀扫描使用的前缀：和 serialize_key 相同，但是最后一个字段是字节数组时不写 0 0 结尾
+// 这样 Version(b"ab") 可以匹配所有以 ab 开头的key，而不只是 ab 本身
+// 只有最后一个字段会被去掉结尾，前面字段的结尾都保留，比如表名的结尾保证 t 不会匹配到 t2 的数据
+pub fn serialize_key_prefix<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
+    let mut ser = Serializer {
+        output: Vec::new(),
+        bytes_end: 0,
+    };
+    key.serialize(&mut ser)?;
+    if ser.bytes_end > 0 && ser.bytes_end == ser.output.len() {
+        ser.output.truncate(ser.bytes_end - 2);
+    }
+    Ok(ser.output)
+}
+
 impl<'a> ser::Serializer for &'a mut Serializer {
     type Ok = ();
 
@@ -51,7 +70,9 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.output.extend(v.to_be_bytes());
+        // 翻转符号位之后按大端编码，负数排在正数之前，编码的字节顺序和数值顺序一致
+        // 和直接按大端编码的旧格式不兼容，KVEngine 打开存储时检查格式版本号，拒绝打开旧格式的存储
+        self.output.extend((v as u64 ^ (1 << 63)).to_be_bytes());
         Ok(())
     }
 
@@ -103,6 +124,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         res.extend([0, 0]); //  0 0 结尾
 
         self.output.extend(res);
+        self.bytes_end = self.output.len();
         Ok(())
     }
 
@@ -297,7 +319,7 @@ impl<'de> Deserializer<'de> {
                         Some((i, 0)) => break i + 1, // index: i  value: 0
                         Some((_, 255)) => res.push(0),
                         _ => {
-                            return Err(Error::Internal(
+                            return Err(Error::Storage(
                                 "[Deserializer] Unexpected Input".to_string(),
                             ))
                         }
@@ -305,7 +327,7 @@ impl<'de> Deserializer<'de> {
                 }
                 Some((_, val)) => res.push(*val),
                 _ => {
-                    return Err(Error::Internal(
+                    return Err(Error::Storage(
                         "[Deserializer] Unexpected Input".to_string(),
                     ))
                 }
@@ -362,7 +384,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let bytes = self.take_bytes(8);
-        let v = i64::from_be_bytes(bytes.try_into()?);
+        let v = (u64::from_be_bytes(bytes.try_into()?) ^ (1 << 63)) as i64;
         visitor.visit_i64(v)
     }
 
@@ -429,11 +451,12 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_str(&String::from_utf8(bytes)?)
     }
 
-    fn deserialize_string<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        let bytes = self.next_bytes()?;
+        visitor.visit_string(String::from_utf8(bytes)?)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -669,4 +692,91 @@ mod tests {
             vec![3, 97, 98, 99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 11],
         );
     }
+
+    #[test]
+    fn test_empty_bytes() {
+        // 空字节数组只编码为 0 0 结尾，可以正确解码，也不会和后面的字段混淆
+        let keys = vec![
+            (
+                MvccKey::Version(vec![], 1),
+                vec![3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            ),
+            (
+                MvccKey::Write(1, vec![]),
+                vec![2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0],
+            ),
+        ];
+        for (key, v) in keys {
+            assert_eq!(serialize_key(&key).unwrap(), v);
+            let res: MvccKey = deserialize_key(&v).unwrap();
+            assert_eq!(res, key);
+        }
+
+        // 排序：空 < "\0" < "a"，且空key的所有版本都排在 "a" 之前
+        let empty_max = serialize_key(&MvccKey::Version(vec![], u64::MAX)).unwrap();
+        let zero = serialize_key(&MvccKey::Version(vec![0], 0)).unwrap();
+        let a = serialize_key(&MvccKey::Version(b"a".to_vec(), 0)).unwrap();
+        assert!(empty_max < zero);
+        assert!(zero < a);
+
+        // 空的前缀只匹配空key本身
+        let prefix = serialize_key(&MvccKeyPrefix::Version(vec![])).unwrap();
+        assert!(empty_max.starts_with(&prefix));
+        assert!(!zero.starts_with(&prefix));
+    }
+
+    #[test]
+    fn test_serialize_key_prefix() -> Result<()> {
+        // 最后一个字段是字节数组时不写 0 0 结尾，其中的 0 仍然转义为 0 255
+        for (prefix, v) in [
+            (vec![], vec![3]),
+            (b"a".to_vec(), vec![3, 97]),
+            (vec![255, 255], vec![3, 255, 255]),
+            (vec![255, 0], vec![3, 255, 0, 255]),
+            (vec![0, 0], vec![3, 0, 255, 0, 255]),
+        ] {
+            assert_eq!(serialize_key_prefix(&MvccKeyPrefix::Version(prefix))?, v);
+        }
+        // 最后一个字段不是字节数组时和 serialize_key 相同
+        assert_eq!(
+            serialize_key_prefix(&MvccKeyPrefix::Write(1))?,
+            serialize_key(&MvccKeyPrefix::Write(1))?
+        );
+        assert_eq!(
+            serialize_key_prefix(&MvccKey::Version(b"ab".to_vec(), 5))?,
+            serialize_key(&MvccKey::Version(b"ab".to_vec(), 5))?
+        );
+
+        // 和完整的key对照：编码后的key以前缀开头，当且仅当原始key以原始前缀开头
+        let raw = vec![
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![0, 255],
+            b"a".to_vec(),
+            b"ab".to_vec(),
+            b"b".to_vec(),
+            vec![255],
+            vec![255, 255],
+            vec![255, 255, 0],
+            vec![255, 0, 255],
+        ];
+        for prefix in raw.iter() {
+            let encoded_prefix = serialize_key_prefix(&MvccKeyPrefix::Version(prefix.clone()))?;
+            for key in raw.iter() {
+                for version in [0, 255, u64::MAX] {
+                    let encoded_key = serialize_key(&MvccKey::Version(key.clone(), version))?;
+                    assert_eq!(
+                        encoded_key.starts_with(&encoded_prefix),
+                        key.starts_with(prefix),
+                        "prefix {:?} key {:?} version {}",
+                        prefix,
+                        key,
+                        version
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
 }
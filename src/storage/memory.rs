@@ -14,6 +14,27 @@ impl MemoryEngine {
             data: BTreeMap::new(),
         }
     }
+
+    // 将其他存储引擎（如磁盘引擎）的当前数据全部加载到内存中
+    pub fn from_engine(engine: &impl Engine) -> Result<Self> {
+        let mut data = BTreeMap::new();
+        let mut iter = engine.scan(..);
+        while let Some((key, value)) = iter.next().transpose()? {
+            data.insert(key, value);
+        }
+        Ok(Self { data })
+    }
+
+    // 将全部数据序列化，用于保存内存数据库的快照
+    pub fn snapshot(&self) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(&self.data)?)
+    }
+
+    // 从快照中恢复数据，会覆盖当前引擎中的全部数据
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<()> {
+        self.data = bincode::deserialize(bytes)?;
+        Ok(())
+    }
 }
 
 impl Engine for MemoryEngine {
@@ -24,7 +45,7 @@ impl Engine for MemoryEngine {
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         let val = self.data.get(&key).cloned();
         Ok(val)
     }
@@ -34,7 +55,7 @@ impl Engine for MemoryEngine {
         Ok(())
     }
 
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_> {
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIter<'_> {
         MemoryEngineIter {
             item: self.data.range(range),
         }
@@ -76,3 +97,63 @@ impl<'a> DoubleEndedIterator for MemoryEngineIter<'a> {
         self.item.next_back().map(|tuple| Self::map(tuple))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryEngine;
+    use crate::error::Result;
+    use crate::storage::disk::DiskEngine;
+    use crate::storage::engine::Engine;
+
+    #[test]
+    fn test_snapshot_restore() -> Result<()> {
+        let mut eng = MemoryEngine::new();
+        eng.set(b"aa".to_vec(), vec![1, 2, 3])?;
+        eng.set(b"bb".to_vec(), vec![])?;
+        eng.set(b"".to_vec(), vec![4])?;
+        eng.set(b"cc".to_vec(), vec![5, 6])?;
+        eng.delete(b"cc".to_vec())?;
+        let snapshot = eng.snapshot()?;
+
+        let mut restored = MemoryEngine::new();
+        restored.set(b"dd".to_vec(), vec![7])?; // 恢复时会被覆盖
+        restored.restore(&snapshot)?;
+
+        let expected = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        let actual = restored.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected, actual);
+        assert_eq!(actual.len(), 3);
+        assert_eq!(restored.get(b"dd".to_vec())?, None);
+
+        // 损坏的快照无法恢复
+        assert!(restored.restore(&snapshot[..snapshot.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_engine() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut disk = DiskEngine::new(p.clone())?;
+        disk.set(b"key1".to_vec(), b"value1".to_vec())?;
+        disk.set(b"key2".to_vec(), b"value2".to_vec())?;
+        disk.set(b"key2".to_vec(), b"value3".to_vec())?;
+        disk.set(b"key3".to_vec(), b"value4".to_vec())?;
+        disk.delete(b"key3".to_vec())?;
+
+        let mem = MemoryEngine::from_engine(&disk)?;
+        let expected = disk.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(mem.scan(..).collect::<Result<Vec<_>>>()?, expected);
+        assert_eq!(mem.get(b"key2".to_vec())?, Some(b"value3".to_vec()));
+        assert_eq!(mem.get(b"key3".to_vec())?, None);
+        drop(disk); // 释放文件锁
+
+        // 重启并重写日志之后再加载，数据保持一致
+        let disk = DiskEngine::new_compact(p.clone())?;
+        let mem = MemoryEngine::from_engine(&disk)?;
+        assert_eq!(disk.scan(..).collect::<Result<Vec<_>>>()?, expected);
+        assert_eq!(mem.scan(..).collect::<Result<Vec<_>>>()?, expected);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+}
@@ -1,16 +1,56 @@
 use crate::error::{Error, Result};
 use crate::storage::engine::Engine;
-use crate::storage::keyencode::{deserialize_key, serialize_key};
+use crate::storage::keyencode::{deserialize_key, serialize_key, serialize_key_prefix};
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
 
 pub type Version = u64;
 
 pub struct Mvcc<E: Engine> {
     // 多版本并发控制，Multi-Version Concurrency Control
     // 这里是基于存储引擎的事务，所以我们既需要泛型，又需要线程安全
-    engine: Arc<Mutex<E>>, // arc是多线程读，mutex是多线程写
+    engine: Arc<Mutex<E>>,             // arc是多线程读，mutex是多线程写
+    sync_stats: Arc<Mutex<SyncStats>>, // 所有副本共享的刷盘统计
+    metrics: Arc<Metrics>,             // 所有副本和事务共享的运行计数
+}
+
+// 刷盘统计：刷盘的次数和最近一次刷盘的时间
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SyncStats {
+    pub count: u64,
+    pub last_sync: Option<SystemTime>,
+}
+
+// 运行计数，从引擎启动开始累计，各个事务直接原子地递增，不需要加锁
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub statements: AtomicU64,    // 执行的语句数，包括执行失败的语句
+    pub rows_scanned: AtomicU64,  // 扫描表时读出的行数，过滤之前的行也计算在内
+    pub conflicts: AtomicU64,     // 写冲突和可串行化事务提交失败的次数
+    pub bytes_written: AtomicU64, // 写入的数据版本的字节数，包括编码后的key和value
+}
+
+impl Metrics {
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    // 按固定顺序列出各项计数的名称和当前值
+    pub fn snapshot(&self) -> Vec<(&'static str, u64)> {
+        [
+            ("statements", &self.statements),
+            ("rows_scanned", &self.rows_scanned),
+            ("conflicts", &self.conflicts),
+            ("bytes_written", &self.bytes_written),
+        ]
+        .into_iter()
+        .map(|(name, counter)| (name, counter.load(Ordering::Relaxed)))
+        .collect()
+    }
 }
 
 impl<E: Engine> Clone for Mvcc<E> {
@@ -18,20 +58,191 @@ impl<E: Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
         Self {
             engine: self.engine.clone(),
+            sync_stats: self.sync_stats.clone(),
+            metrics: self.metrics.clone(),
         }
     }
 }
 
 impl<E: Engine> Mvcc<E> {
-    pub fn new(engine: E) -> Self {
+    pub fn new(mut engine: E) -> Self {
+        // 恢复失败时未完成的事务仍然留在活跃列表中，它们写入的数据依然不可见，只是不会被清理
+        if let Err(e) = Self::recover(&mut engine) {
+            error!("[Mvcc] Failed to recover unfinished transactions: {}", e);
+        }
         Self {
             engine: Arc::new(Mutex::new(engine)),
+            sync_stats: Arc::new(Mutex::new(SyncStats::default())),
+            metrics: Arc::new(Metrics::default()),
         }
     }
 
+    // 启动时回滚上次退出时没有结束的事务，返回回滚的事务数
+    // 事务写入了哪些key只记录在内存中，这里需要扫描全部数据找出这些事务写入的版本
+    // 只有存在未结束的事务时才需要扫描，正常关闭后启动没有额外开销
+    fn recover(engine: &mut E) -> Result<usize> {
+        let mut unfinished = Vec::new();
+        let mut iter = engine.prefix_scan(MvccKeyPrefix::ActiveTransactions.encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            unfinished.push(key);
+        }
+        drop(iter);
+        if unfinished.is_empty() {
+            return Ok(0);
+        }
+
+        let mut versions = HashSet::new();
+        for key in unfinished.iter() {
+            match MvccKey::decode(key)? {
+                MvccKey::ActiveTransactions(version) => versions.insert(version),
+                _ => {
+                    return Err(Error::Storage(format!(
+                        "[Mvcc Recover] Unexpected key: {:?}",
+                        String::from_utf8(key.clone())
+                    )))
+                }
+            };
+        }
+
+        // 这些事务写入的数据版本，以及旧版本的存储格式中逐个key记录的写入信息
+        let mut keys_to_be_deleted = Vec::new();
+        let mut iter =
+            engine.prefix_scan(serialize_key_prefix(&MvccKeyPrefix::Version(Vec::new()))?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            match MvccKey::decode(&key)? {
+                MvccKey::Version(_, version) if versions.contains(&version) => {
+                    keys_to_be_deleted.push(key)
+                }
+                MvccKey::Version(_, _) => {}
+                _ => {
+                    return Err(Error::Storage(format!(
+                        "[Mvcc Recover] Unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            }
+        }
+        drop(iter);
+        for version in versions.iter() {
+            let mut iter = engine.prefix_scan(MvccKeyPrefix::Write(*version).encode()?);
+            while let Some((key, _)) = iter.next().transpose()? {
+                keys_to_be_deleted.push(key);
+            }
+        }
+
+        // 数据删除之后再把事务移出活跃列表，中途再次退出时下次启动会重新回滚
+        let removed = keys_to_be_deleted.len();
+        engine.delete_batch(keys_to_be_deleted)?;
+        engine.delete_batch(unfinished)?;
+        debug!(
+            "[Mvcc] Recovered {} unfinished transactions, removed {} entries",
+            versions.len(),
+            removed
+        );
+        Ok(versions.len())
+    }
+
+    // 把已提交的数据刷到磁盘上，并记录刷盘统计
+    pub fn sync(&self) -> Result<()> {
+        self.engine.lock()?.sync()?;
+        let mut stats = self.sync_stats.lock()?;
+        stats.count += 1;
+        stats.last_sync = Some(SystemTime::now());
+        debug!("[Mvcc] Synced storage, {} syncs in total", stats.count);
+        Ok(())
+    }
+
+    pub fn sync_stats(&self) -> Result<SyncStats> {
+        Ok(*self.sync_stats.lock()?)
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
     pub fn begin(&self) -> Result<MvccTransaction<E>> {
         // 开启事务
-        MvccTransaction::begin(self.engine.clone()) // 直接调用底层的事务实现
+        MvccTransaction::begin(self.engine.clone(), self.metrics.clone()) // 直接调用底层的事务实现
+    }
+
+    // 开启可串行化事务
+    pub fn begin_serializable(&self) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_serializable(self.engine.clone(), self.metrics.clone())
+    }
+
+    // 开启只读事务，不占用版本号，也不写入任何数据
+    pub fn begin_read_only(&self) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_read_only(self.engine.clone(), self.metrics.clone())
+    }
+
+    // 物理删除 prefixes 下已经被删除的key的全部版本，并重写存储，返回删除的条目数
+    // 只有最新版本是删除标记、且该删除对所有活跃事务都可见的key才会被清理，
+    // 仍持有旧快照的事务还能读到的数据会保留，等这些事务结束后再清理
+    // 注意：只读事务不在活跃事务列表中，它们只用于单条语句，清理时不会等待
+    pub fn purge(&self, prefixes: Vec<Vec<u8>>) -> Result<usize> {
+        let mut engine = self.engine.lock()?;
+        let horizon = Self::purge_horizon(&mut engine)?;
+
+        let mut keys_to_be_deleted = Vec::new();
+        for prefix in prefixes {
+            let encode_prefix = serialize_key_prefix(&MvccKeyPrefix::Version(prefix))?;
+            // 同一个key的各个版本是相邻的，且按版本号从小到大排列
+            let mut versions: Vec<Vec<u8>> = Vec::new();
+            let mut current: Option<Vec<u8>> = None;
+            let mut latest_deleted = false;
+            let mut iter = engine.prefix_scan(encode_prefix);
+            while let Some((key, value)) = iter.next().transpose()? {
+                let (raw_key, version) = match MvccKey::decode(&key)? {
+                    MvccKey::Version(raw_key, version) => (raw_key, version),
+                    _ => {
+                        return Err(Error::Storage(format!(
+                            "[Mvcc Purge] Unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                };
+                if current.as_ref() != Some(&raw_key) {
+                    if latest_deleted {
+                        keys_to_be_deleted.append(&mut versions);
+                    }
+                    versions.clear();
+                    current = Some(raw_key);
+                }
+                let value: Option<Vec<u8>> = bincode::deserialize(&value)?;
+                latest_deleted = value.is_none() && version < horizon;
+                versions.push(key);
+            }
+            if latest_deleted {
+                keys_to_be_deleted.append(&mut versions);
+            }
+        }
+
+        let purged = keys_to_be_deleted.len();
+        engine.delete_batch(keys_to_be_deleted)?;
+        debug!(
+            "[Mvcc] Purged {} entries, oldest version visible to all transactions is {}",
+            purged, horizon
+        );
+        engine.compact()?;
+        Ok(purged)
+    }
+
+    // 所有活跃事务都能看到的版本上界：小于它的已提交版本对所有活跃事务可见
+    fn purge_horizon(engine: &mut MutexGuard<E>) -> Result<Version> {
+        let mut horizon = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(version) => bincode::deserialize(&version)?,
+            None => 1,
+        };
+        let mut iter = engine.prefix_scan(MvccKeyPrefix::ActiveTransactions.encode()?);
+        while let Some((_, value)) = iter.next().transpose()? {
+            // 没有记录快照信息的活跃事务无法判断，保守起见不清理任何数据
+            let oldest_invisible = match value.is_empty() {
+                true => 0,
+                false => bincode::deserialize(&value)?,
+            };
+            horizon = horizon.min(oldest_invisible);
+        }
+        Ok(horizon)
     }
 }
 
@@ -39,6 +250,31 @@ pub struct MvccTransaction<E: Engine> {
     // 代表一个具体的事务
     engine: Arc<Mutex<E>>,
     state: TransactionState,
+    // 可串行化事务会自动记录读过的数据，默认的快照隔离下不记录
+    serializable: bool,
+    // 只读事务没有自己的版本号，不能写入数据
+    read_only: bool,
+    // 需要在提交时校验的数据：可串行化事务读过的数据，以及 select ... for update 锁定的行
+    read_set: Mutex<HashSet<ReadKey>>,
+    // 保存点栈，后创建的在栈顶
+    savepoints: Mutex<Vec<Savepoint>>,
+    // 本事务写入过的key，用于回滚时删除数据
+    // 只保存在内存中，不再为每次写入额外存一份带完整key的记录，异常退出时由启动恢复清理
+    writes: Mutex<BTreeSet<Vec<u8>>>,
+    metrics: Arc<Metrics>,
+}
+
+// 保存点：记录创建时本事务已写入的key，及其在本事务中的值
+struct Savepoint {
+    name: String,
+    writes: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+// 提交时需要重新校验的数据
+#[derive(PartialEq, Eq, Hash)]
+enum ReadKey {
+    Key(Vec<u8>),    // get 读取的key
+    Prefix(Vec<u8>), // prefix_scan 扫描的前缀
 }
 
 pub struct TransactionState {
@@ -47,6 +283,9 @@ pub struct TransactionState {
 }
 
 impl TransactionState {
+    // 可见的版本：不在活跃事务列表中，且不大于本事务的版本号
+    // 活跃事务列表是在本事务加入之前扫描的，不包含本事务自己，所以本事务的写入总是可见的
+    // 不能特判 version == self.version：只读事务的版本号是上一个已分配的版本号，这个事务可能还没有提交
     fn is_visible(&self, version: Version) -> bool {
         if self.active_version.contains(&version) {
             false
@@ -61,7 +300,7 @@ pub enum MvccKey {
     // 和数据key类型区分
     NextVersion,                                              // 版本号
     ActiveTransactions(Version),                              // 活跃事务版本号
-    Write(Version, #[serde(with = "serde_bytes")] Vec<u8>),   // 事务写入了哪些key
+    Write(Version, #[serde(with = "serde_bytes")] Vec<u8>), // 旧版本中记录的事务写入了哪些key，现在只在启动恢复时清理
     Version(#[serde(with = "serde_bytes")] Vec<u8>, Version), // (key, 所属version)
 }
 
@@ -72,8 +311,8 @@ impl MvccKey {
     }
 
     // 解码二进制
-    pub fn decode(data: Vec<u8>) -> Result<Self> {
-        deserialize_key(&data)
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        deserialize_key(data)
     }
 }
 
@@ -82,7 +321,7 @@ pub enum MvccKeyPrefix {
     // MvccKey的前缀，用于扫描活跃事务
     NextVersion,        // 版本号前缀
     ActiveTransactions, // 活跃事务前缀
-    Write(Version),     // 事务写信息前缀
+    Write(Version),     // 旧版本事务写信息前缀
     Version(#[serde(with = "serde_bytes")] Vec<u8>),
 }
 impl MvccKeyPrefix {
@@ -94,7 +333,7 @@ impl MvccKeyPrefix {
 
 impl<E: Engine> MvccTransaction<E> {
     // 开启事务
-    pub fn begin(eng: Arc<Mutex<E>>) -> Result<Self> {
+    pub fn begin(eng: Arc<Mutex<E>>, metrics: Arc<Metrics>) -> Result<Self> {
         // 1. 获取存储引擎
         let mut engine = eng.lock()?;
         // 2. 获取全局版本号，这里需要特判：第一个事务的版本号是空值
@@ -110,7 +349,13 @@ impl<E: Engine> MvccTransaction<E> {
         // 4. 获取活跃事务列表
         let active_version = Self::scan_active_transactions(&mut engine)?;
         // 5. 将本事务添加到活跃事务列表
-        engine.set(MvccKey::ActiveTransactions(next_version).encode()?, vec![])?; // 事务活跃列表数据存在key里，value存空值即可
+        // value 记录本事务快照中最小的不可见版本号，清理数据时据此判断哪些版本对所有活跃事务都可见
+        let oldest_invisible = active_version.iter().copied().min().unwrap_or(next_version);
+        engine.set(
+            MvccKey::ActiveTransactions(next_version).encode()?,
+            bincode::serialize(&oldest_invisible)?,
+        )?;
+        debug!("[Mvcc] Begin transaction {}", next_version);
 
         Ok(Self {
             engine: eng.clone(),
@@ -118,9 +363,151 @@ impl<E: Engine> MvccTransaction<E> {
                 version: next_version,
                 active_version,
             },
+            serializable: false,
+            read_only: false,
+            read_set: Mutex::new(HashSet::new()),
+            savepoints: Mutex::new(Vec::new()),
+            writes: Mutex::new(BTreeSet::new()),
+            metrics,
         })
     }
 
+    // 开启可串行化事务
+    // 快照隔离下，两个事务各自读取对方要写的数据，都可以提交成功（写偏斜）
+    // 可串行化事务会记录读过的数据，提交时如果发现这些数据已被其他事务修改并提交，则提交失败
+    pub fn begin_serializable(eng: Arc<Mutex<E>>, metrics: Arc<Metrics>) -> Result<Self> {
+        let mut transaction = Self::begin(eng, metrics)?;
+        transaction.serializable = true;
+        Ok(transaction)
+    }
+
+    // 开启只读事务
+    // 只读取当前的全局版本号和活跃事务列表作为快照，不递增版本号，也不加入活跃事务列表，
+    // 所以不会写入存储引擎，提交和回滚也无需做任何事情
+    pub fn begin_read_only(eng: Arc<Mutex<E>>, metrics: Arc<Metrics>) -> Result<Self> {
+        let mut engine = eng.lock()?;
+        let next_version: Version = match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(version) => bincode::deserialize(&version)?,
+            None => 1,
+        };
+        let active_version = Self::scan_active_transactions(&mut engine)?;
+
+        Ok(Self {
+            engine: eng.clone(),
+            state: TransactionState {
+                // 下一个版本号还没有被分配，快照能看到的是它之前已提交的版本
+                version: next_version - 1,
+                active_version,
+            },
+            serializable: false,
+            read_only: true,
+            read_set: Mutex::new(HashSet::new()),
+            savepoints: Mutex::new(Vec::new()),
+            writes: Mutex::new(BTreeSet::new()),
+            metrics,
+        })
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // 只读事务不能写入
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::Internal(
+                "[Transaction] Cannot write in a read-only transaction".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    // 锁定key（select ... for update），提交时如果发现该key已被其他事务修改并提交，则提交失败
+    // 快照隔离和可串行化事务都可以使用
+    pub fn lock_key(&self, key: Vec<u8>) -> Result<()> {
+        self.check_writable()?;
+        self.read_set.lock()?.insert(ReadKey::Key(key));
+        Ok(())
+    }
+
+    // 创建保存点
+    pub fn savepoint(&self, name: &str) -> Result<()> {
+        self.check_writable()?;
+        let engine = self.engine.lock()?;
+        let mut writes = BTreeMap::new();
+        for raw_key in self.write_keys()? {
+            // 同一个key在本事务中只有一个版本，后续写入会覆盖，所以要记下当前的值
+            let value = engine
+                .get(MvccKey::Version(raw_key.clone(), self.state.version).encode()?)?
+                .ok_or(Error::Storage(format!(
+                    "[Savepoint] Missing version of written key: {:?}",
+                    String::from_utf8(raw_key.clone())
+                )))?;
+            writes.insert(raw_key, value);
+        }
+        self.savepoints.lock()?.push(Savepoint {
+            name: name.into(),
+            writes,
+        });
+        Ok(())
+    }
+
+    // 回滚到保存点：撤销保存点之后的写入，保存点本身保留，之后创建的保存点全部丢弃
+    pub fn rollback_to_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.lock()?;
+        let pos = Self::find_savepoint(&savepoints, name)?;
+        savepoints.truncate(pos + 1);
+        let savepoint = &savepoints[pos];
+
+        let mut engine = self.engine.lock()?;
+        let mut writes = self.writes.lock()?;
+        for raw_key in writes.iter() {
+            let version_key = MvccKey::Version(raw_key.clone(), self.state.version).encode()?;
+            match savepoint.writes.get(raw_key) {
+                // 保存点之前写过的key，恢复为当时的值
+                Some(value) => engine.set(version_key, value.clone())?,
+                // 保存点之后才写入的key，和事务回滚一样删除数据
+                None => engine.delete(version_key)?,
+            }
+        }
+        writes.retain(|raw_key| savepoint.writes.contains_key(raw_key));
+        Ok(())
+    }
+
+    // 释放保存点，之后创建的保存点一并释放，已写入的数据不受影响
+    pub fn release_savepoint(&self, name: &str) -> Result<()> {
+        let mut savepoints = self.savepoints.lock()?;
+        let pos = Self::find_savepoint(&savepoints, name)?;
+        savepoints.truncate(pos);
+        Ok(())
+    }
+
+    // 从栈顶开始查找保存点
+    fn find_savepoint(savepoints: &[Savepoint], name: &str) -> Result<usize> {
+        savepoints
+            .iter()
+            .rposition(|s| s.name == name)
+            .ok_or(Error::NotFound(format!(
+                "[Savepoint] Savepoint \" {} \" does not exist",
+                name
+            )))
+    }
+
+    // 本事务是否写入过以 prefix 开头的key
+    pub fn has_written_prefix(&self, prefix: &[u8]) -> Result<bool> {
+        Ok(self
+            .writes
+            .lock()?
+            .range(prefix.to_vec()..)
+            .next()
+            .is_some_and(|key| key.starts_with(prefix)))
+    }
+
+    // 获取本事务写入过的所有key
+    fn write_keys(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self.writes.lock()?.iter().cloned().collect())
+    }
+
     // 获取事务版本号
     pub fn get_version(&self) -> u64 {
         self.state.version
@@ -132,12 +519,12 @@ impl<E: Engine> MvccTransaction<E> {
         let mut iter = eng.prefix_scan(MvccKeyPrefix::ActiveTransactions.encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
             // key是二进制
-            match MvccKey::decode(key.clone())? {
+            match MvccKey::decode(&key)? {
                 MvccKey::ActiveTransactions(version) => {
                     res.insert(version);
                 }
                 _ => {
-                    return Err(Error::Internal(format!(
+                    return Err(Error::Storage(format!(
                         "[Scan Active Transactions] Unexpected key {:?}",
                         String::from_utf8(key)
                     )))
@@ -148,52 +535,106 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn commit(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
         // 1. 获取存储引擎
         let mut engine = self.engine.lock()?;
-        // 2. 获取事务写信息并删除
-        let mut keys_to_be_deleted = Vec::new();
-        let mut iter = engine.prefix_scan(MvccKeyPrefix::Write(self.state.version).encode()?);
-        while let Some((key, _)) = iter.next().transpose()? {
-            keys_to_be_deleted.push(key);
-        }
-        drop(iter); // 这里后续还要用到对engine的可变引用，而一次生命周期内仅能有一次引用，所以这里手动drop掉iter，停止对engine的可变引用
-        for key in keys_to_be_deleted {
-            engine.delete(key)?;
+        // 先校验读过（或锁定）的数据，校验失败则回滚
+        if !self.validate_read_set(&mut engine)? {
+            debug!(
+                "[Mvcc] Serialization failure in transaction {}",
+                self.state.version
+            );
+            drop(engine);
+            self.rollback()?;
+            Metrics::add(&self.metrics.conflicts, 1);
+            return Err(Error::SerializationFailure);
         }
+        // 2. 写入的数据已经在存储中，清空内存中的写信息即可
+        self.writes.lock()?.clear();
         // 3. 从活跃列表删除本事务
-        engine.delete(MvccKey::ActiveTransactions(self.state.version).encode()?)
+        engine.delete(MvccKey::ActiveTransactions(self.state.version).encode()?)?;
+        debug!("[Mvcc] Commit transaction {}", self.state.version);
+        Ok(())
     }
 
-    pub fn rollback(&self) -> Result<()> {
-        // 1. 获取存储引擎
-        let mut engine = self.engine.lock()?;
-        // 2. 获取事务写信息并删除
-        let mut keys_to_be_deleted = Vec::new();
-        let mut iter = engine.prefix_scan(MvccKeyPrefix::Write(self.state.version).encode()?);
-        while let Some((key, _)) = iter.next().transpose()? {
-            // 这里比commit多一步删除写入log的真实数据
-            match MvccKey::decode(key.clone())? {
-                MvccKey::Write(_, raw_key) => {
-                    // 这里找到的是不含版本信息的key
-                    // 构造带版本信息的key
-                    keys_to_be_deleted
-                        .push(MvccKey::Version(raw_key, self.state.version).encode()?);
+    // 校验读过（或锁定）的数据在本事务开启之后是否被其他已提交的事务修改过
+    fn validate_read_set(&self, engine: &mut MutexGuard<E>) -> Result<bool> {
+        let read_set = self.read_set.lock()?;
+        if read_set.is_empty() {
+            return Ok(true);
+        }
+
+        // 只读事务读到的是一致的快照，不需要校验
+        if self.writes.lock()?.is_empty() {
+            return Ok(true);
+        }
+
+        // 当前仍然活跃的事务还没有提交，其写入不算冲突
+        let active_version = Self::scan_active_transactions(engine)?;
+        for read_key in read_set.iter() {
+            let mut keys = Vec::new();
+            match read_key {
+                ReadKey::Key(key) => {
+                    let from = MvccKey::Version(key.clone(), 0).encode()?;
+                    let to = MvccKey::Version(key.clone(), u64::MAX).encode()?;
+                    let mut iter = engine.scan(from..=to);
+                    while let Some((key, _)) = iter.next().transpose()? {
+                        keys.push(key);
+                    }
                 }
-                _ => {
-                    return Err(Error::Internal(format!(
-                        "[Transaction rollback] Unexpected key: {:?}",
-                        String::from_utf8(key)
-                    )))
+                ReadKey::Prefix(prefix) => {
+                    let encode_prefix =
+                        serialize_key_prefix(&MvccKeyPrefix::Version(prefix.clone()))?;
+                    let mut iter = engine.prefix_scan(encode_prefix);
+                    while let Some((key, _)) = iter.next().transpose()? {
+                        keys.push(key);
+                    }
+                }
+            }
+
+            for key in keys {
+                match MvccKey::decode(&key)? {
+                    MvccKey::Version(_, version) => {
+                        if version != self.state.version
+                            && !self.state.is_visible(version)
+                            && !active_version.contains(&version)
+                        {
+                            return Ok(false);
+                        }
+                    }
+                    _ => {
+                        return Err(Error::Storage(format!(
+                            "[Transaction Commit] Unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
                 }
             }
-            keys_to_be_deleted.push(key);
         }
-        drop(iter);
-        for key in keys_to_be_deleted {
-            engine.delete(key)?;
+        Ok(true)
+    }
+
+    pub fn rollback(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
         }
+        // 1. 获取存储引擎
+        let mut engine = self.engine.lock()?;
+        // 2. 删除本事务写入的数据，先构造出全部要删除的key再批量删除
+        let mut writes = self.writes.lock()?;
+        let keys = writes
+            .iter()
+            // 写信息中是不含版本信息的key，构造带版本信息的key
+            .map(|raw_key| MvccKey::Version(raw_key.clone(), self.state.version).encode())
+            .collect::<Result<Vec<_>>>()?;
+        engine.delete_batch(keys)?;
+        writes.clear();
         // 3. 从活跃列表删除本事务
-        engine.delete(MvccKey::ActiveTransactions(self.state.version).encode()?)
+        engine.delete(MvccKey::ActiveTransactions(self.state.version).encode()?)?;
+        debug!("[Mvcc] Rollback transaction {}", self.state.version);
+        Ok(())
     }
 
     pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
@@ -207,6 +648,7 @@ impl<E: Engine> MvccTransaction<E> {
     // set-delete 通用逻辑
     fn update(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
         // 删除时value置空即可
+        self.check_writable()?;
         // 1. 获取存储引擎
         let mut engine = self.engine.lock()?;
         // 2. 检测是否冲突
@@ -225,51 +667,61 @@ impl<E: Engine> MvccTransaction<E> {
         // to 涵盖最大可能版本
         if let Some((key, _)) = engine.scan(from..=to).last().transpose()? {
             // 取得key的最新版本
-            match MvccKey::decode(key.clone())? {
-                MvccKey::Version(_, version) => {
+            match MvccKey::decode(&key)? {
+                MvccKey::Version(raw_key, version) => {
                     // 要修改的key的version是否对本事务可见
                     if !self.state.is_visible(version) {
+                        debug!(
+                            "[Mvcc] Write conflict in transaction {} on key {:?}, conflicting version {}",
+                            self.state.version,
+                            String::from_utf8_lossy(&raw_key),
+                            version
+                        );
+                        Metrics::add(&self.metrics.conflicts, 1);
                         return Err(Error::WriteConflict);
                     }
                 }
                 _ => {
-                    return Err(Error::Internal(format!(
+                    return Err(Error::Storage(format!(
                         "[Transaction Update] Unexpected key: {:?}",
                         String::from_utf8(key)
                     )))
                 }
             }
         };
-        // 3. 不冲突，写入数据
-        // 3.1 记录本version写入了哪些key，用于回滚数据
-        engine.set(
-            MvccKey::Write(self.state.version, key.clone()).encode()?,
-            vec![],
-        )?;
-        // 3.2 写入实际的key-value数据
-        engine.set(
-            MvccKey::Version(key.clone(), self.state.version).encode()?,
-            bincode::serialize(&value)?,
-        )?;
+        // 3. 不冲突，写入实际的key-value数据
+        let version_key = MvccKey::Version(key.clone(), self.state.version).encode()?;
+        let value = bincode::serialize(&value)?;
+        Metrics::add(
+            &self.metrics.bytes_written,
+            (version_key.len() + value.len()) as u64,
+        );
+        engine.set(version_key, value)?;
+        // 4. 记录本version写入了哪些key，用于回滚数据
+        self.writes.lock()?.insert(key);
         Ok(())
     }
 
     pub fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if self.serializable {
+            self.read_set.lock()?.insert(ReadKey::Key(key.clone()));
+        }
         // 1. 获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let engine = self.engine.lock()?;
         // 2. 判断数据是否符合条件
         let from = MvccKey::Version(key.clone(), 0).encode()?;
         let to = MvccKey::Version(key.clone(), self.state.version).encode()?;
         let mut iter = engine.scan(from..=to).rev(); // rev 反转
         while let Some((key, value)) = iter.next().transpose()? {
-            match MvccKey::decode(key.clone())? {
+            match MvccKey::decode(&key)? {
                 MvccKey::Version(_, version) => {
                     if self.state.is_visible(version) {
-                        return Ok(bincode::deserialize(&value)?);
+                        // 删除标记序列化的是 None，必须按 Option 解码，推断成其他类型会把删除标记读成数据
+                        return Ok(bincode::deserialize::<Option<Vec<u8>>>(&value)?);
                     }
                 }
                 _ => {
-                    return Err(Error::Internal(format!(
+                    return Err(Error::Storage(format!(
                         "[Transaction get] Unexpected key: {:?}",
                         String::from_utf8(key)
                     )))
@@ -280,36 +732,108 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn prefix_scan(&self, prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
-        let mut encode_prefix = MvccKeyPrefix::Version(prefix).encode()?;
-        // 截断最后两个0
-        encode_prefix.truncate(encode_prefix.len() - 2);
+        Ok(self
+            .visible_versions(prefix)?
+            .into_iter()
+            .map(|(k, v)| ScanResult { key: k, value: v })
+            .collect())
+    }
+
+    // 只返回value的前缀扫描，调用方不需要key时使用，比如扫描表中的行
+    pub fn prefix_scan_values(&self, prefix: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        Ok(self.visible_versions(prefix)?.into_values().collect())
+    }
+
+    // 前缀下每个key在快照中可见的最新版本，按key从小到大排列
+    fn visible_versions(&self, prefix: Vec<u8>) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+        if self.serializable {
+            self.read_set
+                .lock()?
+                .insert(ReadKey::Prefix(prefix.clone()));
+        }
+        let eng = self.engine.lock()?;
+        let encode_prefix = serialize_key_prefix(&MvccKeyPrefix::Version(prefix))?;
         let mut iter = eng.prefix_scan(encode_prefix);
         let mut results = BTreeMap::new();
         while let Some((encode_key, encode_value)) = iter.next().transpose()? {
             // 这里拿到的是编码后的kv对，需要进行解码
-            match MvccKey::decode(encode_key.clone())? {
+            match MvccKey::decode(&encode_key)? {
                 MvccKey::Version(key, version) => {
                     if self.state.is_visible(version) {
                         // value 也需要解码
-                        match bincode::deserialize(&encode_value)? {
+                        match bincode::deserialize::<Option<Vec<u8>>>(&encode_value)? {
                             Some(value) => results.insert(key, value),
                             None => results.remove(&key),
                         };
                     }
                 }
                 _ => {
-                    return Err(Error::Internal(format!(
+                    return Err(Error::Storage(format!(
                         "[Transaction Prefix_Scan] Unexpected key: {:?}",
                         String::from_utf8(encode_key)
                     )))
                 }
             }
         }
-        Ok(results
-            .into_iter()
-            .map(|(k, v)| ScanResult { key: k, value: v })
-            .collect())
+        Ok(results)
+    }
+
+    // 按key从大到小的顺序前缀扫描，从存储引擎的末尾向前读取，不需要先读出全部数据再反转
+    pub fn prefix_scan_reverse(&self, prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
+        let mut results = Vec::new();
+        self.visible_versions_reverse(prefix, |key, value| {
+            results.push(ScanResult {
+                key: key.to_vec(),
+                value,
+            })
+        })?;
+        Ok(results)
+    }
+
+    // 只返回value的反向前缀扫描
+    pub fn prefix_scan_values_reverse(&self, prefix: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        let mut results = Vec::new();
+        self.visible_versions_reverse(prefix, |_, value| results.push(value))?;
+        Ok(results)
+    }
+
+    // 按key从大到小依次处理前缀下每个key在快照中可见的最新版本
+    // 同一个key的各个版本按版本号从大到小读到，第一个可见的版本就是该key在快照中的值
+    fn visible_versions_reverse(
+        &self,
+        prefix: Vec<u8>,
+        mut f: impl FnMut(&[u8], Vec<u8>),
+    ) -> Result<()> {
+        if self.serializable {
+            self.read_set
+                .lock()?
+                .insert(ReadKey::Prefix(prefix.clone()));
+        }
+        let eng = self.engine.lock()?;
+        let encode_prefix = serialize_key_prefix(&MvccKeyPrefix::Version(prefix))?;
+        let mut iter = eng.prefix_scan(encode_prefix).rev();
+        let mut last_key: Option<Vec<u8>> = None; // 已经确定了可见版本的key
+        while let Some((encode_key, encode_value)) = iter.next().transpose()? {
+            match MvccKey::decode(&encode_key)? {
+                MvccKey::Version(key, version) => {
+                    if last_key.as_ref() == Some(&key) || !self.state.is_visible(version) {
+                        continue;
+                    }
+                    // 可见的最新版本是删除标记时，这个key在快照中不存在
+                    if let Some(value) = bincode::deserialize::<Option<Vec<u8>>>(&encode_value)? {
+                        f(&key, value);
+                    }
+                    last_key = Some(key);
+                }
+                _ => {
+                    return Err(Error::Storage(format!(
+                        "[Transaction Prefix_Scan] Unexpected key: {:?}",
+                        String::from_utf8(encode_key)
+                    )))
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -370,7 +894,7 @@ mod tests {
         let mut transaction1 = mvcc.begin()?;
         transaction1.set(b"key1".to_vec(), b"val2".to_vec())?;
 
-        let mut transaction2 = mvcc.begin()?;
+        let transaction2 = mvcc.begin()?;
 
         let mut transaction3 = mvcc.begin()?;
         transaction3.set(b"key2".to_vec(), b"val4".to_vec())?;
@@ -465,6 +989,123 @@ mod tests {
         Ok(())
     }
 
+    // 整数编码的key按数值顺序扫描，负数在前
+    fn prefix_scan_integer_order(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let numbers = [3i64, -1, 256, 0, i64::MIN, -300, 1, i64::MAX, -2];
+        let mut transaction = mvcc.begin()?;
+        for n in numbers {
+            transaction.set(serialize_key(&("t", n))?, n.to_string().into_bytes())?;
+        }
+        transaction.set(serialize_key(&("u", 0i64))?, b"other".to_vec())?;
+        transaction.commit()?;
+
+        let mut expected = numbers.to_vec();
+        expected.sort();
+        let transaction = mvcc.begin()?;
+        let prefix = serialize_key(&"t")?;
+        let values = |res: Vec<ScanResult>| {
+            res.into_iter()
+                .map(|r| String::from_utf8(r.value).unwrap().parse::<i64>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values(transaction.prefix_scan(prefix.clone())?), expected);
+        expected.reverse();
+        assert_eq!(values(transaction.prefix_scan_reverse(prefix)?), expected);
+
+        // 解码之后得到原来的数值
+        for n in numbers {
+            assert_eq!(
+                deserialize_key::<(String, i64)>(&serialize_key(&("t", n))?)?.1,
+                n
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_scan_integer_order() -> Result<()> {
+        prefix_scan_integer_order(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        prefix_scan_integer_order(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 反向前缀扫描和正向扫描看到相同的数据，顺序相反
+    fn prefix_scan_reverse(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        for (key, value) in [
+            ("a1", "v1"),
+            ("a2", "v2"),
+            ("a3", "v3"),
+            ("a4", "v4"),
+            ("b1", "v5"),
+        ] {
+            transaction.set(key.into(), value.into())?;
+        }
+        transaction.commit()?;
+
+        // 旧快照
+        let old = mvcc.begin()?;
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"a2".to_vec(), b"v2-1".to_vec())?;
+        transaction.delete(b"a3".to_vec())?;
+        transaction.set(b"a5".to_vec(), b"v6".to_vec())?;
+        transaction.commit()?;
+        // 没有提交的写入
+        let mut active = mvcc.begin()?;
+        active.set(b"a0".to_vec(), b"v7".to_vec())?;
+        active.delete(b"a4".to_vec())?;
+        active.set(b"a1".to_vec(), b"v1-1".to_vec())?;
+        let latest = mvcc.begin_read_only()?;
+
+        let keys = |res: Vec<ScanResult>| {
+            res.into_iter()
+                .map(|r| {
+                    format!(
+                        "{}={}",
+                        String::from_utf8(r.key).unwrap(),
+                        String::from_utf8(r.value).unwrap()
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        for (transaction, expected) in [
+            (&old, vec!["a4=v4", "a3=v3", "a2=v2", "a1=v1"]),
+            (&active, vec!["a5=v6", "a2=v2-1", "a1=v1-1", "a0=v7"]),
+            (&latest, vec!["a5=v6", "a4=v4", "a2=v2-1", "a1=v1"]),
+        ] {
+            let reverse = transaction.prefix_scan_reverse(b"a".to_vec())?;
+            let mut forward = transaction.prefix_scan(b"a".to_vec())?;
+            forward.reverse();
+            assert_eq!(reverse, forward);
+            // 只返回value的扫描和完整的扫描看到相同的数据
+            let values =
+                |res: &Vec<ScanResult>| res.iter().map(|r| r.value.clone()).collect::<Vec<_>>();
+            assert_eq!(
+                transaction.prefix_scan_values_reverse(b"a".to_vec())?,
+                values(&reverse)
+            );
+            let mut forward_values = transaction.prefix_scan_values(b"a".to_vec())?;
+            forward_values.reverse();
+            assert_eq!(forward_values, values(&reverse));
+            assert_eq!(keys(reverse), expected);
+        }
+        assert_eq!(latest.prefix_scan_reverse(b"c".to_vec())?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_scan_reverse() -> Result<()> {
+        prefix_scan_reverse(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        prefix_scan_reverse(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
     // 4. scan isolation
     fn scan_isolation(eng: impl Engine) -> Result<()> {
         let mvcc = Mvcc::new(eng);
@@ -477,7 +1118,7 @@ mod tests {
         transaction.set(b"bcca".to_vec(), b"val6".to_vec())?;
         transaction.commit()?;
 
-        let mut transaction1 = mvcc.begin()?;
+        let transaction1 = mvcc.begin()?;
         let mut transaction2 = mvcc.begin()?;
         transaction2.set(b"acca".to_vec(), b"val4-1".to_vec())?;
         transaction2.set(b"aabb".to_vec(), b"val1-1".to_vec())?;
@@ -715,7 +1356,7 @@ mod tests {
         transaction.set(b"key3".to_vec(), b"val3".to_vec())?;
         transaction.commit()?;
 
-        let mut transaction1 = mvcc.begin()?;
+        let transaction1 = mvcc.begin()?;
         let mut transaction2 = mvcc.begin()?;
 
         transaction2.set(b"key1".to_vec(), b"val1-1".to_vec())?;
@@ -742,7 +1383,7 @@ mod tests {
         transaction.set(b"key3".to_vec(), b"val3".to_vec())?;
         transaction.commit()?;
 
-        let mut transaction1 = mvcc.begin()?;
+        let transaction1 = mvcc.begin()?;
         let mut transaction2 = mvcc.begin()?;
 
         transaction2.set(b"key1".to_vec(), b"val1-1".to_vec())?;
@@ -771,7 +1412,7 @@ mod tests {
         transaction.set(b"key3".to_vec(), b"val3".to_vec())?;
         transaction.commit()?;
 
-        let mut transaction1 = mvcc.begin()?;
+        let transaction1 = mvcc.begin()?;
         let mut transaction2 = mvcc.begin()?;
 
         let iter1 = transaction1.prefix_scan(b"key".to_vec())?;
@@ -858,4 +1499,552 @@ mod tests {
         std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
+
+    // 写入大量 key 的事务回滚：全部写入批量删除，已提交的旧版本不受影响
+    fn rollback_large_transaction(eng: impl Engine) -> Result<()> {
+        const KEYS: u32 = 20_000;
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        for i in (0..KEYS).step_by(2) {
+            transaction.set(i.to_be_bytes().to_vec(), b"committed".to_vec())?;
+        }
+        transaction.commit()?;
+
+        let mut transaction1 = mvcc.begin()?;
+        for i in 0..KEYS {
+            transaction1.set(i.to_be_bytes().to_vec(), b"rolled back".to_vec())?;
+        }
+        transaction1.delete(0u32.to_be_bytes().to_vec())?;
+        transaction1.rollback()?;
+
+        let mut transaction2 = mvcc.begin()?;
+        let values = transaction2.prefix_scan_values(Vec::new())?;
+        assert_eq!(values.len(), KEYS as usize / 2);
+        assert!(values.iter().all(|v| v == b"committed"));
+        // 回滚之后这些 key 上没有未提交的版本，可以直接写入
+        transaction2.set(1u32.to_be_bytes().to_vec(), b"new".to_vec())?;
+        transaction2.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback_large_transaction() -> Result<()> {
+        rollback_large_transaction(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        rollback_large_transaction(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 13. write skew
+    fn write_skew(eng: impl Engine, serializable: bool) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"key1".to_vec(), b"1".to_vec())?;
+        transaction.set(b"key2".to_vec(), b"1".to_vec())?;
+        transaction.commit()?;
+
+        let begin = || {
+            if serializable {
+                mvcc.begin_serializable()
+            } else {
+                mvcc.begin()
+            }
+        };
+        let mut transaction1 = begin()?;
+        let mut transaction2 = begin()?;
+
+        // 两个事务都读取两个key，再各自修改其中一个
+        for t in [&transaction1, &transaction2] {
+            assert_eq!(t.get(b"key1".to_vec())?, Some(b"1".to_vec()));
+            assert_eq!(t.get(b"key2".to_vec())?, Some(b"1".to_vec()));
+        }
+        transaction1.set(b"key1".to_vec(), b"0".to_vec())?;
+        transaction2.set(b"key2".to_vec(), b"0".to_vec())?;
+
+        transaction1.commit()?;
+        if serializable {
+            // 后提交的事务读到的 key1 已被修改，提交失败并回滚
+            assert_eq!(transaction2.commit(), Err(Error::SerializationFailure));
+            let transaction3 = mvcc.begin()?;
+            assert_eq!(transaction3.get(b"key2".to_vec())?, Some(b"1".to_vec()));
+        } else {
+            // 快照隔离下两个事务都可以提交
+            transaction2.commit()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_skew() -> Result<()> {
+        write_skew(MemoryEngine::new(), false)?;
+        write_skew(MemoryEngine::new(), true)?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        write_skew(DiskEngine::new(p.clone())?, true)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 14. serializable read only
+    fn serializable_read_only(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"aa1".to_vec(), b"val1".to_vec())?;
+        transaction.commit()?;
+
+        let transaction1 = mvcc.begin_serializable()?;
+        assert_eq!(transaction1.prefix_scan(b"aa".to_vec())?.len(), 1);
+        assert_eq!(transaction1.get(b"aa1".to_vec())?, Some(b"val1".to_vec()));
+
+        let mut transaction2 = mvcc.begin()?;
+        transaction2.set(b"aa1".to_vec(), b"val2".to_vec())?;
+        transaction2.set(b"aa2".to_vec(), b"val3".to_vec())?;
+        transaction2.commit()?;
+
+        // 只读事务永远不会提交失败
+        transaction1.commit()?;
+
+        // 有写入的可串行化事务，扫描过的前缀被修改后提交失败
+        let mut transaction3 = mvcc.begin_serializable()?;
+        assert_eq!(transaction3.prefix_scan(b"aa".to_vec())?.len(), 2);
+        let mut transaction4 = mvcc.begin()?;
+        transaction4.set(b"aa3".to_vec(), b"val4".to_vec())?;
+        transaction4.commit()?;
+        transaction3.set(b"bb".to_vec(), b"val5".to_vec())?;
+        assert_eq!(transaction3.commit(), Err(Error::SerializationFailure));
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_read_only() -> Result<()> {
+        serializable_read_only(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        serializable_read_only(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 15. lock key
+    fn lock_key(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"key1".to_vec(), b"val1".to_vec())?;
+        transaction.set(b"key2".to_vec(), b"val2".to_vec())?;
+        transaction.commit()?;
+
+        // 快照隔离的事务锁定 key1 之后，key1 被其他事务修改并提交，本事务提交失败
+        let mut transaction1 = mvcc.begin()?;
+        assert_eq!(transaction1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        transaction1.lock_key(b"key1".to_vec())?;
+        let mut transaction2 = mvcc.begin()?;
+        transaction2.set(b"key1".to_vec(), b"val3".to_vec())?;
+        transaction2.commit()?;
+        transaction1.set(b"key2".to_vec(), b"val4".to_vec())?;
+        assert_eq!(transaction1.commit(), Err(Error::SerializationFailure));
+
+        // 没有锁定的key被修改不影响提交
+        let mut transaction3 = mvcc.begin()?;
+        transaction3.lock_key(b"key2".to_vec())?;
+        let mut transaction4 = mvcc.begin()?;
+        transaction4.set(b"key1".to_vec(), b"val5".to_vec())?;
+        transaction4.commit()?;
+        transaction3.set(b"key2".to_vec(), b"val6".to_vec())?;
+        transaction3.commit()?;
+
+        let transaction5 = mvcc.begin()?;
+        assert_eq!(transaction5.get(b"key1".to_vec())?, Some(b"val5".to_vec()));
+        assert_eq!(transaction5.get(b"key2".to_vec())?, Some(b"val6".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_key() -> Result<()> {
+        lock_key(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        lock_key(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    fn savepoint(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"key1".to_vec(), b"val1".to_vec())?;
+        transaction.commit()?;
+
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"key2".to_vec(), b"val2".to_vec())?;
+        transaction.savepoint("s1")?;
+        transaction.set(b"key2".to_vec(), b"val3".to_vec())?;
+        transaction.delete(b"key1".to_vec())?;
+        transaction.set(b"key3".to_vec(), b"val3".to_vec())?;
+        transaction.savepoint("s2")?;
+        transaction.set(b"key4".to_vec(), b"val4".to_vec())?;
+
+        // 保存点嵌套，回滚到 s2 只撤销 key4
+        transaction.rollback_to_savepoint("s2")?;
+        assert_eq!(transaction.get(b"key4".to_vec())?, None);
+        assert_eq!(transaction.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+
+        // 回滚到 s1，s2 一并丢弃，被覆盖和删除的 key 恢复为 s1 时的值
+        transaction.rollback_to_savepoint("s1")?;
+        assert_eq!(transaction.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(transaction.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(transaction.get(b"key3".to_vec())?, None);
+        assert!(transaction.rollback_to_savepoint("s2").is_err());
+
+        // 保存点回滚之后仍然可以继续使用，release 之后不能再回滚
+        transaction.set(b"key5".to_vec(), b"val5".to_vec())?;
+        transaction.rollback_to_savepoint("s1")?;
+        transaction.release_savepoint("s1")?;
+        assert!(transaction.rollback_to_savepoint("s1").is_err());
+        assert!(transaction.release_savepoint("s3").is_err());
+        transaction.commit()?;
+
+        let transaction = mvcc.begin()?;
+        assert_eq!(
+            transaction.prefix_scan(b"key".to_vec())?,
+            vec![
+                ScanResult {
+                    key: b"key1".to_vec(),
+                    value: b"val1".to_vec()
+                },
+                ScanResult {
+                    key: b"key2".to_vec(),
+                    value: b"val2".to_vec()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        savepoint(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        savepoint(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 只读事务
+    fn read_only(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"key1".to_vec(), b"val1".to_vec())?;
+        transaction.commit()?;
+
+        // 未提交的写入对只读事务不可见
+        let mut transaction1 = mvcc.begin()?;
+        transaction1.set(b"key2".to_vec(), b"val2".to_vec())?;
+
+        let mut reader = mvcc.begin_read_only()?;
+        assert_eq!(reader.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(reader.get(b"key2".to_vec())?, None);
+        assert!(reader.set(b"key3".to_vec(), b"val3".to_vec()).is_err());
+        assert!(reader.delete(b"key1".to_vec()).is_err());
+        assert!(reader.lock_key(b"key1".to_vec()).is_err());
+        assert!(reader.savepoint("s1").is_err());
+
+        // 只读事务开启之后提交的写入也不可见
+        transaction1.commit()?;
+        assert_eq!(reader.get(b"key2".to_vec())?, None);
+        reader.commit()?;
+
+        // 只读事务不消耗版本号
+        let transaction2 = mvcc.begin()?;
+        assert_eq!(transaction2.get_version(), transaction1.get_version() + 1);
+        assert_eq!(transaction2.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        transaction2.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        read_only(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        read_only(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 事务自己的写入：读取、范围扫描都可见，其他事务不可见
+    fn own_writes(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"a1".to_vec(), b"v1".to_vec())?;
+        transaction.set(b"a2".to_vec(), b"v2".to_vec())?;
+        transaction.commit()?;
+
+        let mut transaction1 = mvcc.begin()?;
+        let transaction2 = mvcc.begin()?;
+        // 本事务不在自己的活跃事务列表中，其他事务开启时本事务在列表中
+        assert!(!transaction1
+            .state
+            .active_version
+            .contains(&transaction1.get_version()));
+        assert!(transaction2
+            .state
+            .active_version
+            .contains(&transaction1.get_version()));
+
+        transaction1.set(b"a2".to_vec(), b"v2-1".to_vec())?;
+        transaction1.set(b"a3".to_vec(), b"v3".to_vec())?;
+        transaction1.delete(b"a1".to_vec())?;
+        assert_eq!(transaction1.get(b"a1".to_vec())?, None);
+        assert_eq!(transaction1.get(b"a2".to_vec())?, Some(b"v2-1".to_vec()));
+        assert_eq!(transaction1.get(b"a3".to_vec())?, Some(b"v3".to_vec()));
+        assert_eq!(
+            transaction1.prefix_scan(b"a".to_vec())?,
+            vec![
+                ScanResult {
+                    key: b"a2".to_vec(),
+                    value: b"v2-1".to_vec()
+                },
+                ScanResult {
+                    key: b"a3".to_vec(),
+                    value: b"v3".to_vec()
+                },
+            ]
+        );
+
+        // 并发的事务和只读事务都看不到
+        let reader = mvcc.begin_read_only()?;
+        for other in [&transaction2, &reader] {
+            assert_eq!(other.get(b"a1".to_vec())?, Some(b"v1".to_vec()));
+            assert_eq!(
+                other.prefix_scan(b"a".to_vec())?,
+                vec![
+                    ScanResult {
+                        key: b"a1".to_vec(),
+                        value: b"v1".to_vec()
+                    },
+                    ScanResult {
+                        key: b"a2".to_vec(),
+                        value: b"v2".to_vec()
+                    },
+                ]
+            );
+        }
+        transaction1.commit()?;
+        assert_eq!(transaction2.get(b"a3".to_vec())?, None);
+        transaction2.commit()?;
+        assert_eq!(mvcc.begin_read_only()?.prefix_scan(b"a".to_vec())?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_own_writes() -> Result<()> {
+        own_writes(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        own_writes(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 同一个事务中对同一个key的各种写入顺序，get 和前缀扫描都要读到本事务最后一次写入的结果
+    fn read_your_writes(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let mut transaction = mvcc.begin()?;
+        transaction.set(b"r5".to_vec(), b"committed5".to_vec())?;
+        transaction.set(b"r6".to_vec(), b"committed6".to_vec())?;
+        transaction.commit()?;
+
+        let mut transaction = mvcc.begin()?;
+        let other = mvcc.begin()?;
+        let scan = |t: &MvccTransaction<_>| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            Ok(t.prefix_scan(b"r".to_vec())?
+                .into_iter()
+                .map(|r| (r.key, r.value))
+                .collect())
+        };
+        let scan_reverse = |t: &MvccTransaction<_>| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            Ok(t.prefix_scan_reverse(b"r".to_vec())?
+                .into_iter()
+                .map(|r| (r.key, r.value))
+                .collect())
+        };
+
+        // set -> get
+        transaction.set(b"r1".to_vec(), b"a".to_vec())?;
+        assert_eq!(transaction.get(b"r1".to_vec())?, Some(b"a".to_vec()));
+        // set -> set -> get
+        transaction.set(b"r2".to_vec(), b"a".to_vec())?;
+        transaction.set(b"r2".to_vec(), b"b".to_vec())?;
+        assert_eq!(transaction.get(b"r2".to_vec())?, Some(b"b".to_vec()));
+        // set -> delete -> get
+        transaction.set(b"r3".to_vec(), b"a".to_vec())?;
+        transaction.delete(b"r3".to_vec())?;
+        assert_eq!(transaction.get(b"r3".to_vec())?, None);
+        // delete -> set -> get，删除一个不存在的key之后再写入
+        transaction.delete(b"r4".to_vec())?;
+        assert_eq!(transaction.get(b"r4".to_vec())?, None);
+        transaction.set(b"r4".to_vec(), b"a".to_vec())?;
+        assert_eq!(transaction.get(b"r4".to_vec())?, Some(b"a".to_vec()));
+        // 删除已提交的key，本事务的删除标记覆盖更早的已提交版本
+        transaction.delete(b"r5".to_vec())?;
+        assert_eq!(transaction.get(b"r5".to_vec())?, None);
+        // 覆盖已提交的key，删除之后再写入
+        transaction.set(b"r6".to_vec(), b"a".to_vec())?;
+        transaction.delete(b"r6".to_vec())?;
+        assert_eq!(transaction.get(b"r6".to_vec())?, None);
+        transaction.set(b"r6".to_vec(), b"b".to_vec())?;
+        assert_eq!(transaction.get(b"r6".to_vec())?, Some(b"b".to_vec()));
+
+        let expected = vec![
+            (b"r1".to_vec(), b"a".to_vec()),
+            (b"r2".to_vec(), b"b".to_vec()),
+            (b"r4".to_vec(), b"a".to_vec()),
+            (b"r6".to_vec(), b"b".to_vec()),
+        ];
+        let mut reversed = expected.clone();
+        reversed.reverse();
+        assert_eq!(scan(&transaction)?, expected);
+        assert_eq!(scan_reverse(&transaction)?, reversed);
+
+        // 并发的事务只能看到之前已提交的数据
+        let committed = vec![
+            (b"r5".to_vec(), b"committed5".to_vec()),
+            (b"r6".to_vec(), b"committed6".to_vec()),
+        ];
+        for key in [b"r1", b"r2", b"r3", b"r4"] {
+            assert_eq!(other.get(key.to_vec())?, None);
+        }
+        assert_eq!(scan(&other)?, committed);
+
+        // 提交之后新的事务读到相同的结果
+        transaction.commit()?;
+        let reader = mvcc.begin_read_only()?;
+        for (key, value) in expected.iter() {
+            assert_eq!(reader.get(key.clone())?, Some(value.clone()));
+        }
+        assert_eq!(reader.get(b"r3".to_vec())?, None);
+        assert_eq!(reader.get(b"r5".to_vec())?, None);
+        assert_eq!(scan(&reader)?, expected);
+        assert_eq!(scan_reverse(&reader)?, reversed);
+        assert_eq!(scan(&other)?, committed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_your_writes() -> Result<()> {
+        read_your_writes(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        read_your_writes(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 每次写入只在存储中留下一条数据版本记录
+    #[test]
+    fn test_write_log_bytes() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mvcc = Mvcc::new(DiskEngine::new(p.clone())?);
+        let log_size = || std::fs::metadata(&p).map(|m| m.len());
+        let before = log_size()?;
+
+        let mut transaction = mvcc.begin()?;
+        let mut expected = 0;
+        for i in 0..1000 {
+            let key = format!("orders_by_customer_name_{:0>40}", i).into_bytes();
+            let value = b"value".to_vec();
+            // 每条日志记录有 8 字节的头部，分别存放 key 和 value 的长度
+            expected += 8
+                + MvccKey::Version(key.clone(), transaction.get_version())
+                    .encode()?
+                    .len()
+                + bincode::serialize(&Some(value.clone()))?.len();
+            transaction.set(key, value)?;
+        }
+        transaction.commit()?;
+
+        // 除了数据版本之外只有版本号和活跃事务的几条记录
+        let written = (log_size()? - before) as usize;
+        assert!(written >= expected, "{} < {}", written, expected);
+        assert!(
+            written <= expected + expected / 50,
+            "{} > {}",
+            written,
+            expected
+        );
+
+        drop(mvcc);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 启动时回滚上次没有结束的事务
+    #[test]
+    fn test_recover() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        {
+            let mvcc = Mvcc::new(DiskEngine::new(p.clone())?);
+            let mut transaction = mvcc.begin()?;
+            transaction.set(b"key1".to_vec(), b"val1".to_vec())?;
+            transaction.set(b"key2".to_vec(), b"val2".to_vec())?;
+            transaction.commit()?;
+
+            // 事务没有提交也没有回滚就退出了
+            let mut transaction = mvcc.begin()?;
+            transaction.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+            transaction.delete(b"key2".to_vec())?;
+            transaction.set(b"key3".to_vec(), b"val3".to_vec())?;
+        }
+
+        // 旧版本的存储格式中没有结束的事务，逐个key记录了写入信息
+        let mut engine = DiskEngine::new(p.clone())?;
+        let version = 3;
+        engine.set(
+            MvccKey::NextVersion.encode()?,
+            bincode::serialize(&(version + 1))?,
+        )?;
+        engine.set(
+            MvccKey::ActiveTransactions(version).encode()?,
+            bincode::serialize(&version)?,
+        )?;
+        engine.set(MvccKey::Write(version, b"key4".to_vec()).encode()?, vec![])?;
+        engine.set(
+            MvccKey::Version(b"key4".to_vec(), version).encode()?,
+            bincode::serialize(&Some(b"val4".to_vec()))?,
+        )?;
+        drop(engine);
+
+        {
+            let mvcc = Mvcc::new(DiskEngine::new(p.clone())?);
+            let transaction = mvcc.begin()?;
+            assert_eq!(
+                transaction.prefix_scan(b"key".to_vec())?,
+                vec![
+                    ScanResult {
+                        key: b"key1".to_vec(),
+                        value: b"val1".to_vec()
+                    },
+                    ScanResult {
+                        key: b"key2".to_vec(),
+                        value: b"val2".to_vec()
+                    },
+                ]
+            );
+            transaction.commit()?;
+        }
+
+        // 没有结束的事务的数据、写入信息和活跃事务记录都被清理
+        let engine = DiskEngine::new(p.clone())?;
+        let mut keys = Vec::new();
+        let mut iter = engine.scan(..);
+        while let Some((key, _)) = iter.next().transpose()? {
+            keys.push(MvccKey::decode(&key)?);
+        }
+        assert_eq!(
+            keys,
+            vec![
+                MvccKey::NextVersion,
+                MvccKey::Version(b"key1".to_vec(), 1),
+                MvccKey::Version(b"key2".to_vec(), 1),
+            ]
+        );
+        drop(engine);
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
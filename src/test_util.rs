@@ -0,0 +1,203 @@
+//! 编写测试用的辅助函数：不依赖行顺序地比较查询结果、用紧凑的文本表格断言表中的数据、
+//! 不经过 sql 解析直接写入测试数据，以及取出查询结果的行和执行计划。
+//!
+//! 本 crate 的测试中始终可用，下游使用时需要开启 `test-util` feature。
+
+use crate::error::Result;
+use crate::sql::engine::{Engine, Session, Transaction};
+use crate::sql::executor::ResultSet;
+use crate::sql::parser::lexer::{quote_identifier, quote_string};
+use crate::sql::schema::Table;
+use crate::sql::types::{DataType, Row, Value};
+
+/// 把查询结果的行和期望的行当作多重集合比较，不关心顺序，但重复的行必须出现相同的次数。
+///
+/// 不一致时 panic，信息中分别列出缺少的行和多出的行。`result` 不是查询结果时同样 panic。
+pub fn assert_rows_unordered(result: &ResultSet, expected: Vec<Row>) {
+    let rows = match result {
+        ResultSet::Scan { rows, .. } => rows,
+        other => panic!("expected a query result, got: {}", other.to_string()),
+    };
+
+    let mut unexpected: Vec<&Row> = rows.iter().collect();
+    let mut missing = Vec::new();
+    for row in expected.iter() {
+        match unexpected.iter().position(|r| *r == row) {
+            Some(i) => {
+                unexpected.swap_remove(i);
+            }
+            None => missing.push(row),
+        }
+    }
+    if missing.is_empty() && unexpected.is_empty() {
+        return;
+    }
+
+    let mut message = format!(
+        "rows mismatch: got {} rows, expected {} rows\n",
+        rows.len(),
+        expected.len()
+    );
+    for (title, diff) in [
+        ("missing (expected but not returned)", missing),
+        ("unexpected (returned but not expected)", unexpected),
+    ] {
+        if !diff.is_empty() {
+            message.push_str(title);
+            message.push_str(":\n");
+            for row in diff {
+                message.push_str(&format!("  {}\n", format_row(row)));
+            }
+        }
+    }
+    panic!("{}", message);
+}
+
+/// 断言表中的全部数据，不关心行的顺序。
+///
+/// `expected` 每行对应表中的一行，按建表时列的顺序用逗号分隔，空行会被忽略。
+/// 值按表结构中列的类型解析：`NULL` 表示空值；字符串可以不加引号，包含逗号、
+/// 首尾空格或者要表示字符串 `NULL` 时用单引号括起来，引号内的 `''` 表示一个单引号。
+///
+/// ```text
+/// 1, vv, 100
+/// 2, 'a, b', NULL
+/// ```
+///
+/// 期望值无法按列的类型解析时 panic。
+pub fn assert_table<E: Engine + 'static>(
+    session: &mut Session<E>,
+    table_name: &str,
+    expected: &str,
+) -> Result<()> {
+    let table = session.with_transaction(|t| t.must_get_table(table_name.into()))?;
+    let expected = parse_rows(&table, expected);
+    let result = session.execute(&format!("select * from {};", quote_identifier(table_name)))?;
+    assert_rows_unordered(&result, expected);
+    Ok(())
+}
+
+/// 不经过 sql 解析，直接调用引擎接口向表中批量写入行，用于快速准备测试数据。
+///
+/// 处在显式事务中时写入当前事务，否则在单独的事务中写入并提交。
+/// 和 insert 语句一样会检查主键冲突、维护索引，但不会补全省略的列，每行都要给出全部列的值。
+pub fn populate<E: Engine + 'static>(
+    session: &mut Session<E>,
+    table_name: &str,
+    rows: Vec<Row>,
+) -> Result<()> {
+    session.with_transaction(|t| t.create_rows(table_name.into(), rows))
+}
+
+/// 执行查询，返回结果中的全部行。语句不是查询时 panic。
+pub fn query_rows<E: Engine + 'static>(session: &mut Session<E>, sql: &str) -> Result<Vec<Row>> {
+    match session.execute(sql)? {
+        ResultSet::Scan { rows, .. } => Ok(rows),
+        other => panic!("expected a query result, got: {}", other.to_string()),
+    }
+}
+
+/// 在语句前加上 `explain` 执行，返回执行计划的文本。
+pub fn explain_plan<E: Engine + 'static>(session: &mut Session<E>, sql: &str) -> Result<String> {
+    match session.execute(&format!("explain {}", sql))? {
+        ResultSet::Explain { plan } => Ok(plan),
+        other => panic!("expected a plan, got: {}", other.to_string()),
+    }
+}
+
+fn format_row(row: &Row) -> String {
+    let values = row
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => quote_string(s),
+            v => v.to_string(),
+        })
+        .collect::<Vec<_>>();
+    format!("({})", values.join(", "))
+}
+
+fn parse_rows(table: &Table, text: &str) -> Vec<Row> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let cells = split_cells(line)
+                .unwrap_or_else(|e| panic!("line {} of expected table: {}", n + 1, e));
+            if cells.len() != table.columns.len() {
+                panic!(
+                    "line {} of expected table: got {} values, table {} has {} columns",
+                    n + 1,
+                    cells.len(),
+                    quote_identifier(&table.name),
+                    table.columns.len()
+                );
+            }
+            cells
+                .into_iter()
+                .zip(table.columns.iter())
+                .map(|((text, quoted), column)| {
+                    parse_value(&text, quoted, &column.datatype).unwrap_or_else(|| {
+                        panic!(
+                            "line {} of expected table: can not parse {} as {:?} for column {}",
+                            n + 1,
+                            text,
+                            column.datatype,
+                            quote_identifier(&column.name)
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// 按逗号切分一行，返回每个值的文本以及是否带引号
+fn split_cells(line: &str) -> std::result::Result<Vec<(String, bool)>, String> {
+    let mut cells = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let cell = if chars.next_if_eq(&'\'').is_some() {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.next_if_eq(&'\'').is_some() => s.push('\''),
+                    Some('\'') => break,
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated quoted string".into()),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            (s, true)
+        } else {
+            let mut s = String::new();
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                s.push(c);
+            }
+            (s.trim_end().to_string(), false)
+        };
+        cells.push(cell);
+        match chars.next() {
+            Some(',') => continue,
+            None => return Ok(cells),
+            Some(c) => return Err(format!("unexpected character {} after quoted string", c)),
+        }
+    }
+}
+
+fn parse_value(text: &str, quoted: bool, datatype: &DataType) -> Option<Value> {
+    if !quoted && text.eq_ignore_ascii_case("null") {
+        return Some(Value::Null);
+    }
+    match datatype {
+        DataType::String | DataType::Char(_) => Some(Value::String(text.to_string())),
+        _ if quoted => None,
+        DataType::Boolean => match text.to_lowercase().as_str() {
+            "true" => Some(Value::Boolean(true)),
+            "false" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+        DataType::Integer => text.parse().ok().map(Value::Integer),
+        DataType::Float => text.parse().ok().map(Value::Float),
+    }
+}